@@ -20,13 +20,23 @@
 //! Without overlap, "It" in chunk 2 might lose its referent "cat" from chunk 1.
 //! With overlap, the sentence about the cat appears in both chunks.
 //!
+//! ## Headings
+//!
+//! For markup formats (Markdown, reStructuredText, Org), chunks prefer to
+//! break right before a heading so each chunk starts with its section title.
+//! Each format marks headings differently; see [`HeadingStyle`].
+//!
 //! ## UTF-8 Safety
 //!
 //! This chunker works with character counts, not byte counts, to safely handle
 //! multi-byte UTF-8 characters (like smart quotes, emojis, non-ASCII text).
 
+use crate::documents::DocumentType;
+use crate::text;
 use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use tracing::warn;
 
 /// Configuration for text chunking.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,7 +47,52 @@ pub struct ChunkConfig {
 
     /// Number of characters to overlap between consecutive chunks.
     /// Higher overlap = better context preservation but more chunks.
+    /// Capped so consecutive chunks start at least `chunk_size / 4` apart.
     pub overlap: usize,
+
+    /// Chunking fails rather than produce more chunks than this.
+    /// A safety net against configs that would flood the index.
+    #[serde(default = "default_max_chunks")]
+    pub max_chunks: usize,
+
+    /// A final chunk shorter than this (in characters) is merged into the
+    /// chunk before it rather than kept as a sliver of a few words, so the
+    /// last chunk can run past `chunk_size`. Capped at a quarter of
+    /// `chunk_size` (see `ChunkConfig::merge_below`); 0 disables it.
+    #[serde(default = "default_min_chunk_size")]
+    pub min_chunk_size: usize,
+
+    /// Characters that end a sentence, for breaking between sentences.
+    /// `.`, `!` and `?` when unset. CJK text can use `。！？`, which (being
+    /// non-ASCII) don't need to be followed by whitespace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sentence_terminators: Option<Vec<char>>,
+
+    /// Characters that separate words, for breaking when no sentence ends
+    /// in the window. Space, tab and newline when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub word_separators: Option<Vec<char>>,
+
+    /// How the text marks headings, if it does; a break right before a
+    /// heading line is then preferred over any other break point. Follows
+    /// the document type (see `ChunkConfig::for_document_type`), so it
+    /// isn't stored with the rest of the config.
+    #[serde(skip)]
+    pub headings: Option<HeadingStyle>,
+}
+
+/// Default for `ChunkConfig::max_chunks`: a few hundred MB of text at the default size.
+const DEFAULT_MAX_CHUNKS: usize = 100_000;
+
+fn default_max_chunks() -> usize {
+    DEFAULT_MAX_CHUNKS
+}
+
+/// Default for `ChunkConfig::min_chunk_size`.
+const DEFAULT_MIN_CHUNK_SIZE: usize = 50;
+
+fn default_min_chunk_size() -> usize {
+    DEFAULT_MIN_CHUNK_SIZE
 }
 
 impl Default for ChunkConfig {
@@ -45,6 +100,142 @@ impl Default for ChunkConfig {
         ChunkConfig {
             chunk_size: 1000,  // ~250 tokens (rough estimate: 4 chars/token)
             overlap: 200,      // 20% overlap
+            max_chunks: DEFAULT_MAX_CHUNKS,
+            min_chunk_size: DEFAULT_MIN_CHUNK_SIZE,
+            sentence_terminators: None,
+            word_separators: None,
+            headings: None,
+        }
+    }
+}
+
+impl ChunkConfig {
+    /// Creates a validated chunk configuration.
+    ///
+    /// Returns an error if the combination can't produce forward progress
+    /// (see [`ChunkConfig::validate`]).
+    pub fn new(chunk_size: usize, overlap: usize) -> Result<Self, String> {
+        let config = ChunkConfig {
+            chunk_size,
+            overlap,
+            ..ChunkConfig::default()
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// This config with the heading style of `doc_type`, as documents of
+    /// that type are chunked.
+    pub fn for_document_type(&self, doc_type: DocumentType) -> Self {
+        ChunkConfig {
+            headings: HeadingStyle::for_document_type(doc_type),
+            ..self.clone()
+        }
+    }
+
+    /// Checks that the configuration is usable.
+    ///
+    /// Rejects `chunk_size == 0` and `overlap >= chunk_size`, since either
+    /// would leave the chunker without a positive step between chunks,
+    /// and `max_chunks == 0`.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.chunk_size == 0 {
+            return Err("chunk_size must be greater than 0".to_string());
+        }
+        if self.max_chunks == 0 {
+            return Err("max_chunks must be greater than 0".to_string());
+        }
+        if self.overlap >= self.chunk_size {
+            return Err(format!(
+                "overlap ({}) must be smaller than chunk_size ({})",
+                self.overlap, self.chunk_size
+            ));
+        }
+        Ok(())
+    }
+
+    /// Characters between the starts of consecutive chunks.
+    ///
+    /// Normally `chunk_size - overlap`, but never less than a quarter of
+    /// `chunk_size`: with overlap close to chunk_size the step would shrink
+    /// to a character or two and a large document would produce an enormous
+    /// number of nearly identical chunks.
+    pub fn step(&self) -> usize {
+        let min_step = (self.chunk_size / 4).max(1);
+        self.chunk_size.saturating_sub(self.overlap).max(min_step)
+    }
+
+    /// Length below which a final chunk is merged into the one before it.
+    ///
+    /// `min_chunk_size`, but never more than a quarter of `chunk_size`, so
+    /// the default doesn't swallow whole chunks when `chunk_size` is small.
+    pub fn merge_below(&self) -> usize {
+        self.min_chunk_size.min(self.chunk_size / 4)
+    }
+
+    /// Whether `c` ends a sentence, using `sentence_terminators` if set.
+    fn is_sentence_terminator(&self, c: char) -> bool {
+        match &self.sentence_terminators {
+            Some(terminators) => terminators.contains(&c),
+            None => text::is_sentence_terminator(c),
+        }
+    }
+
+    /// Whether `c` separates words, using `word_separators` if set.
+    fn is_word_separator(&self, c: char) -> bool {
+        match &self.word_separators {
+            Some(separators) => separators.contains(&c),
+            None => matches!(c, ' ' | '\t' | '\n'),
+        }
+    }
+}
+
+/// How headings are written in a markup format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeadingStyle {
+    /// `# Title`, `## Subtitle`
+    Markdown,
+    /// A title line underlined (and optionally overlined) with a repeated
+    /// punctuation character, e.g. `=====` or `-----`
+    Rst,
+    /// `* Title`, `** Subtitle`
+    Org,
+}
+
+impl HeadingStyle {
+    /// The heading style of a document type, or `None` for formats
+    /// without headings (plain text, PDF).
+    pub fn for_document_type(doc_type: DocumentType) -> Option<Self> {
+        match doc_type {
+            DocumentType::Md => Some(HeadingStyle::Markdown),
+            DocumentType::Rst => Some(HeadingStyle::Rst),
+            DocumentType::Org => Some(HeadingStyle::Org),
+            DocumentType::Pdf | DocumentType::Txt | DocumentType::Epub | DocumentType::Json => None,
+        }
+    }
+
+    /// Returns true if a heading starts on the line beginning at character `i`.
+    fn starts_heading(&self, char_indices: &[(usize, char)], i: usize) -> bool {
+        let line = line_at(char_indices, i);
+        match self {
+            HeadingStyle::Markdown => is_marker_heading(&line, '#'),
+            HeadingStyle::Org => is_marker_heading(&line, '*'),
+            HeadingStyle::Rst => {
+                let next_start = i + line.chars().count() + 1;
+                let next = line_at(char_indices, next_start);
+                if is_rst_adornment(&line) {
+                    // Overline, title, then a matching underline
+                    let under = line_at(char_indices, next_start + next.chars().count() + 1);
+                    !next.trim().is_empty() && under.trim_end() == line.trim_end()
+                } else {
+                    // Title and underline - unless an overline above is the real start
+                    let overlined = i > 0 && line_before(char_indices, i) == next;
+                    !line.trim().is_empty()
+                        && is_rst_adornment(&next)
+                        && next.trim_end().chars().count() >= line.trim_end().chars().count()
+                        && !overlined
+                }
+            }
         }
     }
 }
@@ -64,6 +255,22 @@ pub struct Chunk {
     pub end_offset: usize,
 }
 
+/// Aggregate statistics about the stored chunks.
+///
+/// Lengths are measured in characters (SQLite's `LENGTH()` on TEXT),
+/// matching how chunk sizes are configured.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChunkStats {
+    pub total_chunks: usize,
+    pub total_documents: usize,
+    /// Sum of all chunk lengths
+    pub total_chars: usize,
+    /// Average chunk length (0.0 when there are no chunks)
+    pub avg_chunk_len: f64,
+    pub min_chunk_len: usize,
+    pub max_chunk_len: usize,
+}
+
 /// Split text into overlapping chunks.
 ///
 /// This function is UTF-8 safe - it works with character indices, not byte indices.
@@ -71,94 +278,233 @@ pub struct Chunk {
 /// 1. Prefers splitting at paragraph boundaries (\n\n)
 /// 2. Falls back to sentence boundaries (. ! ?)
 /// 3. Falls back to word boundaries (spaces)
-/// 4. Last resort: splits at character boundary
-pub fn chunk_text(document_id: &str, text: &str, config: &ChunkConfig) -> Vec<Chunk> {
+/// 4. Last resort: splits at character boundary, exactly `chunk_size`
+///    characters in (e.g. inside a long URL or base64 blob)
+///
+/// No chunk is longer than `chunk_size` characters, except that a short
+/// final chunk is merged into the one before it (see `min_chunk_size`).
+///
+/// If `config.headings` is set, a break right before a heading line is
+/// preferred over any other break point.
+///
+/// Returns an error if `config` fails [`ChunkConfig::validate`], or if the
+/// text would produce more than `config.max_chunks` chunks.
+pub fn chunk_text(document_id: &str, text: &str, config: &ChunkConfig) -> Result<Vec<Chunk>, String> {
     let mut chunks = Vec::new();
-    let text = text.trim();
-
-    if text.is_empty() {
-        return chunks;
+    for chunk in chunk_iter(document_id, text, config)? {
+        if chunks.len() == config.max_chunks {
+            return Err(too_many_chunks(config));
+        }
+        chunks.push(chunk);
     }
+    Ok(chunks)
+}
+
+/// The error [`chunk_text`] returns for text that would produce more than
+/// `config.max_chunks` chunks, for callers enforcing the cap on a
+/// [`chunk_iter`] themselves.
+pub fn too_many_chunks(config: &ChunkConfig) -> String {
+    format!(
+        "Text would produce more than {} chunks (max_chunks); increase chunk_size",
+        config.max_chunks
+    )
+}
 
+/// Split text into overlapping chunks lazily.
+///
+/// Yields the same chunks as [`chunk_text`], one at a time, so a huge
+/// document can be embedded and discarded chunk by chunk instead of
+/// holding every chunk at once. Returns an error if `config` fails
+/// [`ChunkConfig::validate`]. `max_chunks` isn't enforced; limit the
+/// iterator yourself if needed.
+pub fn chunk_iter<'a>(
+    document_id: &'a str,
+    text: &'a str,
+    config: &'a ChunkConfig,
+) -> Result<ChunkIter<'a>, String> {
+    config.validate()?;
+
+    let text = text.trim();
     // Collect character indices for UTF-8 safe slicing
     let char_indices: Vec<(usize, char)> = text.char_indices().collect();
-    let total_chars = char_indices.len();
-
-    // If text is smaller than chunk size, return as single chunk
-    if total_chars <= config.chunk_size {
-        chunks.push(Chunk {
-            id: format!("{}-0", document_id),
-            document_id: document_id.to_string(),
-            chunk_index: 0,
-            content: text.to_string(),
-            start_offset: 0,
-            end_offset: total_chars,
-        });
-        return chunks;
-    }
-
-    let mut start_char = 0; // Character index (not byte)
-    let mut chunk_index = 0;
-
-    while start_char < total_chars {
-        // Calculate the end character position for this chunk
-        let mut end_char = (start_char + config.chunk_size).min(total_chars);
-
-        // If we're not at the end, try to find a good break point
-        if end_char < total_chars {
-            end_char = find_break_point_chars(&char_indices, start_char, end_char);
+
+    // Text that fits in one chunk is returned whole, in a single step
+    let step = if char_indices.len() <= config.chunk_size {
+        char_indices.len().max(1)
+    } else {
+        let step = config.step();
+        if step > config.chunk_size - config.overlap {
+            warn!(
+                overlap = config.overlap,
+                chunk_size = config.chunk_size,
+                step,
+                "Overlap is too close to chunk_size; using a larger step"
+            );
         }
+        step
+    };
 
-        // Get byte positions from character positions for slicing
-        let start_byte = char_indices[start_char].0;
-        let end_byte = if end_char >= total_chars {
-            text.len()
-        } else {
-            char_indices[end_char].0
-        };
+    Ok(ChunkIter {
+        document_id,
+        text,
+        char_indices,
+        config,
+        step,
+        start_char: 0,
+        chunk_index: 0,
+        lookahead: VecDeque::new(),
+    })
+}
+
+/// Iterator over a document's chunks, returned by [`chunk_iter`].
+pub struct ChunkIter<'a> {
+    document_id: &'a str,
+    /// The trimmed text
+    text: &'a str,
+    char_indices: Vec<(usize, char)>,
+    config: &'a ChunkConfig,
+    step: usize,
+    /// Character index (not byte) where the next chunk starts
+    start_char: usize,
+    chunk_index: usize,
+    /// Chunks already cut but not yet yielded. Up to two are held back, so
+    /// a short last chunk can still be merged into the one before it.
+    lookahead: VecDeque<Chunk>,
+}
+
+impl ChunkIter<'_> {
+    /// Cuts the next non-empty chunk, before any merging.
+    fn next_chunk(&mut self) -> Option<Chunk> {
+        let total_chars = self.char_indices.len();
+        while self.start_char < total_chars {
+            let start_char = self.start_char;
+            // Move start position, accounting for overlap
+            self.start_char += self.step;
+
+            // Calculate the end character position for this chunk
+            let mut end_char = (start_char + self.config.chunk_size).min(total_chars);
+
+            // If we're not at the end, try to find a good break point.
+            // Without one (a long "word" with no spaces), hard split at the budget
+            if end_char < total_chars {
+                end_char =
+                    find_break_point_chars(&self.char_indices, start_char, end_char, self.config)
+                        .unwrap_or(end_char);
+            }
 
-        // Extract the chunk content
-        let content = text[start_byte..end_byte].trim().to_string();
+            // Get byte positions from character positions for slicing
+            let start_byte = self.char_indices[start_char].0;
+            let end_byte = self
+                .char_indices
+                .get(end_char)
+                .map_or(self.text.len(), |&(byte, _)| byte);
 
-        if !content.is_empty() {
-            chunks.push(Chunk {
-                id: format!("{}-{}", document_id, chunk_index),
-                document_id: document_id.to_string(),
-                chunk_index,
+            // Extract the chunk content
+            let content = self.text[start_byte..end_byte].trim().to_string();
+            if content.is_empty() {
+                continue;
+            }
+
+            let chunk = Chunk {
+                id: chunk_id(self.document_id, self.chunk_index, &content),
+                document_id: self.document_id.to_string(),
+                chunk_index: self.chunk_index,
                 content,
                 start_offset: start_char,
                 end_offset: end_char,
-            });
-            chunk_index += 1;
+            };
+            self.chunk_index += 1;
+            return Some(chunk);
         }
+        None
+    }
 
-        // Move start position, accounting for overlap
-        let step = if config.chunk_size > config.overlap {
-            config.chunk_size - config.overlap
-        } else {
-            config.chunk_size / 2
-        };
-        start_char += step.max(1);
+    /// Merges `last` into `previous`, which then spans from its own start
+    /// to `last`'s end and gets a new ID since its content changed.
+    fn merge(&self, previous: &mut Chunk, last: Chunk) {
+        let start_byte = self.char_indices[previous.start_offset].0;
+        let end_byte = self
+            .char_indices
+            .get(last.end_offset)
+            .map_or(self.text.len(), |&(byte, _)| byte);
+
+        previous.content = self.text[start_byte..end_byte].trim().to_string();
+        previous.end_offset = last.end_offset.max(previous.end_offset);
+        previous.id = chunk_id(self.document_id, previous.chunk_index, &previous.content);
+    }
+}
+
+impl Iterator for ChunkIter<'_> {
+    type Item = Chunk;
+
+    fn next(&mut self) -> Option<Chunk> {
+        while self.lookahead.len() < 3 {
+            match self.next_chunk() {
+                Some(chunk) => self.lookahead.push_back(chunk),
+                None => break,
+            }
+        }
+
+        // Fewer than three left means the text is used up. A final chunk
+        // shorter than `min_chunk_size` joins the one before it
+        if let [_, last] = self.lookahead.make_contiguous() {
+            if last.content.chars().count() < self.config.merge_below() {
+                let last = self.lookahead.pop_back().expect("checked above");
+                let mut previous = self.lookahead.pop_back().expect("checked above");
+                self.merge(&mut previous, last);
+                return Some(previous);
+            }
+        }
+        self.lookahead.pop_front()
     }
+}
+
+/// Build a chunk ID of the form `"{document_id}-{index}-{hash8}"`.
+///
+/// The short content hash means re-chunking with different settings (or
+/// after the text changed) produces new IDs, so an `INSERT OR REPLACE`
+/// can never leave an old embedding attached to different text.
+fn chunk_id(document_id: &str, chunk_index: usize, content: &str) -> String {
+    format!("{}-{}-{:08x}", document_id, chunk_index, content_hash(content))
+}
 
-    chunks
+/// 32-bit FNV-1a hash of the content.
+///
+/// Used instead of `DefaultHasher`, whose output may change between
+/// Rust releases - chunk IDs are persisted, so they must be stable.
+fn content_hash(content: &str) -> u32 {
+    const FNV_OFFSET: u32 = 0x811c_9dc5;
+    const FNV_PRIME: u32 = 0x0100_0193;
+
+    content.bytes().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(FNV_PRIME)
+    })
 }
 
 /// Find a good break point for chunking (working with character indices).
 ///
 /// Searches backwards from `end_char` to find a natural break point.
-/// Returns a character index (not byte index).
+/// Returns a character index (not byte index) in `start_char + 1..=end_char`,
+/// or `None` if the window has no natural break. Sentence and word breaks
+/// use the characters configured in `config`.
 fn find_break_point_chars(
     char_indices: &[(usize, char)],
     start_char: usize,
     end_char: usize,
-) -> usize {
-    // Look backwards from end for a good break point
-    let search_start = if end_char > start_char + 50 {
-        end_char.saturating_sub(200) // Look in last 200 chars
-    } else {
-        start_char
-    };
+    config: &ChunkConfig,
+) -> Option<usize> {
+    // Look backwards from end for a good break point, in the last 200 chars.
+    // Chunks smaller than that must not search (and break) before their start
+    let search_start = end_char.saturating_sub(200).max(start_char);
+
+    // Best of all, break right before a heading so the section stays together
+    if let Some(style) = config.headings {
+        for i in (search_start + 1..end_char).rev() {
+            if char_indices[i - 1].1 == '\n' && style.starts_heading(char_indices, i) {
+                return Some(i);
+            }
+        }
+    }
 
     // First, look for paragraph break (double newline)
     let mut found_newline = false;
@@ -167,7 +513,7 @@ fn find_break_point_chars(
         if c == '\n' {
             if found_newline {
                 // Found double newline - return position after it
-                return (i + 2).min(end_char);
+                return Some((i + 2).min(end_char));
             }
             found_newline = true;
         } else if !c.is_whitespace() {
@@ -175,15 +521,19 @@ fn find_break_point_chars(
         }
     }
 
-    // Look for sentence break (. ! ? followed by space)
+    // Look for sentence break (. ! ? followed by space),
+    // skipping periods that belong to abbreviations like "Dr."
     for i in (search_start..end_char.saturating_sub(1)).rev() {
         let c = char_indices[i].1;
-        if c == '.' || c == '!' || c == '?' {
-            // Check if followed by whitespace
+        if config.is_sentence_terminator(c) {
+            // Check if followed by whitespace. Scripts with full-width
+            // terminators like 。 don't put spaces between sentences
             if i + 1 < char_indices.len() {
                 let next_c = char_indices[i + 1].1;
-                if next_c.is_whitespace() {
-                    return i + 1; // Return position after punctuation
+                if (next_c.is_whitespace() || !c.is_ascii())
+                    && text::ends_sentence(&word_before(char_indices, i), c)
+                {
+                    return Some(i + 1); // Return position after punctuation
                 }
             }
         }
@@ -191,14 +541,65 @@ fn find_break_point_chars(
 
     // Look for word break (space)
     for i in (search_start..end_char).rev() {
-        let c = char_indices[i].1;
-        if c == ' ' || c == '\n' || c == '\t' {
-            return i + 1; // Return position after space
+        if config.is_word_separator(char_indices[i].1) {
+            return Some(i + 1); // Return position after space
         }
     }
 
-    // No good break point found, use the original end
-    end_char
+    // No good break point found
+    None
+}
+
+/// Collect the line starting at character `i`, without its newline.
+///
+/// Returns an empty string past the end of the text.
+fn line_at(char_indices: &[(usize, char)], i: usize) -> String {
+    char_indices
+        .get(i..)
+        .unwrap_or_default()
+        .iter()
+        .map(|&(_, c)| c)
+        .take_while(|&c| c != '\n')
+        .collect()
+}
+
+/// Collect the line before the one starting at character `i`.
+fn line_before(char_indices: &[(usize, char)], i: usize) -> String {
+    // char_indices[i - 1] is the newline ending the previous line
+    let end = i.saturating_sub(1);
+    let start = char_indices[..end]
+        .iter()
+        .rposition(|&(_, c)| c == '\n')
+        .map_or(0, |pos| pos + 1);
+    char_indices[start..end].iter().map(|&(_, c)| c).collect()
+}
+
+/// Returns true if `line` is `marker`s followed by a space, like `## Title`.
+fn is_marker_heading(line: &str, marker: char) -> bool {
+    let rest = line.trim_start_matches(marker);
+    rest.len() < line.len() && rest.starts_with(' ')
+}
+
+/// Returns true if `line` is a reStructuredText section adornment:
+/// at least two of the same punctuation character, like `=====`.
+fn is_rst_adornment(line: &str) -> bool {
+    let line = line.trim_end();
+    let mut chars = line.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_punctuation() => {
+            line.len() >= 2 && chars.all(|c| c == first)
+        }
+        _ => false,
+    }
+}
+
+/// Collect the non-whitespace run of characters ending just before `i`.
+fn word_before(char_indices: &[(usize, char)], i: usize) -> String {
+    let start = char_indices[..i]
+        .iter()
+        .rposition(|(_, c)| c.is_whitespace())
+        .map_or(0, |pos| pos + 1);
+    char_indices[start..i].iter().map(|(_, c)| c).collect()
 }
 
 /// Initialize the chunks table in SQLite.
@@ -222,49 +623,251 @@ pub fn init_chunks_table(conn: &Connection) -> Result<(), rusqlite::Error> {
         [],
     )?;
 
+    // The chunk config that produced each document's chunks,
+    // so reindexing can reproduce them
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS document_config (
+            document_id TEXT PRIMARY KEY,
+            chunk_size INTEGER NOT NULL,
+            overlap INTEGER NOT NULL,
+            sentence_terminators TEXT,
+            word_separators TEXT,
+            min_chunk_size INTEGER,
+            FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    // Migration: break characters were added later; NULL means the defaults
+    crate::db::add_column_if_missing(conn, "document_config", "sentence_terminators", "TEXT")?;
+    crate::db::add_column_if_missing(conn, "document_config", "word_separators", "TEXT")?;
+    // Likewise the minimum chunk size; NULL means no minimum, as before it existed
+    crate::db::add_column_if_missing(conn, "document_config", "min_chunk_size", "INTEGER")?;
+
+    Ok(())
+}
+
+/// Record the chunk config used for a document.
+pub fn save_document_config(
+    conn: &Connection,
+    document_id: &str,
+    config: &ChunkConfig,
+) -> Result<(), rusqlite::Error> {
+    // Character sets are stored as strings, one character each
+    let chars = |set: &Option<Vec<char>>| set.as_ref().map(|set| set.iter().collect::<String>());
+    conn.execute(
+        "INSERT OR REPLACE INTO document_config
+             (document_id, chunk_size, overlap, sentence_terminators, word_separators,
+              min_chunk_size)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            document_id,
+            config.chunk_size as i64,
+            config.overlap as i64,
+            chars(&config.sentence_terminators),
+            chars(&config.word_separators),
+            config.min_chunk_size as i64,
+        ],
+    )?;
     Ok(())
 }
 
+/// Get the chunk config a document was last chunked with, if recorded.
+pub fn get_document_config(
+    conn: &Connection,
+    document_id: &str,
+) -> Result<Option<ChunkConfig>, rusqlite::Error> {
+    let result = conn.query_row(
+        "SELECT chunk_size, overlap, sentence_terminators, word_separators, min_chunk_size
+         FROM document_config WHERE document_id = ?1",
+        params![document_id],
+        |row| {
+            let chars = |set: Option<String>| set.map(|set| set.chars().collect());
+            Ok(ChunkConfig {
+                chunk_size: row.get::<_, i64>(0)? as usize,
+                overlap: row.get::<_, i64>(1)? as usize,
+                sentence_terminators: chars(row.get(2)?),
+                word_separators: chars(row.get(3)?),
+                min_chunk_size: row.get::<_, Option<i64>>(4)?.unwrap_or(0) as usize,
+                ..ChunkConfig::default()
+            })
+        },
+    );
+
+    match result {
+        Ok(config) => Ok(Some(config)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Re-chunk a document from its stored content, without writing anything.
+///
+/// Uses `config` when given, otherwise the config stored for the document,
+/// falling back to the default. Returns the chunks with the config used;
+/// [`replace_document_chunks`] stores them once they're embedded.
+pub fn rechunk_document(
+    conn: &Connection,
+    document_id: &str,
+    config: Option<ChunkConfig>,
+) -> Result<(Vec<Chunk>, ChunkConfig), String> {
+    let config = match config {
+        Some(config) => config,
+        None => get_document_config(conn, document_id)
+            .map_err(|e| e.to_string())?
+            .unwrap_or_default(),
+    };
+
+    let content = crate::documents::get_document_content(conn, document_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Document content not found: {}", document_id))?;
+
+    let headings = crate::documents::get_document(conn, document_id)
+        .map_err(|e| e.to_string())?
+        .and_then(|doc| HeadingStyle::for_document_type(doc.doc_type));
+
+    let config = ChunkConfig { headings, ..config };
+    let chunks = chunk_text(document_id, &content, &config)?;
+    Ok((chunks, config))
+}
+
+/// Removes a document's chunks and embeddings, then saves `chunks` and
+/// records the `config` they were cut with.
+pub fn replace_document_chunks(
+    conn: &Connection,
+    document_id: &str,
+    chunks: &[Chunk],
+    config: &ChunkConfig,
+) -> Result<(), rusqlite::Error> {
+    crate::vector_store::delete_document_embeddings(conn, document_id)?;
+    delete_document_chunks(conn, document_id)?;
+    save_chunks(conn, chunks)?;
+    save_document_config(conn, document_id, config)
+}
+
 /// Save chunks to the database.
 pub fn save_chunks(conn: &Connection, chunks: &[Chunk]) -> Result<(), rusqlite::Error> {
     for chunk in chunks {
-        conn.execute(
-            "INSERT OR REPLACE INTO chunks (id, document_id, chunk_index, content, start_offset, end_offset)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![
-                chunk.id,
-                chunk.document_id,
-                chunk.chunk_index as i64,
-                chunk.content,
-                chunk.start_offset as i64,
-                chunk.end_offset as i64,
-            ],
-        )?;
+        save_chunk(conn, chunk)?;
     }
     Ok(())
 }
 
+/// Save one chunk, e.g. as it comes from a [`chunk_iter`].
+pub fn save_chunk(conn: &Connection, chunk: &Chunk) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT OR REPLACE INTO chunks (id, document_id, chunk_index, content, start_offset, end_offset)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            chunk.id,
+            chunk.document_id,
+            chunk.chunk_index as i64,
+            chunk.content,
+            chunk.start_offset as i64,
+            chunk.end_offset as i64,
+        ],
+    )?;
+    Ok(())
+}
+
 /// Get all chunks for a document.
 pub fn get_document_chunks(conn: &Connection, document_id: &str) -> Result<Vec<Chunk>, rusqlite::Error> {
+    get_document_chunks_page(conn, document_id, None, 0)
+}
+
+/// Get a page of a document's chunks, ordered by `chunk_index`.
+///
+/// Skips the first `offset` chunks and returns at most `limit` (all the
+/// rest if `None`), so large documents can be browsed a page at a time.
+pub fn get_document_chunks_page(
+    conn: &Connection,
+    document_id: &str,
+    limit: Option<usize>,
+    offset: usize,
+) -> Result<Vec<Chunk>, rusqlite::Error> {
     let mut stmt = conn.prepare(
         "SELECT id, document_id, chunk_index, content, start_offset, end_offset
-         FROM chunks WHERE document_id = ?1 ORDER BY chunk_index"
+         FROM chunks WHERE document_id = ?1 ORDER BY chunk_index
+         LIMIT ?2 OFFSET ?3"
     )?;
 
-    let chunks = stmt.query_map(params![document_id], |row| {
-        Ok(Chunk {
-            id: row.get(0)?,
-            document_id: row.get(1)?,
-            chunk_index: row.get::<_, i64>(2)? as usize,
-            content: row.get(3)?,
-            start_offset: row.get::<_, i64>(4)? as usize,
-            end_offset: row.get::<_, i64>(5)? as usize,
-        })
-    })?;
+    // SQLite treats a negative LIMIT as no limit
+    let limit = limit.map_or(-1, |limit| limit as i64);
+    let chunks = stmt.query_map(params![document_id, limit, offset as i64], row_to_chunk)?;
 
     chunks.collect()
 }
 
+/// Get a single chunk by ID, e.g. for a citation's detail view.
+///
+/// Returns `None` if there's no such chunk.
+pub fn get_chunk(conn: &Connection, chunk_id: &str) -> Result<Option<Chunk>, rusqlite::Error> {
+    let result = conn.query_row(
+        "SELECT id, document_id, chunk_index, content, start_offset, end_offset
+         FROM chunks WHERE id = ?1",
+        params![chunk_id],
+        row_to_chunk,
+    );
+
+    match result {
+        Ok(chunk) => Ok(Some(chunk)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Maps a `SELECT id, document_id, chunk_index, content, start_offset,
+/// end_offset` row to a Chunk.
+fn row_to_chunk(row: &rusqlite::Row) -> Result<Chunk, rusqlite::Error> {
+    Ok(Chunk {
+        id: row.get(0)?,
+        document_id: row.get(1)?,
+        chunk_index: row.get::<_, i64>(2)? as usize,
+        content: row.get(3)?,
+        start_offset: row.get::<_, i64>(4)? as usize,
+        end_offset: row.get::<_, i64>(5)? as usize,
+    })
+}
+
+/// Get the source text around a chunk, for highlighting a citation in its document.
+///
+/// Returns the document content from `pad` characters before the chunk to `pad`
+/// characters after it, clamped to the document bounds. Returns `None` if the chunk
+/// or its document content doesn't exist.
+pub fn get_chunk_source_excerpt(
+    conn: &Connection,
+    chunk_id: &str,
+    pad: usize,
+) -> Result<Option<String>, rusqlite::Error> {
+    let result = conn.query_row(
+        "SELECT c.start_offset, c.end_offset, dc.content
+         FROM chunks c JOIN document_content dc ON dc.document_id = c.document_id
+         WHERE c.id = ?1",
+        params![chunk_id],
+        |row| {
+            Ok((
+                row.get::<_, i64>(0)? as usize,
+                row.get::<_, i64>(1)? as usize,
+                row.get::<_, String>(2)?,
+            ))
+        },
+    );
+
+    let (start_offset, end_offset, content) = match result {
+        Ok(row) => row,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    // Offsets are character positions in the trimmed text (see chunk_text),
+    // so shift them past any leading whitespace in the stored content
+    let leading = content.chars().take_while(|c| c.is_whitespace()).count();
+    let start = (leading + start_offset).saturating_sub(pad);
+    let end = leading + end_offset + pad;
+
+    Ok(Some(content.chars().skip(start).take(end - start).collect()))
+}
+
 /// Get all chunks (for all documents).
 pub fn get_all_chunks(conn: &Connection) -> Result<Vec<Chunk>, rusqlite::Error> {
     let mut stmt = conn.prepare(
@@ -272,16 +875,7 @@ pub fn get_all_chunks(conn: &Connection) -> Result<Vec<Chunk>, rusqlite::Error>
          FROM chunks ORDER BY document_id, chunk_index"
     )?;
 
-    let chunks = stmt.query_map([], |row| {
-        Ok(Chunk {
-            id: row.get(0)?,
-            document_id: row.get(1)?,
-            chunk_index: row.get::<_, i64>(2)? as usize,
-            content: row.get(3)?,
-            start_offset: row.get::<_, i64>(4)? as usize,
-            end_offset: row.get::<_, i64>(5)? as usize,
-        })
-    })?;
+    let chunks = stmt.query_map([], row_to_chunk)?;
 
     chunks.collect()
 }
@@ -309,17 +903,61 @@ pub fn get_chunk_stats(conn: &Connection) -> Result<(usize, usize), rusqlite::Er
     Ok((total_chunks as usize, total_docs as usize))
 }
 
+/// Get detailed chunk statistics, including content-length figures.
+///
+/// Everything is computed in a single aggregate query.
+pub fn chunk_stats(conn: &Connection) -> Result<ChunkStats, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COUNT(*),
+                COUNT(DISTINCT document_id),
+                COALESCE(SUM(LENGTH(content)), 0),
+                COALESCE(MIN(LENGTH(content)), 0),
+                COALESCE(MAX(LENGTH(content)), 0)
+         FROM chunks",
+        [],
+        |row| {
+            let total_chunks = row.get::<_, i64>(0)? as usize;
+            let total_chars = row.get::<_, i64>(2)? as usize;
+
+            Ok(ChunkStats {
+                total_chunks,
+                total_documents: row.get::<_, i64>(1)? as usize,
+                total_chars,
+                avg_chunk_len: if total_chunks > 0 {
+                    total_chars as f64 / total_chunks as f64
+                } else {
+                    0.0
+                },
+                min_chunk_len: row.get::<_, i64>(3)? as usize,
+                max_chunk_len: row.get::<_, i64>(4)? as usize,
+            })
+        },
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Re-chunks and stores a document, as the `reindex_document` command does.
+    fn reindex_document(
+        conn: &Connection,
+        document_id: &str,
+        config: Option<ChunkConfig>,
+    ) -> Result<Vec<Chunk>, String> {
+        let (chunks, config) = rechunk_document(conn, document_id, config)?;
+        replace_document_chunks(conn, document_id, &chunks, &config).map_err(|e| e.to_string())?;
+        Ok(chunks)
+    }
+
     #[test]
     fn test_small_text_single_chunk() {
         let config = ChunkConfig {
             chunk_size: 100,
             overlap: 20,
+            ..Default::default()
         };
-        let chunks = chunk_text("doc-1", "Small text.", &config);
+        let chunks = chunk_text("doc-1", "Small text.", &config).unwrap();
         assert_eq!(chunks.len(), 1);
         assert_eq!(chunks[0].content, "Small text.");
     }
@@ -329,9 +967,10 @@ mod tests {
         let config = ChunkConfig {
             chunk_size: 50,
             overlap: 10,
+            ..Default::default()
         };
         let text = "This is the first sentence. This is the second sentence. This is the third sentence.";
-        let chunks = chunk_text("doc-1", text, &config);
+        let chunks = chunk_text("doc-1", text, &config).unwrap();
 
         // Should have multiple chunks
         assert!(chunks.len() > 1);
@@ -348,12 +987,13 @@ mod tests {
         let config = ChunkConfig {
             chunk_size: 40,
             overlap: 5,
+            ..Default::default()
         };
         let text = "Hello world. This is a test. Another sentence here.";
-        let chunks = chunk_text("doc-1", text, &config);
+        let chunks = chunk_text("doc-1", text, &config).unwrap();
 
         // Should have multiple chunks
-        assert!(chunks.len() >= 1);
+        assert!(!chunks.is_empty());
 
         // All chunks should have non-empty content
         for chunk in &chunks {
@@ -361,17 +1001,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_break_skips_abbreviations() {
+        let config = ChunkConfig {
+            chunk_size: 34,
+            overlap: 5,
+            ..Default::default()
+        };
+        // The last ". " inside the first window is the one after "Dr"
+        let text = "First part here. Then we met Dr. Smith at the clinic today.";
+        let chunks = chunk_text("doc-1", text, &config).unwrap();
+
+        assert_eq!(chunks[0].content, "First part here.");
+    }
+
     #[test]
     fn test_empty_text() {
         let config = ChunkConfig::default();
-        let chunks = chunk_text("doc-1", "", &config);
+        let chunks = chunk_text("doc-1", "", &config).unwrap();
         assert!(chunks.is_empty());
     }
 
     #[test]
     fn test_whitespace_only() {
         let config = ChunkConfig::default();
-        let chunks = chunk_text("doc-1", "   \n\n   ", &config);
+        let chunks = chunk_text("doc-1", "   \n\n   ", &config).unwrap();
         assert!(chunks.is_empty());
     }
 
@@ -381,10 +1035,11 @@ mod tests {
         let config = ChunkConfig {
             chunk_size: 20,
             overlap: 5,
+            ..Default::default()
         };
         // Using Unicode escapes for smart quotes to avoid syntax issues
         let text = "Hello \u{201C}world\u{201D} with émojis 🎉 and más text here.";
-        let chunks = chunk_text("doc-1", text, &config);
+        let chunks = chunk_text("doc-1", text, &config).unwrap();
 
         // Should not panic and produce valid chunks
         assert!(!chunks.is_empty());
@@ -414,6 +1069,10 @@ mod tests {
             size: 100,
             uploaded_at: Utc::now(),
             path: "/tmp/test.txt".to_string(),
+            word_count: 0,
+            source_path: "/tmp/test.txt".to_string(),
+            modified_at: None,
+            language: None,
         };
         crate::documents::save_document(&conn, &doc).unwrap();
 
@@ -447,4 +1106,601 @@ mod tests {
         assert_eq!(total, 2);
         assert_eq!(docs, 1);
     }
+
+    #[test]
+    fn test_chunk_stats() {
+        use chrono::Utc;
+
+        let conn = Connection::open_in_memory().unwrap();
+        crate::documents::init_documents_table(&conn).unwrap();
+        init_chunks_table(&conn).unwrap();
+
+        // Empty table yields zeroed stats
+        let empty = chunk_stats(&conn).unwrap();
+        assert_eq!(empty.total_chunks, 0);
+        assert_eq!(empty.avg_chunk_len, 0.0);
+
+        for id in ["doc-1", "doc-2"] {
+            let doc = crate::documents::Document {
+                id: id.to_string(),
+                name: "test.txt".to_string(),
+                doc_type: crate::documents::DocumentType::Txt,
+                size: 100,
+                uploaded_at: Utc::now(),
+                path: "/tmp/test.txt".to_string(),
+                word_count: 0,
+                source_path: "/tmp/test.txt".to_string(),
+                modified_at: None,
+                language: None,
+            };
+            crate::documents::save_document(&conn, &doc).unwrap();
+        }
+
+        // Lengths 4, 10 and 7 (the last one multibyte: counted in chars)
+        let contents = [("doc-1", "abcd"), ("doc-1", "0123456789"), ("doc-2", "héllo🎉!")];
+        let chunks: Vec<Chunk> = contents
+            .iter()
+            .enumerate()
+            .map(|(i, (doc_id, content))| Chunk {
+                id: format!("chunk-{}", i),
+                document_id: doc_id.to_string(),
+                chunk_index: i,
+                content: content.to_string(),
+                start_offset: 0,
+                end_offset: content.chars().count(),
+            })
+            .collect();
+        save_chunks(&conn, &chunks).unwrap();
+
+        let stats = chunk_stats(&conn).unwrap();
+        assert_eq!(stats.total_chunks, 3);
+        assert_eq!(stats.total_documents, 2);
+        assert_eq!(stats.total_chars, 21);
+        assert!((stats.avg_chunk_len - 7.0).abs() < 1e-9);
+        assert_eq!(stats.min_chunk_len, 4);
+        assert_eq!(stats.max_chunk_len, 10);
+    }
+
+    #[test]
+    fn test_config_validation() {
+        assert!(ChunkConfig::new(100, 20).is_ok());
+        assert!(ChunkConfig::default().validate().is_ok());
+
+        // Zero-sized chunks are rejected
+        assert!(ChunkConfig::new(0, 0).is_err());
+
+        // Overlap must be strictly smaller than the chunk size
+        assert!(ChunkConfig::new(100, 100).is_err());
+        assert!(ChunkConfig::new(100, 150).is_err());
+    }
+
+    #[test]
+    fn test_chunk_text_rejects_invalid_config() {
+        let config = ChunkConfig {
+            chunk_size: 10,
+            overlap: 10,
+            ..Default::default()
+        };
+        let result = chunk_text("doc-1", "Some text that is longer than ten characters.", &config);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_reindex_uses_stored_config() {
+        use chrono::Utc;
+
+        let conn = Connection::open_in_memory().unwrap();
+        crate::documents::init_documents_table(&conn).unwrap();
+        init_chunks_table(&conn).unwrap();
+        crate::vector_store::init_embeddings_table(&conn).unwrap();
+
+        let doc = crate::documents::Document {
+            id: "doc-1".to_string(),
+            name: "test.txt".to_string(),
+            doc_type: crate::documents::DocumentType::Txt,
+            size: 100,
+            uploaded_at: Utc::now(),
+            path: "/tmp/test.txt".to_string(),
+            word_count: 0,
+            source_path: "/tmp/test.txt".to_string(),
+            modified_at: None,
+            language: None,
+        };
+        crate::documents::save_document(&conn, &doc).unwrap();
+        let text = "This is the first sentence. This is the second sentence. This is the third sentence.";
+        crate::documents::save_document_content(&conn, "doc-1", text).unwrap();
+
+        // No config recorded yet
+        assert!(get_document_config(&conn, "doc-1").unwrap().is_none());
+
+        let config = ChunkConfig::new(40, 5).unwrap();
+        save_document_config(&conn, "doc-1", &config).unwrap();
+
+        let stored = get_document_config(&conn, "doc-1").unwrap().unwrap();
+        assert_eq!(stored.chunk_size, 40);
+        assert_eq!(stored.overlap, 5);
+
+        // Reindexing without an explicit config reproduces the stored one
+        let chunks = reindex_document(&conn, "doc-1", None).unwrap();
+        let expected = chunk_text("doc-1", text, &config).unwrap();
+        assert_eq!(chunks.len(), expected.len());
+        assert!(chunks.len() > 1);
+
+        let saved = get_document_chunks(&conn, "doc-1").unwrap();
+        assert_eq!(saved.len(), expected.len());
+        for (a, b) in saved.iter().zip(expected.iter()) {
+            assert_eq!(a.content, b.content);
+        }
+
+        // An explicit config overrides and replaces the stored one
+        reindex_document(&conn, "doc-1", Some(ChunkConfig::default())).unwrap();
+        assert_eq!(get_document_chunks(&conn, "doc-1").unwrap().len(), 1);
+        assert_eq!(get_document_config(&conn, "doc-1").unwrap().unwrap().chunk_size, 1000);
+    }
+
+    #[test]
+    fn test_chunk_ids_include_content_hash() {
+        let config = ChunkConfig {
+            chunk_size: 40,
+            overlap: 5,
+            ..Default::default()
+        };
+        let text = "Hello world. This is a test. Another sentence here.";
+        let edited = "Hello there. This is a test. Another sentence here.";
+        let original = chunk_text("doc-1", text, &config).unwrap();
+        let again = chunk_text("doc-1", text, &config).unwrap();
+        let changed = chunk_text("doc-1", edited, &config).unwrap();
+
+        // Deterministic for identical content
+        let ids: Vec<&str> = original.iter().map(|c| c.id.as_str()).collect();
+        let ids_again: Vec<&str> = again.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ids_again);
+
+        // Format is "{document_id}-{index}-{hash8}"
+        assert!(original[0].id.starts_with("doc-1-0-"));
+        assert_eq!(original[0].id.len(), "doc-1-0-".len() + 8);
+
+        // Changed content at the same index gets a new ID
+        assert_eq!(original[0].chunk_index, changed[0].chunk_index);
+        assert_ne!(original[0].id, changed[0].id);
+    }
+
+    #[test]
+    fn test_reindex_changed_content_replaces_embeddings() {
+        use chrono::Utc;
+
+        let conn = Connection::open_in_memory().unwrap();
+        crate::documents::init_documents_table(&conn).unwrap();
+        init_chunks_table(&conn).unwrap();
+        crate::vector_store::init_embeddings_table(&conn).unwrap();
+
+        let doc = crate::documents::Document {
+            id: "doc-1".to_string(),
+            name: "test.txt".to_string(),
+            doc_type: crate::documents::DocumentType::Txt,
+            size: 100,
+            uploaded_at: Utc::now(),
+            path: "/tmp/test.txt".to_string(),
+            word_count: 0,
+            source_path: "/tmp/test.txt".to_string(),
+            modified_at: None,
+            language: None,
+        };
+        crate::documents::save_document(&conn, &doc).unwrap();
+        crate::documents::save_document_content(&conn, "doc-1", "Original text.").unwrap();
+
+        let old_chunks = reindex_document(&conn, "doc-1", None).unwrap();
+        let old_id = &old_chunks[0].id;
+        crate::vector_store::save_embedding(&conn, old_id, "doc-1", &[1.0, 0.0], "test-model").unwrap();
+
+        // The document text changes, then it's reindexed
+        conn.execute(
+            "UPDATE document_content SET content = 'Edited text.' WHERE document_id = 'doc-1'",
+            [],
+        )
+        .unwrap();
+        let new_chunks = reindex_document(&conn, "doc-1", None).unwrap();
+
+        assert_ne!(old_chunks[0].id, new_chunks[0].id);
+        // The stale vector is gone rather than attached to the new text
+        assert!(crate::vector_store::get_embedding(&conn, &old_chunks[0].id).unwrap().is_none());
+        assert!(crate::vector_store::get_embedding(&conn, &new_chunks[0].id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_breaks_before_headings() {
+        let body = "Some text about the first section that goes on for a while.";
+        let tail = "More text. Following words go on for a while longer than the chunk.";
+
+        // Each case: text, heading style, where the second heading starts
+        let org = format!("* Intro\n{}\n* Details\n{}", body, tail);
+        let rst = format!("Intro\n=====\n{}\nDetails\n-------\n{}", body, tail);
+        // An overlined title breaks before the overline, not the title
+        let overlined = format!("Intro\n=====\n{}\n=======\nDetails\n=======\n{}", body, tail);
+        let cases = [
+            (org, HeadingStyle::Org, "* Details"),
+            (rst, HeadingStyle::Rst, "Details\n-------"),
+            (overlined, HeadingStyle::Rst, "=======\nDetails"),
+        ];
+
+        for (text, style, heading) in cases {
+            let heading_start = text.find(heading).unwrap();
+            // Large enough to reach "More text." past the heading
+            let config = ChunkConfig {
+                chunk_size: heading_start + heading.len() + 20,
+                overlap: 0,
+                ..Default::default()
+            };
+
+            let with_headings = ChunkConfig { headings: Some(style), ..config.clone() };
+            let chunks = chunk_text("doc-1", &text, &with_headings).unwrap();
+            assert_eq!(chunks[0].content, text[..heading_start].trim());
+
+            // Without a heading style the chunk runs on to a later sentence break
+            let chunks = chunk_text("doc-1", &text, &config).unwrap();
+            assert!(chunks[0].content.ends_with("More text."));
+        }
+    }
+
+    #[test]
+    fn test_heading_detection() {
+        assert!(is_marker_heading("** Sub", '*'));
+        assert!(!is_marker_heading("*bold* text", '*'));
+        assert!(is_marker_heading("# Title", '#'));
+        assert!(is_rst_adornment("~~~~~"));
+        assert!(!is_rst_adornment("-- not a line"));
+        assert!(!is_rst_adornment("="));
+    }
+
+    #[test]
+    fn test_chunk_source_excerpt() {
+        use chrono::Utc;
+
+        let conn = Connection::open_in_memory().unwrap();
+        crate::documents::init_documents_table(&conn).unwrap();
+        init_chunks_table(&conn).unwrap();
+
+        let doc = crate::documents::Document {
+            id: "doc-1".to_string(),
+            name: "test.txt".to_string(),
+            doc_type: crate::documents::DocumentType::Txt,
+            size: 100,
+            uploaded_at: Utc::now(),
+            path: "/tmp/test.txt".to_string(),
+            word_count: 0,
+            source_path: "/tmp/test.txt".to_string(),
+            modified_at: None,
+            language: None,
+        };
+        crate::documents::save_document(&conn, &doc).unwrap();
+        // Leading whitespace and multibyte characters shouldn't skew the offsets
+        let text = "\n  Café crème. Naïve façade here. Über straße there. Ünïcödé again.";
+        crate::documents::save_document_content(&conn, "doc-1", text).unwrap();
+
+        let config = ChunkConfig::new(20, 4).unwrap();
+        let chunks = chunk_text("doc-1", text, &config).unwrap();
+        save_chunks(&conn, &chunks).unwrap();
+        assert!(chunks.len() > 2);
+
+        let trimmed: Vec<char> = text.trim().chars().collect();
+        let chunk = &chunks[1];
+        let excerpt = get_chunk_source_excerpt(&conn, &chunk.id, 3).unwrap().unwrap();
+        let expected: String = trimmed[chunk.start_offset - 3..chunk.end_offset + 3].iter().collect();
+        assert_eq!(excerpt, expected);
+        assert!(excerpt.contains(&chunk.content));
+
+        // No padding gives back the chunk's source span
+        let exact = get_chunk_source_excerpt(&conn, &chunk.id, 0).unwrap().unwrap();
+        assert!(exact.contains(&chunk.content));
+        assert_eq!(exact.chars().count(), chunk.end_offset - chunk.start_offset);
+
+        // Padding is clamped at both ends of the document
+        let first = get_chunk_source_excerpt(&conn, &chunks[0].id, 1000).unwrap().unwrap();
+        assert_eq!(first, text);
+
+        assert!(get_chunk_source_excerpt(&conn, "missing", 3).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_long_word_is_hard_split() {
+        let blob = "x".repeat(5000);
+        let config = ChunkConfig::new(1000, 0).unwrap();
+        let chunks = chunk_text("doc-1", &blob, &config).unwrap();
+
+        assert_eq!(chunks.len(), 5);
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.content.chars().count(), 1000);
+            assert_eq!(chunk.start_offset, i * 1000);
+            assert_eq!(chunk.end_offset, (i + 1) * 1000);
+        }
+
+        // With overlap and ordinary words around the blob, chunks still respect the budget
+        let text = format!("Some words before. {} And some words after.", "é".repeat(3000));
+        let chunks = chunk_text("doc-1", &text, &ChunkConfig::default()).unwrap();
+        assert!(chunks.len() > 3);
+        for chunk in &chunks {
+            assert!(chunk.content.chars().count() <= 1000);
+            assert!(chunk.end_offset > chunk.start_offset);
+        }
+        assert_eq!(chunks.last().unwrap().end_offset, text.chars().count());
+    }
+
+    #[test]
+    fn test_near_equal_overlap_keeps_chunk_count_bounded() {
+        let config = ChunkConfig::new(100, 99).unwrap();
+        assert_eq!(config.step(), 25);
+        // Ordinary overlap is left alone
+        assert_eq!(ChunkConfig::default().step(), 800);
+
+        let text = "word ".repeat(2000);
+        let total_chars = text.trim().chars().count();
+        let chunks = chunk_text("doc-1", &text, &config).unwrap();
+
+        // A step of 1 would produce ~10,000 chunks
+        assert!(chunks.len() <= total_chars / 25 + 1);
+        assert_eq!(chunks.last().unwrap().end_offset, total_chars);
+    }
+
+    #[test]
+    fn test_max_chunks_cap() {
+        let config = ChunkConfig {
+            chunk_size: 100,
+            overlap: 0,
+            max_chunks: 10,
+            ..Default::default()
+        };
+        let text = "word ".repeat(2000);
+        let err = chunk_text("doc-1", &text, &config).unwrap_err();
+        assert!(err.contains("max_chunks"));
+
+        // Exactly at the cap is fine
+        let text = "x".repeat(1000);
+        assert_eq!(chunk_text("doc-1", &text, &config).unwrap().len(), 10);
+
+        assert!(ChunkConfig { max_chunks: 0, ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_document_chunks_ordered_and_paged() {
+        use chrono::Utc;
+
+        let conn = Connection::open_in_memory().unwrap();
+        crate::documents::init_documents_table(&conn).unwrap();
+        init_chunks_table(&conn).unwrap();
+
+        let doc = crate::documents::Document {
+            id: "doc-1".to_string(),
+            name: "test.txt".to_string(),
+            doc_type: crate::documents::DocumentType::Txt,
+            size: 100,
+            uploaded_at: Utc::now(),
+            path: "/tmp/test.txt".to_string(),
+            word_count: 0,
+            source_path: "/tmp/test.txt".to_string(),
+            modified_at: None,
+            language: None,
+        };
+        crate::documents::save_document(&conn, &doc).unwrap();
+
+        // Saved out of order; reads come back sorted by chunk_index
+        let chunks: Vec<Chunk> = [3, 0, 4, 1, 2]
+            .into_iter()
+            .map(|i| Chunk {
+                id: format!("doc-1-{}", i),
+                document_id: "doc-1".to_string(),
+                chunk_index: i,
+                content: format!("Chunk {}", i),
+                start_offset: i * 10,
+                end_offset: i * 10 + 7,
+            })
+            .collect();
+        save_chunks(&conn, &chunks).unwrap();
+
+        let indexes = |chunks: Vec<Chunk>| chunks.iter().map(|c| c.chunk_index).collect::<Vec<_>>();
+        assert_eq!(indexes(get_document_chunks(&conn, "doc-1").unwrap()), vec![0, 1, 2, 3, 4]);
+
+        let page = |limit, offset| {
+            indexes(get_document_chunks_page(&conn, "doc-1", limit, offset).unwrap())
+        };
+        assert_eq!(page(Some(2), 0), vec![0, 1]);
+        assert_eq!(page(Some(2), 2), vec![2, 3]);
+        assert_eq!(page(Some(2), 4), vec![4]);
+        assert!(page(Some(2), 6).is_empty());
+        assert_eq!(page(None, 3), vec![3, 4]);
+        assert!(page(Some(0), 0).is_empty());
+    }
+
+    #[test]
+    fn test_cjk_sentence_terminators() {
+        let text = "今日はとても良い天気です。公園で散歩をしました。\
+                    友達と一緒に昼ご飯を食べました！夕方には家に帰りました。\
+                    明日も晴れるでしょうか？";
+        let config = ChunkConfig {
+            chunk_size: 30,
+            overlap: 0,
+            // Keep the short last sentence as its own chunk
+            min_chunk_size: 0,
+            sentence_terminators: Some(vec!['。', '！', '？']),
+            ..Default::default()
+        };
+
+        let chunks = chunk_text("doc-1", text, &config).unwrap();
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(chunk.content.chars().count() <= config.chunk_size);
+            let last = chunk.content.chars().last().unwrap();
+            assert!("。！？".contains(last), "not sentence-aligned: {}", chunk.content);
+        }
+
+        // The defaults find no break in unspaced text and hard split instead
+        let defaults = ChunkConfig {
+            sentence_terminators: None,
+            ..config.clone()
+        };
+        let default_chunks = chunk_text("doc-1", text, &defaults).unwrap();
+        assert!(default_chunks
+            .iter()
+            .any(|c| !"。！？".contains(c.content.chars().last().unwrap())));
+
+        // The character sets are recorded for reindexing
+        let db = crate::db::Database::in_memory().unwrap();
+        db.conn
+            .execute(
+                "INSERT INTO documents (id, name, doc_type, size, uploaded_at, path)
+                 VALUES ('doc-1', 'a.txt', 'txt', 1, '2024-01-01T00:00:00Z', '/tmp/a.txt')",
+                [],
+            )
+            .unwrap();
+        save_document_config(&db.conn, "doc-1", &config).unwrap();
+        let stored = get_document_config(&db.conn, "doc-1").unwrap().unwrap();
+        assert_eq!(stored.sentence_terminators, config.sentence_terminators);
+        assert_eq!(stored.word_separators, None);
+    }
+
+    #[test]
+    fn test_chunk_iter_matches_chunk_text() {
+        let text = format!(
+            "# Intro\n\n{}\n\n## Details\n\n{}tail.",
+            "Some opening words about the topic. ".repeat(12),
+            "abcdefgh. ".repeat(60)
+        );
+        let config = ChunkConfig {
+            chunk_size: 250,
+            overlap: 50,
+            min_chunk_size: 62,
+            ..Default::default()
+        };
+        let fields = |chunks: Vec<Chunk>| -> Vec<(String, usize, String, usize, usize)> {
+            chunks
+                .into_iter()
+                .map(|c| (c.id, c.chunk_index, c.content, c.start_offset, c.end_offset))
+                .collect()
+        };
+
+        let collected = chunk_text("doc-1", &text, &config).unwrap();
+        assert!(collected.len() > 3);
+        // The last chunk was merged, so the lookahead is exercised
+        let no_minimum = ChunkConfig { min_chunk_size: 0, ..config.clone() };
+        assert_eq!(chunk_text("doc-1", &text, &no_minimum).unwrap().len(), collected.len() + 1);
+        let lazy: Vec<Chunk> = chunk_iter("doc-1", &text, &config).unwrap().collect();
+        assert_eq!(fields(lazy), fields(collected));
+
+        let markdown = config.for_document_type(DocumentType::Md);
+        let collected = chunk_text("doc-1", &text, &markdown).unwrap();
+        let lazy: Vec<Chunk> = chunk_iter("doc-1", &text, &markdown).unwrap().collect();
+        assert_eq!(fields(lazy), fields(collected));
+
+        // Short and empty text
+        assert_eq!(chunk_iter("doc-1", " short ", &config).unwrap().count(), 1);
+        assert_eq!(chunk_iter("doc-1", "  ", &config).unwrap().count(), 0);
+        assert!(chunk_iter("doc-1", &text, &ChunkConfig { chunk_size: 0, ..config }).is_err());
+    }
+
+    #[test]
+    fn test_short_last_chunk_is_merged() {
+        // 8 sentences of 10 characters, then a short tail
+        let text = format!("{}tail.", "abcdefgh. ".repeat(8));
+        let total_chars = text.chars().count();
+        let config = ChunkConfig {
+            chunk_size: 40,
+            overlap: 0,
+            min_chunk_size: 10,
+            ..Default::default()
+        };
+
+        let no_minimum = ChunkConfig {
+            min_chunk_size: 0,
+            ..config.clone()
+        };
+        let unmerged = chunk_text("doc-1", &text, &no_minimum).unwrap();
+        let sliver = unmerged.last().unwrap();
+        assert_eq!(sliver.content, "tail.");
+
+        let chunks = chunk_text("doc-1", &text, &config).unwrap();
+        assert_eq!(chunks.len(), unmerged.len() - 1);
+
+        // The tail is absorbed into the previous chunk, and the offsets still
+        // point at exactly its content
+        let last = chunks.last().unwrap();
+        let previous = &unmerged[unmerged.len() - 2];
+        assert_eq!(last.chunk_index, previous.chunk_index);
+        assert_eq!(last.start_offset, previous.start_offset);
+        assert_eq!(last.end_offset, total_chars);
+        assert!(last.content.ends_with("tail."));
+        let span: String = text
+            .chars()
+            .skip(last.start_offset)
+            .take(last.end_offset - last.start_offset)
+            .collect();
+        assert_eq!(last.content, span.trim());
+        assert_ne!(last.id, previous.id);
+
+        // A default config caps the minimum for small chunk sizes
+        assert_eq!(ChunkConfig { chunk_size: 40, ..Default::default() }.merge_below(), 10);
+        assert_eq!(ChunkConfig::default().merge_below(), 50);
+    }
+
+    #[test]
+    fn test_get_chunk() {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::documents::init_documents_table(&conn).unwrap();
+        init_chunks_table(&conn).unwrap();
+        let text = "One sentence here. Another one there.";
+        let doc = crate::documents::load_document_from_text(text, "notes", "doc-1").unwrap();
+        crate::documents::save_document(&conn, &doc.metadata).unwrap();
+        let chunks = chunk_text("doc-1", text, &ChunkConfig::default()).unwrap();
+        save_chunks(&conn, &chunks).unwrap();
+
+        let chunk = get_chunk(&conn, &chunks[0].id).unwrap().unwrap();
+        assert_eq!(chunk.document_id, "doc-1");
+        assert_eq!(chunk.content, chunks[0].content);
+        assert_eq!(chunk.end_offset, chunks[0].end_offset);
+
+        assert!(get_chunk(&conn, "doc-1-99-00000000").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_chunk_offsets_match_content() {
+        let texts = [
+            "Short intro line.\n\nA second paragraph with several words in it. Another \
+             sentence follows here! And one more?\n\n\nThird paragraph after extra blank \
+             lines.\n\nDr. Smith said so. The end."
+                .repeat(6),
+            format!("  \n\t{}\n\n", "word ".repeat(150)),
+            "# Title\n\nIntro text here.\n\n## Part one\n\nSome body. More body.\n\n\
+             ## Part two\n\nClosing words.\n\n"
+                .repeat(8),
+            "Grüße aus Köln. Ünïcödé wörds hére. 日本語の文章です。次の文。\n\n"
+                .repeat(20),
+            format!("{}tail.", "abcdefgh. ".repeat(40)),
+            "x".repeat(700),
+        ];
+        let configs = [(30, 0), (60, 15), (120, 40), (180, 0), (250, 50), (1000, 200)];
+
+        for text in &texts {
+            // Offsets are character positions in the trimmed text
+            let chars: Vec<char> = text.trim().chars().collect();
+            for &(chunk_size, overlap) in &configs {
+                for headings in [None, Some(HeadingStyle::Markdown)] {
+                    let config =
+                        ChunkConfig { chunk_size, overlap, headings, ..Default::default() };
+                    let chunks = chunk_text("doc-1", text, &config).unwrap();
+                    for chunk in &chunks {
+                        assert!(chunk.start_offset < chunk.end_offset, "{:?}", chunk);
+                        assert!(chunk.end_offset <= chars.len(), "{:?}", chunk);
+                        let span: String =
+                            chars[chunk.start_offset..chunk.end_offset].iter().collect();
+                        assert_eq!(
+                            span.trim(),
+                            chunk.content,
+                            "chunk_size {} overlap {}",
+                            chunk_size,
+                            overlap
+                        );
+                    }
+                    assert!(chunks.windows(2).all(|w| w[0].start_offset < w[1].start_offset));
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,145 @@
+//! Error type returned by all Tauri commands.
+//!
+//! Tauri sends a command's `Err` value to the frontend as JSON. A plain
+//! `String` gives the frontend nothing to branch on, so commands return
+//! `CommandError` instead, which serializes as:
+//!
+//! ```json
+//! { "kind": "NotFound", "message": "Chat not found: abc" }
+//! ```
+//!
+//! `From` impls for the module error types let commands use `?` directly.
+
+use crate::db::DbError;
+use crate::documents::DocumentError;
+use crate::embeddings::EmbeddingError;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::sync::PoisonError;
+
+/// Error returned to the frontend by a command.
+#[derive(Debug)]
+pub enum CommandError {
+    /// The requested chat, document, etc. doesn't exist
+    NotFound(String),
+    /// A SQLite error
+    Database(String),
+    /// The embedding model failed to load or run (or isn't loaded)
+    Embedding(String),
+    /// The request was rejected, e.g. a blank title or unsupported file
+    InvalidInput(String),
+    /// Reading or writing a file failed
+    Io(String),
+    /// The operation was stopped through `cancel_operation`
+    Cancelled,
+    /// Anything else, e.g. a poisoned lock or a failed background task
+    Internal(String),
+}
+
+impl CommandError {
+    /// The variant name, sent to the frontend as `kind`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CommandError::NotFound(_) => "NotFound",
+            CommandError::Database(_) => "Database",
+            CommandError::Embedding(_) => "Embedding",
+            CommandError::InvalidInput(_) => "InvalidInput",
+            CommandError::Io(_) => "Io",
+            CommandError::Cancelled => "Cancelled",
+            CommandError::Internal(_) => "Internal",
+        }
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::NotFound(msg)
+            | CommandError::Database(msg)
+            | CommandError::Embedding(msg)
+            | CommandError::InvalidInput(msg)
+            | CommandError::Io(msg)
+            | CommandError::Internal(msg) => write!(f, "{}", msg),
+            CommandError::Cancelled => write!(f, "Operation cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Serializes as `{ kind, message }` rather than serde's default enum shape.
+impl Serialize for CommandError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("CommandError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl From<rusqlite::Error> for CommandError {
+    fn from(e: rusqlite::Error) -> Self {
+        CommandError::Database(e.to_string())
+    }
+}
+
+impl From<DbError> for CommandError {
+    fn from(e: DbError) -> Self {
+        match e {
+            DbError::Sqlite(e) => CommandError::from(e),
+            DbError::InvalidInput(msg) => CommandError::InvalidInput(msg),
+        }
+    }
+}
+
+impl From<DocumentError> for CommandError {
+    fn from(e: DocumentError) -> Self {
+        match e {
+            DocumentError::NotFound(_) => CommandError::NotFound(e.to_string()),
+            DocumentError::DatabaseError(e) => CommandError::from(e),
+            DocumentError::CorruptRow(_) => CommandError::Database(e.to_string()),
+            DocumentError::InvalidInput(msg) => CommandError::InvalidInput(msg),
+            DocumentError::IoError(_) => CommandError::Io(e.to_string()),
+            DocumentError::PdfError(_)
+            | DocumentError::EpubError(_)
+            | DocumentError::JsonError(_)
+            | DocumentError::UnsupportedFormat(_)
+            | DocumentError::TooLarge { .. } => {
+                CommandError::InvalidInput(e.to_string())
+            }
+        }
+    }
+}
+
+impl From<EmbeddingError> for CommandError {
+    fn from(e: EmbeddingError) -> Self {
+        CommandError::Embedding(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(e: std::io::Error) -> Self {
+        CommandError::Io(e.to_string())
+    }
+}
+
+/// A mutex was poisoned by a panic on another thread.
+impl<T> From<PoisonError<T>> for CommandError {
+    fn from(e: PoisonError<T>) -> Self {
+        CommandError::Internal(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serializes_kind_and_message() {
+        let json = serde_json::to_value(CommandError::NotFound("Chat not found: x".into())).unwrap();
+        assert_eq!(json, serde_json::json!({ "kind": "NotFound", "message": "Chat not found: x" }));
+
+        let json = serde_json::to_value(CommandError::Cancelled).unwrap();
+        assert_eq!(json["kind"], "Cancelled");
+        assert_eq!(json["message"], "Operation cancelled");
+    }
+}
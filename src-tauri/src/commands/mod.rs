@@ -2,11 +2,20 @@
 //!
 //! Commands are the bridge between your TypeScript/React frontend and Rust backend.
 //! The `#[tauri::command]` macro generates the IPC glue code automatically.
+//! Every command returns `Result<T, CommandError>` (see the `error` module).
 
-use crate::db::{ChatWithMessages, Database, Message};
+mod error;
+
+pub use error::CommandError;
+
+use crate::db::{estimate_tokens, Chat, ChatWithMessages, Database, DbError, Message, Settings};
 use chrono::Utc;
-use std::sync::Mutex;
-use tauri::State;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, TryLockError};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Runtime, State};
+use tracing::{info, instrument, warn};
 use uuid::Uuid;
 
 /// Wrapper for thread-safe database access.
@@ -19,26 +28,291 @@ use uuid::Uuid;
 /// Why wrap in a struct?
 /// - Makes the State type more readable
 /// - Allows adding more fields later if needed (e.g., connection pool)
+///
+/// Lock order: a command that needs both this and `EmbeddingState` locks
+/// the model first, and never takes the model lock while holding this
+/// one. Where it can, it also finishes its embedding work and releases
+/// the model before locking the database, so database commands don't
+/// wait behind a long embedding job. `IndexState` and `IncognitoState`
+/// are only locked while holding this one.
 pub struct DbState(pub Mutex<Database>);
 
+/// The on-disk database, set aside while an incognito session runs
+/// against a private in-memory one in `DbState`.
+#[derive(Default)]
+pub struct IncognitoState(pub Mutex<Option<Database>>);
+
+/// Cancellation flags for long-running operations, keyed by an
+/// operation id chosen by the frontend.
+///
+/// An operation registers itself when it starts, checks its flag between
+/// batches, and removes its entry when it finishes (see `OperationGuard`).
+#[derive(Default)]
+pub struct CancellationState(pub Mutex<HashMap<String, Arc<AtomicBool>>>);
+
+impl CancellationState {
+    /// Registers an operation and returns a guard holding its flag.
+    fn register(&self, operation_id: &str) -> Result<OperationGuard<'_>, CommandError> {
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut ops = self.0.lock()?;
+        ops.insert(operation_id.to_string(), flag.clone());
+
+        Ok(OperationGuard {
+            state: self,
+            operation_id: operation_id.to_string(),
+            flag,
+        })
+    }
+
+    /// Flags an operation for cancellation.
+    ///
+    /// Returns false if no operation with that id is running.
+    fn cancel(&self, operation_id: &str) -> Result<bool, CommandError> {
+        let ops = self.0.lock()?;
+        match ops.get(operation_id) {
+            Some(flag) => {
+                flag.store(true, Ordering::SeqCst);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+/// Keeps an operation registered while it runs.
+///
+/// Dropping the guard (on success, error or cancellation) removes
+/// the operation's entry from `CancellationState`.
+struct OperationGuard<'a> {
+    state: &'a CancellationState,
+    operation_id: String,
+    flag: Arc<AtomicBool>,
+}
+
+impl OperationGuard<'_> {
+    fn flag(&self) -> &AtomicBool {
+        &self.flag
+    }
+}
+
+impl Drop for OperationGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut ops) = self.state.0.lock() {
+            ops.remove(&self.operation_id);
+        }
+    }
+}
+
+/// Sends events to the frontend.
+///
+/// Implemented for `AppHandle`; tests pass a recorder instead, so the
+/// events a command sends can be checked without a running app.
+pub trait EventSink {
+    fn send<S: Serialize + Clone>(&self, event: &str, payload: S);
+}
+
+impl<R: Runtime> EventSink for AppHandle<R> {
+    fn send<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        // Events are best-effort; a failed emit shouldn't fail the command
+        self.emit(event, payload).ok();
+    }
+}
+
+/// Runs `step` over `items` in batches, checking `cancel` before each one.
+///
+/// Returns `CommandError::Cancelled` as soon as the flag is seen set.
+fn for_each_batch<T, F>(
+    items: &[T],
+    batch_size: usize,
+    cancel: Option<&AtomicBool>,
+    mut step: F,
+) -> Result<(), CommandError>
+where
+    F: FnMut(&[T]) -> Result<(), CommandError>,
+{
+    for batch in items.chunks(batch_size.max(1)) {
+        if cancel.is_some_and(|flag| flag.load(Ordering::SeqCst)) {
+            return Err(CommandError::Cancelled);
+        }
+        step(batch)?;
+    }
+    Ok(())
+}
+
+/// Requests cancellation of a running operation.
+///
+/// Returns false if the operation isn't running (it may already have finished).
+#[tauri::command]
+pub fn cancel_operation(
+    cancellation: State<'_, CancellationState>,
+    operation_id: String,
+) -> Result<bool, CommandError> {
+    cancellation.cancel(&operation_id)
+}
+
+/// Deletes all chats, documents and indexes, returning the app to a fresh state.
+///
+/// `confirm` must be true; it guards against an accidental call wiping
+/// everything. Uploaded document files are removed from disk as well.
+/// Settings are kept.
+#[tauri::command]
+pub fn reset_app_data(
+    db: State<'_, DbState>,
+    index: State<'_, IndexState>,
+    paths: State<'_, AppPaths>,
+    confirm: bool,
+) -> Result<(), CommandError> {
+    if !confirm {
+        return Err(CommandError::InvalidInput("Reset not confirmed".to_string()));
+    }
+
+    let db = db.0.lock()?;
+    db.reset()?;
+    index.clear()?;
+
+    // Remove the stored copies of uploaded files; their rows are gone
+    if let Ok(entries) = std::fs::read_dir(&paths.documents_dir) {
+        for entry in entries.flatten() {
+            std::fs::remove_file(entry.path()).ok(); // Ignore errors if file can't be deleted
+        }
+    }
+
+    Ok(())
+}
+
+/// Starts an incognito session: until `end_incognito_session`, chats and
+/// documents go to a private in-memory database instead of the one on disk.
+///
+/// The current settings carry over. Returns false if a session is
+/// already running.
+#[tauri::command]
+pub fn start_incognito_session(
+    db: State<'_, DbState>,
+    index: State<'_, IndexState>,
+    incognito: State<'_, IncognitoState>,
+) -> Result<bool, CommandError> {
+    begin_incognito(&db, &index, &incognito)
+}
+
+/// Ends the incognito session, discarding everything added during it
+/// (including stored copies of its uploaded files) and switching back to
+/// the on-disk database. Returns false if no session was running.
+#[tauri::command]
+pub fn end_incognito_session(
+    db: State<'_, DbState>,
+    index: State<'_, IndexState>,
+    incognito: State<'_, IncognitoState>,
+    paths: State<'_, AppPaths>,
+) -> Result<bool, CommandError> {
+    finish_incognito(&db, &index, &incognito, &paths)
+}
+
+/// Does the work of `start_incognito_session`.
+fn begin_incognito(
+    db: &DbState,
+    index: &IndexState,
+    incognito: &IncognitoState,
+) -> Result<bool, CommandError> {
+    let mut db = db.0.lock()?;
+    let mut saved = incognito.0.lock()?;
+    if saved.is_some() {
+        return Ok(false);
+    }
+
+    let private = Database::in_memory()?;
+    private.update_settings(&db.get_settings()?)?;
+    *saved = Some(std::mem::replace(&mut *db, private));
+    index.switch_database(true)?;
+    info!("Started incognito session");
+    Ok(true)
+}
+
+/// Does the work of `end_incognito_session`.
+fn finish_incognito(
+    db: &DbState,
+    index: &IndexState,
+    incognito: &IncognitoState,
+    paths: &AppPaths,
+) -> Result<bool, CommandError> {
+    let mut db = db.0.lock()?;
+    let Some(persistent) = incognito.0.lock()?.take() else {
+        return Ok(false);
+    };
+
+    for doc in documents::get_all_documents(&db.conn)? {
+        let path = PathBuf::from(&doc.path);
+        if path.starts_with(&paths.documents_dir) {
+            std::fs::remove_file(&path).ok(); // Ignore errors if file can't be deleted
+        }
+    }
+
+    // Dropping the in-memory database discards the session's data
+    *db = persistent;
+    index.switch_database(false)?;
+    info!("Ended incognito session");
+    Ok(true)
+}
+
+/// Get the app settings.
+#[tauri::command]
+pub fn get_settings(db: State<'_, DbState>) -> Result<Settings, CommandError> {
+    let db = db.0.lock()?;
+    db.get_settings().map_err(CommandError::from)
+}
+
+/// Replace the app settings.
+///
+/// `chunk_size` only applies to documents ingested (or reindexed without a
+/// config) afterwards; it must be larger than the default chunk overlap.
+#[tauri::command]
+pub fn update_settings(db: State<'_, DbState>, settings: Settings) -> Result<(), CommandError> {
+    ingest_chunk_config(&settings)?;
+    let db = db.0.lock()?;
+    db.update_settings(&settings).map_err(CommandError::from)
+}
+
+/// Title given to chats until they're renamed (or auto-titled).
+const DEFAULT_CHAT_TITLE: &str = "New Conversation";
+
+/// Maximum number of words in an auto-generated chat title.
+const TITLE_MAX_WORDS: usize = 6;
+
+/// Maximum length (in characters) of an auto-generated chat title.
+const TITLE_MAX_CHARS: usize = 40;
+
 /// Creates a new chat conversation.
 ///
+/// `title` sets the initial title, e.g. when a chat is started from a
+/// prompt or the UI has a translated default. `None` (or a blank title)
+/// uses "New Conversation". Only chats with that default title are
+/// auto-titled from their first message.
+///
 /// `State<'_, DbState>` is Tauri's dependency injection - it provides
 /// access to the database we'll set up in main.rs.
 ///
 /// The `'_` is a lifetime elision - Rust figures out the correct lifetime.
 #[tauri::command]
-pub fn create_chat(db: State<'_, DbState>) -> Result<ChatWithMessages, String> {
+pub fn create_chat(
+    db: State<'_, DbState>,
+    title: Option<String>,
+) -> Result<ChatWithMessages, CommandError> {
     // Lock the mutex to get exclusive database access
     // `.lock()` returns a Result because another thread might have panicked while holding the lock
-    // `.map_err()` converts any error to a String for Tauri's error handling
-    let db = db.0.lock().map_err(|e| e.to_string())?;
+    // `?` converts the poisoned-lock error into a `CommandError` via `From`
+    let db = db.0.lock()?;
+    new_chat(&db, title)
+}
 
+/// Does the work of `create_chat`.
+fn new_chat(db: &Database, title: Option<String>) -> Result<ChatWithMessages, CommandError> {
     // Generate a unique ID using UUID v4 (random)
     let id = Uuid::new_v4().to_string();
-    let title = "New Conversation".to_string();
+    let title = title
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| DEFAULT_CHAT_TITLE.to_string());
 
-    db.create_chat(&id, &title).map_err(|e| e.to_string())?;
+    db.create_chat(&id, &title)?;
 
     // Return a ChatWithMessages with empty messages array
     Ok(ChatWithMessages {
@@ -52,23 +326,121 @@ pub fn create_chat(db: State<'_, DbState>) -> Result<ChatWithMessages, String> {
 
 /// Gets all chats (without messages, for the sidebar).
 #[tauri::command]
-pub fn get_all_chats(db: State<'_, DbState>) -> Result<Vec<crate::db::Chat>, String> {
-    let db = db.0.lock().map_err(|e| e.to_string())?;
-    db.get_all_chats().map_err(|e| e.to_string())
+pub fn get_all_chats(db: State<'_, DbState>) -> Result<Vec<crate::db::Chat>, CommandError> {
+    let db = db.0.lock()?;
+    db.get_all_chats().map_err(CommandError::from)
+}
+
+/// Gets the last `limit` messages of a chat in chronological order.
+#[tauri::command]
+pub fn get_recent_messages(
+    db: State<'_, DbState>,
+    chat_id: String,
+    limit: usize,
+) -> Result<Vec<Message>, CommandError> {
+    let db = db.0.lock()?;
+    db.get_recent_messages(&chat_id, limit).map_err(CommandError::from)
+}
+
+/// Finds chats whose title contains `query` (case-insensitive).
+#[tauri::command]
+pub fn find_chats_by_title(
+    db: State<'_, DbState>,
+    query: String,
+) -> Result<Vec<crate::db::Chat>, CommandError> {
+    let db = db.0.lock()?;
+    db.find_chats_by_title(query.trim()).map_err(CommandError::from)
 }
 
 /// Gets a single chat with all its messages.
 #[tauri::command]
-pub fn get_chat(db: State<'_, DbState>, chat_id: String) -> Result<Option<ChatWithMessages>, String> {
-    let db = db.0.lock().map_err(|e| e.to_string())?;
-    db.get_chat(&chat_id).map_err(|e| e.to_string())
+pub fn get_chat(db: State<'_, DbState>, chat_id: String) -> Result<Option<ChatWithMessages>, CommandError> {
+    let db = db.0.lock()?;
+    db.get_chat(&chat_id).map_err(CommandError::from)
+}
+
+/// Gets the documents a chat's answers cited, each once.
+#[tauri::command]
+pub fn get_chat_cited_documents(
+    db: State<'_, DbState>,
+    chat_id: String,
+) -> Result<Vec<DocumentResponse>, CommandError> {
+    let db = db.0.lock()?;
+    let docs = db.get_chat_cited_documents(&chat_id)?;
+    Ok(docs.into_iter().map(DocumentResponse::from).collect())
+}
+
+/// Checks whether a chat exists, without fetching it.
+#[tauri::command]
+pub fn chat_exists(db: State<'_, DbState>, chat_id: String) -> Result<bool, CommandError> {
+    let db = db.0.lock()?;
+    db.chat_exists(&chat_id).map_err(CommandError::from)
+}
+
+/// Gets a chat's message count, last message preview and token estimate,
+/// without loading its messages.
+#[tauri::command]
+pub fn get_chat_summary(
+    db: State<'_, DbState>,
+    chat_id: String,
+) -> Result<Option<crate::db::ChatSummary>, CommandError> {
+    let db = db.0.lock()?;
+    db.get_chat_summary(&chat_id).map_err(CommandError::from)
+}
+
+/// Gets the most recently updated chat with its messages, to reopen on
+/// launch. Returns None if there are no chats.
+#[tauri::command]
+pub fn get_last_chat(db: State<'_, DbState>) -> Result<Option<ChatWithMessages>, CommandError> {
+    let db = db.0.lock()?;
+    db.get_most_recent_chat().map_err(CommandError::from)
 }
 
 /// Deletes a chat and all its messages.
 #[tauri::command]
-pub fn delete_chat(db: State<'_, DbState>, chat_id: String) -> Result<bool, String> {
-    let db = db.0.lock().map_err(|e| e.to_string())?;
-    db.delete_chat(&chat_id).map_err(|e| e.to_string())
+pub fn delete_chat(db: State<'_, DbState>, chat_id: String) -> Result<bool, CommandError> {
+    let db = db.0.lock()?;
+    db.delete_chat(&chat_id).map_err(CommandError::from)
+}
+
+/// Imports a chat from the JSON `get_chat` returns, keeping its ids and
+/// timestamps. An existing chat with the same id is replaced only if
+/// `overwrite` is set.
+#[tauri::command]
+pub fn import_chat(
+    db: State<'_, DbState>,
+    json: String,
+    overwrite: bool,
+) -> Result<(), CommandError> {
+    let chat: ChatWithMessages = serde_json::from_str(&json)
+        .map_err(|e| CommandError::InvalidInput(format!("Invalid chat JSON: {}", e)))?;
+    let db = db.0.lock()?;
+    db.import_chat(chat, overwrite).map_err(CommandError::from)
+}
+
+/// Total tokens across a chat's messages, for budgeting an LLM context window.
+#[tauri::command]
+pub fn get_chat_token_total(db: State<'_, DbState>, chat_id: String) -> Result<usize, CommandError> {
+    let db = db.0.lock()?;
+    db.get_chat_token_total(&chat_id).map_err(CommandError::from)
+}
+
+/// Number of messages sent per day (`[date, count]` pairs, oldest first),
+/// for one chat or, without `chat_id`, all of them.
+#[tauri::command]
+pub fn messages_per_day(
+    db: State<'_, DbState>,
+    chat_id: Option<String>,
+) -> Result<Vec<(String, usize)>, CommandError> {
+    let db = db.0.lock()?;
+    db.messages_per_day(chat_id).map_err(CommandError::from)
+}
+
+/// Deletes a single message, returning true if it existed.
+#[tauri::command]
+pub fn delete_message(db: State<'_, DbState>, message_id: String) -> Result<bool, CommandError> {
+    let db = db.0.lock()?;
+    db.delete_message(&message_id).map_err(CommandError::from)
 }
 
 /// Input structure for adding a message.
@@ -90,9 +462,87 @@ pub struct AddMessageInput {
 #[tauri::command]
 pub fn add_message(
     db: State<'_, DbState>,
+    model: State<'_, EmbeddingState>,
+    input: AddMessageInput,
+) -> Result<Message, CommandError> {
+    // Measure with the model first; it's never locked while the database is
+    let first_user_message =
+        input.role == "user" && next_user_message_is_first(&db, &input.chat_id)?;
+    let metrics = MessageMetrics::measure_if_idle(&model, &input.content, first_user_message)?;
+
+    let db = db.0.lock()?;
+    add_message_to_chat(&db, input, &metrics)
+}
+
+/// A message's token count and, if it's its chat's first user message,
+/// its embedding for `find_similar_chats`.
+///
+/// Worked out with the embedding model before the database is locked.
+#[derive(Default)]
+struct MessageMetrics {
+    /// Counted with the model's tokenizer, or `None` to estimate from the
+    /// length when the model (or its tokenizer) wasn't available
+    tokens: Option<usize>,
+    first_message_embedding: Option<Vec<f32>>,
+}
+
+impl MessageMetrics {
+    /// Counts `content`'s tokens with `model`, and embeds it too if it's
+    /// a `first_user_message`. A failed encode is only logged; the message
+    /// is saved either way.
+    fn measure(model: &dyn Embedder, content: &str, first_user_message: bool) -> Self {
+        let first_message_embedding = if first_user_message {
+            model
+                .encode(content)
+                .map_err(|e| warn!(error = %e, "Failed to embed first message"))
+                .ok()
+        } else {
+            None
+        };
+        MessageMetrics {
+            tokens: model
+                .tokenizer()
+                .and_then(|tokenizer| embeddings::count_tokens(tokenizer, content).ok()),
+            first_message_embedding,
+        }
+    }
+
+    /// Like `measure`, but doesn't wait for the model: if it isn't loaded
+    /// or another command is using it, the tokens are estimated and
+    /// nothing is embedded.
+    fn measure_if_idle(
+        model: &EmbeddingState,
+        content: &str,
+        first_user_message: bool,
+    ) -> Result<Self, CommandError> {
+        let metrics =
+            with_idle_model(model, |model| Self::measure(model, content, first_user_message))?;
+        Ok(metrics.unwrap_or_default())
+    }
+
+    /// The token count, estimated from `content`'s length if not counted.
+    fn tokens(&self, content: &str) -> usize {
+        self.tokens.unwrap_or_else(|| estimate_tokens(content))
+    }
+}
+
+/// Whether a user message added to `chat_id` now would be its first.
+/// Holds the database lock only for the query.
+fn next_user_message_is_first(db: &DbState, chat_id: &str) -> Result<bool, CommandError> {
+    Ok(db.0.lock()?.first_user_message_id(chat_id)?.is_none())
+}
+
+/// Body of `add_message`, separated from the `State` plumbing.
+///
+/// Returns `NotFound` if the chat doesn't exist.
+fn add_message_to_chat(
+    db: &Database,
     input: AddMessageInput,
-) -> Result<Message, String> {
-    let db = db.0.lock().map_err(|e| e.to_string())?;
+    metrics: &MessageMetrics,
+) -> Result<Message, CommandError> {
+    if db.get_chat_title(&input.chat_id)?.is_none() {
+        return Err(CommandError::NotFound(format!("Chat not found: {}", input.chat_id)));
+    }
 
     let message = Message {
         id: Uuid::new_v4().to_string(),
@@ -103,42 +553,392 @@ pub fn add_message(
         sources: input.sources,
     };
 
-    db.add_message(&message).map_err(|e| e.to_string())?;
+    db.add_message_counted(&message, |text| metrics.tokens(text))?;
+    auto_title_chat(db, &message)?;
+    if let Some(embedding) = &metrics.first_message_embedding {
+        save_first_message_embedding(db, &message, embedding)?;
+    }
 
     Ok(message)
 }
 
+/// Stores `embedding` if `message` is its chat's first user message, for
+/// `find_similar_chats`.
+///
+/// Checked again here, under the database lock, in case another message
+/// was added since the embedding was made.
+fn save_first_message_embedding(
+    db: &Database,
+    message: &Message,
+    embedding: &[f32],
+) -> Result<(), CommandError> {
+    if message.role == "user"
+        && db.first_user_message_id(&message.chat_id)?.as_deref() == Some(message.id.as_str())
+    {
+        db.set_first_message_embedding(&message.chat_id, embedding)?;
+    }
+    Ok(())
+}
+
+/// Similarity a chat's first message needs to `find_similar_chats`
+/// when no threshold is given.
+const DEFAULT_SIMILAR_CHAT_THRESHOLD: f32 = 0.9;
+
+/// A chat returned by `find_similar_chats`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimilarChat {
+    pub chat: Chat,
+    /// Cosine similarity of the chat's first message to the text
+    pub similarity: f32,
+}
+
+/// Finds chats that started with (nearly) the same message as `text`,
+/// so the UI can suggest continuing one of them instead.
+///
+/// `threshold` defaults to 0.9. Only chats whose first message was sent
+/// while the embedding model was loaded (and not busy) can be found.
+#[tauri::command]
+pub fn find_similar_chats(
+    db: State<'_, DbState>,
+    model: State<'_, EmbeddingState>,
+    text: String,
+    threshold: Option<f32>,
+) -> Result<Vec<SimilarChat>, CommandError> {
+    // Release the model before locking the database for the scan
+    let embedding = {
+        let model = model.0.lock()?;
+        model.as_ref().ok_or_else(model_not_loaded)?.encode(&text)?
+    };
+
+    let db = db.0.lock()?;
+    let threshold = threshold.unwrap_or(DEFAULT_SIMILAR_CHAT_THRESHOLD);
+    let chats = db.find_similar_chats_by_first_message(&embedding, threshold)?;
+    Ok(chats
+        .into_iter()
+        .map(|(chat, similarity)| SimilarChat { chat, similarity })
+        .collect())
+}
+
+/// Adds many messages in one transaction (e.g. when restoring a conversation).
+///
+/// Unlike `add_message`, this doesn't auto-title the chat.
+/// Returns the created messages in input order.
+#[tauri::command]
+pub fn add_messages(
+    db: State<'_, DbState>,
+    model: State<'_, EmbeddingState>,
+    inputs: Vec<AddMessageInput>,
+) -> Result<Vec<Message>, CommandError> {
+
+    // Space timestamps by a microsecond so input order survives
+    // the `ORDER BY timestamp` when the chat is loaded
+    let now = Utc::now();
+    let messages: Vec<Message> = inputs
+        .into_iter()
+        .enumerate()
+        .map(|(i, input)| Message {
+            id: Uuid::new_v4().to_string(),
+            chat_id: input.chat_id,
+            role: input.role,
+            content: input.content,
+            timestamp: now + chrono::Duration::microseconds(i as i64),
+            sources: input.sources,
+        })
+        .collect();
+
+    // Count tokens before locking the database, estimating if the model is busy
+    let counts: HashMap<&str, usize> = with_idle_model(&model, |model| {
+        let Some(tokenizer) = model.tokenizer() else {
+            return HashMap::new();
+        };
+        messages
+            .iter()
+            .filter_map(|m| {
+                let count = embeddings::count_tokens(tokenizer, &m.content).ok()?;
+                Some((m.content.as_str(), count))
+            })
+            .collect()
+    })?
+    .unwrap_or_default();
+
+    let db = db.0.lock()?;
+    db.add_messages_counted(&messages, |text| {
+        counts.get(text).copied().unwrap_or_else(|| estimate_tokens(text))
+    })?;
+
+    Ok(messages)
+}
+
+/// Suggests a concise chat title from the first message.
+///
+/// Keeps at most 6 words / 40 characters, cutting at a word boundary,
+/// and strips trailing punctuation. Falls back to the default title
+/// if nothing usable is left.
+pub fn suggest_chat_title(first_message: &str) -> String {
+    let mut title = String::new();
+
+    for word in first_message.split_whitespace().take(TITLE_MAX_WORDS) {
+        let separator = if title.is_empty() { 0 } else { 1 };
+        if title.chars().count() + separator + word.chars().count() > TITLE_MAX_CHARS {
+            // A single overlong first word still gets a (hard-cut) title
+            if title.is_empty() {
+                title = word.chars().take(TITLE_MAX_CHARS).collect();
+            }
+            break;
+        }
+        if separator == 1 {
+            title.push(' ');
+        }
+        title.push_str(word);
+    }
+
+    let title = title.trim_end_matches(|c: char| c.is_ascii_punctuation());
+
+    if title.is_empty() {
+        DEFAULT_CHAT_TITLE.to_string()
+    } else {
+        title.to_string()
+    }
+}
+
+/// Titles a chat from `message` if it's a user message and the chat
+/// still has the default title. Custom titles are never overwritten.
+fn auto_title_chat(db: &Database, message: &Message) -> Result<(), DbError> {
+    if message.role != "user" {
+        return Ok(());
+    }
+
+    if db.get_chat_title(&message.chat_id)?.as_deref() == Some(DEFAULT_CHAT_TITLE) {
+        let title = suggest_chat_title(&message.content);
+        if title != DEFAULT_CHAT_TITLE {
+            db.update_chat_title(&message.chat_id, &title)?;
+        }
+    }
+
+    Ok(())
+}
+
 /// Updates a chat's title.
+///
+/// Returns false if the chat doesn't exist, and an error for a blank title.
 #[tauri::command]
 pub fn update_chat_title(
     db: State<'_, DbState>,
     chat_id: String,
     title: String,
-) -> Result<(), String> {
-    let db = db.0.lock().map_err(|e| e.to_string())?;
-    db.update_chat_title(&chat_id, &title).map_err(|e| e.to_string())
+) -> Result<bool, CommandError> {
+    let db = db.0.lock()?;
+    db.update_chat_title(&chat_id, &title).map_err(CommandError::from)
+}
+
+/// Pins or unpins a chat so it stays at the top of the sidebar.
+///
+/// Returns false if the chat doesn't exist.
+#[tauri::command]
+pub fn set_chat_pinned(
+    db: State<'_, DbState>,
+    chat_id: String,
+    pinned: bool,
+) -> Result<bool, CommandError> {
+    let db = db.0.lock()?;
+    db.set_chat_pinned(&chat_id, pinned).map_err(CommandError::from)
+}
+
+/// Adds a tag to a chat.
+#[tauri::command]
+pub fn add_chat_tag(db: State<'_, DbState>, chat_id: String, tag: String) -> Result<(), CommandError> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err(CommandError::InvalidInput("Tag cannot be empty".to_string()));
+    }
+
+    let db = db.0.lock()?;
+    db.add_chat_tag(&chat_id, tag).map_err(CommandError::from)
+}
+
+/// Removes a tag from a chat.
+#[tauri::command]
+pub fn remove_chat_tag(db: State<'_, DbState>, chat_id: String, tag: String) -> Result<bool, CommandError> {
+    let db = db.0.lock()?;
+    db.remove_chat_tag(&chat_id, tag.trim()).map_err(CommandError::from)
+}
+
+/// Gets the tags on a chat.
+#[tauri::command]
+pub fn get_chat_tags(db: State<'_, DbState>, chat_id: String) -> Result<Vec<String>, CommandError> {
+    let db = db.0.lock()?;
+    db.get_chat_tags(&chat_id).map_err(CommandError::from)
+}
+
+/// Gets all chats with a given tag.
+#[tauri::command]
+pub fn get_chats_by_tag(db: State<'_, DbState>, tag: String) -> Result<Vec<crate::db::Chat>, CommandError> {
+    let db = db.0.lock()?;
+    db.get_chats_by_tag(tag.trim()).map_err(CommandError::from)
+}
+
+/// A retrieved chunk cited by an assistant message, stored as JSON in
+/// `Message::sources` (the frontend's `DocumentSource`).
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSource {
+    pub document_id: String,
+    pub document_name: String,
+    /// The chunk's text
+    pub chunk: String,
+    /// The chunk's search score
+    pub relevance: f32,
 }
 
-/// Basic chat command - placeholder for future RAG integration.
+/// Sends a user message to a chat and returns the assistant's reply.
 ///
-/// Currently just echoes the message. Will be replaced with:
-/// 1. Embed the question
-/// 2. Search vector store
-/// 3. Build context prompt
-/// 4. Generate response with LLM
+/// Stores the user message, retrieves relevant chunks (if the embedding
+/// model is loaded), generates a reply and stores it with its sources.
+/// Both messages are saved in one transaction, so a failure leaves the
+/// chat unchanged. Generation is still a placeholder that echoes the
+/// message; the frontend no longer needs to call `add_message` itself.
 #[tauri::command]
-pub async fn chat(message: String) -> Result<String, String> {
-    // Placeholder response - will integrate RAG + LLM later
-    Ok(format!("Echo: {}", message))
+pub async fn chat(
+    db: State<'_, DbState>,
+    model: State<'_, EmbeddingState>,
+    index: State<'_, IndexState>,
+    chat_id: String,
+    message: String,
+) -> Result<Message, CommandError> {
+    chat_turn(&db, &model, &index, &chat_id, &message)
+}
+
+/// Does the work of `chat`.
+///
+/// The model work (token counts and the query embedding) is done under the
+/// model lock alone. The database is locked afterwards: briefly to retrieve
+/// sources, then for the transaction that stores both messages.
+fn chat_turn(
+    db: &DbState,
+    model: &EmbeddingState,
+    index: &IndexState,
+    chat_id: &str,
+    message: &str,
+) -> Result<Message, CommandError> {
+    if message.trim().is_empty() {
+        return Err(CommandError::InvalidInput("Message cannot be empty".to_string()));
+    }
+
+    let first_user_message = next_user_message_is_first(db, chat_id)?;
+    let (metrics, query) = {
+        let model = model.0.lock()?;
+        match model.as_deref() {
+            Some(model) => (
+                MessageMetrics::measure(model, message, first_user_message),
+                Some((model.encode_query(message)?, model.model_id().to_string())),
+            ),
+            None => (MessageMetrics::default(), None),
+        }
+    };
+
+    let sources = match query {
+        Some((embedding, model_id)) => {
+            let db = db.0.lock()?;
+            retrieve_sources(&db, index, &embedding, &model_id)?
+        }
+        None => Vec::new(),
+    };
+    let reply = generate_reply(message, &sources);
+    let reply_metrics = MessageMetrics::measure_if_idle(model, &reply, false)?;
+    let sources = if sources.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_string(&sources).map_err(|e| CommandError::Internal(e.to_string()))?)
+    };
+
+    let db = db.0.lock()?;
+    let tx = db.conn.unchecked_transaction()?;
+    add_message_to_chat(
+        &db,
+        AddMessageInput {
+            chat_id: chat_id.to_string(),
+            role: "user".to_string(),
+            content: message.to_string(),
+            sources: None,
+        },
+        &metrics,
+    )?;
+    let assistant = add_message_to_chat(
+        &db,
+        AddMessageInput {
+            chat_id: chat_id.to_string(),
+            role: "assistant".to_string(),
+            content: reply,
+            sources,
+        },
+        &reply_metrics,
+    )?;
+
+    tx.commit()?;
+    Ok(assistant)
+}
+
+/// Finds the chunks most relevant to a query embedded by `model_id`, using
+/// the retrieval settings. Adjacent chunks of a document come back as one
+/// source, without the text they overlap on repeated.
+fn retrieve_sources(
+    db: &Database,
+    index: &IndexState,
+    query_embedding: &[f32],
+    model_id: &str,
+) -> Result<Vec<DocumentSource>, CommandError> {
+    let settings = db.get_settings()?;
+    let metric = SimilarityMetric::default();
+    let results =
+        index.search(db, query_embedding, settings.retrieval_top_k, model_id, metric)?;
+
+    let mut results = apply_min_score(results, metric, settings.min_score);
+    normalize_scores(results.iter_mut().map(|r| &mut r.score), metric, &settings);
+
+    // Stitch adjacent chunks together so their overlap isn't sent twice
+    let mut chunks_by_id = HashMap::new();
+    for result in &results {
+        if let Some(chunk) = chunker::get_chunk(&db.conn, &result.chunk_id)? {
+            chunks_by_id.insert(chunk.id.clone(), chunk);
+        }
+    }
+    let results = vector_store::merge_overlapping_results(&results, &chunks_by_id);
+
+    let mut names: HashMap<String, String> = HashMap::new();
+    let mut sources = Vec::new();
+    for result in results {
+        if !names.contains_key(&result.document_id) {
+            let name = documents::get_document(&db.conn, &result.document_id)?
+                .map_or_else(|| result.document_id.clone(), |doc| doc.name);
+            names.insert(result.document_id.clone(), name);
+        }
+        sources.push(DocumentSource {
+            document_name: names[&result.document_id].clone(),
+            document_id: result.document_id,
+            chunk: result.content,
+            relevance: result.score,
+        });
+    }
+    Ok(sources)
+}
+
+/// Placeholder for LLM generation: echoes the message.
+///
+/// Will build a prompt from `sources` once a language model is integrated.
+fn generate_reply(message: &str, _sources: &[DocumentSource]) -> String {
+    format!("Echo: {}", message)
 }
 
 // ============================================================================
 // Document Commands
 // ============================================================================
 
-use crate::chunker::{self, Chunk, ChunkConfig};
-use crate::documents::{self, Document};
-use std::path::PathBuf;
+use crate::chunker::{self, Chunk, ChunkConfig, ChunkIter};
+use crate::documents::{self, Document, LoadedDocument};
+use crate::text;
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
 
 /// Application state for storing the documents directory path.
 pub struct AppPaths {
@@ -146,7 +946,7 @@ pub struct AppPaths {
 }
 
 /// Response type for document operations (matches frontend expectations).
-#[derive(serde::Serialize)]
+#[derive(serde::Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct DocumentResponse {
     pub id: String,
@@ -155,6 +955,9 @@ pub struct DocumentResponse {
     pub doc_type: String,
     pub size: u64,
     pub uploaded_at: String,
+    pub word_count: u64,
+    /// ISO 639-3 language code, if one was detected
+    pub language: Option<String>,
 }
 
 impl From<Document> for DocumentResponse {
@@ -165,127 +968,943 @@ impl From<Document> for DocumentResponse {
             doc_type: doc.doc_type.as_str().to_string(),
             size: doc.size,
             uploaded_at: doc.uploaded_at.to_rfc3339(),
+            word_count: doc.word_count,
+            language: doc.language,
         }
     }
 }
 
+/// Payload of the `document-ingested` event, sent when an upload finishes.
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentIngested {
+    pub document: DocumentResponse,
+    pub chunk_count: usize,
+    /// 0 if the embedding model wasn't loaded
+    pub embedding_count: usize,
+}
+
+/// Payload of the `document-deleted` event.
+#[derive(serde::Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentDeleted {
+    pub document_id: String,
+}
+
 /// Get all documents.
 #[tauri::command]
-pub fn get_all_documents(db: State<'_, DbState>) -> Result<Vec<DocumentResponse>, String> {
-    let db = db.0.lock().map_err(|e| e.to_string())?;
-    let docs = documents::get_all_documents(&db.conn).map_err(|e| e.to_string())?;
+pub fn get_all_documents(db: State<'_, DbState>) -> Result<Vec<DocumentResponse>, CommandError> {
+    let db = db.0.lock()?;
+    let docs = documents::get_all_documents(&db.conn)?;
     Ok(docs.into_iter().map(DocumentResponse::from).collect())
 }
 
-/// Upload and process a document from a file path.
+/// Get one page of documents, newest first, for a document browser.
 ///
-/// This command:
-/// 1. Reads the file from the given path
-/// 2. Extracts text content based on file type
-/// 3. Copies the file to the app's documents directory
-/// 4. Saves metadata and content to the database
-/// 5. Chunks the text and generates embeddings (if model is loaded)
+/// Use `count_documents` for the total number of pages.
 #[tauri::command]
-pub async fn upload_document(
+pub fn get_documents_paged(
     db: State<'_, DbState>,
-    paths: State<'_, AppPaths>,
-    model: State<'_, EmbeddingState>,
-    file_path: String,
-) -> Result<DocumentResponse, String> {
-    let source_path = PathBuf::from(&file_path);
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<DocumentResponse>, CommandError> {
+    let db = db.0.lock()?;
+    let docs = documents::get_all_documents_paged(&db.conn, limit, offset)?;
+    Ok(docs.into_iter().map(DocumentResponse::from).collect())
+}
 
-    // Validate the file exists
-    if !source_path.exists() {
-        return Err(format!("File not found: {}", file_path));
-    }
+/// Count all documents.
+#[tauri::command]
+pub fn count_documents(db: State<'_, DbState>) -> Result<usize, CommandError> {
+    let db = db.0.lock()?;
+    documents::count_documents(&db.conn).map_err(CommandError::from)
+}
+
+/// Export the whole document catalog (metadata plus chunk and embedding
+/// counts, no content) as JSON.
+#[tauri::command]
+pub fn export_documents(db: State<'_, DbState>) -> Result<String, CommandError> {
+    let db = db.0.lock()?;
+    documents::export_documents_json(&db.conn).map_err(CommandError::from)
+}
+
+/// Get all documents in a language, by ISO 639-3 code (e.g. `"eng"`).
+#[tauri::command]
+pub fn get_documents_by_language(
+    db: State<'_, DbState>,
+    language: String,
+) -> Result<Vec<DocumentResponse>, CommandError> {
+    let db = db.0.lock()?;
+    let docs = documents::get_documents_by_language(&db.conn, &language)?;
+    Ok(docs.into_iter().map(DocumentResponse::from).collect())
+}
+
+/// Get a single document's metadata.
+#[tauri::command]
+pub fn get_document(
+    db: State<'_, DbState>,
+    document_id: String,
+) -> Result<Option<DocumentResponse>, CommandError> {
+    let db = db.0.lock()?;
+    let doc = documents::get_document(&db.conn, &document_id)?;
+    Ok(doc.map(DocumentResponse::from))
+}
+
+/// Options for `upload_document`.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct IngestOptions {
+    /// Lets the upload be stopped with `cancel_operation`
+    pub operation_id: Option<String>,
+    /// Copy the file into the app's documents directory and point the
+    /// document's `path` at the copy, so it survives the original being
+    /// moved or deleted. When false, `path` is the original file.
+    pub copy_into_store: bool,
+    /// Only ingest these pages of a PDF, numbered from 1 with an exclusive
+    /// end, e.g. `{ "start": 2, "end": 4 }` for pages 2 and 3. Ignored for
+    /// other formats, and not remembered by `reextract_document`.
+    pub pdf_pages: Option<std::ops::Range<usize>>,
+    /// Only ingest these fields of JSON records, as dot paths like
+    /// `author.name`, instead of every string. Ignored for other formats,
+    /// and not remembered by `reextract_document`.
+    pub json_fields: Option<Vec<String>>,
+}
+
+impl Default for IngestOptions {
+    fn default() -> Self {
+        Self {
+            operation_id: None,
+            copy_into_store: true,
+            pdf_pages: None,
+            json_fields: None,
+        }
+    }
+}
+
+/// Number of chunks embedded per batch during ingest and indexing.
+///
+/// Cancellation is checked between batches.
+const EMBED_BATCH_SIZE: usize = 32;
+
+/// The text embedded for each chunk: its content, cleaned up by
+/// `text::normalize_chunk_text` if `normalize` is set (the
+/// `normalize_chunks` setting). Stored content and offsets never change.
+fn embedding_inputs(chunks: &[Chunk], normalize: bool) -> Vec<String> {
+    chunks
+        .iter()
+        .map(|c| if normalize { text::normalize_chunk_text(&c.content) } else { c.content.clone() })
+        .collect()
+}
+
+/// The ID of the model that embedded a document's chunks, and one
+/// embedding per chunk.
+type ChunkEmbeddings = (String, Vec<Vec<f32>>);
+
+/// Embeds `chunks` as passages a batch at a time, returning the model's ID
+/// with the vectors, or `None` if no model is loaded.
+///
+/// Only the model is locked, so database commands don't wait behind the
+/// encode (see `DbState`). Returns `Cancelled` if `cancel` is set between
+/// batches.
+fn embed_chunks(
+    model: &EmbeddingState,
+    chunks: &[Chunk],
+    normalize: bool,
+    cancel: Option<&AtomicBool>,
+) -> Result<Option<ChunkEmbeddings>, CommandError> {
+    let model_guard = model.0.lock()?;
+    let Some(embedding_model) = model_guard.as_ref() else {
+        return Ok(None);
+    };
+
+    let inputs = embedding_inputs(chunks, normalize);
+    let mut embeddings = Vec::with_capacity(inputs.len());
+    for_each_batch(&inputs, EMBED_BATCH_SIZE, cancel, |batch| {
+        let texts: Vec<&str> = batch.iter().map(String::as_str).collect();
+        embeddings.extend(embedding_model.encode_batch(&texts)?);
+        Ok(())
+    })?;
+    Ok(Some((embedding_model.model_id().to_string(), embeddings)))
+}
+
+/// Replaces a document's embeddings with `embeddings` of `chunks`, in one
+/// transaction.
+///
+/// Returns false, saving nothing, if the document's chunks changed since
+/// `chunks` were read (e.g. it was reindexed in the meantime).
+fn replace_document_embeddings(
+    db: &Database,
+    document_id: &str,
+    chunks: &[Chunk],
+    (model_id, embeddings): &ChunkEmbeddings,
+) -> Result<bool, CommandError> {
+    let tx = db.conn.unchecked_transaction()?;
+    let current = chunker::get_document_chunks(&tx, document_id)?;
+    if !current.iter().map(|c| &c.id).eq(chunks.iter().map(|c| &c.id)) {
+        return Ok(false);
+    }
+
+    vector_store::delete_document_embeddings(&tx, document_id)?;
+    for (chunk, embedding) in chunks.iter().zip(embeddings) {
+        vector_store::save_embedding(&tx, &chunk.id, document_id, embedding, model_id)?;
+    }
+    tx.commit()?;
+    Ok(true)
+}
+
+/// The chunk config new documents are ingested with: the defaults, with the
+/// `chunk_size` setting. Returns `InvalidInput` if that size doesn't leave
+/// room for the default overlap.
+fn ingest_chunk_config(settings: &Settings) -> Result<ChunkConfig, CommandError> {
+    ChunkConfig::new(settings.chunk_size, ChunkConfig::default().overlap)
+        .map_err(CommandError::InvalidInput)
+}
+
+/// Upload and process a document from a file path.
+///
+/// This command:
+/// 1. Reads the file from the given path
+/// 2. Extracts text content based on file type
+/// 3. Copies the file to the app's documents directory (unless
+///    `copy_into_store` is false)
+/// 4. Chunks the text and generates embeddings (if model is loaded)
+/// 5. Saves metadata, content, chunks and embeddings to the database
+///
+/// If `operation_id` is given, the upload can be stopped with `cancel_operation`
+/// while embeddings are generated; a cancelled upload stores nothing.
+/// Omitting `options` uses `IngestOptions::default()`.
+///
+/// Emits a `document-ingested` event carrying a `DocumentIngested` payload
+/// once the document is stored.
+#[tauri::command]
+pub async fn upload_document(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    paths: State<'_, AppPaths>,
+    model: State<'_, EmbeddingState>,
+    cancellation: State<'_, CancellationState>,
+    file_path: String,
+    options: Option<IngestOptions>,
+) -> Result<DocumentResponse, CommandError> {
+    let options = options.unwrap_or_default();
+    ingest_document(&db, &paths, &model, &cancellation, &app, &file_path, &options)
+}
+
+/// Read a PDF's title, author and page count without ingesting it, e.g.
+/// to offer a page range (`pdfPages`) before calling `upload_document`.
+#[tauri::command]
+pub async fn get_pdf_metadata(file_path: String) -> Result<documents::PdfMeta, CommandError> {
+    Ok(documents::extract_pdf_metadata(Path::new(&file_path))?)
+}
+
+/// Does the work of `upload_document`, sending events through `events`.
+#[instrument(name = "ingest", skip_all, fields(path = file_path))]
+fn ingest_document(
+    db: &DbState,
+    paths: &AppPaths,
+    model: &EmbeddingState,
+    cancellation: &CancellationState,
+    events: &impl EventSink,
+    file_path: &str,
+    options: &IngestOptions,
+) -> Result<DocumentResponse, CommandError> {
+    let source_path = PathBuf::from(file_path);
+
+    // Validate the file exists
+    if !source_path.exists() {
+        return Err(CommandError::NotFound(format!("File not found: {}", file_path)));
+    }
+
+    let operation = options
+        .operation_id
+        .as_deref()
+        .map(|op_id| cancellation.register(op_id))
+        .transpose()?;
 
     // Generate a unique ID
     let id = Uuid::new_v4().to_string();
 
     // Load and extract text from the document
-    let loaded = documents::load_document(&source_path, &id)
-        .map_err(|e| e.to_string())?;
+    let load_options = documents::LoadOptions {
+        pdf_pages: options.pdf_pages.clone(),
+        json_fields: options.json_fields.clone(),
+        ..Default::default()
+    };
+    let loaded = documents::load_document_with_options(&source_path, &id, &load_options)?;
+    let file_hash = documents::hash_file(&source_path)?;
+
+    let mut doc = loaded.metadata.clone();
+
+    // Copy the file to our documents directory for safekeeping. The ID
+    // prefix keeps files with the same name from overwriting each other.
+    let stored_copy = if options.copy_into_store {
+        let file_name = source_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("document");
+
+        let dest_path = paths.documents_dir.join(format!("{}_{}", id, file_name));
+        std::fs::copy(&source_path, &dest_path)
+            .map_err(|e| CommandError::Io(format!("Failed to copy file: {}", e)))?;
+
+        // Update the document metadata with the new path
+        doc.path = dest_path.to_string_lossy().to_string();
+        Some(dest_path)
+    } else {
+        None
+    };
 
-    // Copy the file to our documents directory for safekeeping
-    let file_name = source_path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("document");
+    // Chunk and embed, then save everything in one transaction
+    let (config, normalize) = ingest_settings(db)?;
+    let cancel = operation.as_ref().map(|op| op.flag());
+    let prepared = {
+        let model = model.0.lock()?;
+        let embedder = model.as_deref();
+        prepare_loaded(loaded, Some(file_hash), &config, normalize, embedder, cancel)
+    };
+    let saved = prepared.and_then(|prepared| {
+        let db = db.0.lock()?;
+        let embedding_count = save_prepared_document(&db, &doc, &prepared)?;
+        Ok((prepared, embedding_count))
+    });
+    let (prepared, embedding_count) = match saved {
+        Ok(saved) => saved,
+        Err(e) => {
+            if let Some(dest_path) = &stored_copy {
+                std::fs::remove_file(dest_path).ok();
+            }
+            return Err(e);
+        }
+    };
+    let chunk_count = prepared.chunk_count;
+
+    info!(
+        name = %doc.name,
+        bytes = doc.size,
+        chars = prepared.loaded.content.len(),
+        chunks = chunk_count,
+        embeddings = embedding_count,
+        "Uploaded document"
+    );
 
-    let dest_path = paths.documents_dir.join(format!("{}_{}", id, file_name));
-    std::fs::copy(&source_path, &dest_path)
-        .map_err(|e| format!("Failed to copy file: {}", e))?;
+    let response = DocumentResponse::from(doc);
+    events.send(
+        "document-ingested",
+        DocumentIngested {
+            document: response.clone(),
+            chunk_count,
+            embedding_count,
+        },
+    );
 
-    // Update the document metadata with the new path
-    let mut doc = loaded.metadata;
-    doc.path = dest_path.to_string_lossy().to_string();
+    Ok(response)
+}
+
+/// The chunk config and `normalize_chunks` setting new documents are
+/// ingested with. Holds the database lock only to read the settings.
+fn ingest_settings(db: &DbState) -> Result<(ChunkConfig, bool), CommandError> {
+    let settings = db.0.lock()?.get_settings()?;
+    Ok((ingest_chunk_config(&settings)?, settings.normalize_chunks))
+}
 
-    // Save to database
-    let db = db.0.lock().map_err(|e| e.to_string())?;
-    documents::save_document(&db.conn, &doc).map_err(|e| e.to_string())?;
-    documents::save_document_content(&db.conn, &doc.id, &loaded.content)
-        .map_err(|e| e.to_string())?;
+/// Makes pasted text searchable, e.g. a block of notes that isn't a file.
+///
+/// The text is stored as a plain text document called `name`, then
+/// chunked and embedded like an upload. Its `path` is just `name`, so it
+/// can't be re-extracted. Emits `document-ingested` like `upload_document`.
+#[tauri::command]
+pub fn ingest_text(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    model: State<'_, EmbeddingState>,
+    name: String,
+    text: String,
+) -> Result<DocumentResponse, CommandError> {
+    let model = model.0.lock()?;
+    ingest_pasted_text(&db, model.as_deref(), &app, &name, &text)
+}
 
-    // Chunk the document for RAG
-    let config = ChunkConfig::default();
-    let chunks = chunker::chunk_text(&doc.id, &loaded.content, &config);
-    chunker::save_chunks(&db.conn, &chunks).map_err(|e| e.to_string())?;
+/// Does the work of `ingest_text`, sending events through `events`.
+fn ingest_pasted_text(
+    db: &DbState,
+    model: Option<&dyn Embedder>,
+    events: &impl EventSink,
+    name: &str,
+    text: &str,
+) -> Result<DocumentResponse, CommandError> {
+    if name.trim().is_empty() {
+        return Err(CommandError::InvalidInput("Document name cannot be empty".to_string()));
+    }
+    if text.trim().is_empty() {
+        return Err(CommandError::InvalidInput("Text cannot be empty".to_string()));
+    }
 
-    // Generate embeddings if model is loaded
-    let mut embeddings_count = 0;
-    {
-        let model_guard = model.0.lock().map_err(|e| e.to_string())?;
-        if let Some(embedding_model) = model_guard.as_ref() {
-            // Generate embeddings for all chunks
-            let texts: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
-            match embedding_model.encode_batch(&texts) {
-                Ok(embeddings) => {
-                    for (chunk, embedding) in chunks.iter().zip(embeddings.iter()) {
-                        vector_store::save_embedding(&db.conn, &chunk.id, &doc.id, embedding)
-                            .map_err(|e| e.to_string())?;
+    let id = Uuid::new_v4().to_string();
+    let loaded = documents::load_document_from_text(text, name.trim(), &id)?;
+    ingest_in_memory(db, model, events, loaded)
+}
+
+/// Ingest a file's contents sent by the frontend, e.g. a drag-and-drop or
+/// clipboard upload, without writing them to a temporary file first.
+///
+/// The document type comes from `file_name`'s extension. As with
+/// `ingest_text`, the document's `path` is just `file_name`, so it can't
+/// be re-extracted. Emits `document-ingested` like `upload_document`.
+#[tauri::command]
+pub fn ingest_bytes(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    model: State<'_, EmbeddingState>,
+    file_name: String,
+    bytes: Vec<u8>,
+) -> Result<DocumentResponse, CommandError> {
+    let id = Uuid::new_v4().to_string();
+    let loaded = documents::load_document_from_bytes(&bytes, &file_name, &id)?;
+
+    let model = model.0.lock()?;
+    ingest_in_memory(&db, model.as_deref(), &app, loaded)
+}
+
+/// Chunks, embeds and stores a document that has no file on disk, for
+/// `ingest_text` and `ingest_bytes`.
+fn ingest_in_memory(
+    db: &DbState,
+    model: Option<&dyn Embedder>,
+    events: &impl EventSink,
+    loaded: LoadedDocument,
+) -> Result<DocumentResponse, CommandError> {
+    let doc = loaded.metadata.clone();
+
+    let (config, normalize) = ingest_settings(db)?;
+    let prepared = prepare_loaded(loaded, None, &config, normalize, model, None)?;
+    let embedding_count = {
+        let db = db.0.lock()?;
+        save_prepared_document(&db, &doc, &prepared)?
+    };
+    let chunk_count = prepared.chunk_count;
+
+    info!(
+        name = %doc.name,
+        chars = prepared.loaded.content.len(),
+        chunks = chunk_count,
+        embeddings = embedding_count,
+        "Ingested document from memory"
+    );
+
+    let response = DocumentResponse::from(doc);
+    events.send(
+        "document-ingested",
+        DocumentIngested {
+            document: response.clone(),
+            chunk_count,
+            embedding_count,
+        },
+    );
+
+    Ok(response)
+}
+
+/// Payload of the `directory-ingest-progress` event, sent by
+/// `ingest_directory` each time a file is handled.
+#[derive(serde::Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryIngestProgress {
+    /// Supported files found in the directory
+    pub total: usize,
+    /// Files handled so far, however they turned out
+    pub processed: usize,
+    pub ingested: usize,
+    /// Files whose content was already ingested
+    pub skipped: usize,
+    /// Files that couldn't be read, extracted or stored
+    pub failed: usize,
+}
+
+/// Result of `ingest_directory`.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryIngestReport {
+    /// The newly ingested documents
+    pub documents: Vec<DocumentResponse>,
+    /// Files whose content was already ingested
+    pub skipped: usize,
+    /// Files with an extension `upload_document` doesn't support
+    pub unsupported: usize,
+    /// Files that couldn't be read, extracted or stored
+    pub failed: usize,
+}
+
+/// Ingest every supported file in a directory, and in its subdirectories
+/// if `recursive` is set.
+///
+/// Files are loaded, chunked and embedded in parallel; writes still go
+/// through `DbState` one document at a time. Files are copied into the
+/// documents directory, as with a default `upload_document`. Unsupported
+/// files and files whose content was already ingested are skipped, and a
+/// file that fails doesn't stop the others.
+///
+/// Emits `document-ingested` for each stored document, and a
+/// `directory-ingest-progress` event carrying a `DirectoryIngestProgress`
+/// payload as files are handled.
+#[tauri::command]
+pub async fn ingest_directory(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    paths: State<'_, AppPaths>,
+    model: State<'_, EmbeddingState>,
+    dir: String,
+    recursive: bool,
+) -> Result<DirectoryIngestReport, CommandError> {
+    ingest_directory_files(&db, &paths, &model, &app, Path::new(&dir), recursive)
+}
+
+/// A document loaded, chunked and (if the model is loaded) embedded, ready
+/// to store.
+///
+/// The chunks themselves aren't kept: `chunks` cuts them again, the same
+/// each time, so they're streamed into the database.
+struct PreparedDocument {
+    loaded: LoadedDocument,
+    /// The file's hash, or `None` for pasted text
+    file_hash: Option<String>,
+    config: ChunkConfig,
+    chunk_count: usize,
+    /// The chunks' embeddings, if the model was loaded
+    embeddings: Option<ChunkEmbeddings>,
+}
+
+impl PreparedDocument {
+    fn chunks(&self) -> Result<ChunkIter<'_>, CommandError> {
+        let doc = &self.loaded.metadata;
+        chunker::chunk_iter(&doc.id, &self.loaded.content, &self.config)
+            .map_err(CommandError::InvalidInput)
+    }
+}
+
+/// Does the work of `ingest_directory`, sending events through `events`.
+#[instrument(name = "ingest_directory", skip(db, paths, model, events))]
+fn ingest_directory_files(
+    db: &DbState,
+    paths: &AppPaths,
+    model: &EmbeddingState,
+    events: &impl EventSink,
+    dir: &Path,
+    recursive: bool,
+) -> Result<DirectoryIngestReport, CommandError> {
+    if !dir.is_dir() {
+        return Err(CommandError::NotFound(format!("Directory not found: {}", dir.display())));
+    }
+
+    let (supported, unsupported): (Vec<PathBuf>, Vec<PathBuf>) =
+        documents::find_files(dir, recursive)?
+            .into_iter()
+            .partition(|path| documents::is_supported_file(path));
+
+    let hashed: Vec<(PathBuf, std::io::Result<String>)> = supported
+        .into_par_iter()
+        .map(|path| {
+            let hash = documents::hash_file(&path);
+            (path, hash)
+        })
+        .collect();
+
+    // Skip content that's already stored, or that appears twice in the directory
+    let mut progress = DirectoryIngestProgress {
+        total: hashed.len(),
+        ..Default::default()
+    };
+    let mut pending = Vec::new();
+    let (config, normalize) = {
+        let db = db.0.lock()?;
+        let mut seen = HashSet::new();
+        for (path, hash) in hashed {
+            match hash {
+                Ok(hash) => {
+                    if !seen.insert(hash.clone())
+                        || documents::find_document_by_hash(&db.conn, &hash)?.is_some()
+                    {
+                        progress.skipped += 1;
+                    } else {
+                        pending.push((path, hash));
                     }
-                    embeddings_count = chunks.len();
                 }
                 Err(e) => {
-                    println!("Warning: Failed to generate embeddings: {}", e);
+                    warn!(path = %path.display(), error = %e, "Failed to read file");
+                    progress.failed += 1;
                 }
             }
         }
+        let settings = db.get_settings()?;
+        (ingest_chunk_config(&settings)?, settings.normalize_chunks)
+    };
+    progress.processed = progress.skipped + progress.failed;
+    events.send("directory-ingest-progress", progress.clone());
+
+    let model_guard = model.0.lock()?;
+    let embedding_model = model_guard.as_deref();
+    let mut stored = Vec::new();
+
+    std::thread::scope(|scope| {
+        // Workers prepare files in parallel; this thread stores them as they
+        // arrive, so only one of them writes to the database at a time
+        let (sender, receiver) = std::sync::mpsc::channel();
+        scope.spawn(move || {
+            pending.into_par_iter().for_each_with(sender, |sender, (path, hash)| {
+                let prepared =
+                    prepare_document(&path, hash, &config, normalize, embedding_model);
+                sender.send((path, prepared)).ok();
+            });
+        });
+
+        for (path, prepared) in receiver {
+            match prepared.and_then(|prepared| store_prepared_document(db, paths, prepared)) {
+                Ok(Some(ingested)) => {
+                    progress.ingested += 1;
+                    stored.push(ingested.document.clone());
+                    events.send("document-ingested", ingested);
+                }
+                Ok(None) => progress.skipped += 1,
+                Err(e) => {
+                    warn!(path = %path.display(), error = %e, "Failed to ingest file");
+                    progress.failed += 1;
+                }
+            }
+            progress.processed += 1;
+            events.send("directory-ingest-progress", progress.clone());
+        }
+    });
+
+    info!(
+        ingested = progress.ingested,
+        skipped = progress.skipped,
+        unsupported = unsupported.len(),
+        failed = progress.failed,
+        "Ingested directory"
+    );
+
+    Ok(DirectoryIngestReport {
+        documents: stored,
+        skipped: progress.skipped,
+        unsupported: unsupported.len(),
+        failed: progress.failed,
+    })
+}
+
+/// Loads, chunks and embeds a file without touching the database.
+fn prepare_document(
+    path: &Path,
+    file_hash: String,
+    config: &ChunkConfig,
+    normalize: bool,
+    model: Option<&dyn Embedder>,
+) -> Result<PreparedDocument, CommandError> {
+    let id = Uuid::new_v4().to_string();
+    let loaded = documents::load_document(path, &id)?;
+    prepare_loaded(loaded, Some(file_hash), config, normalize, model, None)
+}
+
+/// Chunks a loaded document and embeds the chunks if there's a `model`,
+/// without touching the database.
+///
+/// Chunks are embedded a batch at a time as they're cut, so only one batch
+/// is held at once. The embedding inputs are normalized if `normalize` is
+/// set. If `cancel` is set between batches, `Cancelled` is returned; other
+/// embedding failures are only logged and drop the embeddings, so the
+/// document can still be stored and indexed later.
+fn prepare_loaded(
+    loaded: LoadedDocument,
+    file_hash: Option<String>,
+    config: &ChunkConfig,
+    normalize: bool,
+    model: Option<&dyn Embedder>,
+    cancel: Option<&AtomicBool>,
+) -> Result<PreparedDocument, CommandError> {
+    let doc = &loaded.metadata;
+    let config = config.for_document_type(doc.doc_type);
+    let mut chunks =
+        chunker::chunk_iter(&doc.id, &loaded.content, &config).map_err(CommandError::InvalidInput)?;
+
+    let mut chunk_count = 0;
+    let mut vectors = Vec::new();
+    let mut embed_error = None;
+    loop {
+        let batch: Vec<Chunk> = chunks.by_ref().take(EMBED_BATCH_SIZE).collect();
+        if batch.is_empty() {
+            break;
+        }
+        chunk_count += batch.len();
+        if chunk_count > config.max_chunks {
+            return Err(CommandError::InvalidInput(chunker::too_many_chunks(&config)));
+        }
+
+        // After a failure the rest are still counted, but not embedded
+        let Some(model) = model.filter(|_| embed_error.is_none()) else {
+            continue;
+        };
+        if cancel.is_some_and(|flag| flag.load(Ordering::SeqCst)) {
+            return Err(CommandError::Cancelled);
+        }
+        let inputs = embedding_inputs(&batch, normalize);
+        let texts: Vec<&str> = inputs.iter().map(String::as_str).collect();
+        match model.encode_batch(&texts) {
+            Ok(batch_vectors) => vectors.extend(batch_vectors),
+            Err(e) => embed_error = Some(e),
+        }
     }
 
-    println!(
-        "Uploaded document: {} ({} bytes, {} chars, {} chunks, {} embeddings)",
-        doc.name,
-        doc.size,
-        loaded.content.len(),
-        chunks.len(),
-        embeddings_count
-    );
+    let embeddings = match (model, embed_error) {
+        (Some(model), None) => Some((model.model_id().to_string(), vectors)),
+        (Some(_), Some(e)) => {
+            warn!(error = %e, "Failed to generate embeddings");
+            None
+        }
+        (None, _) => None,
+    };
+
+    Ok(PreparedDocument {
+        loaded,
+        file_hash,
+        config,
+        chunk_count,
+        embeddings,
+    })
+}
+
+/// Copies a prepared document's file into the documents directory and
+/// stores it in one transaction.
+///
+/// Returns `None` if a file with the same hash was stored in the meantime.
+fn store_prepared_document(
+    db: &DbState,
+    paths: &AppPaths,
+    prepared: PreparedDocument,
+) -> Result<Option<DocumentIngested>, CommandError> {
+    let db = db.0.lock()?;
+    if let Some(file_hash) = &prepared.file_hash {
+        if documents::find_document_by_hash(&db.conn, file_hash)?.is_some() {
+            return Ok(None);
+        }
+    }
+
+    let mut doc = prepared.loaded.metadata.clone();
+    let source_path = PathBuf::from(&doc.source_path);
+    let file_name = source_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("document");
+    let dest_path = paths.documents_dir.join(format!("{}_{}", doc.id, file_name));
+    std::fs::copy(&source_path, &dest_path)
+        .map_err(|e| CommandError::Io(format!("Failed to copy file: {}", e)))?;
+    doc.path = dest_path.to_string_lossy().to_string();
+
+    let embedding_count = match save_prepared_document(&db, &doc, &prepared) {
+        Ok(count) => count,
+        Err(e) => {
+            std::fs::remove_file(&dest_path).ok();
+            return Err(e);
+        }
+    };
+
+    Ok(Some(DocumentIngested {
+        document: DocumentResponse::from(doc),
+        chunk_count: prepared.chunk_count,
+        embedding_count,
+    }))
+}
+
+/// Writes a prepared document, stored as `doc`, in one transaction.
+///
+/// Returns the number of embeddings saved.
+fn save_prepared_document(
+    db: &Database,
+    doc: &Document,
+    prepared: &PreparedDocument,
+) -> Result<usize, CommandError> {
+    let tx = db.conn.unchecked_transaction()?;
+    documents::save_document(&tx, doc)?;
+    documents::save_document_content(&tx, &doc.id, &prepared.loaded.content)?;
+    if let Some(file_hash) = &prepared.file_hash {
+        documents::set_file_hash(&tx, &doc.id, file_hash)?;
+    }
+    chunker::save_document_config(&tx, &doc.id, &prepared.config)?;
+
+    let (model_id, mut embeddings) = match &prepared.embeddings {
+        Some((model_id, embeddings)) => (model_id.as_str(), embeddings.iter()),
+        None => ("", [].iter()),
+    };
+    let mut embedding_count = 0;
+    for chunk in prepared.chunks()? {
+        chunker::save_chunk(&tx, &chunk)?;
+        if let Some(embedding) = embeddings.next() {
+            vector_store::save_embedding(&tx, &chunk.id, &doc.id, embedding, model_id)?;
+            embedding_count += 1;
+        }
+    }
 
-    Ok(DocumentResponse::from(doc))
+    tx.commit()?;
+    Ok(embedding_count)
 }
 
 /// Delete a document.
+///
+/// The stored copy of the file is deleted too. Files outside the app's
+/// documents directory (uploaded without `copy_into_store`) are the user's
+/// originals and are left alone.
+///
+/// Emits a `document-deleted` event carrying a `DocumentDeleted` payload
+/// if the document existed.
 #[tauri::command]
 pub fn delete_document_cmd(
+    app: AppHandle,
     db: State<'_, DbState>,
+    paths: State<'_, AppPaths>,
     document_id: String,
-) -> Result<bool, String> {
-    let db = db.0.lock().map_err(|e| e.to_string())?;
+) -> Result<bool, CommandError> {
+    remove_document(&db, &paths, &app, &document_id)
+}
+
+/// Does the work of `delete_document_cmd`, sending events through `events`.
+fn remove_document(
+    db: &DbState,
+    paths: &AppPaths,
+    events: &impl EventSink,
+    document_id: &str,
+) -> Result<bool, CommandError> {
+    let db = db.0.lock()?;
 
     // Get the document to find its file path
-    if let Some(doc) = documents::get_document(&db.conn, &document_id)
-        .map_err(|e| e.to_string())?
+    if let Some(doc) = documents::get_document(&db.conn, document_id)?
     {
-        // Delete the file from disk
+        // Delete the stored copy from disk
         let path = PathBuf::from(&doc.path);
-        if path.exists() {
+        if path.starts_with(&paths.documents_dir) && path.exists() {
             std::fs::remove_file(&path).ok(); // Ignore errors if file can't be deleted
         }
     }
 
+    // Clear embeddings and chunks explicitly rather than relying only on
+    // CASCADE, which needs `PRAGMA foreign_keys` on this connection
+    vector_store::delete_document_embeddings(&db.conn, document_id)?;
+    chunker::delete_document_chunks(&db.conn, document_id)?;
+
     // Delete from database
-    documents::delete_document(&db.conn, &document_id).map_err(|e| e.to_string())
+    let deleted = documents::delete_document(&db.conn, document_id)?;
+    if deleted {
+        events.send(
+            "document-deleted",
+            DocumentDeleted {
+                document_id: document_id.to_string(),
+            },
+        );
+    }
+
+    Ok(deleted)
+}
+
+/// Drop a document's embeddings to free space, e.g. before switching models.
+///
+/// The document and its text are kept. Chunks are deleted too unless
+/// `keep_chunks` is set, in which case `index_document` can re-embed them
+/// without re-chunking. Returns the number of embeddings removed.
+#[tauri::command]
+pub fn clear_document_embeddings(
+    db: State<'_, DbState>,
+    document_id: String,
+    keep_chunks: bool,
+) -> Result<usize, CommandError> {
+    let db = db.0.lock()?;
+    clear_embeddings(&db, &document_id, keep_chunks)
+}
+
+/// Does the work of `clear_document_embeddings`.
+fn clear_embeddings(
+    db: &Database,
+    document_id: &str,
+    keep_chunks: bool,
+) -> Result<usize, CommandError> {
+    if documents::get_document(&db.conn, document_id)?.is_none() {
+        return Err(CommandError::NotFound(format!("Document not found: {}", document_id)));
+    }
+
+    let tx = db.conn.unchecked_transaction()?;
+    let removed = vector_store::delete_document_embeddings(&tx, document_id)?;
+    if !keep_chunks {
+        chunker::delete_document_chunks(&tx, document_id)?;
+    }
+    tx.commit()?;
+
+    Ok(removed)
+}
+
+/// Check whether a document's source file changed on disk since it was ingested.
+///
+/// Returns a `NotFound` error if the document or its source file is gone.
+#[tauri::command]
+pub fn document_is_stale(db: State<'_, DbState>, document_id: String) -> Result<bool, CommandError> {
+    let db = db.0.lock()?;
+    documents::document_is_stale(&db.conn, &document_id).map_err(CommandError::from)
+}
+
+/// Re-extract a document's text from its file, replacing the stored content.
+///
+/// Returns the new content's length in characters. Chunks and embeddings
+/// are kept until the document is reindexed.
+#[tauri::command]
+pub fn reextract_document(db: State<'_, DbState>, document_id: String) -> Result<usize, CommandError> {
+    let db = db.0.lock()?;
+    documents::reextract_document(&db.conn, &document_id).map_err(CommandError::from)
+}
+
+/// Give a document a new display name. Its file and path are unchanged.
+///
+/// Returns false if the document doesn't exist, and an error for a blank name.
+#[tauri::command]
+pub fn rename_document(
+    db: State<'_, DbState>,
+    document_id: String,
+    name: String,
+) -> Result<bool, CommandError> {
+    let db = db.0.lock()?;
+    documents::rename_document(&db.conn, &document_id, &name).map_err(CommandError::from)
+}
+
+/// Set how much a document's chunks are favored in search results.
+///
+/// Scores are weighted by `boost`: 1.0 is neutral, 2.0 strongly favors the
+/// document and 0.5 demotes it. It must be a positive number. Returns false
+/// if the document doesn't exist.
+#[tauri::command]
+pub fn set_document_boost(
+    db: State<'_, DbState>,
+    document_id: String,
+    boost: f32,
+) -> Result<bool, CommandError> {
+    if !(boost.is_finite() && boost > 0.0) {
+        return Err(CommandError::InvalidInput("Boost must be a positive number".to_string()));
+    }
+    let db = db.0.lock()?;
+    documents::set_document_boost(&db.conn, &document_id, boost).map_err(CommandError::from)
+}
+
+/// Mute a document to leave it out of search without deleting it, or
+/// unmute it. Its chunks and embeddings are kept either way.
+///
+/// Returns false if the document doesn't exist.
+#[tauri::command]
+pub fn set_document_muted(
+    db: State<'_, DbState>,
+    document_id: String,
+    muted: bool,
+) -> Result<bool, CommandError> {
+    let db = db.0.lock()?;
+    documents::set_document_muted(&db.conn, &document_id, muted).map_err(CommandError::from)
+}
+
+/// Check whether a document exists, without fetching it.
+#[tauri::command]
+pub fn document_exists(db: State<'_, DbState>, document_id: String) -> Result<bool, CommandError> {
+    let db = db.0.lock()?;
+    documents::document_exists(&db.conn, &document_id).map_err(CommandError::from)
 }
 
 /// Get document content (extracted text).
@@ -293,9 +1912,22 @@ pub fn delete_document_cmd(
 pub fn get_document_content(
     db: State<'_, DbState>,
     document_id: String,
-) -> Result<Option<String>, String> {
-    let db = db.0.lock().map_err(|e| e.to_string())?;
-    documents::get_document_content(&db.conn, &document_id).map_err(|e| e.to_string())
+) -> Result<Option<String>, CommandError> {
+    let db = db.0.lock()?;
+    documents::get_document_content(&db.conn, &document_id).map_err(CommandError::from)
+}
+
+/// Get a character range of a document's content, for previews.
+#[tauri::command]
+pub fn get_document_content_range(
+    db: State<'_, DbState>,
+    document_id: String,
+    char_start: usize,
+    char_len: usize,
+) -> Result<Option<String>, CommandError> {
+    let db = db.0.lock()?;
+    documents::get_document_content_range(&db.conn, &document_id, char_start, char_len)
+        .map_err(CommandError::from)
 }
 
 // ============================================================================
@@ -327,116 +1959,646 @@ impl From<Chunk> for ChunkResponse {
     }
 }
 
-/// Get all chunks for a document.
+/// Get a document's chunks in order, to see exactly how it was split.
+///
+/// `limit` and `offset` page through large documents; without them every
+/// chunk is returned.
 #[tauri::command]
 pub fn get_document_chunks(
     db: State<'_, DbState>,
     document_id: String,
-) -> Result<Vec<ChunkResponse>, String> {
-    let db = db.0.lock().map_err(|e| e.to_string())?;
-    let chunks = chunker::get_document_chunks(&db.conn, &document_id)
-        .map_err(|e| e.to_string())?;
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Result<Vec<ChunkResponse>, CommandError> {
+    let db = db.0.lock()?;
+    let chunks =
+        chunker::get_document_chunks_page(&db.conn, &document_id, limit, offset.unwrap_or(0))?;
     Ok(chunks.into_iter().map(ChunkResponse::from).collect())
 }
 
+/// Get a single chunk by ID, e.g. to show a citation's details.
+///
+/// Returns `None` if the chunk doesn't exist (e.g. the document was reindexed).
+#[tauri::command]
+pub fn get_chunk(
+    db: State<'_, DbState>,
+    chunk_id: String,
+) -> Result<Option<ChunkResponse>, CommandError> {
+    let db = db.0.lock()?;
+    let chunk = chunker::get_chunk(&db.conn, &chunk_id)?;
+    Ok(chunk.map(ChunkResponse::from))
+}
+
+/// Get the document text around a chunk, `pad` characters on each side.
+///
+/// Used to highlight a citation in its source document.
+#[tauri::command]
+pub fn get_chunk_source_excerpt(
+    db: State<'_, DbState>,
+    chunk_id: String,
+    pad: usize,
+) -> Result<Option<String>, CommandError> {
+    let db = db.0.lock()?;
+    chunker::get_chunk_source_excerpt(&db.conn, &chunk_id, pad).map_err(CommandError::from)
+}
+
+/// Re-chunk a document and regenerate its embeddings.
+///
+/// When `config` is omitted, the config the document was last chunked
+/// with is reused, so reindexing is reproducible.
+/// Returns the number of chunks produced.
+///
+/// If `operation_id` is given, reindexing can be stopped with
+/// `cancel_operation`; the document then keeps its old chunks.
+#[tauri::command]
+pub async fn reindex_document(
+    db: State<'_, DbState>,
+    model: State<'_, EmbeddingState>,
+    cancellation: State<'_, CancellationState>,
+    document_id: String,
+    config: Option<ChunkConfig>,
+    operation_id: Option<String>,
+) -> Result<usize, CommandError> {
+    let operation = operation_id
+        .as_deref()
+        .map(|op_id| cancellation.register(op_id))
+        .transpose()?;
+    let cancel = operation.as_ref().map(|op| op.flag());
+    rechunk_and_embed(&db, &model, &document_id, config, cancel)
+}
+
+/// Does the work of `reindex_document`.
+///
+/// The old chunks and embeddings stay in place while the new ones are
+/// embedded, then are replaced in one transaction.
+fn rechunk_and_embed(
+    db: &DbState,
+    model: &EmbeddingState,
+    document_id: &str,
+    config: Option<ChunkConfig>,
+    cancel: Option<&AtomicBool>,
+) -> Result<usize, CommandError> {
+    let not_found = || CommandError::NotFound(format!("Document not found: {}", document_id));
+    let (chunks, config, normalize) = {
+        let db = db.0.lock()?;
+        if documents::get_document(&db.conn, document_id)?.is_none() {
+            return Err(not_found());
+        }
+        let (chunks, config) = chunker::rechunk_document(&db.conn, document_id, config)
+            .map_err(CommandError::InvalidInput)?;
+        (chunks, config, db.get_settings()?.normalize_chunks)
+    };
+
+    // Regenerate embeddings if the model is loaded
+    let embeddings = embed_chunks(model, &chunks, normalize, cancel)?;
+
+    let db = db.0.lock()?;
+    let tx = db.conn.unchecked_transaction()?;
+    if documents::get_document(&tx, document_id)?.is_none() {
+        return Err(not_found());
+    }
+    chunker::replace_document_chunks(&tx, document_id, &chunks, &config)?;
+    if let Some((model_id, embeddings)) = &embeddings {
+        for (chunk, embedding) in chunks.iter().zip(embeddings) {
+            vector_store::save_embedding(&tx, &chunk.id, document_id, embedding, model_id)?;
+        }
+    }
+    tx.commit()?;
+
+    Ok(chunks.len())
+}
+
 /// Get chunk statistics.
 #[tauri::command]
-pub fn get_chunk_stats(db: State<'_, DbState>) -> Result<(usize, usize), String> {
-    let db = db.0.lock().map_err(|e| e.to_string())?;
-    chunker::get_chunk_stats(&db.conn).map_err(|e| e.to_string())
+pub fn get_chunk_stats(db: State<'_, DbState>) -> Result<(usize, usize), CommandError> {
+    let db = db.0.lock()?;
+    chunker::get_chunk_stats(&db.conn).map_err(CommandError::from)
+}
+
+/// Get detailed chunk statistics (counts plus content-length figures).
+#[tauri::command]
+pub fn chunk_stats(db: State<'_, DbState>) -> Result<chunker::ChunkStats, CommandError> {
+    let db = db.0.lock()?;
+    chunker::chunk_stats(&db.conn).map_err(CommandError::from)
+}
+
+/// Most chunks `preview_chunking` returns; `total` still counts them all.
+const PREVIEW_MAX_CHUNKS: usize = 50;
+
+/// Result of a dry-run chunking.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkPreview {
+    /// The first chunks, at most `PREVIEW_MAX_CHUNKS` of them
+    pub chunks: Vec<ChunkResponse>,
+    /// How many chunks the whole document produces
+    pub total: usize,
+}
+
+/// Chunk a file with `config` without storing anything, so settings can be
+/// tried out before uploading.
+#[tauri::command]
+pub fn preview_chunking(path: String, config: ChunkConfig) -> Result<ChunkPreview, CommandError> {
+    preview_document_chunks(&path, &config, PREVIEW_MAX_CHUNKS)
+}
+
+/// Does the work of `preview_chunking`, returning at most `max_chunks`.
+///
+/// Chunks the same way `ingest_document` does, so the preview matches what
+/// an upload would store. The chunks carry a placeholder document ID.
+fn preview_document_chunks(
+    path: &str,
+    config: &ChunkConfig,
+    max_chunks: usize,
+) -> Result<ChunkPreview, CommandError> {
+    let loaded = documents::load_document(&PathBuf::from(path), "preview")?;
+    let config = config.for_document_type(loaded.metadata.doc_type);
+    let mut chunks = chunker::chunk_iter(&loaded.metadata.id, &loaded.content, &config)
+        .map_err(CommandError::InvalidInput)?;
+
+    // Only the returned chunks are kept; the rest are just counted
+    let shown: Vec<ChunkResponse> =
+        chunks.by_ref().take(max_chunks).map(ChunkResponse::from).collect();
+    let total = shown.len() + chunks.count();
+    if total > config.max_chunks {
+        return Err(CommandError::InvalidInput(chunker::too_many_chunks(&config)));
+    }
+    Ok(ChunkPreview { chunks: shown, total })
 }
 
 // ============================================================================
 // Embedding Commands
 // ============================================================================
 
-use crate::embeddings::EmbeddingModel;
-use crate::vector_store::{self, SearchResult};
+use crate::embeddings::{
+    self, Embedder, EmbeddingError, EmbeddingModel, EmbeddingModelBuilder, Pooling,
+    RemoteEmbedder, RemoteEmbedderConfig, RetryConfig, SimilarityMetric,
+};
+use tokenizers::Tokenizer;
+use crate::vector_store::{
+    self, DocumentAggregation, DocumentSearchResult, SearchResult, VectorIndex,
+};
 
 /// Wrapper for thread-safe embedding model access.
 ///
 /// The model is wrapped in Option because it's loaded on-demand,
-/// not at startup (to avoid slow app launch).
-pub struct EmbeddingState(pub Mutex<Option<EmbeddingModel>>);
+/// not at startup (to avoid slow app launch). It's either the local
+/// model (`init_embedding_model`) or a remote endpoint
+/// (`init_remote_embedder`). Locked before `DbState`; see there for the
+/// lock order.
+pub struct EmbeddingState(pub Mutex<Option<Box<dyn Embedder>>>);
+
+/// Runs `f` with the embedding model if it's loaded and no other command is
+/// using it. Returns `None` otherwise, rather than waiting behind e.g. a
+/// long indexing job.
+fn with_idle_model<T>(
+    model: &EmbeddingState,
+    f: impl FnOnce(&dyn Embedder) -> T,
+) -> Result<Option<T>, CommandError> {
+    match model.0.try_lock() {
+        Ok(guard) => Ok(guard.as_deref().map(f)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Poisoned(e)) => Err(e.into()),
+    }
+}
+
+/// Error for commands that need the embedding model before it's loaded.
+fn model_not_loaded() -> CommandError {
+    CommandError::Embedding(
+        "Embedding model not loaded. Call init_embedding_model first.".to_string(),
+    )
+}
+
+/// The embeddings searches rank, held in memory so a search doesn't read
+/// every stored vector from SQLite.
+///
+/// Built on the first search - or loaded from the snapshot, if one was
+/// saved and nothing changed since - and rebuilt once the embeddings have
+/// changed. Only locked while holding `DbState`.
+#[derive(Default)]
+pub struct IndexState {
+    index: Mutex<Option<VectorIndex>>,
+    /// Where each built index is saved for the next launch; `None` keeps
+    /// it in memory only
+    snapshot_path: Option<PathBuf>,
+    /// Set during an incognito session, whose index mustn't be saved over
+    /// (or loaded from) the on-disk database's snapshot
+    incognito: AtomicBool,
+}
+
+impl IndexState {
+    /// An index that's saved to (and first loaded from) `snapshot_path`.
+    pub fn with_snapshot(snapshot_path: PathBuf) -> Self {
+        IndexState {
+            index: Mutex::new(None),
+            snapshot_path: Some(snapshot_path),
+            incognito: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the top `k` chunks embedded by `model_id` for the query,
+    /// like `vector_store::search_similar`.
+    fn search(
+        &self,
+        db: &Database,
+        query_embedding: &[f32],
+        k: usize,
+        model_id: &str,
+        metric: SimilarityMetric,
+    ) -> Result<Vec<SearchResult>, CommandError> {
+        let mut index = self.index.lock()?;
+        let fresh = match index.as_ref() {
+            Some(index) => index.model_id() == model_id && !index.is_stale(&db.conn)?,
+            None => false,
+        };
+        let index = match &mut *index {
+            Some(index) if fresh => index,
+            slot => slot.insert(self.load_or_build(db, model_id)?),
+        };
+        Ok(index.search(query_embedding, k, metric))
+    }
+
+    /// Loads the snapshot if it's fresh and for `model_id`, otherwise
+    /// builds the index and saves a new snapshot (unless it's empty, which
+    /// is quicker to build than to load). Snapshot errors are only logged:
+    /// the index can always be built from the database.
+    fn load_or_build(&self, db: &Database, model_id: &str) -> Result<VectorIndex, CommandError> {
+        let path = self.snapshot_path.as_ref();
+        let Some(path) = path.filter(|_| !self.incognito.load(Ordering::SeqCst)) else {
+            return Ok(VectorIndex::build(&db.conn, model_id)?);
+        };
+
+        match VectorIndex::load(path, &db.conn) {
+            Ok(Some(index)) if index.model_id() == model_id => return Ok(index),
+            Ok(_) => {}
+            Err(e) => warn!(error = %e, "Ignoring unreadable index snapshot"),
+        }
+
+        let index = VectorIndex::build(&db.conn, model_id)?;
+        info!(embeddings = index.len(), "Built vector index");
+        if index.is_empty() {
+            return Ok(index);
+        }
+        if let Err(e) = index.save(path) {
+            warn!(error = %e, "Failed to save index snapshot");
+        }
+        Ok(index)
+    }
+
+    /// Drops the index when `DbState` switches to (or back from) an
+    /// incognito session's database, keeping the snapshot for afterwards.
+    fn switch_database(&self, incognito: bool) -> Result<(), CommandError> {
+        *self.index.lock()? = None;
+        self.incognito.store(incognito, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Drops the index and deletes its snapshot, e.g. when the database
+    /// is reset (a new database's counters could match the old snapshot).
+    fn clear(&self) -> Result<(), CommandError> {
+        *self.index.lock()? = None;
+        if let Some(path) = &self.snapshot_path {
+            std::fs::remove_file(path).ok(); // There may be no snapshot yet
+        }
+        Ok(())
+    }
+}
+
+/// Settings for `init_embedding_model`. Anything left out keeps the
+/// default: all-MiniLM-L6-v2 on the CPU, mean pooling, no prefixes.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EmbeddingModelOptions {
+    /// Hugging Face model ID; must be a BERT-style sentence transformer
+    pub model_id: Option<String>,
+    /// `"mean"`, `"cls"` or `"max"`, as the checkpoint was trained
+    pub pooling: Option<Pooling>,
+    /// Prepended to search queries, e.g. `"query: "` for e5 models
+    pub query_prefix: Option<String>,
+    /// Prepended to document passages, e.g. `"passage: "` for e5 models
+    pub passage_prefix: Option<String>,
+    /// Hugging Face cache directory to load from and download into
+    pub cache_dir: Option<PathBuf>,
+    /// Only load from the cache, failing if a file is missing
+    pub offline: bool,
+    /// Download attempts per file, including the first
+    pub retry_attempts: Option<u32>,
+    /// Delay before the first download retry, in milliseconds
+    pub retry_delay_ms: Option<u64>,
+    /// Run on the first CUDA GPU, falling back to the CPU if there's none
+    pub gpu: bool,
+}
+
+impl EmbeddingModelOptions {
+    fn builder(self) -> Result<EmbeddingModelBuilder, CommandError> {
+        let mut builder = EmbeddingModelBuilder::new()
+            .with_pooling(self.pooling.unwrap_or_default())
+            .with_offline(self.offline);
+
+        if let Some(model_id) = &self.model_id {
+            builder = builder.with_model_id(model_id);
+        }
+        if self.query_prefix.is_some() || self.passage_prefix.is_some() {
+            builder = builder.with_prefixes(
+                self.query_prefix.as_deref().unwrap_or(""),
+                self.passage_prefix.as_deref().unwrap_or(""),
+            );
+        }
+        if let Some(cache_dir) = self.cache_dir {
+            builder = builder.with_cache_dir(cache_dir);
+        }
+        if self.gpu {
+            let device = candle_core::Device::cuda_if_available(0)
+                .map_err(|e| CommandError::Internal(format!("Failed to open GPU: {}", e)))?;
+            builder = builder.with_device(device);
+        }
+
+        let mut retry = RetryConfig::default();
+        if let Some(attempts) = self.retry_attempts {
+            retry.max_attempts = attempts;
+        }
+        if let Some(delay_ms) = self.retry_delay_ms {
+            retry.base_delay = std::time::Duration::from_millis(delay_ms);
+        }
+
+        Ok(builder.with_retry(retry))
+    }
+}
 
 /// Initialize the embedding model.
 ///
 /// Downloads the model from Hugging Face if not cached (~90MB).
 /// This should be called before indexing or searching.
+/// Download progress is emitted as `model-download-progress` events
+/// carrying a `DownloadProgress` payload.
+///
+/// Without `options` an already loaded model is kept. With them, the model
+/// is loaded as configured and replaces the current one. Embeddings are
+/// stored under the model ID, so changing only the pooling or prefixes
+/// needs a reindex to keep old and new vectors comparable.
+///
+/// Once loaded, the model's tokenizer counts the tokens of any messages
+/// stored before token counts were tracked.
 #[tauri::command]
-pub async fn init_embedding_model(model: State<'_, EmbeddingState>) -> Result<String, String> {
+pub async fn init_embedding_model(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    model: State<'_, EmbeddingState>,
+    options: Option<EmbeddingModelOptions>,
+) -> Result<String, CommandError> {
     // Check if already loaded
-    {
-        let guard = model.0.lock().map_err(|e| e.to_string())?;
+    if options.is_none() {
+        let guard = model.0.lock()?;
         if guard.is_some() {
             return Ok("Model already loaded".to_string());
         }
     }
+    let builder = options.map(EmbeddingModelOptions::builder).transpose()?;
 
     // Load the model (this might download it)
     // Run in blocking task since model loading is CPU-intensive
-    let loaded_model = tokio::task::spawn_blocking(|| {
-        EmbeddingModel::new()
+    let loaded_model = tokio::task::spawn_blocking(move || {
+        // Progress is best-effort; a failed emit shouldn't abort the download
+        let report = |progress: embeddings::DownloadProgress| {
+            app.emit("model-download-progress", progress).ok();
+        };
+        match builder {
+            Some(builder) => builder.build_with_progress(report),
+            None => EmbeddingModel::new_with_progress(report),
+        }
     })
     .await
-    .map_err(|e| format!("Task join error: {}", e))?
-    .map_err(|e| e.to_string())?;
+    .map_err(|e| CommandError::Internal(format!("Task join error: {}", e)))??;
+
+    // Before storing the model, so the database isn't locked while it is
+    if let Some(tokenizer) = loaded_model.tokenizer() {
+        let backfilled = backfill_token_counts(&*db.0.lock()?, tokenizer)?;
+        if backfilled > 0 {
+            info!(messages = backfilled, "Backfilled message token counts");
+        }
+    }
 
     // Store in state
-    let mut guard = model.0.lock().map_err(|e| e.to_string())?;
-    *guard = Some(loaded_model);
+    let mut guard = model.0.lock()?;
+    *guard = Some(Box::new(loaded_model));
 
     Ok("Model loaded successfully".to_string())
 }
 
+/// Counts the tokens of messages stored without a token count, with
+/// `tokenizer`. A message it fails on gets an estimate instead.
+fn backfill_token_counts(db: &Database, tokenizer: &Tokenizer) -> Result<usize, CommandError> {
+    let count = |text: &str| {
+        embeddings::count_tokens(tokenizer, text).unwrap_or_else(|_| estimate_tokens(text))
+    };
+    Ok(db.backfill_message_token_counts(count)?)
+}
+
+/// Use an OpenAI-compatible `/v1/embeddings` endpoint, such as Ollama's,
+/// instead of the local model.
+///
+/// Replaces whichever model is loaded, so this is also how to switch
+/// endpoints. A test sentence is embedded first, so a wrong URL or key
+/// fails here rather than on the next upload. Embeddings are stored under
+/// `config.model`; documents indexed with another model need reindexing.
+#[tauri::command]
+pub async fn init_remote_embedder(
+    model: State<'_, EmbeddingState>,
+    config: RemoteEmbedderConfig,
+) -> Result<String, CommandError> {
+    // reqwest's blocking client can't run on the async runtime's threads
+    let embedder = tokio::task::spawn_blocking(move || {
+        let embedder = RemoteEmbedder::new(config)?;
+        embedder.encode(SELF_TEST_SENTENCE)?;
+        Ok::<_, EmbeddingError>(embedder)
+    })
+    .await
+    .map_err(|e| CommandError::Internal(format!("Task join error: {}", e)))??;
+
+    let mut guard = model.0.lock()?;
+    *guard = Some(Box::new(embedder));
+
+    Ok("Remote embedder connected".to_string())
+}
+
+/// Sentence encoded by `test_embedding_model`.
+const SELF_TEST_SENTENCE: &str = "The quick brown fox jumps over the lazy dog.";
+
+/// How far from 1.0 the self-test vector's norm may be. The model
+/// normalizes its output, so anything further off means it's broken.
+const SELF_TEST_NORM_TOLERANCE: f32 = 1e-3;
+
+/// Result of `test_embedding_model`.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelSelfTest {
+    /// True if the vector is non-empty, finite and unit length
+    pub ok: bool,
+    /// Number of dimensions (384 for the default model)
+    pub dim: usize,
+    /// L2 norm of the vector
+    pub norm: f32,
+    /// Time taken to encode the sentence
+    pub elapsed_ms: u64,
+}
+
+/// Check that the embedding model works, for a "Test model" button.
+///
+/// Loads the model if it isn't yet (as `init_embedding_model` does, with
+/// download progress events), then encodes a fixed sentence. A model that
+/// fails to load returns an `Embedding` error; one that loads but produces
+/// a bad vector returns `ok: false`.
+#[tauri::command]
+pub async fn test_embedding_model(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    model: State<'_, EmbeddingState>,
+) -> Result<ModelSelfTest, CommandError> {
+    init_embedding_model(app, db, model.clone(), None).await?;
+
+    let guard = model.0.lock()?;
+    let embedding_model = guard.as_ref().ok_or_else(model_not_loaded)?;
+    run_self_test(|text| embedding_model.encode(text))
+}
+
+/// Does the work of `test_embedding_model` with the given encoder.
+fn run_self_test(
+    encode: impl FnOnce(&str) -> Result<Vec<f32>, EmbeddingError>,
+) -> Result<ModelSelfTest, CommandError> {
+    let start = std::time::Instant::now();
+    let embedding = encode(SELF_TEST_SENTENCE)?;
+    let elapsed_ms = start.elapsed().as_millis() as u64;
+
+    let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let ok = !embedding.is_empty()
+        && embedding.iter().all(|x| x.is_finite())
+        && (norm - 1.0).abs() < SELF_TEST_NORM_TOLERANCE;
+
+    Ok(ModelSelfTest {
+        ok,
+        dim: embedding.len(),
+        norm,
+        elapsed_ms,
+    })
+}
+
+/// Tokenizer used by `count_tokens` while the embedding model isn't loaded,
+/// with the ID of the model it belongs to.
+///
+/// Starts empty and is loaded on first use, which only needs the small
+/// `tokenizer.json` rather than the whole model.
+pub struct TokenizerState(pub Mutex<Option<(String, Tokenizer)>>);
+
+/// Counts the tokens in `text`, e.g. for a live count as the user types.
+///
+/// Uses the embedding model's tokenizer if the local model is loaded,
+/// otherwise a tokenizer loaded once on the first call and kept for later
+/// ones (also when a remote endpoint, which has no local tokenizer, is).
+/// `options` pick that tokenizer's model and cache directory, as for
+/// `init_embedding_model`.
+#[tauri::command]
+pub async fn count_tokens(
+    model: State<'_, EmbeddingState>,
+    tokenizer: State<'_, TokenizerState>,
+    text: String,
+    options: Option<EmbeddingModelOptions>,
+) -> Result<usize, CommandError> {
+    let options = options.unwrap_or_default();
+    let model_id = options.model_id.clone().unwrap_or_else(|| embeddings::MODEL_ID.to_string());
+    let builder = options.builder()?;
+    count_tokens_lazily(&model, &tokenizer, &model_id, || builder.load_tokenizer(), &text)
+}
+
+/// Does the work of `count_tokens`, calling `load` if no tokenizer for
+/// `model_id` is loaded yet.
+fn count_tokens_lazily(
+    model: &EmbeddingState,
+    tokenizer: &TokenizerState,
+    model_id: &str,
+    load: impl FnOnce() -> Result<Tokenizer, EmbeddingError>,
+    text: &str,
+) -> Result<usize, CommandError> {
+    if let Some(tokenizer) = model.0.lock()?.as_ref().and_then(|model| model.tokenizer()) {
+        return Ok(embeddings::count_tokens(tokenizer, text)?);
+    }
+
+    let mut cached = tokenizer.0.lock()?;
+    let tokenizer = match cached.as_mut() {
+        Some((id, tokenizer)) if id == model_id => tokenizer,
+        _ => &mut cached.insert((model_id.to_string(), load()?)).1,
+    };
+    Ok(embeddings::count_tokens(tokenizer, text)?)
+}
+
+/// Embed `text` as a document passage, for frontends that score vectors
+/// themselves.
+///
+/// With `raw` set the vector isn't L2-normalized, keeping the magnitude
+/// information some re-rankers use. Must call `init_embedding_model` first.
+#[tauri::command]
+pub async fn embed_text(
+    model: State<'_, EmbeddingState>,
+    text: String,
+    raw: Option<bool>,
+) -> Result<Vec<f32>, CommandError> {
+    let guard = model.0.lock()?;
+    let embedding_model = guard.as_ref().ok_or_else(model_not_loaded)?;
+    let embedding = if raw.unwrap_or(false) {
+        embedding_model.encode_raw(&text)?
+    } else {
+        embedding_model.encode(&text)?
+    };
+    Ok(embedding)
+}
+
 /// Check if the embedding model is loaded.
 #[tauri::command]
-pub fn is_model_loaded(model: State<'_, EmbeddingState>) -> Result<bool, String> {
-    let guard = model.0.lock().map_err(|e| e.to_string())?;
+pub fn is_model_loaded(model: State<'_, EmbeddingState>) -> Result<bool, CommandError> {
+    let guard = model.0.lock()?;
     Ok(guard.is_some())
 }
 
 /// Index a document by generating embeddings for all its chunks.
 ///
-/// Must call `init_embedding_model` first.
+/// Must call `init_embedding_model` first. If `operation_id` is given,
+/// indexing can be stopped with `cancel_operation`, keeping any
+/// embeddings the document already had.
 #[tauri::command]
 pub async fn index_document(
     db: State<'_, DbState>,
     model: State<'_, EmbeddingState>,
+    cancellation: State<'_, CancellationState>,
     document_id: String,
-) -> Result<usize, String> {
-    // Get the embedding model
-    let model_guard = model.0.lock().map_err(|e| e.to_string())?;
-    let embedding_model = model_guard
-        .as_ref()
-        .ok_or("Embedding model not loaded. Call init_embedding_model first.")?;
+    operation_id: Option<String>,
+) -> Result<usize, CommandError> {
+    let operation = operation_id
+        .as_deref()
+        .map(|op_id| cancellation.register(op_id))
+        .transpose()?;
 
     // Get all chunks for this document
-    let db_guard = db.0.lock().map_err(|e| e.to_string())?;
-    let chunks = chunker::get_document_chunks(&db_guard.conn, &document_id)
-        .map_err(|e| e.to_string())?;
+    let (chunks, normalize) = {
+        let db = db.0.lock()?;
+        let chunks = chunker::get_document_chunks(&db.conn, &document_id)?;
+        (chunks, db.get_settings()?.normalize_chunks)
+    };
 
     if chunks.is_empty() {
         return Ok(0);
     }
 
-    // Generate embeddings for all chunks
-    let texts: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
-    let embeddings = embedding_model
-        .encode_batch(&texts)
-        .map_err(|e| e.to_string())?;
-
-    // Save embeddings to database
-    for (chunk, embedding) in chunks.iter().zip(embeddings.iter()) {
-        vector_store::save_embedding(&db_guard.conn, &chunk.id, &document_id, embedding)
-            .map_err(|e| e.to_string())?;
+    let cancel = operation.as_ref().map(|op| op.flag());
+    let embeddings =
+        embed_chunks(&model, &chunks, normalize, cancel)?.ok_or_else(model_not_loaded)?;
+    if !replace_document_embeddings(&*db.0.lock()?, &document_id, &chunks, &embeddings)? {
+        return Err(CommandError::InvalidInput(format!(
+            "Document {} changed while it was being indexed",
+            document_id
+        )));
     }
 
     let count = chunks.len();
-    println!(
-        "Indexed document {} with {} chunk embeddings",
-        document_id, count
-    );
+    info!(document_id, embeddings = count, "Indexed document");
 
     Ok(count)
 }
@@ -444,101 +2606,1297 @@ pub async fn index_document(
 /// Search for chunks similar to a query.
 ///
 /// Returns the top k most similar chunks across all documents.
+/// `top_k` defaults to the `retrieval_top_k` setting. For similarity
+/// metrics, results scoring below the `min_score` setting are dropped.
+/// `metric` defaults to the dot product, which suits this model's
+/// normalized vectors. Scores are mapped to [0, 1] when the
+/// `normalize_scores` setting is on. With `explain`, each result also lists
+/// the query keywords its chunk contains.
 #[tauri::command]
 pub async fn search_documents(
     db: State<'_, DbState>,
     model: State<'_, EmbeddingState>,
+    index: State<'_, IndexState>,
     query: String,
     top_k: Option<usize>,
-) -> Result<Vec<SearchResult>, String> {
-    let k = top_k.unwrap_or(5);
-
-    // Get the embedding model
-    let model_guard = model.0.lock().map_err(|e| e.to_string())?;
-    let embedding_model = model_guard
-        .as_ref()
-        .ok_or("Embedding model not loaded. Call init_embedding_model first.")?;
-
-    // Embed the query
-    let query_embedding = embedding_model
-        .encode(&query)
-        .map_err(|e| e.to_string())?;
+    metric: Option<SimilarityMetric>,
+    explain: Option<bool>,
+) -> Result<Vec<SearchResult>, CommandError> {
+    // Embed the query (with the model's query prefix, if any), then
+    // release the model before searching
+    let (query_embedding, model_id) = {
+        let model_guard = model.0.lock()?;
+        let embedding_model = model_guard.as_ref().ok_or_else(model_not_loaded)?;
+        (embedding_model.encode_query(&query)?, embedding_model.model_id().to_string())
+    };
 
     // Search for similar chunks
-    let db_guard = db.0.lock().map_err(|e| e.to_string())?;
-    let results = vector_store::search_similar(&db_guard.conn, &query_embedding, k)
-        .map_err(|e| e.to_string())?;
+    let db_guard = db.0.lock()?;
+    let settings = db_guard.get_settings()?;
+    let metric = metric.unwrap_or_default();
+    let results = index.search(
+        &db_guard,
+        &query_embedding,
+        top_k.unwrap_or(settings.retrieval_top_k),
+        &model_id,
+        metric,
+    )?;
+
+    let mut results = apply_min_score(results, metric, settings.min_score);
+    normalize_scores(results.iter_mut().map(|r| &mut r.score), metric, &settings);
+    if explain.unwrap_or(false) {
+        vector_store::explain_results(&mut results, &query);
+    }
+    Ok(results)
+}
+
+/// Rank whole documents by relevance to a query, rather than chunks.
+///
+/// Each document's chunk scores are combined by `aggregation` (the best
+/// chunk's score by default); see `DocumentAggregation`. `top_k` defaults
+/// to the `retrieval_top_k` setting and `metric` to the dot product. Scores
+/// are mapped to [0, 1] when the `normalize_scores` setting is on.
+#[tauri::command]
+pub async fn nearest_documents(
+    db: State<'_, DbState>,
+    model: State<'_, EmbeddingState>,
+    query: String,
+    top_k: Option<usize>,
+    metric: Option<SimilarityMetric>,
+    aggregation: Option<DocumentAggregation>,
+) -> Result<Vec<DocumentSearchResult>, CommandError> {
+    // Release the model before locking the database, as `search_documents` does
+    let (query_embedding, model_id) = {
+        let model_guard = model.0.lock()?;
+        let embedding_model = model_guard.as_ref().ok_or_else(model_not_loaded)?;
+        (embedding_model.encode_query(&query)?, embedding_model.model_id().to_string())
+    };
 
+    let db_guard = db.0.lock()?;
+    let settings = db_guard.get_settings()?;
+    let metric = metric.unwrap_or_default();
+    let mut results = vector_store::search_documents_aggregated(
+        &db_guard.conn,
+        &query_embedding,
+        top_k.unwrap_or(settings.retrieval_top_k),
+        &model_id,
+        metric,
+        aggregation.unwrap_or_default(),
+    )?;
+
+    normalize_scores(results.iter_mut().map(|r| &mut r.score), metric, &settings);
     Ok(results)
 }
 
+/// Rank embeddings the frontend already holds (e.g. from `embed_text`)
+/// against `query`, without touching the database.
+///
+/// Returns the best `k` as `(id, cosine similarity)` pairs, best first.
+/// Candidates with a different dimension than `query` are skipped.
+#[tauri::command]
+pub fn rank_embeddings(
+    query: Vec<f32>,
+    candidates: Vec<(String, Vec<f32>)>,
+    k: usize,
+) -> Result<Vec<(String, f32)>, CommandError> {
+    Ok(vector_store::rank_embeddings(&query, &candidates, k))
+}
+
+/// Pairwise similarities between a document's chunks, from `chunk_similarities`.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkSimilarities {
+    /// The document's embedded chunks in order; row and column `i` of
+    /// `matrix` belong to `chunk_ids[i]`
+    pub chunk_ids: Vec<String>,
+    /// Cosine similarities, symmetric with 1.0 on the diagonal
+    pub matrix: Vec<Vec<f32>>,
+}
+
+/// Compare every chunk of a document with every other, e.g. to show
+/// "related chunks" or cluster a long document into sections.
+///
+/// Uses the stored embeddings, so the model needn't be loaded. Chunks
+/// without an embedding are left out.
+#[tauri::command]
+pub fn chunk_similarities(
+    db: State<'_, DbState>,
+    document_id: String,
+) -> Result<ChunkSimilarities, CommandError> {
+    let db = db.0.lock()?;
+    document_chunk_similarities(&db, &document_id)
+}
+
+/// Does the work of `chunk_similarities`.
+fn document_chunk_similarities(
+    db: &Database,
+    document_id: &str,
+) -> Result<ChunkSimilarities, CommandError> {
+    let mut chunk_ids = Vec::new();
+    let mut embeddings = Vec::new();
+    for chunk in chunker::get_document_chunks(&db.conn, document_id)? {
+        if let Some(embedding) = vector_store::get_embedding(&db.conn, &chunk.id)? {
+            chunk_ids.push(chunk.id);
+            embeddings.push(embedding);
+        }
+    }
+
+    // Chunks embedded by different models can't be compared
+    if embeddings.windows(2).any(|pair| pair[0].len() != pair[1].len()) {
+        return Err(CommandError::InvalidInput(format!(
+            "Document {} has embeddings of different dimensions; reindex it first",
+            document_id
+        )));
+    }
+
+    Ok(ChunkSimilarities {
+        chunk_ids,
+        matrix: embeddings::similarity_matrix(&embeddings),
+    })
+}
+
+/// Drops results scoring below `min_score`.
+///
+/// Only applies to similarity metrics; Euclidean scores are distances,
+/// where the threshold has no meaning.
+fn apply_min_score(
+    results: Vec<SearchResult>,
+    metric: SimilarityMetric,
+    min_score: f32,
+) -> Vec<SearchResult> {
+    if !metric.higher_is_better() {
+        return results;
+    }
+    results.into_iter().filter(|r| r.score >= min_score).collect()
+}
+
+/// Maps chunk or document scores to [0, 1] if the `normalize_scores`
+/// setting is on. Runs after `apply_min_score`, whose threshold is in raw
+/// units.
+fn normalize_scores<'a>(
+    scores: impl IntoIterator<Item = &'a mut f32>,
+    metric: SimilarityMetric,
+    settings: &Settings,
+) {
+    if settings.normalize_scores {
+        for score in scores {
+            *score = vector_store::normalize_score(*score, metric);
+        }
+    }
+}
+
 /// Get embedding statistics.
 #[tauri::command]
-pub fn get_embedding_stats(db: State<'_, DbState>) -> Result<(usize, usize), String> {
-    let db = db.0.lock().map_err(|e| e.to_string())?;
-    vector_store::get_embedding_stats(&db.conn).map_err(|e| e.to_string())
+pub fn get_embedding_stats(db: State<'_, DbState>) -> Result<(usize, usize), CommandError> {
+    let db = db.0.lock()?;
+    vector_store::get_embedding_stats(&db.conn).map_err(CommandError::from)
+}
+
+/// Combined health of the RAG index, for the diagnostics panel.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RagStatus {
+    pub document_count: usize,
+    pub chunk_count: usize,
+    pub embedding_count: usize,
+    /// Chunks without an embedding (not searchable yet)
+    pub unembedded_chunks: usize,
+    /// True when every chunk has an embedding
+    pub fully_indexed: bool,
+}
+
+/// Get document, chunk and embedding counts plus orphan detection in one call.
+#[tauri::command]
+pub fn rag_status(db: State<'_, DbState>) -> Result<RagStatus, CommandError> {
+    let db = db.0.lock()?;
+
+    let document_count = documents::count_documents(&db.conn)?;
+    let (chunk_count, _) = chunker::get_chunk_stats(&db.conn)?;
+    let (embedding_count, _) = vector_store::get_embedding_stats(&db.conn)?;
+    let unembedded_chunks = vector_store::count_unembedded_chunks(&db.conn)?;
+
+    Ok(RagStatus {
+        document_count,
+        chunk_count,
+        embedding_count,
+        unembedded_chunks,
+        fully_indexed: unembedded_chunks == 0,
+    })
+}
+
+/// Remove embeddings and chunks left dangling by partial failures.
+#[tauri::command]
+pub fn repair_integrity(db: State<'_, DbState>) -> Result<crate::db::RepairReport, CommandError> {
+    let db = db.0.lock()?;
+    db.repair_integrity().map_err(CommandError::from)
+}
+
+/// Compact the database file and refresh query statistics.
+///
+/// Returns the size before and after, so the UI can show reclaimed space.
+#[tauri::command]
+pub fn optimize_database(db: State<'_, DbState>) -> Result<crate::db::OptimizeReport, CommandError> {
+    let db = db.0.lock()?;
+    db.optimize().map_err(CommandError::from)
 }
 
 /// Index all documents that don't have embeddings yet.
 ///
 /// Useful for indexing documents uploaded before the model was loaded,
 /// or after upgrading the app.
+///
+/// If `operation_id` is given, indexing can be stopped with `cancel_operation`;
+/// documents already indexed keep their embeddings.
 #[tauri::command]
 pub async fn index_all_documents(
     db: State<'_, DbState>,
     model: State<'_, EmbeddingState>,
-) -> Result<(usize, usize), String> {
-    // Get the embedding model
-    let model_guard = model.0.lock().map_err(|e| e.to_string())?;
-    let embedding_model = model_guard
-        .as_ref()
-        .ok_or("Embedding model not loaded. Call init_embedding_model first.")?;
+    cancellation: State<'_, CancellationState>,
+    operation_id: Option<String>,
+) -> Result<(usize, usize), CommandError> {
+    let operation = operation_id
+        .as_deref()
+        .map(|op_id| cancellation.register(op_id))
+        .transpose()?;
+    index_unindexed_documents(&db, &model, operation.as_ref().map(|op| op.flag()))
+}
 
-    let db_guard = db.0.lock().map_err(|e| e.to_string())?;
+/// Does the work of `index_all_documents`.
+///
+/// Each document is embedded without the database locked, then its
+/// embeddings are saved in their own transaction.
+fn index_unindexed_documents(
+    db: &DbState,
+    model: &EmbeddingState,
+    cancel: Option<&AtomicBool>,
+) -> Result<(usize, usize), CommandError> {
+    if model.0.lock()?.is_none() {
+        return Err(model_not_loaded());
+    }
 
     // Get all documents
-    let docs = documents::get_all_documents(&db_guard.conn).map_err(|e| e.to_string())?;
+    let (docs, normalize) = {
+        let db = db.0.lock()?;
+        (documents::get_all_documents(&db.conn)?, db.get_settings()?.normalize_chunks)
+    };
 
     let mut total_chunks = 0;
     let mut docs_indexed = 0;
 
     for doc in &docs {
         // Get chunks for this document
-        let chunks = chunker::get_document_chunks(&db_guard.conn, &doc.id)
-            .map_err(|e| e.to_string())?;
+        let chunks = {
+            let db = db.0.lock()?;
+            let chunks = chunker::get_document_chunks(&db.conn, &doc.id)?;
+
+            // Check if first chunk already has embedding (skip if already indexed)
+            match chunks.first() {
+                Some(first) if !vector_store::has_embedding(&db.conn, &first.id)? => chunks,
+                _ => continue,
+            }
+        };
 
-        if chunks.is_empty() {
+        let embeddings =
+            embed_chunks(model, &chunks, normalize, cancel)?.ok_or_else(model_not_loaded)?;
+        if !replace_document_embeddings(&*db.0.lock()?, &doc.id, &chunks, &embeddings)? {
+            warn!(name = %doc.name, "Document changed while it was being indexed; skipped");
             continue;
         }
 
-        // Check if first chunk already has embedding (skip if already indexed)
-        if vector_store::has_embedding(&db_guard.conn, &chunks[0].id)
-            .map_err(|e| e.to_string())?
+        total_chunks += chunks.len();
+        docs_indexed += 1;
+        info!(name = %doc.name, chunks = chunks.len(), "Indexed document");
+    }
+
+    info!(documents = docs_indexed, chunks = total_chunks, "Indexing complete");
+
+    Ok((docs_indexed, total_chunks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::MockEmbedder;
+
+    fn user_message(chat_id: &str, content: &str) -> Message {
+        Message {
+            id: Uuid::new_v4().to_string(),
+            chat_id: chat_id.to_string(),
+            role: "user".to_string(),
+            content: content.to_string(),
+            timestamp: Utc::now(),
+            sources: None,
+        }
+    }
+
+    #[test]
+    fn test_cancel_stops_batches_and_cleans_up() {
+        let state = CancellationState::default();
+        let items: Vec<usize> = (0..10).collect();
+        let mut processed = Vec::new();
+
         {
-            continue;
+            let operation = state.register("op-1").unwrap();
+            assert!(state.0.lock().unwrap().contains_key("op-1"));
+
+            let result = for_each_batch(&items, 2, Some(operation.flag()), |batch| {
+                processed.extend_from_slice(batch);
+                // Cancel from "the frontend" after the second batch
+                if processed.len() == 4 {
+                    assert!(state.cancel("op-1").unwrap());
+                }
+                Ok(())
+            });
+
+            assert!(matches!(result, Err(CommandError::Cancelled)));
         }
 
-        // Generate embeddings for all chunks
-        let texts: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
-        let embeddings = embedding_model
-            .encode_batch(&texts)
-            .map_err(|e| e.to_string())?;
+        // Stopped after two batches, and the guard removed the entry
+        assert_eq!(processed, vec![0, 1, 2, 3]);
+        assert!(state.0.lock().unwrap().is_empty());
+        assert!(!state.cancel("op-1").unwrap());
+    }
+
+    #[test]
+    fn test_batches_run_to_completion_without_cancel() {
+        let items: Vec<usize> = (0..5).collect();
+        let mut count = 0;
+        for_each_batch(&items, 2, None, |batch| {
+            count += batch.len();
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn test_suggest_chat_title_truncates_at_word_boundary() {
+        assert_eq!(suggest_chat_title("What is Rust?"), "What is Rust");
+        assert_eq!(
+            suggest_chat_title("How do I configure the chunk size for large documents please"),
+            "How do I configure the chunk"
+        );
+        // The character budget cuts before the word that would overflow it
+        assert_eq!(
+            suggest_chat_title("Internationalization considerations regarding documentation"),
+            "Internationalization considerations"
+        );
+        assert_eq!(suggest_chat_title("  multiple\n\nlines   here.  "), "multiple lines here");
+        assert_eq!(suggest_chat_title("?!"), DEFAULT_CHAT_TITLE);
+        assert_eq!(suggest_chat_title(&"x".repeat(60)).chars().count(), TITLE_MAX_CHARS);
+    }
+
+    #[test]
+    fn test_auto_title_only_replaces_default_title() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("default", DEFAULT_CHAT_TITLE).unwrap();
+        db.create_chat("custom", "My custom title").unwrap();
 
-        // Save embeddings
-        for (chunk, embedding) in chunks.iter().zip(embeddings.iter()) {
-            vector_store::save_embedding(&db_guard.conn, &chunk.id, &doc.id, embedding)
-                .map_err(|e| e.to_string())?;
+        for chat_id in ["default", "custom"] {
+            let message = user_message(chat_id, "Explain the borrow checker to me.");
+            db.add_message(&message).unwrap();
+            auto_title_chat(&db, &message).unwrap();
         }
 
-        total_chunks += chunks.len();
-        docs_indexed += 1;
-        println!("Indexed document: {} ({} chunks)", doc.name, chunks.len());
+        assert_eq!(
+            db.get_chat_title("default").unwrap().as_deref(),
+            Some("Explain the borrow checker to me")
+        );
+        assert_eq!(db.get_chat_title("custom").unwrap().as_deref(), Some("My custom title"));
+
+        // Later messages don't retitle an auto-titled chat
+        let message = user_message("default", "Something else entirely");
+        auto_title_chat(&db, &message).unwrap();
+        assert_eq!(
+            db.get_chat_title("default").unwrap().as_deref(),
+            Some("Explain the borrow checker to me")
+        );
     }
 
-    println!(
-        "Indexing complete: {} documents, {} chunks",
-        docs_indexed, total_chunks
-    );
+    #[test]
+    fn test_chat_stores_both_messages() {
+        let db = DbState(Mutex::new(Database::new(":memory:").unwrap()));
+        db.0.lock().unwrap().create_chat("chat-1", DEFAULT_CHAT_TITLE).unwrap();
+        let model = EmbeddingState(Mutex::new(None));
+        let index = IndexState::default();
+
+        let reply = chat_turn(&db, &model, &index, "chat-1", "What is ownership?").unwrap();
+        assert_eq!(reply.role, "assistant");
+        assert_eq!(reply.chat_id, "chat-1");
+        // No model loaded, so nothing to cite
+        assert!(reply.sources.is_none());
+
+        let chat = db.0.lock().unwrap().get_chat("chat-1").unwrap().unwrap();
+        let roles: Vec<&str> = chat.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant"]);
+        assert_eq!(chat.messages[0].content, "What is ownership?");
+        assert_eq!(chat.messages[1].id, reply.id);
+        // The user message titles the chat as `add_message` would
+        assert_eq!(chat.title, "What is ownership");
+
+        // A missing chat stores nothing
+        assert!(matches!(
+            chat_turn(&db, &model, &index, "missing", "Hello"),
+            Err(CommandError::NotFound(_))
+        ));
+        assert!(matches!(
+            chat_turn(&db, &model, &index, "chat-1", "  "),
+            Err(CommandError::InvalidInput(_))
+        ));
+        let chat = db.0.lock().unwrap().get_chat("chat-1").unwrap().unwrap();
+        assert_eq!(chat.messages.len(), 2);
+    }
 
-    Ok((docs_indexed, total_chunks))
+    #[test]
+    fn test_create_chat_title() {
+        let db = Database::new(":memory:").unwrap();
+
+        let chat = new_chat(&db, Some("  Trip planning ".to_string())).unwrap();
+        assert_eq!(chat.title, "Trip planning");
+        assert_eq!(db.get_chat_title(&chat.id).unwrap().as_deref(), Some("Trip planning"));
+
+        for title in [None, Some("   ".to_string())] {
+            let chat = new_chat(&db, title).unwrap();
+            assert_eq!(chat.title, DEFAULT_CHAT_TITLE);
+            assert_eq!(db.get_chat_title(&chat.id).unwrap().as_deref(), Some(DEFAULT_CHAT_TITLE));
+        }
+    }
+
+    #[test]
+    fn test_auto_title_ignores_assistant_messages() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", DEFAULT_CHAT_TITLE).unwrap();
+
+        let mut message = user_message("chat-1", "Hello there");
+        message.role = "assistant".to_string();
+        auto_title_chat(&db, &message).unwrap();
+
+        assert_eq!(db.get_chat_title("chat-1").unwrap().as_deref(), Some(DEFAULT_CHAT_TITLE));
+    }
+
+    #[test]
+    fn test_missing_chat_error_is_not_found() {
+        let db = Database::new(":memory:").unwrap();
+        let input = AddMessageInput {
+            chat_id: "missing".to_string(),
+            role: "user".to_string(),
+            content: "Hello".to_string(),
+            sources: None,
+        };
+
+        let err = add_message_to_chat(&db, input, &MessageMetrics::default()).unwrap_err();
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["kind"], "NotFound");
+        assert_eq!(json["message"], "Chat not found: missing");
+    }
+
+    #[test]
+    fn test_add_message_doesnt_wait_for_busy_model() {
+        let db = DbState(Mutex::new(Database::new(":memory:").unwrap()));
+        db.0.lock().unwrap().create_chat("chat-1", DEFAULT_CHAT_TITLE).unwrap();
+        let model = EmbeddingState(Mutex::new(None));
+
+        // Another command holds the model: the count is estimated instead
+        let busy = model.0.lock().unwrap();
+        assert!(next_user_message_is_first(&db, "chat-1").unwrap());
+        let metrics = MessageMetrics::measure_if_idle(&model, "Hello there", true).unwrap();
+        drop(busy);
+        assert_eq!(metrics.tokens, None);
+        assert_eq!(metrics.tokens("12345678"), 2);
+
+        // Only the chat's first user message keeps its embedding
+        let db = db.0.lock().unwrap();
+        let embedded = MessageMetrics {
+            tokens: Some(3),
+            first_message_embedding: Some(vec![1.0, 0.0]),
+        };
+        let input = |content: &str| AddMessageInput {
+            chat_id: "chat-1".to_string(),
+            role: "user".to_string(),
+            content: content.to_string(),
+            sources: None,
+        };
+        add_message_to_chat(&db, input("Hello there"), &embedded).unwrap();
+        let embedded = MessageMetrics {
+            first_message_embedding: Some(vec![0.0, 1.0]),
+            ..embedded
+        };
+        add_message_to_chat(&db, input("Anyone?"), &embedded).unwrap();
+
+        let similar = db.find_similar_chats_by_first_message(&[1.0, 0.0], 0.99).unwrap();
+        assert_eq!(similar.len(), 1);
+        assert_eq!(db.get_chat_token_total("chat-1").unwrap(), 6);
+    }
+
+    /// Records sent events instead of emitting them.
+    #[derive(Default)]
+    struct RecordingSink(std::cell::RefCell<Vec<(String, serde_json::Value)>>);
+
+    impl EventSink for RecordingSink {
+        fn send<S: Serialize + Clone>(&self, event: &str, payload: S) {
+            let payload = serde_json::to_value(payload).unwrap();
+            self.0.borrow_mut().push((event.to_string(), payload));
+        }
+    }
+
+    /// Pasted notes long enough to span several 300-character chunks.
+    const PASTED_NOTES: &str = "Sourdough starter needs feeding twice a day with equal weights \
+        of flour and water. Keep it somewhere warm and discard half before each feeding.\r\n\r\n\
+        The tomato seedlings went into the raised bed in May. They need staking once they \
+        reach knee height, and watering at the base rather than on the leaves.\r\n\r\n\
+        Bike maintenance: clean and lube the chain every few hundred kilometres, check tyre \
+        pressure weekly, and replace brake pads when the grooves wear away.";
+
+    /// A database whose settings chunk `PASTED_NOTES` into several chunks.
+    fn small_chunk_db() -> DbState {
+        let db = Database::in_memory().unwrap();
+        let settings = Settings {
+            chunk_size: 300,
+            ..db.get_settings().unwrap()
+        };
+        db.update_settings(&settings).unwrap();
+        DbState(Mutex::new(db))
+    }
+
+    #[test]
+    fn test_ingest_pasted_text() {
+        let db = small_chunk_db();
+        let events = RecordingSink::default();
+
+        let doc = ingest_pasted_text(&db, None, &events, " Notes ", PASTED_NOTES).unwrap();
+        assert_eq!(doc.name, "Notes");
+        assert_eq!(doc.doc_type, "txt");
+
+        let db = db.0.lock().unwrap();
+        // Stored with normalized line endings, so paragraphs survive
+        let content = documents::get_document_content(&db.conn, &doc.id).unwrap().unwrap();
+        assert_eq!(content.matches("\n\n").count(), 2);
+        assert!(!content.contains('\r'));
+
+        let chunks = chunker::get_document_chunks(&db.conn, &doc.id).unwrap();
+        assert!(chunks.len() > 1);
+        let sent = events.0.borrow();
+        assert_eq!(sent[0].0, "document-ingested");
+        assert_eq!(sent[0].1["chunkCount"], chunks.len());
+        assert_eq!(sent[0].1["embeddingCount"], 0);
+
+        assert!(matches!(
+            ingest_pasted_text(&small_chunk_db(), None, &events, "Empty", " \n "),
+            Err(CommandError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_ingest_bytes_without_a_file() {
+        let db = small_chunk_db();
+        let events = RecordingSink::default();
+        let bytes = PASTED_NOTES.as_bytes();
+
+        let loaded = documents::load_document_from_bytes(bytes, "notes.md", "doc-1").unwrap();
+        let doc = ingest_in_memory(&db, Some(&MockEmbedder), &events, loaded).unwrap();
+        assert_eq!((doc.name.as_str(), doc.doc_type.as_str()), ("notes.md", "md"));
+
+        let db = db.0.lock().unwrap();
+        let chunks = chunker::get_document_chunks(&db.conn, &doc.id).unwrap();
+        assert!(chunks.len() > 1);
+        assert_eq!(events.0.borrow()[0].1["embeddingCount"], chunks.len());
+    }
+
+    #[test]
+    fn test_ingest_with_mock_embedder() {
+        let db = small_chunk_db();
+        let events = RecordingSink::default();
+
+        let doc =
+            ingest_pasted_text(&db, Some(&MockEmbedder), &events, "Notes", PASTED_NOTES).unwrap();
+        let chunk_count = events.0.borrow()[0].1["chunkCount"].as_u64().unwrap();
+        assert_eq!(events.0.borrow()[0].1["embeddingCount"], chunk_count);
+
+        let db = db.0.lock().unwrap();
+        let (total, _) = vector_store::get_embedding_stats(&db.conn).unwrap();
+        assert_eq!(total as u64, chunk_count);
+
+        let query = MockEmbedder.encode_query("when to lube the bike chain").unwrap();
+        let results = vector_store::search_similar(
+            &db.conn,
+            &query,
+            1,
+            MockEmbedder.model_id(),
+            SimilarityMetric::default(),
+        )
+        .unwrap();
+        assert_eq!(results[0].document_id, doc.id);
+        assert!(results[0].content.contains("chain"));
+
+        // Retrieval goes through the same trait (and may stitch on neighbours)
+        let index = IndexState::default();
+        let sources = retrieve_sources(&db, &index, &query, MockEmbedder.model_id()).unwrap();
+        assert!(sources[0].chunk.contains(&results[0].content));
+    }
+
+    #[test]
+    fn test_embeddings_use_normalized_text() {
+        let db = small_chunk_db();
+        let events = RecordingSink::default();
+        let text = "Each chunk is turned into an embed-\nding   before   storage.";
+
+        let doc = ingest_pasted_text(&db, Some(&MockEmbedder), &events, "Notes", text).unwrap();
+        let db = db.0.lock().unwrap();
+        let chunk = &chunker::get_document_chunks(&db.conn, &doc.id).unwrap()[0];
+        // The stored chunk keeps the extracted text
+        assert!(chunk.content.contains("embed-\nding   before"));
+
+        let stored = vector_store::get_embedding(&db.conn, &chunk.id).unwrap().unwrap();
+        let normalized = "Each chunk is turned into an embedding before storage.";
+        assert_eq!(stored, MockEmbedder.encode(normalized).unwrap());
+        assert_ne!(stored, MockEmbedder.encode(&chunk.content).unwrap());
+    }
+
+    #[test]
+    fn test_retrieve_sources_merges_adjacent_chunks() {
+        let db = small_chunk_db();
+        let events = RecordingSink::default();
+        let doc =
+            ingest_pasted_text(&db, Some(&MockEmbedder), &events, "Notes", PASTED_NOTES).unwrap();
+
+        let db = db.0.lock().unwrap();
+        let chunk_count = chunker::get_document_chunks(&db.conn, &doc.id).unwrap().len();
+        let settings = Settings {
+            retrieval_top_k: chunk_count,
+            min_score: -1.0,
+            ..db.get_settings().unwrap()
+        };
+        db.update_settings(&settings).unwrap();
+
+        // Every chunk matches, and together they cover the whole document
+        let query = MockEmbedder.encode_query(PASTED_NOTES).unwrap();
+        let index = IndexState::default();
+        let sources = retrieve_sources(&db, &index, &query, MockEmbedder.model_id()).unwrap();
+        let content = documents::get_document_content(&db.conn, &doc.id).unwrap().unwrap();
+        assert_eq!(sources.len(), 1);
+        assert_eq!(sources[0].chunk, content);
+    }
+
+    #[test]
+    fn test_index_state_follows_database() {
+        let dir = std::env::temp_dir().join(format!("localchatbot-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let snapshot = dir.join("vector_index.bin");
+        let db = small_chunk_db();
+        let events = RecordingSink::default();
+        let query = MockEmbedder.encode_query("tyre pressure and brake pads").unwrap();
+        let (model_id, metric) = (MockEmbedder.model_id(), SimilarityMetric::Dot);
+        let search = |index: &IndexState| {
+            let db = db.0.lock().unwrap();
+            let results = index.search(&db, &query, 3, model_id, metric);
+            let expected =
+                vector_store::search_similar(&db.conn, &query, 3, model_id, metric).unwrap();
+            let ids = |results: &[SearchResult]| -> Vec<String> {
+                results.iter().map(|r| r.chunk_id.clone()).collect()
+            };
+            assert_eq!(ids(&results.unwrap()), ids(&expected));
+            expected.len()
+        };
+
+        let index = IndexState::with_snapshot(snapshot.clone());
+        assert_eq!(search(&index), 0);
+        assert!(!snapshot.exists());
+        ingest_pasted_text(&db, Some(&MockEmbedder), &events, "Notes", PASTED_NOTES).unwrap();
+        // Rebuilt now that there are embeddings, and saved for next time
+        assert_eq!(search(&index), 3);
+        assert!(snapshot.is_file());
+        assert_eq!(search(&IndexState::with_snapshot(snapshot.clone())), 3);
+
+        index.clear().unwrap();
+        assert!(!snapshot.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_incognito_session_discards_its_data() {
+        let fixture = IngestFixture::new("incognito");
+        let (db, paths) = (&fixture.db, &fixture.paths);
+        let (index, incognito) = (IndexState::default(), IncognitoState::default());
+        db.0.lock().unwrap().create_chat("kept", "Kept").unwrap();
+        let file = fixture.write_file("kept.txt", "Stays on disk.");
+        let kept = fixture.ingest(&file, &IngestOptions::default());
+
+        assert!(begin_incognito(db, &index, &incognito).unwrap());
+        assert!(!begin_incognito(db, &index, &incognito).unwrap());
+        {
+            let db = db.0.lock().unwrap();
+            assert!(db.get_chat("kept").unwrap().is_none());
+            assert!(documents::get_all_documents(&db.conn).unwrap().is_empty());
+            db.create_chat("private", "Private").unwrap();
+        }
+        let file = fixture.write_file("private.txt", "Gone after the session.");
+        fixture.ingest(&file, &IngestOptions::default());
+        assert_eq!(std::fs::read_dir(&paths.documents_dir).unwrap().count(), 2);
+
+        assert!(finish_incognito(db, &index, &incognito, paths).unwrap());
+        assert!(!finish_incognito(db, &index, &incognito, paths).unwrap());
+        let db = db.0.lock().unwrap();
+        assert!(db.get_chat("kept").unwrap().is_some());
+        assert!(db.get_chat("private").unwrap().is_none());
+        let docs = documents::get_all_documents(&db.conn).unwrap();
+        assert_eq!(docs.iter().map(|d| &d.id).collect::<Vec<_>>(), [&kept.id]);
+        // Only the private document's stored copy was removed
+        assert_eq!(std::fs::read_dir(&paths.documents_dir).unwrap().count(), 1);
+        assert!(Path::new(&docs[0].path).is_file());
+    }
+
+    #[test]
+    fn test_index_and_reindex_replace_embeddings() {
+        let db = small_chunk_db();
+        let model = EmbeddingState(Mutex::new(Some(Box::new(MockEmbedder))));
+        let events = RecordingSink::default();
+        let doc = ingest_pasted_text(&db, None, &events, "Notes", PASTED_NOTES).unwrap();
+        let stored = || {
+            let db = db.0.lock().unwrap();
+            let chunks = chunker::get_document_chunks(&db.conn, &doc.id).unwrap();
+            let embeddings: usize = db
+                .conn
+                .query_row(
+                    "SELECT COUNT(*) FROM embeddings WHERE document_id = ?1",
+                    [&doc.id],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            (chunks.into_iter().map(|c| c.id).collect::<Vec<_>>(), embeddings)
+        };
+        let (chunk_ids, embeddings) = stored();
+        assert_eq!(embeddings, 0);
+
+        let no_model = EmbeddingState(Mutex::new(None));
+        assert!(matches!(
+            index_unindexed_documents(&db, &no_model, None),
+            Err(CommandError::Embedding(_))
+        ));
+        let indexed = index_unindexed_documents(&db, &model, None).unwrap();
+        assert_eq!(indexed, (1, chunk_ids.len()));
+        assert_eq!(index_unindexed_documents(&db, &model, None).unwrap(), (0, 0));
+        assert_eq!(stored(), (chunk_ids.clone(), chunk_ids.len()));
+
+        // A cancelled reindex leaves the old chunks and embeddings in place
+        let config = ChunkConfig::new(120, 20).unwrap();
+        let cancelled = AtomicBool::new(true);
+        let cancelled =
+            rechunk_and_embed(&db, &model, &doc.id, Some(config.clone()), Some(&cancelled));
+        assert!(matches!(cancelled, Err(CommandError::Cancelled)));
+        assert_eq!(stored(), (chunk_ids.clone(), chunk_ids.len()));
+
+        let count = rechunk_and_embed(&db, &model, &doc.id, Some(config), None).unwrap();
+        let (new_ids, embeddings) = stored();
+        assert_ne!(new_ids, chunk_ids);
+        assert_eq!((new_ids.len(), embeddings), (count, count));
+    }
+
+    #[test]
+    fn test_chunk_similarities() {
+        let db = small_chunk_db();
+        let events = RecordingSink::default();
+        let doc =
+            ingest_pasted_text(&db, Some(&MockEmbedder), &events, "Notes", PASTED_NOTES).unwrap();
+
+        let db = db.0.lock().unwrap();
+        let chunks = chunker::get_document_chunks(&db.conn, &doc.id).unwrap();
+        let similarities = document_chunk_similarities(&db, &doc.id).unwrap();
+        let chunk_ids: Vec<&str> = chunks.iter().map(|chunk| chunk.id.as_str()).collect();
+        assert_eq!(similarities.chunk_ids, chunk_ids);
+
+        let matrix = &similarities.matrix;
+        assert_eq!(matrix.len(), chunks.len());
+        for (i, row) in matrix.iter().enumerate() {
+            assert!((row[i] - 1.0).abs() < 1e-5);
+            for (j, score) in row.iter().enumerate() {
+                assert_eq!(*score, matrix[j][i]);
+            }
+        }
+
+        // A chunk from another model's space can't be compared
+        vector_store::save_embedding(&db.conn, &chunks[0].id, &doc.id, &[1.0, 0.0], "other")
+            .unwrap();
+        assert!(matches!(
+            document_chunk_similarities(&db, &doc.id),
+            Err(CommandError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    #[ignore] // Requires model download, run with: cargo test -- --ignored
+    fn test_ingest_pasted_text_is_searchable() {
+        let db = small_chunk_db();
+        let model = EmbeddingModel::new().unwrap();
+        let events = RecordingSink::default();
+
+        let doc = ingest_pasted_text(&db, Some(&model), &events, "Notes", PASTED_NOTES).unwrap();
+        let chunk_count = events.0.borrow()[0].1["chunkCount"].as_u64().unwrap();
+        assert_eq!(events.0.borrow()[0].1["embeddingCount"], chunk_count);
+
+        let query = model.encode_query("How often should I oil my bicycle chain?").unwrap();
+        let db = db.0.lock().unwrap();
+        let results = vector_store::search_similar(
+            &db.conn,
+            &query,
+            1,
+            model.model_id(),
+            SimilarityMetric::default(),
+        )
+        .unwrap();
+        assert_eq!(results[0].document_id, doc.id);
+        assert!(results[0].content.contains("chain"));
+    }
+
+    /// A temp directory with a document store inside it, and the state
+    /// `ingest_document` needs, with no model loaded. The directory is
+    /// removed on drop.
+    struct IngestFixture {
+        dir: PathBuf,
+        db: DbState,
+        paths: AppPaths,
+        model: EmbeddingState,
+        cancellation: CancellationState,
+        events: RecordingSink,
+    }
+
+    impl IngestFixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("{}-{}", name, Uuid::new_v4()));
+            let documents_dir = dir.join("documents");
+            std::fs::create_dir_all(&documents_dir).unwrap();
+            Self {
+                dir,
+                db: DbState(Mutex::new(Database::in_memory().unwrap())),
+                paths: AppPaths { documents_dir },
+                model: EmbeddingState(Mutex::new(None)),
+                cancellation: CancellationState::default(),
+                events: RecordingSink::default(),
+            }
+        }
+
+        /// Writes a file to upload, outside the document store.
+        fn write_file(&self, name: &str, contents: &str) -> PathBuf {
+            let file = self.dir.join(name);
+            std::fs::write(&file, contents).unwrap();
+            file
+        }
+
+        fn ingest(&self, file: &Path, options: &IngestOptions) -> DocumentResponse {
+            let file_path = file.to_str().unwrap();
+            ingest_document(
+                &self.db,
+                &self.paths,
+                &self.model,
+                &self.cancellation,
+                &self.events,
+                file_path,
+                options,
+            )
+            .unwrap()
+        }
+    }
+
+    impl Drop for IngestFixture {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+
+    #[test]
+    fn test_ingest_and_delete_emit_events() {
+        let fixture = IngestFixture::new("ingest-events");
+        let file = fixture.write_file("notes.txt", "Some notes worth keeping around.");
+        let (db, paths, events) = (&fixture.db, &fixture.paths, &fixture.events);
+
+        let doc = fixture.ingest(&file, &IngestOptions::default());
+
+        {
+            let sent = events.0.borrow();
+            assert_eq!(sent.len(), 1);
+            let (event, payload) = &sent[0];
+            assert_eq!(event, "document-ingested");
+            assert_eq!(payload["document"]["id"], doc.id.as_str());
+            assert_eq!(payload["chunkCount"], 1);
+            // No model loaded, so nothing was embedded
+            assert_eq!(payload["embeddingCount"], 0);
+        }
+
+        assert!(remove_document(db, paths, events, &doc.id).unwrap());
+        // Deleting again finds nothing and sends nothing
+        assert!(!remove_document(db, paths, events, &doc.id).unwrap());
+
+        let sent = events.0.borrow();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[1].0, "document-deleted");
+        assert_eq!(sent[1].1["documentId"], doc.id.as_str());
+    }
+
+    #[test]
+    fn test_clear_document_embeddings_keeps_content() {
+        let db = Database::in_memory().unwrap();
+        db.conn
+            .execute_batch(
+                "INSERT INTO documents (id, name, doc_type, size, uploaded_at, path) VALUES
+                    ('a', 'a.txt', 'txt', 1, '2024-01-01T00:00:00Z', '/tmp/a.txt'),
+                    ('b', 'b.txt', 'txt', 1, '2024-01-01T00:00:00Z', '/tmp/b.txt');
+                 INSERT INTO document_content (document_id, content)
+                 VALUES ('a', 'x'), ('b', 'y');",
+            )
+            .unwrap();
+        for doc in ["a", "b"] {
+            for i in 0..2 {
+                let chunk_id = format!("{}-{}", doc, i);
+                db.conn
+                    .execute(
+                        "INSERT INTO chunks (id, document_id, chunk_index, content,
+                                             start_offset, end_offset)
+                         VALUES (?1, ?2, ?3, 'x', 0, 1)",
+                        rusqlite::params![chunk_id, doc, i],
+                    )
+                    .unwrap();
+                vector_store::save_embedding(&db.conn, &chunk_id, doc, &[1.0, 0.0], "m").unwrap();
+            }
+        }
+        let count = |table: &str, doc: &str| -> i64 {
+            db.conn
+                .query_row(
+                    &format!("SELECT COUNT(*) FROM {} WHERE document_id = ?1", table),
+                    [doc],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+
+        assert_eq!(clear_embeddings(&db, "a", true).unwrap(), 2);
+        assert_eq!((count("embeddings", "a"), count("chunks", "a")), (0, 2));
+        assert_eq!(clear_embeddings(&db, "a", false).unwrap(), 0);
+        assert_eq!(count("chunks", "a"), 0);
+
+        // The document and its text survive; the other document is untouched
+        assert!(documents::get_document(&db.conn, "a").unwrap().is_some());
+        assert_eq!(documents::get_document_content(&db.conn, "a").unwrap().as_deref(), Some("x"));
+        assert_eq!((count("embeddings", "b"), count("chunks", "b")), (2, 2));
+
+        assert!(matches!(clear_embeddings(&db, "missing", false), Err(CommandError::NotFound(_))));
+    }
+
+    /// Records the name of every span created while it's the subscriber.
+    #[derive(Clone, Default)]
+    struct SpanRecorder(Arc<Mutex<Vec<String>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanRecorder {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _id: &tracing::span::Id,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            self.0.lock().unwrap().push(attrs.metadata().name().to_string());
+        }
+    }
+
+    #[test]
+    fn test_ingest_emits_span() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let fixture = IngestFixture::new("ingest-span");
+        let file = fixture.write_file("notes.txt", "Some notes worth keeping around.");
+
+        let recorder = SpanRecorder::default();
+        let subscriber = tracing_subscriber::registry().with(recorder.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            fixture.ingest(&file, &IngestOptions::default());
+        });
+
+        assert!(recorder.0.lock().unwrap().iter().any(|name| name == "ingest"));
+    }
+
+    #[test]
+    fn test_ingest_copy_into_store() {
+        let fixture = IngestFixture::new("ingest-copy");
+        let file = fixture.write_file("notes.txt", "Some notes worth keeping around.");
+        let (db, paths, events) = (&fixture.db, &fixture.paths, &fixture.events);
+        let documents_dir = &paths.documents_dir;
+        let ingest = |copy_into_store| {
+            let options = IngestOptions {
+                copy_into_store,
+                ..Default::default()
+            };
+            fixture.ingest(&file, &options)
+        };
+        let stored_path = |id: &str| {
+            let db = db.0.lock().unwrap();
+            PathBuf::from(documents::get_document(&db.conn, id).unwrap().unwrap().path)
+        };
+
+        // Copied: the path points into the store, prefixed with the ID so the
+        // same file name can be uploaded twice
+        let first = ingest(true);
+        let second = ingest(true);
+        for doc in [&first, &second] {
+            let path = stored_path(&doc.id);
+            assert_eq!(path, documents_dir.join(format!("{}_notes.txt", doc.id)));
+            assert!(path.exists());
+        }
+
+        // Deleting removes the stored copy but never the original
+        let first_copy = stored_path(&first.id);
+        assert!(remove_document(db, paths, events, &first.id).unwrap());
+        assert!(!first_copy.exists());
+        assert!(file.exists());
+
+        // Not copied: the path is the original, which survives deletion
+        let linked = ingest(false);
+        assert_eq!(stored_path(&linked.id), file);
+        assert!(remove_document(db, paths, events, &linked.id).unwrap());
+        assert!(file.exists());
+    }
+
+    #[test]
+    fn test_preview_chunking_matches_ingest() {
+        let fixture = IngestFixture::new("preview");
+        let text: String = (0..200)
+            .map(|i| format!("Sentence number {} talks about chunk previews. ", i))
+            .collect();
+        let file = fixture.write_file("long.txt", &text);
+        let file_path = file.to_str().unwrap();
+
+        let preview =
+            preview_document_chunks(file_path, &ChunkConfig::default(), usize::MAX).unwrap();
+        assert!(preview.total > 2);
+        assert_eq!(preview.chunks.len(), preview.total);
+
+        // Nothing was written, and ingesting stores exactly the previewed chunks
+        let db = &fixture.db;
+        assert!(documents::get_all_documents(&db.0.lock().unwrap().conn).unwrap().is_empty());
+        let doc = fixture.ingest(&file, &IngestOptions::default());
+        let stored = chunker::get_document_chunks(&db.0.lock().unwrap().conn, &doc.id).unwrap();
+        assert_eq!(stored.len(), preview.total);
+        for (stored, previewed) in stored.iter().zip(&preview.chunks) {
+            assert_eq!(stored.chunk_index, previewed.chunk_index);
+            assert_eq!(stored.content, previewed.content);
+            assert_eq!(stored.start_offset, previewed.start_offset);
+            assert_eq!(stored.end_offset, previewed.end_offset);
+        }
+
+        // The cap truncates the chunks but not the total
+        let capped = preview_document_chunks(file_path, &ChunkConfig::default(), 2).unwrap();
+        assert_eq!(capped.chunks.len(), 2);
+        assert_eq!(capped.total, preview.total);
+
+        // Invalid configs are rejected
+        let config = ChunkConfig {
+            chunk_size: 0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            preview_document_chunks(file_path, &config, 2),
+            Err(CommandError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_ingest_directory_skips_unsupported_and_duplicates() {
+        let dir = std::env::temp_dir().join(format!("ingest-dir-{}", Uuid::new_v4()));
+        let source = dir.join("source");
+        let documents_dir = dir.join("documents");
+        std::fs::create_dir_all(source.join("nested")).unwrap();
+        std::fs::create_dir_all(&documents_dir).unwrap();
+        std::fs::write(source.join("notes.txt"), "Plain text notes.").unwrap();
+        std::fs::write(source.join("guide.md"), "# Guide\n\nSome markdown.").unwrap();
+        std::fs::write(source.join("copy-of-notes.txt"), "Plain text notes.").unwrap();
+        std::fs::write(source.join("report.docx"), "Not supported").unwrap();
+        std::fs::write(source.join("image.png"), [0u8, 1, 2]).unwrap();
+        std::fs::write(source.join("nested").join("deep.org"), "* Heading\nOrg text.").unwrap();
+
+        let db = DbState(Mutex::new(Database::in_memory().unwrap()));
+        let paths = AppPaths { documents_dir };
+        let model = EmbeddingState(Mutex::new(None));
+        let events = RecordingSink::default();
+        let names = |report: &DirectoryIngestReport| {
+            let mut names: Vec<String> = report.documents.iter().map(|d| d.name.clone()).collect();
+            names.sort();
+            names
+        };
+
+        // The copy has the same content as notes.txt, so only one of them is stored
+        let report = ingest_directory_files(&db, &paths, &model, &events, &source, false).unwrap();
+        assert_eq!(report.documents.len(), 2);
+        assert!(names(&report).contains(&"guide.md".to_string()));
+        assert_eq!(report.skipped, 1);
+        assert_eq!(report.unsupported, 2);
+        assert_eq!(report.failed, 0);
+        {
+            let db = db.0.lock().unwrap();
+            assert_eq!(documents::count_documents(&db.conn).unwrap(), 2);
+            for doc in &report.documents {
+                assert!(!chunker::get_document_chunks(&db.conn, &doc.id).unwrap().is_empty());
+            }
+        }
+
+        let sent = events.0.borrow();
+        let progress: Vec<&serde_json::Value> = sent
+            .iter()
+            .filter(|(event, _)| event == "directory-ingest-progress")
+            .map(|(_, payload)| payload)
+            .collect();
+        let last = progress.last().unwrap();
+        assert_eq!(last["total"], 3);
+        assert_eq!(last["processed"], 3);
+        assert_eq!(last["ingested"], 2);
+        assert_eq!(sent.iter().filter(|(event, _)| event == "document-ingested").count(), 2);
+        drop(sent);
+
+        // Recursing picks up the nested file and skips everything already stored
+        let report = ingest_directory_files(&db, &paths, &model, &events, &source, true).unwrap();
+        assert_eq!(names(&report), vec!["deep.org"]);
+        assert_eq!(report.skipped, 3);
+        assert_eq!(report.unsupported, 2);
+        assert_eq!(documents::count_documents(&db.0.lock().unwrap().conn).unwrap(), 3);
+
+        assert!(matches!(
+            ingest_directory_files(&db, &paths, &model, &events, &dir.join("missing"), true),
+            Err(CommandError::NotFound(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// A word-level tokenizer that splits on whitespace and punctuation.
+    fn stub_tokenizer() -> Tokenizer {
+        use tokenizers::models::wordlevel::WordLevel;
+        use tokenizers::pre_tokenizers::whitespace::Whitespace;
+
+        let vocab = [("[UNK]", 0), ("hello", 1), ("world", 2)]
+            .into_iter()
+            .map(|(word, id)| (word.to_string(), id))
+            .collect();
+        let model = WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Some(Whitespace {}));
+        tokenizer
+    }
+
+    #[test]
+    fn test_count_tokens_loads_tokenizer_once() {
+        let model = EmbeddingState(Mutex::new(None));
+        let tokenizer = TokenizerState(Mutex::new(None));
+        let loads = std::cell::Cell::new(0);
+        let load = || {
+            loads.set(loads.get() + 1);
+            Ok(stub_tokenizer())
+        };
+
+        // "hello", ",", "world", "!" and two unknown words
+        let text = "hello, world! unknown words";
+        let count = count_tokens_lazily(&model, &tokenizer, "stub", load, text);
+        assert_eq!(count.unwrap(), 6);
+        assert_eq!(count_tokens_lazily(&model, &tokenizer, "stub", load, "").unwrap(), 0);
+        assert_eq!(loads.get(), 1);
+
+        // Asking for another model's tokenizer loads that one instead
+        count_tokens_lazily(&model, &tokenizer, "other", load, "hello").unwrap();
+        assert_eq!(loads.get(), 2);
+
+        let failed = count_tokens_lazily(
+            &model,
+            &TokenizerState(Mutex::new(None)),
+            "stub",
+            || Err(EmbeddingError::ModelLoad("offline".to_string())),
+            "hello",
+        );
+        assert!(matches!(failed, Err(CommandError::Embedding(_))));
+    }
+
+    #[test]
+    fn test_backfill_token_counts_with_tokenizer() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Chat").unwrap();
+        db.add_message(&user_message("chat-1", "hello, world!")).unwrap();
+        db.add_message(&user_message("chat-1", "unknown")).unwrap();
+
+        assert_eq!(backfill_token_counts(&db, &stub_tokenizer()).unwrap(), 2);
+        assert_eq!(backfill_token_counts(&db, &stub_tokenizer()).unwrap(), 0);
+        // "hello", ",", "world", "!" and one unknown word
+        assert_eq!(db.get_chat_token_total("chat-1").unwrap(), 5);
+    }
+
+    #[test]
+    fn test_self_test_with_stub_encoder() {
+        let result = run_self_test(|text| {
+            assert_eq!(text, SELF_TEST_SENTENCE);
+            Ok(vec![0.6, 0.0, 0.8])
+        })
+        .unwrap();
+        assert!(result.ok);
+        assert_eq!(result.dim, 3);
+        assert!((result.norm - 1.0).abs() < 1e-6);
+
+        // Vectors that aren't unit length or aren't finite fail the check
+        for broken in [vec![], vec![3.0, 4.0], vec![f32::NAN, 1.0]] {
+            assert!(!run_self_test(|_| Ok(broken)).unwrap().ok);
+        }
+
+        let failed = run_self_test(|_| Err(EmbeddingError::Inference("boom".to_string())));
+        assert!(matches!(failed, Err(CommandError::Embedding(_))));
+    }
+
+    #[test]
+    #[ignore] // Requires model download, run with: cargo test -- --ignored
+    fn test_self_test_with_real_model() {
+        let model = EmbeddingModel::new().expect("Failed to load model");
+        let result = run_self_test(|text| model.encode(text)).unwrap();
+
+        assert!(result.ok);
+        assert_eq!(result.dim, crate::embeddings::EMBEDDING_DIM);
+        assert!((result.norm - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_embedding_model_options_from_frontend() {
+        let json = r#"{
+            "modelId": "intfloat/e5-small-v2",
+            "pooling": "cls",
+            "queryPrefix": "query: ",
+            "passagePrefix": "passage: ",
+            "retryAttempts": 5
+        }"#;
+        let options: EmbeddingModelOptions = serde_json::from_str(json).unwrap();
+        assert_eq!(options.model_id.as_deref(), Some("intfloat/e5-small-v2"));
+        assert_eq!(options.pooling, Some(Pooling::Cls));
+        assert_eq!(options.query_prefix.as_deref(), Some("query: "));
+        assert_eq!(options.retry_attempts, Some(5));
+        assert!(!options.offline && !options.gpu);
+        assert!(options.builder().is_ok());
+
+        // Everything is optional, but unknown pooling names are rejected
+        assert!(serde_json::from_str::<EmbeddingModelOptions>("{}").is_ok());
+        assert!(serde_json::from_str::<EmbeddingModelOptions>(r#"{"pooling": "idf"}"#).is_err());
+    }
+
+    #[test]
+    fn test_min_score_only_filters_similarities() {
+        let result = |chunk_id: &str, score| SearchResult {
+            chunk_id: chunk_id.to_string(),
+            document_id: "doc".to_string(),
+            content: String::new(),
+            score,
+            explanation: None,
+        };
+        let results = vec![result("close", 0.9), result("edge", 0.5), result("far", 0.1)];
+        let ids = |results: Vec<SearchResult>| -> Vec<String> {
+            results.into_iter().map(|r| r.chunk_id).collect()
+        };
+
+        let kept = apply_min_score(results.clone(), SimilarityMetric::Cosine, 0.5);
+        assert_eq!(ids(kept), vec!["close", "edge"]);
+
+        // Distances are never thresholded
+        let kept = apply_min_score(results, SimilarityMetric::Euclidean, 0.5);
+        assert_eq!(ids(kept), vec!["close", "edge", "far"]);
+    }
 }
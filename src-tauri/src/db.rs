@@ -10,6 +10,8 @@ use chrono::{DateTime, Utc};
 use rusqlite::{Connection, params};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
 
 /// Represents a chat conversation.
 ///
@@ -23,6 +25,8 @@ pub struct Chat {
     pub title: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Pinned chats are listed before all others
+    pub pinned: bool,
 }
 
 /// Represents a single message in a chat.
@@ -30,7 +34,7 @@ pub struct Chat {
 /// Note: `sources` stores JSON as a string in SQLite.
 /// SQLite doesn't have a native JSON type, so we serialize DocumentSource[]
 /// to a JSON string when storing and deserialize when reading.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Message {
     pub id: String,
     pub chat_id: String,
@@ -41,7 +45,7 @@ pub struct Message {
 }
 
 /// A chat with all its messages - used when loading a full conversation.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ChatWithMessages {
     pub id: String,
     pub title: String,
@@ -50,6 +54,168 @@ pub struct ChatWithMessages {
     pub updated_at: DateTime<Utc>,
 }
 
+/// Lightweight metadata about a chat, for the sidebar.
+///
+/// Computed without loading the chat's messages.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatSummary {
+    pub id: String,
+    pub title: String,
+    pub message_count: usize,
+    /// Start of the most recent message (`None` for an empty chat)
+    pub last_message_preview: Option<String>,
+    /// Rough token count of all messages (4 characters per token)
+    pub approx_tokens: usize,
+}
+
+/// What `Database::repair_integrity` cleaned up.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RepairReport {
+    /// Embeddings whose chunk no longer exists
+    pub orphaned_embeddings: usize,
+    /// Chunks whose document no longer exists
+    pub orphaned_chunks: usize,
+}
+
+/// Database size before and after `Database::optimize`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OptimizeReport {
+    /// Size in bytes before optimizing
+    pub size_before: u64,
+    /// Size in bytes after optimizing
+    pub size_after: u64,
+}
+
+/// User-adjustable settings, stored as key/value rows in the `settings` table.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Settings {
+    /// How many chunks retrieval returns when the caller doesn't say
+    pub retrieval_top_k: usize,
+    /// Results scoring below this are dropped (for similarity metrics,
+    /// not distances)
+    pub min_score: f32,
+    /// Chunk size in characters for newly ingested documents
+    pub chunk_size: usize,
+    /// Clean up hyphenation and spacing in chunk text before embedding it
+    /// (see `text::normalize_chunk_text`). Stored chunks are unaffected.
+    pub normalize_chunks: bool,
+    /// Report similarity scores mapped from [-1, 1] to [0, 1] (see
+    /// `vector_store::normalize_score`). `min_score` applies before mapping.
+    pub normalize_scores: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            retrieval_top_k: 5,
+            min_score: 0.0,
+            chunk_size: 1000,
+            normalize_chunks: true,
+            normalize_scores: false,
+        }
+    }
+}
+
+/// Setting keys, one per `Settings` field.
+pub const SETTING_RETRIEVAL_TOP_K: &str = "retrieval_top_k";
+pub const SETTING_MIN_SCORE: &str = "min_score";
+pub const SETTING_CHUNK_SIZE: &str = "chunk_size";
+pub const SETTING_NORMALIZE_CHUNKS: &str = "normalize_chunks";
+pub const SETTING_NORMALIZE_SCORES: &str = "normalize_scores";
+
+/// Number of characters kept in `ChatSummary::last_message_preview`.
+const PREVIEW_CHARS: usize = 80;
+
+/// Characters per token for estimates - the same rough figure the chunker uses.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Estimates how many tokens `text` is, for when no tokenizer is at hand.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Error type for database operations that validate their input.
+///
+/// Most methods only fail on SQLite errors and return `rusqlite::Error`
+/// directly; this adds a variant for rejected input.
+#[derive(Debug)]
+pub enum DbError {
+    Sqlite(rusqlite::Error),
+    InvalidInput(String),
+}
+
+impl std::fmt::Display for DbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DbError::Sqlite(e) => write!(f, "Database error: {}", e),
+            DbError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<rusqlite::Error> for DbError {
+    fn from(e: rusqlite::Error) -> Self {
+        DbError::Sqlite(e)
+    }
+}
+
+/// Reads the schema to check that SQLite can decode the file.
+///
+/// A missing or wrong SQLCipher key only shows up on the first read, as the
+/// unhelpful "file is not a database"; that error gets `hint` as its message.
+fn ensure_readable(conn: &Connection, hint: &str) -> Result<(), rusqlite::Error> {
+    match conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0)) {
+        Ok(_) => Ok(()),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::NotADatabase => {
+            Err(rusqlite::Error::SqliteFailure(e, Some(hint.to_string())))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Every table in the database, ordered so that tables referencing
+/// another come before it (the order they can safely be dropped in).
+const ALL_TABLES: [&str; 10] = [
+    "embeddings",
+    "chunks",
+    "document_config",
+    "document_content",
+    "documents",
+    "chat_embeddings",
+    "chat_tags",
+    "tags",
+    "messages",
+    "chats",
+];
+
+/// How long a write waits for another connection's lock by default.
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_millis(5000);
+
+/// Connection settings applied when a database is opened.
+///
+/// Only one `Database` is open in the app, but SQLite tools or a second
+/// instance reading the same file can still hold locks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectionOptions {
+    /// How long to retry when the database is locked before failing with
+    /// "database is locked"
+    pub busy_timeout: Duration,
+    /// Use write-ahead logging, so readers don't block the writer (or each
+    /// other). Ignored for in-memory databases.
+    pub wal: bool,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+            wal: true,
+        }
+    }
+}
+
 /// Database wrapper that manages SQLite connection and operations.
 ///
 /// In Rust, we often wrap external resources in our own struct to:
@@ -68,30 +234,119 @@ impl Database {
     /// Unlike languages with `new` keywords, Rust constructors are just
     /// regular associated functions that return Self.
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, rusqlite::Error> {
+        Self::with_options(path, ConnectionOptions::default())
+    }
+
+    /// Like `new`, with non-default connection settings.
+    pub fn with_options<P: AsRef<Path>>(
+        path: P,
+        options: ConnectionOptions,
+    ) -> Result<Self, rusqlite::Error> {
         // Open or create the SQLite database file
         let conn = Connection::open(path)?;
 
+        // An encrypted database can't be read without its key
+        ensure_readable(&conn, "file is encrypted (open it with a key) or is not a database")?;
+
+        Self::from_connection(conn, options)
+    }
+
+    /// Opens or creates a database encrypted at rest with SQLCipher.
+    ///
+    /// The whole file, schema included, is encrypted with `key`. Opening an
+    /// existing database with the wrong key (or one that was never
+    /// encrypted) fails with an error saying so.
+    #[cfg(feature = "sqlcipher")]
+    pub fn new_encrypted<P: AsRef<Path>>(path: P, key: &str) -> Result<Self, rusqlite::Error> {
+        if key.is_empty() {
+            // SQLCipher treats an empty key as "no encryption"
+            return Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_MISUSE),
+                Some("encryption key cannot be empty".to_string()),
+            ));
+        }
+
+        let conn = Connection::open(path)?;
+
+        // The key must be set before anything reads the file
+        conn.pragma_update(None, "key", key)?;
+        ensure_readable(&conn, "wrong encryption key, or the database is not encrypted")?;
+
+        Self::from_connection(conn, ConnectionOptions::default())
+    }
+
+    /// Finishes opening a database: applies `options`, enables foreign keys
+    /// and creates the schema.
+    fn from_connection(
+        conn: Connection,
+        options: ConnectionOptions,
+    ) -> Result<Self, rusqlite::Error> {
+        conn.busy_timeout(options.busy_timeout)?;
+        if options.wal {
+            // Returns the mode actually used: "memory" for in-memory databases
+            let mode: String =
+                conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))?;
+            if !mode.eq_ignore_ascii_case("wal") && !mode.eq_ignore_ascii_case("memory") {
+                tracing::warn!(mode, "Couldn't enable WAL mode");
+            }
+        }
+
         // Enable foreign key enforcement FIRST (SQLite has it off by default)
-        // This must be done before creating any tables with foreign keys
+        // This must be done before creating any tables with foreign keys.
+        // The pragma is per-connection: every module shares `conn`, so
+        // CASCADE deletes work for chats, documents, chunks and embeddings alike.
         conn.execute("PRAGMA foreign_keys = ON", [])?;
 
         // Create a new Database instance
         let db = Database { conn };
 
         // Initialize tables - the `?` operator propagates errors
-        // If init_schema() returns Err, this function returns early with that error
-        db.init_schema()?;
+        // If init_all_tables() returns Err, this function returns early with that error
+        db.init_all_tables()?;
+
+        Ok(db)
+    }
+
+    /// Creates a private in-memory database with the full schema.
+    ///
+    /// Nothing is written to disk and the data disappears when the
+    /// `Database` is dropped. Like `new`, this creates every table (chats,
+    /// documents, chunks, embeddings), so an incognito session (see
+    /// `start_incognito_session`) can run the whole ingest pipeline against it.
+    pub fn in_memory() -> Result<Self, rusqlite::Error> {
+        // Each ":memory:" connection gets its own separate database
+        Self::new(":memory:")
+    }
+
+    /// Creates every table the app uses, across all modules.
+    fn init_all_tables(&self) -> Result<(), rusqlite::Error> {
+        self.init_schema()?;
 
         // Initialize document tables
-        crate::documents::init_documents_table(&db.conn)?;
+        crate::documents::init_documents_table(&self.conn)?;
 
         // Initialize chunk tables
-        crate::chunker::init_chunks_table(&db.conn)?;
+        crate::chunker::init_chunks_table(&self.conn)?;
 
         // Initialize embedding/vector store tables
-        crate::vector_store::init_embeddings_table(&db.conn)?;
+        crate::vector_store::init_embeddings_table(&self.conn)?;
 
-        Ok(db)
+        Ok(())
+    }
+
+    /// Deletes all app data by dropping and recreating every table.
+    ///
+    /// Runs in a single transaction, so a failure leaves the data untouched.
+    /// Tables are dropped children-first so foreign keys never dangle.
+    pub fn reset(&self) -> Result<(), rusqlite::Error> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        for table in ALL_TABLES {
+            tx.execute(&format!("DROP TABLE IF EXISTS {}", table), [])?;
+        }
+        self.init_all_tables()?;
+
+        tx.commit()
     }
 
     /// Initializes the database schema.
@@ -105,11 +360,15 @@ impl Database {
                 id TEXT PRIMARY KEY,
                 title TEXT NOT NULL,
                 created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
+                updated_at TEXT NOT NULL,
+                pinned INTEGER NOT NULL DEFAULT 0
             )",
             [],
         )?;
 
+        // Migration: databases created before pinning existed lack the column
+        add_column_if_missing(&self.conn, "chats", "pinned", "INTEGER NOT NULL DEFAULT 0")?;
+
         // Messages table - stores individual messages
         // FOREIGN KEY ensures referential integrity with CASCADE delete
         self.conn.execute(
@@ -120,20 +379,138 @@ impl Database {
                 content TEXT NOT NULL,
                 timestamp TEXT NOT NULL,
                 sources TEXT,
+                token_count INTEGER,
                 FOREIGN KEY (chat_id) REFERENCES chats(id) ON DELETE CASCADE
             )",
             [],
         )?;
 
+        // Migration: older databases lack token counts; existing rows stay
+        // NULL until `backfill_message_token_counts` fills them, which
+        // `init_embedding_model` does once the tokenizer is loaded
+        add_column_if_missing(&self.conn, "messages", "token_count", "INTEGER")?;
+
         // Create index for faster message lookups by chat_id
         self.conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_messages_chat_id ON messages(chat_id)",
             [],
         )?;
 
+        // Tags table - each tag name is stored once
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS tags (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE
+            )",
+            [],
+        )?;
+
+        // Join table between chats and tags
+        // Rows disappear with either side via CASCADE
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS chat_tags (
+                chat_id TEXT NOT NULL,
+                tag_id INTEGER NOT NULL,
+                PRIMARY KEY (chat_id, tag_id),
+                FOREIGN KEY (chat_id) REFERENCES chats(id) ON DELETE CASCADE,
+                FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // Embedding of each chat's first user message, for spotting
+        // near-duplicate chats. Stored as little-endian f32 bytes
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS chat_embeddings (
+                chat_id TEXT PRIMARY KEY,
+                embedding BLOB NOT NULL,
+                FOREIGN KEY (chat_id) REFERENCES chats(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // Settings table - values are stored as text and parsed on read.
+        // Not in ALL_TABLES, so settings survive a reset
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Seed the defaults; settings already stored are left alone
+        let defaults = Settings::default();
+        for (key, value) in [
+            (SETTING_RETRIEVAL_TOP_K, defaults.retrieval_top_k.to_string()),
+            (SETTING_MIN_SCORE, defaults.min_score.to_string()),
+            (SETTING_CHUNK_SIZE, defaults.chunk_size.to_string()),
+            (SETTING_NORMALIZE_CHUNKS, defaults.normalize_chunks.to_string()),
+            (SETTING_NORMALIZE_SCORES, defaults.normalize_scores.to_string()),
+        ] {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO settings (key, value) VALUES (?1, ?2)",
+                params![key, value],
+            )?;
+        }
+
         Ok(())
     }
 
+    /// Removes index rows left behind by partial failures.
+    ///
+    /// Deletes embeddings whose chunk is gone, then chunks whose document is
+    /// gone (their embeddings go with them via CASCADE). Foreign keys normally
+    /// prevent this, but rows written while they were off, or by older
+    /// versions of the app, can still dangle. Runs in one transaction.
+    pub fn repair_integrity(&self) -> Result<RepairReport, rusqlite::Error> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        let orphaned_embeddings = tx.execute(
+            "DELETE FROM embeddings WHERE chunk_id NOT IN (SELECT id FROM chunks)",
+            [],
+        )?;
+        let orphaned_chunks = tx.execute(
+            "DELETE FROM chunks WHERE document_id NOT IN (SELECT id FROM documents)",
+            [],
+        )?;
+
+        tx.commit()?;
+
+        Ok(RepairReport {
+            orphaned_embeddings,
+            orphaned_chunks,
+        })
+    }
+
+    /// Reclaims free space and refreshes query planner statistics.
+    ///
+    /// SQLite doesn't shrink the file when rows are deleted; it keeps the
+    /// freed pages for reuse. `VACUUM` rebuilds the file without them,
+    /// `ANALYZE` recollects index statistics and `PRAGMA optimize` lets
+    /// SQLite apply any other tuning it thinks is worthwhile.
+    ///
+    /// `VACUUM` can't run inside a transaction. In WAL mode the rebuilt
+    /// pages land in the log first, so it's checkpointed back into the file.
+    pub fn optimize(&self) -> Result<OptimizeReport, rusqlite::Error> {
+        let size_before = self.size_bytes()?;
+
+        self.conn.execute_batch("VACUUM; ANALYZE; PRAGMA optimize;")?;
+        self.conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+
+        Ok(OptimizeReport {
+            size_before,
+            size_after: self.size_bytes()?,
+        })
+    }
+
+    /// Size of the database in bytes (page count times page size).
+    fn size_bytes(&self) -> Result<u64, rusqlite::Error> {
+        let page_count: i64 = self.conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let page_size: i64 = self.conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        Ok((page_count * page_size) as u64)
+    }
+
     /// Creates a new chat conversation.
     ///
     /// Returns the created Chat struct on success.
@@ -152,49 +529,122 @@ impl Database {
             title: title.to_string(),
             created_at: now,
             updated_at: now,
+            pinned: false,
         })
     }
 
-    /// Retrieves all chats, ordered by most recently updated.
+    /// Retrieves all chats, pinned first, then by most recently updated.
     ///
     /// This demonstrates Rust iterators and collecting results.
     pub fn get_all_chats(&self) -> Result<Vec<Chat>, rusqlite::Error> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, title, created_at, updated_at FROM chats ORDER BY updated_at DESC"
+            "SELECT id, title, created_at, updated_at, pinned FROM chats
+             ORDER BY pinned DESC, updated_at DESC"
         )?;
 
         // `query_map` returns an iterator over rows
         // We map each row to a Chat struct, then collect into a Vec
-        let chats = stmt.query_map([], |row| {
-            Ok(Chat {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                // Parse ISO 8601 datetime strings back to DateTime<Utc>
-                created_at: parse_datetime(&row.get::<_, String>(2)?),
-                updated_at: parse_datetime(&row.get::<_, String>(3)?),
-            })
-        })?;
+        let chats = stmt.query_map([], row_to_chat)?;
 
         // Collect results, propagating any errors
         // The turbofish `::<Vec<_>>` tells Rust what type to collect into
         chats.collect::<Result<Vec<_>, _>>()
     }
 
+    /// Finds chats whose title contains `query`, most recently updated first.
+    ///
+    /// Matching is case-insensitive (for ASCII letters, as with SQLite's
+    /// `LIKE`). `%` and `_` in the query match literally, not as wildcards.
+    pub fn find_chats_by_title(&self, query: &str) -> Result<Vec<Chat>, rusqlite::Error> {
+        let pattern = format!("%{}%", escape_like(query));
+
+        let mut stmt = self.conn.prepare(
+            "SELECT id, title, created_at, updated_at, pinned FROM chats
+             WHERE title LIKE ?1 ESCAPE '\\'
+             ORDER BY updated_at DESC"
+        )?;
+
+        let chats = stmt.query_map(params![pattern], row_to_chat)?;
+        chats.collect::<Result<Vec<_>, _>>()
+    }
+
+    /// Gets the ID of a chat's first user message, if it has one.
+    pub fn first_user_message_id(&self, chat_id: &str) -> Result<Option<String>, rusqlite::Error> {
+        let result = self.conn.query_row(
+            "SELECT id FROM messages WHERE chat_id = ?1 AND role = 'user'
+             ORDER BY timestamp ASC, rowid ASC LIMIT 1",
+            params![chat_id],
+            |row| row.get(0),
+        );
+
+        match result {
+            Ok(id) => Ok(Some(id)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stores the embedding of a chat's first user message, replacing any
+    /// previous one.
+    pub fn set_first_message_embedding(
+        &self,
+        chat_id: &str,
+        embedding: &[f32],
+    ) -> Result<(), rusqlite::Error> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO chat_embeddings (chat_id, embedding) VALUES (?1, ?2)",
+            params![chat_id, crate::vector_store::embedding_to_bytes(embedding)],
+        )?;
+        Ok(())
+    }
+
+    /// Finds chats whose first user message has a cosine similarity of at
+    /// least `threshold` to `embedding`, most similar first.
+    ///
+    /// Used to suggest merging chats that start with the same question.
+    /// Chats whose first message was never embedded (e.g. it was sent before
+    /// the model loaded) are never returned.
+    pub fn find_similar_chats_by_first_message(
+        &self,
+        embedding: &[f32],
+        threshold: f32,
+    ) -> Result<Vec<(Chat, f32)>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.id, c.title, c.created_at, c.updated_at, c.pinned, e.embedding
+             FROM chat_embeddings e
+             JOIN chats c ON c.id = e.chat_id"
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            let bytes: Vec<u8> = row.get(5)?;
+            Ok((row_to_chat(row)?, crate::vector_store::bytes_to_embedding(&bytes)))
+        })?;
+
+        let mut matches = Vec::new();
+        for row in rows {
+            let (chat, stored) = row?;
+            // Vectors from a model with a different dimension can't be compared
+            if stored.len() != embedding.len() {
+                continue;
+            }
+            let similarity = crate::embeddings::cosine_similarity(embedding, &stored);
+            if similarity >= threshold {
+                matches.push((chat, similarity));
+            }
+        }
+
+        matches.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(matches)
+    }
+
     /// Gets a single chat with all its messages.
     pub fn get_chat(&self, chat_id: &str) -> Result<Option<ChatWithMessages>, rusqlite::Error> {
         // First, get the chat metadata
         let mut chat_stmt = self.conn.prepare(
-            "SELECT id, title, created_at, updated_at FROM chats WHERE id = ?1"
+            "SELECT id, title, created_at, updated_at, pinned FROM chats WHERE id = ?1"
         )?;
 
-        let chat = chat_stmt.query_row(params![chat_id], |row| {
-            Ok(Chat {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                created_at: parse_datetime(&row.get::<_, String>(2)?),
-                updated_at: parse_datetime(&row.get::<_, String>(3)?),
-            })
-        });
+        let chat = chat_stmt.query_row(params![chat_id], row_to_chat);
 
         // Handle the case where chat doesn't exist
         let chat = match chat {
@@ -209,16 +659,7 @@ impl Database {
              FROM messages WHERE chat_id = ?1 ORDER BY timestamp ASC"
         )?;
 
-        let messages = msg_stmt.query_map(params![chat_id], |row| {
-            Ok(Message {
-                id: row.get(0)?,
-                chat_id: row.get(1)?,
-                role: row.get(2)?,
-                content: row.get(3)?,
-                timestamp: parse_datetime(&row.get::<_, String>(4)?),
-                sources: row.get(5)?,
-            })
-        })?;
+        let messages = msg_stmt.query_map(params![chat_id], row_to_message)?;
 
         let messages: Vec<Message> = messages.collect::<Result<Vec<_>, _>>()?;
 
@@ -231,6 +672,142 @@ impl Database {
         }))
     }
 
+    /// Gets the documents cited by a chat's assistant messages, each once,
+    /// in the order they were first cited.
+    ///
+    /// Citations come from each message's `sources` JSON (an array of
+    /// objects with a `documentId`). Messages with no sources or sources
+    /// that don't parse are skipped, as are documents deleted since.
+    pub fn get_chat_cited_documents(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<crate::documents::Document>, crate::documents::DocumentError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT sources FROM messages
+             WHERE chat_id = ?1 AND role = 'assistant' AND sources IS NOT NULL
+             ORDER BY timestamp ASC, rowid ASC",
+        )?;
+        let sources = stmt
+            .query_map(params![chat_id], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut seen = std::collections::HashSet::new();
+        let mut documents = Vec::new();
+        for json in sources {
+            let Ok(serde_json::Value::Array(cited)) = serde_json::from_str(&json) else {
+                continue;
+            };
+            for source in cited {
+                let Some(id) = source.get("documentId").and_then(|id| id.as_str()) else {
+                    continue;
+                };
+                if seen.insert(id.to_string()) {
+                    documents.extend(crate::documents::get_document(&self.conn, id)?);
+                }
+            }
+        }
+
+        Ok(documents)
+    }
+
+    /// Checks whether a chat exists without loading it.
+    pub fn chat_exists(&self, chat_id: &str) -> Result<bool, rusqlite::Error> {
+        self.conn
+            .prepare("SELECT 1 FROM chats WHERE id = ?1 LIMIT 1")?
+            .exists(params![chat_id])
+    }
+
+    /// Gets the most recently updated chat with all its messages, so the
+    /// last conversation can be reopened on launch.
+    ///
+    /// Pinning is ignored. Returns None if there are no chats.
+    pub fn get_most_recent_chat(&self) -> Result<Option<ChatWithMessages>, rusqlite::Error> {
+        let chat_id: String = match self.conn.query_row(
+            "SELECT id FROM chats ORDER BY updated_at DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        ) {
+            Ok(id) => id,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        self.get_chat(&chat_id)
+    }
+
+    /// Gets a chat's summary: message count, last message preview and token estimate.
+    ///
+    /// Uses one aggregate query plus one row for the latest message.
+    /// Returns None if the chat doesn't exist.
+    pub fn get_chat_summary(&self, chat_id: &str) -> Result<Option<ChatSummary>, rusqlite::Error> {
+        // LEFT JOIN so a chat without messages still yields a row (with zero counts)
+        let result = self.conn.query_row(
+            "SELECT c.id, c.title, COUNT(m.id), COALESCE(SUM(LENGTH(m.content)), 0)
+             FROM chats c LEFT JOIN messages m ON m.chat_id = c.id
+             WHERE c.id = ?1
+             GROUP BY c.id",
+            params![chat_id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)? as usize,
+                    row.get::<_, i64>(3)? as usize,
+                ))
+            },
+        );
+
+        let (id, title, message_count, total_chars) = match result {
+            Ok(row) => row,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        // Only fetch one character past the preview length, to know if it was cut
+        let last = self.conn.query_row(
+            "SELECT substr(content, 1, ?2) FROM messages
+             WHERE chat_id = ?1 ORDER BY timestamp DESC LIMIT 1",
+            params![chat_id, PREVIEW_CHARS as i64 + 1],
+            |row| row.get::<_, String>(0),
+        );
+
+        let last_message_preview = match last {
+            Ok(content) => Some(preview(&content)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(e),
+        };
+
+        Ok(Some(ChatSummary {
+            id,
+            title,
+            message_count,
+            last_message_preview,
+            approx_tokens: total_chars.div_ceil(CHARS_PER_TOKEN),
+        }))
+    }
+
+    /// Gets the latest `limit` messages of a chat, oldest first.
+    ///
+    /// For building LLM prompts from the last few turns without loading
+    /// the whole conversation.
+    pub fn get_recent_messages(
+        &self,
+        chat_id: &str,
+        limit: usize,
+    ) -> Result<Vec<Message>, rusqlite::Error> {
+        // Take the newest rows, then flip them back into chronological order
+        let mut stmt = self.conn.prepare(
+            "SELECT id, chat_id, role, content, timestamp, sources
+             FROM messages WHERE chat_id = ?1 ORDER BY timestamp DESC LIMIT ?2"
+        )?;
+
+        let messages = stmt.query_map(params![chat_id, limit as i64], row_to_message)?;
+        let mut messages = messages.collect::<Result<Vec<_>, _>>()?;
+        messages.reverse();
+
+        Ok(messages)
+    }
+
     /// Deletes a chat and all its messages (via CASCADE).
     pub fn delete_chat(&self, chat_id: &str) -> Result<bool, rusqlite::Error> {
         let rows_affected = self.conn.execute(
@@ -243,10 +820,30 @@ impl Database {
     }
 
     /// Adds a message to a chat.
+    ///
+    /// Its token count is left NULL for `backfill_message_token_counts`;
+    /// use `add_message_counted` to count with a tokenizer right away.
     pub fn add_message(&self, message: &Message) -> Result<(), rusqlite::Error> {
+        self.insert_message(message, None)
+    }
+
+    /// Adds a message to a chat, storing `count_tokens(content)` as its token count.
+    pub fn add_message_counted(
+        &self,
+        message: &Message,
+        count_tokens: impl Fn(&str) -> usize,
+    ) -> Result<(), rusqlite::Error> {
+        self.insert_message(message, Some(count_tokens(&message.content)))
+    }
+
+    fn insert_message(
+        &self,
+        message: &Message,
+        token_count: Option<usize>,
+    ) -> Result<(), rusqlite::Error> {
         self.conn.execute(
-            "INSERT INTO messages (id, chat_id, role, content, timestamp, sources)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO messages (id, chat_id, role, content, timestamp, sources, token_count)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
             params![
                 message.id,
                 message.chat_id,
@@ -254,6 +851,7 @@ impl Database {
                 message.content,
                 message.timestamp.to_rfc3339(),
                 message.sources,
+                token_count.map(|count| count as i64),
             ],
         )?;
 
@@ -266,75 +864,678 @@ impl Database {
         Ok(())
     }
 
-    /// Updates a chat's title.
-    pub fn update_chat_title(&self, chat_id: &str, title: &str) -> Result<(), rusqlite::Error> {
-        self.conn.execute(
-            "UPDATE chats SET title = ?1, updated_at = ?2 WHERE id = ?3",
-            params![title, Utc::now().to_rfc3339(), chat_id],
-        )?;
-        Ok(())
-    }
-}
+    /// Deletes a single message.
+    ///
+    /// The chat's `updated_at` is set back to the timestamp of its latest
+    /// remaining message, so deleting the newest message doesn't leave the
+    /// chat sorted as if it were still active. If no messages remain,
+    /// `updated_at` is left as it was. Returns true if a message was deleted.
+    pub fn delete_message(&self, message_id: &str) -> Result<bool, rusqlite::Error> {
+        let tx = self.conn.unchecked_transaction()?;
 
-/// Helper function to parse datetime strings.
-///
-/// Falls back to current time if parsing fails - this is a pragmatic choice
-/// to prevent crashes on corrupted data. In production, you might want
-/// to handle this differently based on your requirements.
-fn parse_datetime(s: &str) -> DateTime<Utc> {
-    DateTime::parse_from_rfc3339(s)
-        .map(|dt| dt.with_timezone(&Utc))
-        .unwrap_or_else(|_| Utc::now())
-}
+        let chat_id: String = match tx.query_row(
+            "SELECT chat_id FROM messages WHERE id = ?1",
+            params![message_id],
+            |row| row.get(0),
+        ) {
+            Ok(chat_id) => chat_id,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(false),
+            Err(e) => return Err(e),
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        tx.execute("DELETE FROM messages WHERE id = ?1", params![message_id])?;
 
-    /// Tests run with `cargo test` in the src-tauri directory.
-    /// The `#[test]` attribute marks this as a test function.
-    #[test]
-    fn test_create_and_retrieve_chat() {
-        // ":memory:" creates an in-memory database for testing
-        let db = Database::new(":memory:").unwrap();
+        // MAX over RFC 3339 strings works because every timestamp is stored in UTC
+        tx.execute(
+            "UPDATE chats SET updated_at = (SELECT MAX(timestamp) FROM messages WHERE chat_id = ?1)
+             WHERE id = ?1 AND EXISTS (SELECT 1 FROM messages WHERE chat_id = ?1)",
+            params![chat_id],
+        )?;
 
-        let chat = db.create_chat("test-1", "Test Chat").unwrap();
-        assert_eq!(chat.id, "test-1");
-        assert_eq!(chat.title, "Test Chat");
+        tx.commit()?;
+        Ok(true)
+    }
 
-        let chats = db.get_all_chats().unwrap();
-        assert_eq!(chats.len(), 1);
-        assert_eq!(chats[0].title, "Test Chat");
+    /// Adds many messages at once, inside a single transaction.
+    ///
+    /// Much faster than calling `add_message` in a loop when restoring or
+    /// importing a conversation. Each affected chat's `updated_at` is bumped
+    /// once, after all messages are inserted. Either every message lands or none do.
+    /// Token counts are left NULL, as in `add_message`.
+    pub fn add_messages(&self, messages: &[Message]) -> Result<(), rusqlite::Error> {
+        self.insert_messages(messages, |_| None)
     }
 
-    #[test]
-    fn test_add_message() {
-        let db = Database::new(":memory:").unwrap();
-        db.create_chat("chat-1", "Test").unwrap();
+    /// Like `add_messages`, counting each message's tokens with `count_tokens`.
+    pub fn add_messages_counted(
+        &self,
+        messages: &[Message],
+        count_tokens: impl Fn(&str) -> usize,
+    ) -> Result<(), rusqlite::Error> {
+        self.insert_messages(messages, |content| Some(count_tokens(content)))
+    }
 
-        let msg = Message {
-            id: "msg-1".to_string(),
-            chat_id: "chat-1".to_string(),
-            role: "user".to_string(),
-            content: "Hello!".to_string(),
-            timestamp: Utc::now(),
-            sources: None,
-        };
+    fn insert_messages(
+        &self,
+        messages: &[Message],
+        count_tokens: impl Fn(&str) -> Option<usize>,
+    ) -> Result<(), rusqlite::Error> {
+        // `unchecked_transaction` works through `&self`; the transaction
+        // rolls back automatically if it's dropped without commit
+        let tx = self.conn.unchecked_transaction()?;
 
-        db.add_message(&msg).unwrap();
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO messages (id, chat_id, role, content, timestamp, sources, token_count)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
 
-        let chat = db.get_chat("chat-1").unwrap().unwrap();
-        assert_eq!(chat.messages.len(), 1);
-        assert_eq!(chat.messages[0].content, "Hello!");
-    }
+            for message in messages {
+                stmt.execute(params![
+                    message.id,
+                    message.chat_id,
+                    message.role,
+                    message.content,
+                    message.timestamp.to_rfc3339(),
+                    message.sources,
+                    count_tokens(&message.content).map(|count| count as i64),
+                ])?;
+            }
+        }
 
-    #[test]
-    fn test_delete_chat_cascades() {
-        let db = Database::new(":memory:").unwrap();
-        db.create_chat("chat-1", "Test").unwrap();
+        let mut chat_ids: Vec<&str> = messages.iter().map(|m| m.chat_id.as_str()).collect();
+        chat_ids.sort_unstable();
+        chat_ids.dedup();
 
-        let msg = Message {
-            id: "msg-1".to_string(),
+        let now = Utc::now().to_rfc3339();
+        for chat_id in chat_ids {
+            tx.execute(
+                "UPDATE chats SET updated_at = ?1 WHERE id = ?2",
+                params![now, chat_id],
+            )?;
+        }
+
+        tx.commit()
+    }
+
+    /// Imports a chat exported with `get_chat`, keeping its ids and timestamps.
+    ///
+    /// If a chat with the same id exists, it's replaced when `overwrite` is
+    /// set and the import is rejected otherwise. A message id already used by
+    /// another chat is always rejected. Runs in one transaction, so a failed
+    /// import changes nothing. Token counts are left NULL, as in `add_message`.
+    pub fn import_chat(&self, chat: ChatWithMessages, overwrite: bool) -> Result<(), DbError> {
+        if let Some(message) = chat.messages.iter().find(|m| m.chat_id != chat.id) {
+            return Err(DbError::InvalidInput(format!(
+                "Message {} belongs to chat {}, not {}",
+                message.id, message.chat_id, chat.id
+            )));
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM chats WHERE id = ?1)",
+            params![chat.id],
+            |row| row.get(0),
+        )?;
+        if exists {
+            if !overwrite {
+                return Err(DbError::InvalidInput(format!("Chat already exists: {}", chat.id)));
+            }
+            // Cascades to the old messages
+            tx.execute("DELETE FROM chats WHERE id = ?1", params![chat.id])?;
+        }
+
+        tx.execute(
+            "INSERT INTO chats (id, title, created_at, updated_at) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                chat.id,
+                chat.title,
+                chat.created_at.to_rfc3339(),
+                chat.updated_at.to_rfc3339()
+            ],
+        )?;
+
+        {
+            let mut taken = tx.prepare("SELECT EXISTS(SELECT 1 FROM messages WHERE id = ?1)")?;
+            let mut insert = tx.prepare(
+                "INSERT INTO messages (id, chat_id, role, content, timestamp, sources, token_count)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+
+            for message in &chat.messages {
+                if taken.query_row(params![message.id], |row| row.get(0))? {
+                    return Err(DbError::InvalidInput(format!(
+                        "Message id already in use: {}",
+                        message.id
+                    )));
+                }
+                insert.execute(params![
+                    message.id,
+                    message.chat_id,
+                    message.role,
+                    message.content,
+                    message.timestamp.to_rfc3339(),
+                    message.sources,
+                    None::<i64>,
+                ])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Total tokens across a chat's messages, for fitting it into a context window.
+    ///
+    /// Messages stored before token counts existed (and not yet backfilled)
+    /// count as their length-based estimate. A missing chat totals 0.
+    pub fn get_chat_token_total(&self, chat_id: &str) -> Result<usize, rusqlite::Error> {
+        let total: i64 = self.conn.query_row(
+            "SELECT COALESCE(SUM(COALESCE(token_count, (length(content) + ?2 - 1) / ?2)), 0)
+             FROM messages WHERE chat_id = ?1",
+            params![chat_id, CHARS_PER_TOKEN as i64],
+            |row| row.get(0),
+        )?;
+        Ok(total as usize)
+    }
+
+    /// Number of messages sent on each day, oldest first, for a usage chart.
+    ///
+    /// Days are `YYYY-MM-DD` in UTC, and days without messages are left out.
+    /// `chat_id` limits the count to one chat; `None` counts all chats.
+    pub fn messages_per_day(
+        &self,
+        chat_id: Option<String>,
+    ) -> Result<Vec<(String, usize)>, rusqlite::Error> {
+        // SQLite's date() understands the stored RFC 3339 timestamps,
+        // including their UTC offset
+        let mut stmt = self.conn.prepare(
+            "SELECT date(timestamp) AS day, COUNT(*) FROM messages
+             WHERE ?1 IS NULL OR chat_id = ?1
+             GROUP BY day
+             ORDER BY day ASC"
+        )?;
+
+        let days = stmt.query_map(params![chat_id], |row| {
+            Ok((row.get(0)?, row.get::<_, i64>(1)? as usize))
+        })?;
+        days.collect()
+    }
+
+    /// Counts tokens for messages that don't have a token count yet.
+    ///
+    /// Backfills messages stored before token counts were tracked.
+    /// Returns the number of messages updated.
+    pub fn backfill_message_token_counts(
+        &self,
+        count_tokens: impl Fn(&str) -> usize,
+    ) -> Result<usize, rusqlite::Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, content FROM messages WHERE token_count IS NULL")?;
+        let counts = stmt
+            .query_map([], |row| {
+                let id: String = row.get(0)?;
+                let content: String = row.get(1)?;
+                Ok((id, count_tokens(&content)))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        let mut updated = 0;
+        for (id, count) in counts {
+            updated += tx.execute(
+                "UPDATE messages SET token_count = ?1 WHERE id = ?2",
+                params![count as i64, id],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(updated)
+    }
+
+    /// Updates a chat's title.
+    ///
+    /// Returns true if the chat exists and was updated, false if there's
+    /// no chat with that id. Empty or whitespace-only titles are rejected.
+    pub fn update_chat_title(&self, chat_id: &str, title: &str) -> Result<bool, DbError> {
+        if title.trim().is_empty() {
+            return Err(DbError::InvalidInput("Chat title cannot be empty".to_string()));
+        }
+
+        let rows_affected = self.conn.execute(
+            "UPDATE chats SET title = ?1, updated_at = ?2 WHERE id = ?3",
+            params![title, Utc::now().to_rfc3339(), chat_id],
+        )?;
+        Ok(rows_affected > 0)
+    }
+
+    /// Gets just a chat's title, without loading its messages.
+    pub fn get_chat_title(&self, chat_id: &str) -> Result<Option<String>, rusqlite::Error> {
+        let result = self.conn.query_row(
+            "SELECT title FROM chats WHERE id = ?1",
+            params![chat_id],
+            |row| row.get(0),
+        );
+
+        match result {
+            Ok(title) => Ok(Some(title)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Pins or unpins a chat.
+    ///
+    /// Returns true if the chat exists. Doesn't touch `updated_at`,
+    /// so unpinning drops the chat back to its chronological position.
+    pub fn set_chat_pinned(&self, chat_id: &str, pinned: bool) -> Result<bool, rusqlite::Error> {
+        let rows_affected = self.conn.execute(
+            "UPDATE chats SET pinned = ?1 WHERE id = ?2",
+            params![pinned, chat_id],
+        )?;
+        Ok(rows_affected > 0)
+    }
+
+    /// Tags a chat, creating the tag if it doesn't exist yet.
+    ///
+    /// Tagging a chat with a tag it already has is a no-op.
+    pub fn add_chat_tag(&self, chat_id: &str, tag: &str) -> Result<(), rusqlite::Error> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO tags (name) VALUES (?1)",
+            params![tag],
+        )?;
+
+        self.conn.execute(
+            "INSERT OR IGNORE INTO chat_tags (chat_id, tag_id)
+             SELECT ?1, id FROM tags WHERE name = ?2",
+            params![chat_id, tag],
+        )?;
+
+        Ok(())
+    }
+
+    /// Removes a tag from a chat.
+    ///
+    /// Returns true if the chat had the tag.
+    pub fn remove_chat_tag(&self, chat_id: &str, tag: &str) -> Result<bool, rusqlite::Error> {
+        let rows_affected = self.conn.execute(
+            "DELETE FROM chat_tags
+             WHERE chat_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+            params![chat_id, tag],
+        )?;
+        Ok(rows_affected > 0)
+    }
+
+    /// Gets the tag names on a chat, alphabetically.
+    pub fn get_chat_tags(&self, chat_id: &str) -> Result<Vec<String>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT t.name FROM tags t
+             JOIN chat_tags ct ON ct.tag_id = t.id
+             WHERE ct.chat_id = ?1
+             ORDER BY t.name"
+        )?;
+
+        let tags = stmt.query_map(params![chat_id], |row| row.get(0))?;
+        tags.collect()
+    }
+
+    /// Gets all chats with a given tag, ordered like `get_all_chats`.
+    pub fn get_chats_by_tag(&self, tag: &str) -> Result<Vec<Chat>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.id, c.title, c.created_at, c.updated_at, c.pinned FROM chats c
+             JOIN chat_tags ct ON ct.chat_id = c.id
+             JOIN tags t ON t.id = ct.tag_id
+             WHERE t.name = ?1
+             ORDER BY c.pinned DESC, c.updated_at DESC"
+        )?;
+
+        let chats = stmt.query_map(params![tag], row_to_chat)?;
+        chats.collect()
+    }
+
+    /// Reads a setting, parsed as `T`. Returns `None` if it isn't set.
+    ///
+    /// A stored value that doesn't parse as `T` is an `InvalidInput` error.
+    pub fn get_setting<T>(&self, key: &str) -> Result<Option<T>, DbError>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        let value: String = match self.conn.query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![key],
+            |row| row.get(0),
+        ) {
+            Ok(value) => value,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        value.parse().map(Some).map_err(|e| {
+            DbError::InvalidInput(format!("Setting {} has invalid value '{}': {}", key, value, e))
+        })
+    }
+
+    /// Stores a setting, replacing any previous value.
+    pub fn set_setting<T: ToString>(&self, key: &str, value: T) -> Result<(), rusqlite::Error> {
+        self.conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Reads all settings, using the default for any that aren't stored.
+    pub fn get_settings(&self) -> Result<Settings, DbError> {
+        let defaults = Settings::default();
+        Ok(Settings {
+            retrieval_top_k: self
+                .get_setting(SETTING_RETRIEVAL_TOP_K)?
+                .unwrap_or(defaults.retrieval_top_k),
+            min_score: self.get_setting(SETTING_MIN_SCORE)?.unwrap_or(defaults.min_score),
+            chunk_size: self.get_setting(SETTING_CHUNK_SIZE)?.unwrap_or(defaults.chunk_size),
+            normalize_chunks: self
+                .get_setting(SETTING_NORMALIZE_CHUNKS)?
+                .unwrap_or(defaults.normalize_chunks),
+            normalize_scores: self
+                .get_setting(SETTING_NORMALIZE_SCORES)?
+                .unwrap_or(defaults.normalize_scores),
+        })
+    }
+
+    /// Stores all settings in one transaction.
+    ///
+    /// `retrieval_top_k` and `chunk_size` must be positive and `min_score`
+    /// finite; otherwise nothing is stored.
+    pub fn update_settings(&self, settings: &Settings) -> Result<(), DbError> {
+        if settings.retrieval_top_k == 0 {
+            return Err(DbError::InvalidInput("retrieval_top_k must be greater than 0".to_string()));
+        }
+        if settings.chunk_size == 0 {
+            return Err(DbError::InvalidInput("chunk_size must be greater than 0".to_string()));
+        }
+        if !settings.min_score.is_finite() {
+            return Err(DbError::InvalidInput("min_score must be a finite number".to_string()));
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        self.set_setting(SETTING_RETRIEVAL_TOP_K, settings.retrieval_top_k)?;
+        self.set_setting(SETTING_MIN_SCORE, settings.min_score)?;
+        self.set_setting(SETTING_CHUNK_SIZE, settings.chunk_size)?;
+        self.set_setting(SETTING_NORMALIZE_CHUNKS, settings.normalize_chunks)?;
+        self.set_setting(SETTING_NORMALIZE_SCORES, settings.normalize_scores)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// Maps a `SELECT id, title, created_at, updated_at, pinned` row to a Chat.
+fn row_to_chat(row: &rusqlite::Row) -> Result<Chat, rusqlite::Error> {
+    Ok(Chat {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        // Parse ISO 8601 datetime strings back to DateTime<Utc>
+        created_at: parse_datetime(&row.get::<_, String>(2)?),
+        updated_at: parse_datetime(&row.get::<_, String>(3)?),
+        pinned: row.get(4)?,
+    })
+}
+
+/// Maps a `SELECT id, chat_id, role, content, timestamp, sources` row to a Message.
+fn row_to_message(row: &rusqlite::Row) -> Result<Message, rusqlite::Error> {
+    Ok(Message {
+        id: row.get(0)?,
+        chat_id: row.get(1)?,
+        role: row.get(2)?,
+        content: row.get(3)?,
+        timestamp: parse_datetime(&row.get::<_, String>(4)?),
+        sources: row.get(5)?,
+    })
+}
+
+/// Adds a column to an existing table if it isn't there yet.
+///
+/// `CREATE TABLE IF NOT EXISTS` leaves tables from older app versions
+/// untouched, so columns added later are migrated in with this. Returns
+/// true if the column was added, for migrations that backfill it.
+pub(crate) fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<bool, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+
+    Ok(!exists)
+}
+
+/// Escapes `LIKE` wildcards (and the escape character itself) with `\`.
+fn escape_like(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Shortens `content` to `PREVIEW_CHARS` characters, adding "..." if cut.
+fn preview(content: &str) -> String {
+    if content.chars().count() > PREVIEW_CHARS {
+        let cut: String = content.chars().take(PREVIEW_CHARS).collect();
+        format!("{}...", cut.trim_end())
+    } else {
+        content.to_string()
+    }
+}
+
+/// Helper function to parse datetime strings.
+///
+/// Falls back to current time if parsing fails - this is a pragmatic choice
+/// to prevent crashes on corrupted data. In production, you might want
+/// to handle this differently based on your requirements.
+fn parse_datetime(s: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests run with `cargo test` in the src-tauri directory.
+    /// The `#[test]` attribute marks this as a test function.
+    #[test]
+    fn test_create_and_retrieve_chat() {
+        // ":memory:" creates an in-memory database for testing
+        let db = Database::new(":memory:").unwrap();
+
+        let chat = db.create_chat("test-1", "Test Chat").unwrap();
+        assert_eq!(chat.id, "test-1");
+        assert_eq!(chat.title, "Test Chat");
+
+        let chats = db.get_all_chats().unwrap();
+        assert_eq!(chats.len(), 1);
+        assert_eq!(chats[0].title, "Test Chat");
+    }
+
+    #[test]
+    fn test_add_message() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Test").unwrap();
+
+        let msg = Message {
+            id: "msg-1".to_string(),
+            chat_id: "chat-1".to_string(),
+            role: "user".to_string(),
+            content: "Hello!".to_string(),
+            timestamp: Utc::now(),
+            sources: None,
+        };
+
+        db.add_message(&msg).unwrap();
+
+        let chat = db.get_chat("chat-1").unwrap().unwrap();
+        assert_eq!(chat.messages.len(), 1);
+        assert_eq!(chat.messages[0].content, "Hello!");
+    }
+
+    #[test]
+    fn test_chat_token_total() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Test").unwrap();
+        db.create_chat("chat-2", "Other").unwrap();
+
+        let message = |id: &str, chat_id: &str, content: &str| Message {
+            id: id.to_string(),
+            chat_id: chat_id.to_string(),
+            role: "user".to_string(),
+            content: content.to_string(),
+            timestamp: Utc::now(),
+            sources: None,
+        };
+        // One "token" per word, standing in for a tokenizer
+        let count_words = |text: &str| text.split_whitespace().count();
+
+        db.add_message_counted(&message("msg-1", "chat-1", "one two three"), count_words)
+            .unwrap();
+        db.add_messages_counted(
+            &[
+                message("msg-2", "chat-1", "four five"),
+                message("msg-3", "chat-1", "six"),
+                message("msg-4", "chat-2", "not in chat one"),
+            ],
+            count_words,
+        )
+        .unwrap();
+        // Left uncounted, so estimated: 9 characters at 4 per token rounds up to 3
+        db.add_message(&message("msg-5", "chat-1", "ninechars")).unwrap();
+
+        let stored: i64 = db
+            .conn
+            .query_row(
+                "SELECT SUM(token_count) FROM messages WHERE chat_id = 'chat-1'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(db.get_chat_token_total("chat-1").unwrap(), stored as usize + 3);
+        assert_eq!(db.get_chat_token_total("chat-1").unwrap(), 3 + 2 + 1 + 3);
+        assert_eq!(db.get_chat_token_total("chat-2").unwrap(), 4);
+        assert_eq!(db.get_chat_token_total("missing").unwrap(), 0);
+
+        // Rows from before token counts fall back to the estimate until backfilled
+        db.conn
+            .execute("UPDATE messages SET token_count = NULL WHERE id = 'msg-1'", [])
+            .unwrap();
+        assert_eq!(db.get_chat_token_total("chat-1").unwrap(), 4 + 2 + 1 + 3);
+        assert_eq!(db.backfill_message_token_counts(count_words).unwrap(), 2);
+        assert_eq!(db.backfill_message_token_counts(count_words).unwrap(), 0);
+        assert_eq!(db.get_chat_token_total("chat-1").unwrap(), 3 + 2 + 1 + 1);
+    }
+
+    #[test]
+    fn test_delete_message_fixes_chat_timestamp() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Test").unwrap();
+
+        let start = Utc::now() - chrono::Duration::hours(3);
+        let messages: Vec<Message> = (0..3)
+            .map(|i| Message {
+                id: format!("msg-{}", i),
+                chat_id: "chat-1".to_string(),
+                role: "user".to_string(),
+                content: format!("Message {}", i),
+                timestamp: start + chrono::Duration::hours(i),
+                sources: None,
+            })
+            .collect();
+        db.add_messages(&messages).unwrap();
+
+        // Deleting a middle message keeps the latest one's timestamp
+        assert!(db.delete_message("msg-1").unwrap());
+        let chat = db.get_chat("chat-1").unwrap().unwrap();
+        let ids: Vec<&str> = chat.messages.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["msg-0", "msg-2"]);
+        assert_eq!(chat.updated_at, messages[2].timestamp);
+
+        // Deleting the latest falls back to the one before it
+        assert!(db.delete_message("msg-2").unwrap());
+        let chat = db.get_chat("chat-1").unwrap().unwrap();
+        assert_eq!(chat.updated_at, messages[0].timestamp);
+
+        // Deleting the last message leaves updated_at alone
+        assert!(db.delete_message("msg-0").unwrap());
+        let chat = db.get_chat("chat-1").unwrap().unwrap();
+        assert!(chat.messages.is_empty());
+        assert_eq!(chat.updated_at, messages[0].timestamp);
+
+        assert!(!db.delete_message("msg-0").unwrap());
+    }
+
+    #[test]
+    fn test_messages_per_day() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Test").unwrap();
+        db.create_chat("chat-2", "Other").unwrap();
+
+        let at = |rfc3339: &str| DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Utc);
+        for (id, chat_id, timestamp) in [
+            ("msg-1", "chat-1", at("2024-03-01T09:00:00.123456789Z")),
+            ("msg-2", "chat-1", at("2024-03-01T23:59:59Z")),
+            // 01:30 on the 3rd in UTC+3 is still the 2nd in UTC
+            ("msg-3", "chat-1", at("2024-03-03T01:30:00+03:00")),
+            ("msg-4", "chat-2", at("2024-03-02T12:00:00Z")),
+        ] {
+            db.add_message(&Message {
+                id: id.to_string(),
+                chat_id: chat_id.to_string(),
+                role: "user".to_string(),
+                content: "Hi".to_string(),
+                timestamp,
+                sources: None,
+            })
+            .unwrap();
+        }
+
+        let day = |date: &str, count| (date.to_string(), count);
+        assert_eq!(
+            db.messages_per_day(None).unwrap(),
+            vec![day("2024-03-01", 2), day("2024-03-02", 2)]
+        );
+        assert_eq!(
+            db.messages_per_day(Some("chat-2".to_string())).unwrap(),
+            vec![day("2024-03-02", 1)]
+        );
+        assert!(db.messages_per_day(Some("missing".to_string())).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_delete_chat_cascades() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Test").unwrap();
+
+        let msg = Message {
+            id: "msg-1".to_string(),
             chat_id: "chat-1".to_string(),
             role: "user".to_string(),
             content: "Hello!".to_string(),
@@ -349,4 +1550,824 @@ mod tests {
         let chat = db.get_chat("chat-1").unwrap();
         assert!(chat.is_none());
     }
+
+    #[test]
+    fn test_find_similar_chats_by_first_message() {
+        let db = Database::new(":memory:").unwrap();
+        for (chat_id, embedding) in [
+            ("chat-1", [1.0, 0.0, 0.0]),
+            ("chat-2", [0.99, 0.14, 0.0]),
+            ("chat-3", [0.0, 0.0, 1.0]),
+        ] {
+            db.create_chat(chat_id, "Test").unwrap();
+            db.set_first_message_embedding(chat_id, &embedding).unwrap();
+        }
+        db.create_chat("not-embedded", "Test").unwrap();
+
+        let similar = db.find_similar_chats_by_first_message(&[1.0, 0.0, 0.0], 0.95).unwrap();
+        let ids: Vec<&str> = similar.iter().map(|(chat, _)| chat.id.as_str()).collect();
+        assert_eq!(ids, vec!["chat-1", "chat-2"]);
+        assert!(similar[0].1 > similar[1].1);
+
+        // Deleting a chat removes its embedding
+        db.delete_chat("chat-2").unwrap();
+        let similar = db.find_similar_chats_by_first_message(&[1.0, 0.0, 0.0], 0.95).unwrap();
+        assert_eq!(similar.len(), 1);
+    }
+
+    #[test]
+    fn test_chat_cited_documents() {
+        use crate::documents::{self, Document, DocumentType};
+
+        let db = Database::new(":memory:").unwrap();
+        for id in ["doc-1", "doc-2", "doc-3"] {
+            let doc = Document {
+                id: id.to_string(),
+                name: format!("{}.txt", id),
+                doc_type: DocumentType::Txt,
+                size: 1,
+                uploaded_at: Utc::now(),
+                path: format!("/tmp/{}.txt", id),
+                word_count: 0,
+                source_path: format!("/tmp/{}.txt", id),
+                modified_at: None,
+                language: None,
+            };
+            documents::save_document(&db.conn, &doc).unwrap();
+        }
+
+        db.create_chat("chat-1", "Cited").unwrap();
+        let base = Utc::now();
+        let sources = [
+            ("user", Some(r#"[{"documentId":"doc-3"}]"#)),
+            ("assistant", Some(r#"[{"documentId":"doc-2"},{"documentId":"doc-1"}]"#)),
+            ("assistant", None),
+            ("assistant", Some("not json")),
+            ("assistant", Some(r#"[{"documentId":"doc-1"},{"chunkId":"x"}]"#)),
+            ("assistant", Some(r#"[{"documentId":"deleted"}]"#)),
+        ];
+        for (i, (role, sources)) in sources.into_iter().enumerate() {
+            db.add_message(&Message {
+                id: format!("msg-{}", i),
+                chat_id: "chat-1".to_string(),
+                role: role.to_string(),
+                content: "Answer".to_string(),
+                timestamp: base + chrono::Duration::seconds(i as i64),
+                sources: sources.map(str::to_string),
+            })
+            .unwrap();
+        }
+
+        let cited: Vec<String> = db
+            .get_chat_cited_documents("chat-1")
+            .unwrap()
+            .into_iter()
+            .map(|doc| doc.id)
+            .collect();
+        assert_eq!(cited, vec!["doc-2", "doc-1"]);
+        assert!(db.get_chat_cited_documents("missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_chat_exists() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Chat").unwrap();
+
+        assert!(db.chat_exists("chat-1").unwrap());
+        assert!(!db.chat_exists("missing").unwrap());
+
+        db.delete_chat("chat-1").unwrap();
+        assert!(!db.chat_exists("chat-1").unwrap());
+    }
+
+    #[test]
+    fn test_most_recent_chat() {
+        let db = Database::new(":memory:").unwrap();
+        assert!(db.get_most_recent_chat().unwrap().is_none());
+
+        db.create_chat("older", "Older").unwrap();
+        db.create_chat("newer", "Newer").unwrap();
+        db.conn
+            .execute(
+                "UPDATE chats SET updated_at = ?1 WHERE id = 'older'",
+                params!["2020-01-01T00:00:00+00:00"],
+            )
+            .unwrap();
+        db.add_message(&Message {
+            id: "msg-1".to_string(),
+            chat_id: "newer".to_string(),
+            role: "user".to_string(),
+            content: "Hello".to_string(),
+            timestamp: Utc::now(),
+            sources: None,
+        })
+        .unwrap();
+
+        let chat = db.get_most_recent_chat().unwrap().unwrap();
+        assert_eq!(chat.id, "newer");
+        assert_eq!(chat.messages.len(), 1);
+
+        // Pinning doesn't count; touching the older chat does
+        db.set_chat_pinned("newer", true).unwrap();
+        assert_eq!(db.get_most_recent_chat().unwrap().unwrap().id, "newer");
+        db.update_chat_title("older", "Renamed").unwrap();
+        assert_eq!(db.get_most_recent_chat().unwrap().unwrap().id, "older");
+    }
+
+    #[test]
+    fn test_pinned_chats_sort_first() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("old", "Old").unwrap();
+        db.create_chat("new", "New").unwrap();
+
+        // Make "new" clearly the most recently updated
+        db.conn
+            .execute(
+                "UPDATE chats SET updated_at = ?1 WHERE id = 'old'",
+                params!["2020-01-01T00:00:00+00:00"],
+            )
+            .unwrap();
+
+        let chats = db.get_all_chats().unwrap();
+        assert_eq!(chats[0].id, "new");
+        assert!(!chats[0].pinned);
+
+        assert!(db.set_chat_pinned("old", true).unwrap());
+        let chats = db.get_all_chats().unwrap();
+        assert_eq!(chats[0].id, "old");
+        assert!(chats[0].pinned);
+        assert_eq!(chats[1].id, "new");
+
+        // Unknown chats report false
+        assert!(!db.set_chat_pinned("missing", true).unwrap());
+    }
+
+    #[test]
+    fn test_pinned_migration_on_existing_db() {
+        // A directory of its own, so the -wal and -shm files go with it
+        let dir = std::env::temp_dir().join(format!("chat-migration-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chat.db");
+
+        // Simulate a database created before the pinned column existed
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute(
+                "CREATE TABLE chats (
+                    id TEXT PRIMARY KEY,
+                    title TEXT NOT NULL,
+                    created_at TEXT NOT NULL,
+                    updated_at TEXT NOT NULL
+                )",
+                [],
+            )
+            .unwrap();
+            let now = Utc::now().to_rfc3339();
+            conn.execute(
+                "INSERT INTO chats (id, title, created_at, updated_at) VALUES ('c1', 'Legacy', ?1, ?1)",
+                params![now],
+            )
+            .unwrap();
+        }
+
+        let db = Database::new(&path).unwrap();
+        let chats = db.get_all_chats().unwrap();
+        assert_eq!(chats.len(), 1);
+        assert!(!chats[0].pinned);
+        assert!(db.set_chat_pinned("c1", true).unwrap());
+
+        drop(db);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_chat_tags() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Rust notes").unwrap();
+        db.create_chat("chat-2", "Recipes").unwrap();
+
+        db.add_chat_tag("chat-1", "work").unwrap();
+        db.add_chat_tag("chat-1", "rust").unwrap();
+        db.add_chat_tag("chat-1", "rust").unwrap(); // duplicate is ignored
+        db.add_chat_tag("chat-2", "home").unwrap();
+
+        assert_eq!(db.get_chat_tags("chat-1").unwrap(), vec!["rust", "work"]);
+
+        let work_chats = db.get_chats_by_tag("work").unwrap();
+        assert_eq!(work_chats.len(), 1);
+        assert_eq!(work_chats[0].id, "chat-1");
+        assert!(db.get_chats_by_tag("unknown").unwrap().is_empty());
+
+        assert!(db.remove_chat_tag("chat-1", "work").unwrap());
+        assert!(!db.remove_chat_tag("chat-1", "work").unwrap());
+        assert_eq!(db.get_chat_tags("chat-1").unwrap(), vec!["rust"]);
+    }
+
+    #[test]
+    fn test_delete_chat_removes_tag_associations() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Test").unwrap();
+        db.add_chat_tag("chat-1", "work").unwrap();
+
+        db.delete_chat("chat-1").unwrap();
+
+        let count: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM chat_tags", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+        assert!(db.get_chats_by_tag("work").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_update_chat_title() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Old").unwrap();
+
+        assert!(db.update_chat_title("chat-1", "New").unwrap());
+        assert_eq!(db.get_chat_title("chat-1").unwrap().as_deref(), Some("New"));
+
+        // Missing chat reports false rather than silently succeeding
+        assert!(!db.update_chat_title("missing", "New").unwrap());
+
+        // Blank titles are rejected and leave the title unchanged
+        assert!(matches!(
+            db.update_chat_title("chat-1", "   "),
+            Err(DbError::InvalidInput(_))
+        ));
+        assert_eq!(db.get_chat_title("chat-1").unwrap().as_deref(), Some("New"));
+    }
+
+    #[test]
+    fn test_add_messages_batch() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Test").unwrap();
+
+        // Count updated_at bumps with a temporary trigger
+        db.conn
+            .execute_batch(
+                "CREATE TEMP TABLE bumps (n INTEGER);
+                 CREATE TEMP TRIGGER count_bumps AFTER UPDATE OF updated_at ON chats
+                 BEGIN INSERT INTO bumps VALUES (1); END;",
+            )
+            .unwrap();
+
+        let base = Utc::now();
+        let messages: Vec<Message> = (0..100)
+            .map(|i| Message {
+                id: format!("msg-{}", i),
+                chat_id: "chat-1".to_string(),
+                role: if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
+                content: format!("Message {}", i),
+                timestamp: base + chrono::Duration::seconds(i),
+                sources: None,
+            })
+            .collect();
+
+        db.add_messages(&messages).unwrap();
+
+        let chat = db.get_chat("chat-1").unwrap().unwrap();
+        assert_eq!(chat.messages.len(), 100);
+        assert_eq!(chat.messages[0].content, "Message 0");
+        assert_eq!(chat.messages[99].content, "Message 99");
+
+        let bumps: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM bumps", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(bumps, 1);
+    }
+
+    #[test]
+    fn test_import_chat_round_trip() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Exported").unwrap();
+        let base = Utc::now() - chrono::Duration::days(3);
+        for i in 0..3 {
+            db.add_message(&Message {
+                id: format!("msg-{}", i),
+                chat_id: "chat-1".to_string(),
+                role: if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
+                content: format!("Message {}", i),
+                timestamp: base + chrono::Duration::seconds(i),
+                sources: (i == 1).then(|| "[{\"documentId\":\"doc-1\"}]".to_string()),
+            })
+            .unwrap();
+        }
+        let exported = db.get_chat("chat-1").unwrap().unwrap();
+        let json = serde_json::to_string(&exported).unwrap();
+
+        let fresh = Database::new(":memory:").unwrap();
+        fresh.import_chat(serde_json::from_str(&json).unwrap(), false).unwrap();
+        assert_eq!(fresh.get_chat("chat-1").unwrap().unwrap(), exported);
+        assert_eq!(fresh.get_chat_token_total("chat-1").unwrap(), 9);
+        // Imported counts are left for the backfill
+        assert_eq!(fresh.backfill_message_token_counts(estimate_tokens).unwrap(), 3);
+
+        // A second import collides unless it overwrites
+        let mut edited = exported.clone();
+        edited.title = "Edited".to_string();
+        edited.messages.truncate(1);
+        assert!(matches!(
+            fresh.import_chat(edited.clone(), false),
+            Err(DbError::InvalidInput(_))
+        ));
+        assert_eq!(fresh.get_chat("chat-1").unwrap().unwrap(), exported);
+        fresh.import_chat(edited.clone(), true).unwrap();
+        assert_eq!(fresh.get_chat("chat-1").unwrap().unwrap(), edited);
+
+        // Message ids owned by another chat are rejected, leaving nothing behind
+        let mut other = exported.clone();
+        other.id = "chat-2".to_string();
+        for message in &mut other.messages {
+            message.chat_id = "chat-2".to_string();
+        }
+        assert!(matches!(fresh.import_chat(other, true), Err(DbError::InvalidInput(_))));
+        assert!(fresh.get_chat("chat-2").unwrap().is_none());
+
+        // So are messages that don't belong to the imported chat
+        let mut mismatched = exported;
+        mismatched.messages[0].chat_id = "elsewhere".to_string();
+        assert!(matches!(fresh.import_chat(mismatched, true), Err(DbError::InvalidInput(_))));
+        assert_eq!(fresh.get_chat("chat-1").unwrap().unwrap(), edited);
+    }
+
+    #[test]
+    fn test_settings_seeded_and_round_trip() {
+        let db = Database::new(":memory:").unwrap();
+
+        // Defaults are seeded into the table, not just returned as a fallback
+        assert_eq!(db.get_settings().unwrap(), Settings::default());
+        assert_eq!(db.get_setting::<usize>(SETTING_RETRIEVAL_TOP_K).unwrap(), Some(5));
+        assert_eq!(db.get_setting::<String>("missing").unwrap(), None);
+
+        db.set_setting(SETTING_MIN_SCORE, 0.35f32).unwrap();
+        assert_eq!(db.get_setting::<f32>(SETTING_MIN_SCORE).unwrap(), Some(0.35));
+
+        let settings = Settings {
+            retrieval_top_k: 8,
+            min_score: 0.5,
+            chunk_size: 1500,
+            normalize_chunks: false,
+            normalize_scores: true,
+        };
+        db.update_settings(&settings).unwrap();
+        assert_eq!(db.get_settings().unwrap(), settings);
+
+        // Invalid values are rejected without storing anything
+        let invalid = Settings {
+            retrieval_top_k: 0,
+            ..settings.clone()
+        };
+        assert!(matches!(db.update_settings(&invalid), Err(DbError::InvalidInput(_))));
+        assert_eq!(db.get_settings().unwrap(), settings);
+
+        // A stored value of the wrong type is an error, not a silent default
+        db.set_setting(SETTING_CHUNK_SIZE, "large").unwrap();
+        assert!(matches!(db.get_settings(), Err(DbError::InvalidInput(_))));
+
+        // Settings outlive a reset, and reopening doesn't re-seed over them
+        db.set_setting(SETTING_CHUNK_SIZE, 1500).unwrap();
+        db.reset().unwrap();
+        assert_eq!(db.get_settings().unwrap(), settings);
+    }
+
+    #[test]
+    fn test_add_messages_rolls_back_on_error() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Test").unwrap();
+
+        let message = Message {
+            id: "dup".to_string(),
+            chat_id: "chat-1".to_string(),
+            role: "user".to_string(),
+            content: "Hello".to_string(),
+            timestamp: Utc::now(),
+            sources: None,
+        };
+
+        // Duplicate primary key fails the second insert
+        assert!(db.add_messages(&[message.clone(), message]).is_err());
+
+        let chat = db.get_chat("chat-1").unwrap().unwrap();
+        assert!(chat.messages.is_empty());
+    }
+
+    #[test]
+    fn test_foreign_keys_enabled_and_cascade_removes_messages() {
+        let db = Database::new(":memory:").unwrap();
+
+        let enabled: i64 = db
+            .conn
+            .query_row("PRAGMA foreign_keys", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(enabled, 1);
+
+        db.create_chat("chat-1", "Test").unwrap();
+        for i in 0..3 {
+            db.add_message(&Message {
+                id: format!("msg-{}", i),
+                chat_id: "chat-1".to_string(),
+                role: "user".to_string(),
+                content: "Hello!".to_string(),
+                timestamp: Utc::now(),
+                sources: None,
+            })
+            .unwrap();
+        }
+
+        assert!(db.delete_chat("chat-1").unwrap());
+
+        // Check the rows themselves: get_chat returning None doesn't prove
+        // the messages were removed
+        let remaining: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM messages WHERE chat_id = 'chat-1'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+
+    #[test]
+    fn test_reset_empties_all_tables() {
+        use crate::documents::{self, Document, DocumentType};
+
+        let db = Database::new(":memory:").unwrap();
+
+        // Seed every table
+        db.create_chat("chat-1", "Test").unwrap();
+        db.add_message(&Message {
+            id: "msg-1".to_string(),
+            chat_id: "chat-1".to_string(),
+            role: "user".to_string(),
+            content: "Hello".to_string(),
+            timestamp: Utc::now(),
+            sources: None,
+        })
+        .unwrap();
+        db.add_chat_tag("chat-1", "work").unwrap();
+        db.set_first_message_embedding("chat-1", &[1.0, 0.0]).unwrap();
+
+        let doc = Document {
+            id: "doc-1".to_string(),
+            name: "test.txt".to_string(),
+            doc_type: DocumentType::Txt,
+            size: 12,
+            uploaded_at: Utc::now(),
+            path: "/tmp/test.txt".to_string(),
+            word_count: 0,
+            source_path: "/tmp/test.txt".to_string(),
+            modified_at: None,
+            language: None,
+        };
+        documents::save_document(&db.conn, &doc).unwrap();
+        documents::save_document_content(&db.conn, "doc-1", "Test content").unwrap();
+        let config = crate::chunker::ChunkConfig::default();
+        let chunks = crate::chunker::chunk_text("doc-1", "Test content", &config).unwrap();
+        crate::chunker::replace_document_chunks(&db.conn, "doc-1", &chunks, &config).unwrap();
+        let chunk_id = crate::chunker::get_document_chunks(&db.conn, "doc-1").unwrap()[0].id.clone();
+        crate::vector_store::save_embedding(&db.conn, &chunk_id, "doc-1", &[1.0, 0.0], "test-model")
+            .unwrap();
+
+        let count = |table: &str| -> i64 {
+            db.conn
+                .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+                .unwrap()
+        };
+        for table in ALL_TABLES {
+            assert!(count(table) > 0, "{} should be seeded", table);
+        }
+
+        db.reset().unwrap();
+
+        // Tables still exist (COUNT would fail otherwise) but are empty
+        for table in ALL_TABLES {
+            assert_eq!(count(table), 0, "{} should be empty", table);
+        }
+
+        // And the schema is usable again
+        db.create_chat("chat-2", "After reset").unwrap();
+        assert_eq!(db.get_all_chats().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_chat_summary() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Summary").unwrap();
+        db.create_chat("empty", "Empty").unwrap();
+
+        let now = Utc::now();
+        let long = "x".repeat(100);
+        let contents = ["Hi", "Hello! How can I help?", long.as_str()];
+        for (i, content) in contents.iter().enumerate() {
+            db.add_message(&Message {
+                id: format!("msg-{}", i),
+                chat_id: "chat-1".to_string(),
+                role: if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
+                content: content.to_string(),
+                timestamp: now + chrono::Duration::seconds(i as i64),
+                sources: None,
+            })
+            .unwrap();
+        }
+
+        let summary = db.get_chat_summary("chat-1").unwrap().unwrap();
+        assert_eq!(summary.title, "Summary");
+        assert_eq!(summary.message_count, 3);
+        assert_eq!(summary.last_message_preview, Some(format!("{}...", "x".repeat(80))));
+        // 2 + 22 + 100 = 124 characters
+        assert_eq!(summary.approx_tokens, 31);
+
+        let empty = db.get_chat_summary("empty").unwrap().unwrap();
+        assert_eq!(empty.message_count, 0);
+        assert_eq!(empty.last_message_preview, None);
+        assert_eq!(empty.approx_tokens, 0);
+
+        assert!(db.get_chat_summary("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_repair_integrity_removes_orphans() {
+        let db = Database::new(":memory:").unwrap();
+
+        // Write dangling rows with enforcement off, as an older version might have
+        db.conn.execute("PRAGMA foreign_keys = OFF", []).unwrap();
+        crate::vector_store::save_embedding(&db.conn, "gone-0", "gone", &[1.0, 0.0], "test-model")
+            .unwrap();
+        crate::chunker::save_chunks(
+            &db.conn,
+            &[crate::chunker::Chunk {
+                id: "gone-1".to_string(),
+                document_id: "gone".to_string(),
+                chunk_index: 1,
+                content: "Orphaned chunk".to_string(),
+                start_offset: 0,
+                end_offset: 14,
+            }],
+        )
+        .unwrap();
+        db.conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+
+        let report = db.repair_integrity().unwrap();
+        assert_eq!(
+            report,
+            RepairReport {
+                orphaned_embeddings: 1,
+                orphaned_chunks: 1,
+            }
+        );
+
+        // Nothing left to repair
+        assert_eq!(db.repair_integrity().unwrap(), RepairReport::default());
+    }
+
+    #[test]
+    fn test_concurrent_writers_wait_for_lock() {
+        let dir = std::env::temp_dir().join(format!("chat-busy-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chat.db");
+        let first = Database::new(&path).unwrap();
+        let second = Database::new(&path).unwrap();
+
+        let mode: String =
+            first.conn.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+        assert_eq!(mode, "wal");
+
+        // Hold the write lock briefly on another thread
+        first.conn.execute_batch("BEGIN IMMEDIATE").unwrap();
+        first.create_chat("chat-1", "First").unwrap();
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            first.conn.execute_batch("COMMIT").unwrap();
+        });
+
+        // Waits for the lock instead of failing with "database is locked"
+        second.create_chat("chat-2", "Second").unwrap();
+        holder.join().unwrap();
+        assert_eq!(second.get_all_chats().unwrap().len(), 2);
+
+        // Without a timeout the same write fails straight away
+        let impatient = Database::with_options(
+            &path,
+            ConnectionOptions { busy_timeout: Duration::ZERO, ..Default::default() },
+        )
+        .unwrap();
+        second.conn.execute_batch("BEGIN IMMEDIATE").unwrap();
+        assert!(impatient.create_chat("chat-3", "Third").is_err());
+        second.conn.execute_batch("ROLLBACK").unwrap();
+
+        drop((second, impatient));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_optimize_reclaims_space() {
+        let dir = std::env::temp_dir().join(format!("chat-optimize-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chat.db");
+        let db = Database::new(&path).unwrap();
+
+        db.create_chat("chat-1", "Big chat").unwrap();
+        let messages: Vec<Message> = (0..500)
+            .map(|i| Message {
+                id: format!("msg-{}", i),
+                chat_id: "chat-1".to_string(),
+                role: "user".to_string(),
+                content: "x".repeat(1000),
+                timestamp: Utc::now(),
+                sources: None,
+            })
+            .collect();
+        db.add_messages(&messages).unwrap();
+        db.delete_chat("chat-1").unwrap();
+
+        let report = db.optimize().unwrap();
+        assert!(report.size_after < report.size_before);
+        assert_eq!(report.size_after, std::fs::metadata(&path).unwrap().len());
+
+        drop(db);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_in_memory_supports_ingest_pipeline() {
+        use crate::documents::{self, Document, DocumentType};
+
+        let db = Database::in_memory().unwrap();
+
+        let doc = Document {
+            id: "doc-1".to_string(),
+            name: "notes.txt".to_string(),
+            doc_type: DocumentType::Txt,
+            size: 40,
+            uploaded_at: Utc::now(),
+            path: "/tmp/notes.txt".to_string(),
+            word_count: 7,
+            source_path: "/tmp/notes.txt".to_string(),
+            modified_at: None,
+            language: None,
+        };
+        documents::save_document(&db.conn, &doc).unwrap();
+        let text = "Rust has ownership. Python has GC.";
+        documents::save_document_content(&db.conn, "doc-1", text).unwrap();
+
+        let config = crate::chunker::ChunkConfig::new(20, 0).unwrap();
+        let chunks = crate::chunker::chunk_text("doc-1", text, &config).unwrap();
+        crate::chunker::save_chunks(&db.conn, &chunks).unwrap();
+        assert!(chunks.len() >= 2);
+
+        // Give each chunk a distinct unit vector
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut embedding = vec![0.0; chunks.len()];
+            embedding[i] = 1.0;
+            crate::vector_store::save_embedding(
+                &db.conn,
+                &chunk.id,
+                "doc-1",
+                &embedding,
+                "test-model",
+            )
+            .unwrap();
+        }
+
+        let mut query = vec![0.0; chunks.len()];
+        query[0] = 1.0;
+        let results = crate::vector_store::search_similar(
+            &db.conn,
+            &query,
+            1,
+            "test-model",
+            crate::embeddings::SimilarityMetric::Dot,
+        )
+        .unwrap();
+        assert_eq!(results[0].chunk_id, chunks[0].id);
+
+        // A second in-memory database is completely separate
+        let other = Database::in_memory().unwrap();
+        assert!(documents::get_all_documents(&other.conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_new_creates_every_table() {
+        let db = Database::new(":memory:").unwrap();
+
+        for table in ALL_TABLES {
+            let exists: bool = db
+                .conn
+                .query_row(
+                    "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+                    params![table],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert!(exists, "{} should be created by Database::new", table);
+        }
+
+        // Chunks and embeddings can be written straight away
+        db.conn
+            .execute(
+                "INSERT INTO documents (id, name, doc_type, size, uploaded_at, path)
+                 VALUES ('doc-1', 'a.txt', 'txt', 1, ?1, '/tmp/a.txt')",
+                params![Utc::now().to_rfc3339()],
+            )
+            .unwrap();
+        crate::chunker::save_chunks(
+            &db.conn,
+            &[crate::chunker::Chunk {
+                id: "doc-1-0".to_string(),
+                document_id: "doc-1".to_string(),
+                chunk_index: 0,
+                content: "a".to_string(),
+                start_offset: 0,
+                end_offset: 1,
+            }],
+        )
+        .unwrap();
+        crate::vector_store::save_embedding(&db.conn, "doc-1-0", "doc-1", &[1.0], "test-model")
+            .unwrap();
+        assert!(crate::vector_store::has_embedding(&db.conn, "doc-1-0").unwrap());
+    }
+
+    #[test]
+    fn test_find_chats_by_title() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("rust", "Learning Rust ownership").unwrap();
+        db.create_chat("trust", "Building TRUST with users").unwrap();
+        db.create_chat("python", "Python tips").unwrap();
+        db.create_chat("percent", "Growth of 100% this year").unwrap();
+        db.create_chat("underscore", "snake_case naming").unwrap();
+
+        // Partial and case-insensitive matches, newest first
+        let ids = |query: &str| -> Vec<String> {
+            db.find_chats_by_title(query).unwrap().into_iter().map(|c| c.id).collect()
+        };
+        assert_eq!(ids("rust"), vec!["trust", "rust"]);
+        assert_eq!(ids("PYTH"), vec!["python"]);
+        assert!(ids("golang").is_empty());
+
+        // Wildcard characters match literally
+        assert_eq!(ids("100%"), vec!["percent"]);
+        assert_eq!(ids("%"), vec!["percent"]);
+        assert_eq!(ids("e_c"), vec!["underscore"]);
+        assert!(ids("Learning_Rust").is_empty());
+    }
+
+    #[test]
+    fn test_get_recent_messages() {
+        let db = Database::new(":memory:").unwrap();
+        db.create_chat("chat-1", "Test").unwrap();
+
+        let now = Utc::now();
+        for i in 0..10 {
+            db.add_message(&Message {
+                id: format!("msg-{}", i),
+                chat_id: "chat-1".to_string(),
+                role: "user".to_string(),
+                content: format!("Message {}", i),
+                timestamp: now + chrono::Duration::seconds(i),
+                sources: None,
+            })
+            .unwrap();
+        }
+
+        let recent = db.get_recent_messages("chat-1", 3).unwrap();
+        let ids: Vec<&str> = recent.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["msg-7", "msg-8", "msg-9"]);
+
+        // A limit past the end returns everything
+        assert_eq!(db.get_recent_messages("chat-1", 50).unwrap().len(), 10);
+        assert!(db.get_recent_messages("missing", 3).unwrap().is_empty());
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn test_encrypted_database_needs_correct_key() {
+        let dir = std::env::temp_dir().join(format!("chat-encrypted-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chat.db");
+
+        {
+            let db = Database::new_encrypted(&path, "correct horse").unwrap();
+            db.create_chat("chat-1", "Secret chat").unwrap();
+        }
+
+        // Nothing readable on disk
+        let raw = std::fs::read(&path).unwrap();
+        assert!(!raw.starts_with(b"SQLite format 3"));
+        assert!(!raw.windows(11).any(|w| w == b"Secret chat"));
+
+        // The right key reopens it
+        {
+            let db = Database::new_encrypted(&path, "correct horse").unwrap();
+            assert_eq!(db.get_chat("chat-1").unwrap().unwrap().title, "Secret chat");
+        }
+
+        // A wrong key, an empty key or no key at all fail clearly
+        let err = Database::new_encrypted(&path, "wrong key").err().unwrap();
+        assert!(err.to_string().contains("wrong encryption key"), "{}", err);
+        assert!(Database::new_encrypted(&path, "").is_err());
+        let err = Database::new(&path).err().unwrap();
+        assert!(err.to_string().contains("encrypted"), "{}", err);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
 }
@@ -1,7 +1,7 @@
 //! Document loading and management module.
 //!
 //! This module handles:
-//! - Loading documents from disk (PDF, TXT, MD)
+//! - Loading documents from disk (PDF, TXT, MD, RST, Org, EPUB, JSON)
 //! - Extracting text content from different formats
 //! - Storing document metadata in SQLite
 //!
@@ -14,8 +14,12 @@
 use chrono::{DateTime, Utc};
 use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::path::Path;
+use std::io::Cursor;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 /// Supported document types.
 ///
@@ -27,6 +31,14 @@ pub enum DocumentType {
     Pdf,
     Txt,
     Md,
+    /// reStructuredText
+    Rst,
+    /// Emacs Org mode
+    Org,
+    /// EPUB ebook
+    Epub,
+    /// JSON or JSON Lines records
+    Json,
 }
 
 impl DocumentType {
@@ -38,6 +50,10 @@ impl DocumentType {
             "pdf" => Some(DocumentType::Pdf),
             "txt" => Some(DocumentType::Txt),
             "md" | "markdown" => Some(DocumentType::Md),
+            "rst" => Some(DocumentType::Rst),
+            "org" => Some(DocumentType::Org),
+            "epub" => Some(DocumentType::Epub),
+            "json" | "jsonl" | "ndjson" => Some(DocumentType::Json),
             _ => None,
         }
     }
@@ -48,6 +64,10 @@ impl DocumentType {
             DocumentType::Pdf => "pdf",
             DocumentType::Txt => "txt",
             DocumentType::Md => "md",
+            DocumentType::Rst => "rst",
+            DocumentType::Org => "org",
+            DocumentType::Epub => "epub",
+            DocumentType::Json => "json",
         }
     }
 }
@@ -63,6 +83,15 @@ pub struct Document {
     pub uploaded_at: DateTime<Utc>,
     /// Path where the document is stored
     pub path: String,
+    /// Number of words in the extracted text, computed at ingest
+    pub word_count: u64,
+    /// Where the file was ingested from (`path` is the app's own copy, or the
+    /// same file if the upload didn't copy it into the store)
+    pub source_path: String,
+    /// The source file's modification time at ingest, if the OS reports one
+    pub modified_at: Option<DateTime<Utc>>,
+    /// ISO 639-3 code of the content's language (`None` if it couldn't be detected)
+    pub language: Option<String>,
 }
 
 /// Result of loading a document - includes both metadata and extracted text.
@@ -80,9 +109,17 @@ pub struct LoadedDocument {
 pub enum DocumentError {
     IoError(std::io::Error),
     PdfError(String),
+    EpubError(String),
+    JsonError(String),
     UnsupportedFormat(String),
     DatabaseError(rusqlite::Error),
     NotFound(String),
+    /// The file is bigger than the allowed maximum, in bytes
+    TooLarge { size: u64, limit: u64 },
+    /// A stored row holds a value the app can't read back, e.g. an unknown `doc_type`
+    CorruptRow(String),
+    /// The request was rejected, e.g. a blank name
+    InvalidInput(String),
 }
 
 impl std::fmt::Display for DocumentError {
@@ -90,9 +127,16 @@ impl std::fmt::Display for DocumentError {
         match self {
             DocumentError::IoError(e) => write!(f, "IO error: {}", e),
             DocumentError::PdfError(e) => write!(f, "PDF error: {}", e),
+            DocumentError::EpubError(e) => write!(f, "EPUB error: {}", e),
+            DocumentError::JsonError(e) => write!(f, "JSON error: {}", e),
             DocumentError::UnsupportedFormat(ext) => write!(f, "Unsupported format: {}", ext),
             DocumentError::DatabaseError(e) => write!(f, "Database error: {}", e),
             DocumentError::NotFound(id) => write!(f, "Document not found: {}", id),
+            DocumentError::TooLarge { size, limit } => {
+                write!(f, "File too large: {} bytes (limit is {} bytes)", size, limit)
+            }
+            DocumentError::CorruptRow(msg) => write!(f, "Corrupt document row: {}", msg),
+            DocumentError::InvalidInput(msg) => write!(f, "{}", msg),
         }
     }
 }
@@ -108,10 +152,33 @@ impl From<std::io::Error> for DocumentError {
 
 impl From<rusqlite::Error> for DocumentError {
     fn from(e: rusqlite::Error) -> Self {
-        DocumentError::DatabaseError(e)
+        match e {
+            // Raised by `row_to_document`; unwrap it so callers can match on it
+            rusqlite::Error::FromSqlConversionFailure(_, _, ref source)
+                if source.is::<CorruptRow>() =>
+            {
+                DocumentError::CorruptRow(source.to_string())
+            }
+            e => DocumentError::DatabaseError(e),
+        }
+    }
+}
+
+/// Why `row_to_document` rejected a row.
+///
+/// Row mappers can only return `rusqlite::Error`, so this travels inside
+/// one and becomes `DocumentError::CorruptRow` on conversion.
+#[derive(Debug)]
+struct CorruptRow(String);
+
+impl std::fmt::Display for CorruptRow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
+impl std::error::Error for CorruptRow {}
+
 /// Initialize the documents table in SQLite.
 pub fn init_documents_table(conn: &Connection) -> Result<(), rusqlite::Error> {
     conn.execute(
@@ -121,11 +188,45 @@ pub fn init_documents_table(conn: &Connection) -> Result<(), rusqlite::Error> {
             doc_type TEXT NOT NULL,
             size INTEGER NOT NULL,
             uploaded_at TEXT NOT NULL,
-            path TEXT NOT NULL
+            path TEXT NOT NULL,
+            word_count INTEGER NOT NULL DEFAULT 0,
+            source_path TEXT NOT NULL DEFAULT '',
+            modified_at TEXT,
+            language TEXT
         )",
         [],
     )?;
 
+    // Migration: older databases lack word counts; existing rows get 0
+    // until `recompute_word_counts` backfills them, below
+    let word_count_added = crate::db::add_column_if_missing(
+        conn,
+        "documents",
+        "word_count",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+
+    // Migration: older databases didn't record where files came from or their mtime
+    crate::db::add_column_if_missing(conn, "documents", "source_path", "TEXT NOT NULL DEFAULT ''")?;
+    crate::db::add_column_if_missing(conn, "documents", "modified_at", "TEXT")?;
+
+    // Migration: language detection was added later; existing rows stay NULL
+    crate::db::add_column_if_missing(conn, "documents", "language", "TEXT")?;
+
+    // Migration: file hashes were added later; existing rows stay NULL, so
+    // their files aren't recognized as already ingested
+    crate::db::add_column_if_missing(conn, "documents", "file_hash", "TEXT")?;
+
+    // Migration: retrieval boosts were added later; existing documents rank normally
+    crate::db::add_column_if_missing(conn, "documents", "boost", "REAL NOT NULL DEFAULT 1.0")?;
+
+    // Migration: muting was added later; existing documents stay searchable
+    crate::db::add_column_if_missing(conn, "documents", "muted", "INTEGER NOT NULL DEFAULT 0")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_documents_file_hash ON documents(file_hash)",
+        [],
+    )?;
+
     // Also create a table to store extracted text content
     // This avoids re-extracting text every time we need it
     conn.execute(
@@ -137,70 +238,563 @@ pub fn init_documents_table(conn: &Connection) -> Result<(), rusqlite::Error> {
         [],
     )?;
 
+    if word_count_added {
+        recompute_word_counts(conn)?;
+    }
+
     Ok(())
 }
 
-/// Extract text from a PDF file.
+/// Metadata from a PDF's document information dictionary.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfMeta {
+    /// The `Title` entry, if set and not blank
+    pub title: Option<String>,
+    /// The `Author` entry, if set and not blank
+    pub author: Option<String>,
+    pub page_count: usize,
+}
+
+/// Read a PDF's title, author and page count without extracting its text.
+pub fn extract_pdf_metadata(path: &Path) -> Result<PdfMeta, DocumentError> {
+    let bytes = fs::read(path)?;
+    Ok(pdf_meta(&load_pdf(&bytes)?))
+}
+
+/// Extract text from PDF bytes already in memory, all pages or just
+/// `pages`, along with the PDF's metadata.
+///
+/// Pages are numbered from 1, as in PDF viewers, and the end is exclusive:
+/// `2..4` is pages 2 and 3. The whole document is still parsed; the range
+/// only limits which pages' text is extracted. An end past the last page
+/// is clamped; an empty range, or one starting past the last page, is an
+/// error.
 ///
 /// PDF extraction can be tricky - not all PDFs have extractable text
 /// (e.g., scanned documents). The `pdf-extract` crate handles common cases.
-fn extract_pdf_text(path: &Path) -> Result<String, DocumentError> {
-    // Read the PDF bytes
-    let bytes = fs::read(path)?;
+fn extract_pdf_from_mem(
+    bytes: &[u8],
+    pages: Option<Range<usize>>,
+) -> Result<(String, PdfMeta), DocumentError> {
+    let pdf_error = |e: &dyn std::fmt::Display| DocumentError::PdfError(e.to_string());
 
-    // Extract text using pdf-extract
-    // This crate handles the complexity of PDF parsing
-    pdf_extract::extract_text_from_mem(&bytes)
-        .map_err(|e| DocumentError::PdfError(e.to_string()))
+    let pdf = load_pdf(bytes)?;
+    let meta = pdf_meta(&pdf);
+
+    let mut text = String::new();
+    let mut output = pdf_extract::PlainTextOutput::new(&mut text);
+    match pages {
+        None => pdf_extract::output_doc(&pdf, &mut output).map_err(|e| pdf_error(&e))?,
+        Some(pages) => {
+            if pages.start == 0 || pages.is_empty() || pages.start > meta.page_count {
+                return Err(pdf_error(&format!(
+                    "Invalid page range {}..{} for a PDF with {} pages (pages start at 1)",
+                    pages.start, pages.end, meta.page_count
+                )));
+            }
+            for page in pages.start..pages.end.min(meta.page_count + 1) {
+                pdf_extract::output_doc_page(&pdf, &mut output, page as u32)
+                    .map_err(|e| pdf_error(&e))?;
+            }
+        }
+    }
+    Ok((text, meta))
+}
+
+/// Parse a PDF, opening it if it's encrypted with an empty password as
+/// `pdf_extract::extract_text_from_mem` does.
+fn load_pdf(bytes: &[u8]) -> Result<pdf_extract::Document, DocumentError> {
+    let pdf_error = |e: &dyn std::fmt::Display| DocumentError::PdfError(e.to_string());
+
+    let mut pdf = pdf_extract::Document::load_mem(bytes).map_err(|e| pdf_error(&e))?;
+    if pdf.is_encrypted() {
+        pdf.decrypt("").map_err(|e| pdf_error(&e))?;
+    }
+    Ok(pdf)
+}
+
+/// Reads the metadata of a parsed PDF. Missing or undecodable entries
+/// are left out.
+fn pdf_meta(pdf: &pdf_extract::Document) -> PdfMeta {
+    let info = pdf
+        .trailer
+        .get(b"Info")
+        .and_then(|info| pdf.dereference(info))
+        .and_then(|(_, info)| info.as_dict())
+        .ok();
+    let text_entry = |key: &[u8]| {
+        let value = info?.get(key).and_then(|value| pdf.dereference(value)).ok()?.1;
+        let text = pdf_extract::decode_text_string(value).ok()?;
+        Some(text.trim().to_string()).filter(|text| !text.is_empty())
+    };
+
+    PdfMeta {
+        title: text_entry(b"Title"),
+        author: text_entry(b"Author"),
+        page_count: pdf.get_pages().len(),
+    }
+}
+
+/// Extract text from an EPUB ebook in memory, along with its title if it has one.
+///
+/// Chapters are read in spine (reading) order, each one's XHTML reduced to
+/// plain text, and joined with blank lines so chapter breaks are paragraph
+/// breaks for the chunker. Also returns the title from the book's metadata.
+fn extract_epub_text_from_mem(bytes: &[u8]) -> Result<(String, Option<String>), DocumentError> {
+    let mut book = epub::doc::EpubDoc::from_reader(Cursor::new(bytes.to_vec()))
+        .map_err(|e| DocumentError::EpubError(e.to_string()))?;
+
+    let title = book
+        .mdata("title")
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty());
+
+    let mut chapters = Vec::new();
+    loop {
+        if let Some((html, _mime)) = book.get_current_str() {
+            let text = html_to_text(&html);
+            if !text.is_empty() {
+                chapters.push(text);
+            }
+        }
+        if !book.go_next() {
+            break;
+        }
+    }
+
+    Ok((chapters.join("\n\n"), title))
+}
+
+/// Elements whose start and end separate paragraphs.
+const HTML_BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "body", "dd", "div", "dl", "dt", "figcaption",
+    "figure", "footer", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li", "nav", "ol",
+    "p", "pre", "section", "table", "tr", "ul",
+];
+
+/// Elements whose content isn't text the reader sees.
+const HTML_SKIPPED_TAGS: &[&str] = &["head", "script", "style"];
+
+/// Reduces an (X)HTML page to plain text.
+///
+/// Tags are dropped, block elements (paragraphs, headings, list items, ...)
+/// become blank lines, `<br>` a line break, and runs of whitespace collapse
+/// to one space. Common entities are decoded. Everything in `<head>`,
+/// `<script>` and `<style>` is skipped.
+fn html_to_text(html: &str) -> String {
+    let mut out = String::new();
+    let mut skipping: Option<String> = None; // Tag whose end we're waiting for
+    let mut rest = html;
+
+    while let Some(lt) = rest.find('<') {
+        if skipping.is_none() {
+            push_html_text(&mut out, &rest[..lt]);
+        }
+        rest = &rest[lt..];
+
+        // Comments can contain '>', so find their real end
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let Some(gt) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..gt];
+        rest = &rest[gt + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+
+        if let Some(skipped) = &skipping {
+            if closing && name == *skipped {
+                skipping = None;
+            }
+            continue;
+        }
+
+        if HTML_SKIPPED_TAGS.contains(&name.as_str()) && !closing && !tag.ends_with('/') {
+            skipping = Some(name);
+        } else if name == "br" {
+            end_html_line(&mut out, "\n");
+        } else if HTML_BLOCK_TAGS.contains(&name.as_str()) {
+            end_html_line(&mut out, "\n\n");
+        }
+    }
+    if skipping.is_none() {
+        push_html_text(&mut out, rest);
+    }
+
+    out.trim().to_string()
+}
+
+/// Appends text between tags, decoding entities and collapsing whitespace.
+fn push_html_text(out: &mut String, text: &str) {
+    for c in decode_html_entities(text).chars() {
+        if c.is_whitespace() {
+            // Collapse runs, and never start a line with a space
+            if !(out.is_empty() || out.ends_with(' ') || out.ends_with('\n')) {
+                out.push(' ');
+            }
+        } else {
+            out.push(c);
+        }
+    }
+}
+
+/// Ends the current line, so `out` ends with `break_with` (`"\n"` or `"\n\n"`).
+fn end_html_line(out: &mut String, break_with: &str) {
+    let trimmed = out.trim_end_matches(' ').len();
+    out.truncate(trimmed);
+    if out.is_empty() || out.ends_with(break_with) {
+        return;
+    }
+    // Upgrade a single line break to a blank line rather than adding a third
+    if out.ends_with('\n') && break_with == "\n\n" {
+        out.push('\n');
+    } else {
+        out.push_str(break_with);
+    }
+}
+
+/// Decodes `&amp;`-style named entities and numeric character references.
+///
+/// Only the entities common in ebooks are known; others are left as-is.
+fn decode_html_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let decoded = rest.find(';').and_then(|semi| {
+            let entity = &rest[1..semi];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => {
+                    let hex = entity.strip_prefix("#x").or(entity.strip_prefix("#X"));
+                    let code = match hex {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                    };
+                    code.and_then(char::from_u32)
+                }
+            };
+            c.map(|c| (c, semi))
+        });
+
+        match decoded {
+            Some((c, semi)) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Extract text from a JSON or JSON Lines file in memory.
+///
+/// Each record becomes a paragraph: every line if `json_lines` is set (for
+/// `.jsonl`/`.ndjson` files), every element of a top-level JSON array, or
+/// else the whole document. `text_fields` picks which fields of a record
+/// are rendered, as dot paths like `author.name` (arrays along the way are
+/// searched element by element). Without it, every string value in the
+/// record is rendered.
+fn extract_json_text_from_mem(
+    bytes: &[u8],
+    json_lines: bool,
+    text_fields: Option<&[String]>,
+) -> Result<String, DocumentError> {
+    let text = extract_text_from_mem(bytes)?;
+
+    let records: Vec<Value> = if json_lines {
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| DocumentError::JsonError(format!("line {}: {}", i + 1, e)))
+            })
+            .collect::<Result<_, _>>()?
+    } else {
+        match serde_json::from_str(&text).map_err(|e| DocumentError::JsonError(e.to_string()))? {
+            Value::Array(items) => items,
+            value => vec![value],
+        }
+    };
+
+    let paragraphs: Vec<String> = records
+        .iter()
+        .map(|record| render_json_record(record, text_fields))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect();
+    Ok(paragraphs.join("\n\n"))
+}
+
+/// Whether `path` is JSON Lines (one record per line) rather than JSON.
+fn is_json_lines(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jsonl") || ext.eq_ignore_ascii_case("ndjson"))
+}
+
+/// Renders a JSON record's selected fields (or all its strings), one per line.
+fn render_json_record(record: &Value, text_fields: Option<&[String]>) -> String {
+    let mut values = Vec::new();
+    match text_fields {
+        Some(fields) => {
+            for field in fields {
+                let path: Vec<&str> = field.split('.').collect();
+                collect_json_field(record, &path, &mut values);
+            }
+        }
+        None => collect_json_strings(record, &mut values),
+    }
+    values.join("\n")
+}
+
+/// Follows a dot path into `value` and collects the text of what it reaches.
+///
+/// Arrays are fanned out over, so `authors.name` finds every author's name.
+/// Selected numbers and booleans are included; objects contribute all
+/// their strings.
+fn collect_json_field(value: &Value, path: &[&str], out: &mut Vec<String>) {
+    match (value, path.split_first()) {
+        (Value::Array(items), _) => {
+            for item in items {
+                collect_json_field(item, path, out);
+            }
+        }
+        (Value::Object(map), Some((key, rest))) => {
+            if let Some(value) = map.get(*key) {
+                collect_json_field(value, rest, out);
+            }
+        }
+        (Value::Object(_), None) => collect_json_strings(value, out),
+        (Value::String(s), None) => push_json_text(s, out),
+        (Value::Number(n), None) => out.push(n.to_string()),
+        (Value::Bool(b), None) => out.push(b.to_string()),
+        // Nulls, and paths that continue past a scalar
+        _ => {}
+    }
+}
+
+/// Collects every string value in `value`, depth first.
+fn collect_json_strings(value: &Value, out: &mut Vec<String>) {
+    match value {
+        Value::String(s) => push_json_text(s, out),
+        Value::Array(items) => items.iter().for_each(|item| collect_json_strings(item, out)),
+        Value::Object(map) => map.values().for_each(|value| collect_json_strings(value, out)),
+        _ => {}
+    }
+}
+
+fn push_json_text(s: &str, out: &mut Vec<String>) {
+    let s = s.trim();
+    if !s.is_empty() {
+        out.push(s.to_string());
+    }
 }
 
-/// Extract text from a plain text or markdown file.
+/// Decode a plain text or markup file already in memory.
 ///
-/// For TXT and MD files, we simply read the content as UTF-8.
-/// Markdown is kept as-is (we don't strip formatting).
-fn extract_text_file(path: &Path) -> Result<String, DocumentError> {
-    fs::read_to_string(path).map_err(DocumentError::from)
+/// For TXT, MD, RST and Org files, we simply read the content as UTF-8.
+/// Markup is kept as-is (we don't strip formatting); the chunker uses
+/// the heading markers to pick break points. Invalid UTF-8 is reported as
+/// an IO error, the same as `fs::read_to_string`.
+fn extract_text_from_mem(bytes: &[u8]) -> Result<String, DocumentError> {
+    let text = std::str::from_utf8(bytes)
+        .map_err(|e| DocumentError::from(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))?;
+    Ok(normalize_text_file(text))
+}
+
+/// Strips a leading UTF-8 byte order mark and converts Windows (`\r\n`) and
+/// old Mac (`\r`) line endings to `\n`.
+///
+/// Files saved on Windows often have both. Left in, the BOM ends up in the
+/// first chunk and `\r\n\r\n` hides the `\n\n` paragraph breaks the chunker
+/// looks for.
+fn normalize_text_file(text: &str) -> String {
+    let text = text.strip_prefix('\u{FEFF}').unwrap_or(text);
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// Largest file `load_document` accepts, in bytes (100 MB).
+///
+/// Extraction holds the whole file and its text in memory, so a
+/// multi-gigabyte file could exhaust it. Use `load_document_with_limit`
+/// for a different limit.
+pub const DEFAULT_MAX_DOCUMENT_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Rejects a document of `size` bytes if it's over `limit`.
+fn check_document_size(size: u64, limit: u64) -> Result<(), DocumentError> {
+    if size > limit {
+        return Err(DocumentError::TooLarge { size, limit });
+    }
+    Ok(())
 }
 
 /// Load a document from disk and extract its text content.
 ///
 /// This is the main entry point for document loading.
 /// It determines the file type, extracts text, and returns both
-/// metadata and content.
+/// metadata and content. Files over `DEFAULT_MAX_DOCUMENT_BYTES`
+/// are rejected with `TooLarge`.
 pub fn load_document(path: &Path, id: &str) -> Result<LoadedDocument, DocumentError> {
-    // Get file metadata
+    load_document_with_limit(path, id, DEFAULT_MAX_DOCUMENT_BYTES)
+}
+
+/// Like `load_document`, but rejects files over `max_bytes` instead of the default.
+///
+/// The size comes from the file's metadata, so an oversized file is
+/// rejected before any of it is read.
+pub fn load_document_with_limit(
+    path: &Path,
+    id: &str,
+    max_bytes: u64,
+) -> Result<LoadedDocument, DocumentError> {
+    let options = LoadOptions {
+        max_bytes,
+        ..LoadOptions::default()
+    };
+    load_document_with_options(path, id, &options)
+}
+
+/// Options for `load_document_with_options`.
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    /// Files over this size are rejected with `TooLarge`
+    pub max_bytes: u64,
+    /// Only extract these PDF pages (see `extract_pdf_from_mem`).
+    /// Ignored for other formats.
+    pub pdf_pages: Option<Range<usize>>,
+    /// Only render these fields of JSON records (see `extract_json_text_from_mem`).
+    /// Ignored for other formats.
+    pub json_fields: Option<Vec<String>>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions {
+            max_bytes: DEFAULT_MAX_DOCUMENT_BYTES,
+            pdf_pages: None,
+            json_fields: None,
+        }
+    }
+}
+
+/// Like `load_document`, with a custom size limit, PDF page range or JSON fields.
+pub fn load_document_with_options(
+    path: &Path,
+    id: &str,
+    options: &LoadOptions,
+) -> Result<LoadedDocument, DocumentError> {
+    // Check the size and type before reading the whole file
     let metadata = fs::metadata(path)?;
-    let size = metadata.len();
+    check_document_size(metadata.len(), options.max_bytes)?;
+    document_type(path)?;
+    // Not every platform/filesystem reports an mtime
+    let modified_at = metadata.modified().ok().map(DateTime::<Utc>::from);
 
-    // Determine file type from extension
+    let mut loaded = load_from_mem(&fs::read(path)?, path, id, options)?;
+    loaded.metadata.modified_at = modified_at;
+    Ok(loaded)
+}
+
+/// Load a document from bytes in memory, e.g. a drag-and-drop or clipboard upload.
+///
+/// Works like `load_document`, but the type comes from `filename`'s extension
+/// and nothing is read from disk. `path` and `source_path` are set to the filename,
+/// and there's no modification time, so the document is never reported stale.
+/// The same `DEFAULT_MAX_DOCUMENT_BYTES` limit applies.
+pub fn load_document_from_bytes(
+    bytes: &[u8],
+    filename: &str,
+    id: &str,
+) -> Result<LoadedDocument, DocumentError> {
+    load_from_mem(bytes, Path::new(filename), id, &LoadOptions::default())
+}
+
+/// The type of document `path` is, from its extension.
+fn document_type(path: &Path) -> Result<DocumentType, DocumentError> {
     let extension = path
         .extension()
         .and_then(|e| e.to_str())
         .ok_or_else(|| DocumentError::UnsupportedFormat("no extension".to_string()))?;
 
-    let doc_type = DocumentType::from_extension(extension)
-        .ok_or_else(|| DocumentError::UnsupportedFormat(extension.to_string()))?;
+    DocumentType::from_extension(extension)
+        .ok_or_else(|| DocumentError::UnsupportedFormat(extension.to_string()))
+}
+
+/// Does the work of both loaders once the file's bytes are in memory.
+///
+/// The type comes from `path`'s extension, which is also recorded as the
+/// document's `path` and `source_path`. There's no modification time.
+fn load_from_mem(
+    bytes: &[u8],
+    path: &Path,
+    id: &str,
+    options: &LoadOptions,
+) -> Result<LoadedDocument, DocumentError> {
+    check_document_size(bytes.len() as u64, options.max_bytes)?;
+    let doc_type = document_type(path)?;
 
     // Get filename
-    let name = path
+    let file_name = path
         .file_name()
         .and_then(|n| n.to_str())
         .unwrap_or("unknown")
         .to_string();
 
-    // Extract text based on document type
-    let content = match doc_type {
-        DocumentType::Pdf => extract_pdf_text(path)?,
-        DocumentType::Txt | DocumentType::Md => extract_text_file(path)?,
+    // Extract text based on document type (PDFs and ebooks may also have a title)
+    let (content, title) = match doc_type {
+        DocumentType::Pdf => {
+            let (text, meta) = extract_pdf_from_mem(bytes, options.pdf_pages.clone())?;
+            (text, meta.title)
+        }
+        DocumentType::Txt | DocumentType::Md | DocumentType::Rst | DocumentType::Org => {
+            (extract_text_from_mem(bytes)?, None)
+        }
+        DocumentType::Epub => extract_epub_text_from_mem(bytes)?,
+        DocumentType::Json => {
+            let fields = options.json_fields.as_deref();
+            (extract_json_text_from_mem(bytes, is_json_lines(path), fields)?, None)
+        }
     };
+    let name = title.unwrap_or(file_name);
 
     let document = Document {
         id: id.to_string(),
         name,
         doc_type,
-        size,
+        size: bytes.len() as u64,
         uploaded_at: Utc::now(),
         path: path.to_string_lossy().to_string(),
+        word_count: crate::text::count_words(&content) as u64,
+        source_path: path.to_string_lossy().to_string(),
+        modified_at: None,
+        language: crate::text::detect_language(&content).map(String::from),
     };
 
     Ok(LoadedDocument {
@@ -209,11 +803,154 @@ pub fn load_document(path: &Path, id: &str) -> Result<LoadedDocument, DocumentEr
     })
 }
 
+/// Build a plain text document from text that didn't come from a file,
+/// e.g. notes pasted into the app.
+///
+/// `path` and `source_path` are set to `name`, as for
+/// `load_document_from_bytes`, and the `DEFAULT_MAX_DOCUMENT_BYTES` limit
+/// applies. Line endings are normalized as for `.txt` files.
+pub fn load_document_from_text(
+    text: &str,
+    name: &str,
+    id: &str,
+) -> Result<LoadedDocument, DocumentError> {
+    check_document_size(text.len() as u64, DEFAULT_MAX_DOCUMENT_BYTES)?;
+    let content = normalize_text_file(text);
+
+    let document = Document {
+        id: id.to_string(),
+        name: name.to_string(),
+        doc_type: DocumentType::Txt,
+        size: text.len() as u64,
+        uploaded_at: Utc::now(),
+        path: name.to_string(),
+        word_count: crate::text::count_words(&content) as u64,
+        source_path: name.to_string(),
+        modified_at: None,
+        language: crate::text::detect_language(&content).map(String::from),
+    };
+
+    Ok(LoadedDocument {
+        metadata: document,
+        content,
+    })
+}
+
+/// Whether a file's extension is one `load_document` can handle.
+pub fn is_supported_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .and_then(DocumentType::from_extension)
+        .is_some()
+}
+
+/// Lists the files in `dir`, and in its subdirectories if `recursive` is set.
+///
+/// Symlinked directories aren't followed, so a link cycle can't recurse
+/// forever. Paths are sorted so the order doesn't depend on the filesystem.
+pub fn find_files(dir: &Path, recursive: bool) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                if recursive {
+                    dirs.push(path);
+                }
+            } else if path.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// SHA-256 of a file's bytes, as lowercase hex.
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Record the hash of the file a document was ingested from.
+pub fn set_file_hash(conn: &Connection, document_id: &str, hash: &str) -> Result<(), DocumentError> {
+    conn.execute(
+        "UPDATE documents SET file_hash = ?1 WHERE id = ?2",
+        params![hash, document_id],
+    )?;
+    Ok(())
+}
+
+/// Find a document ingested from a file with this hash, returning its ID.
+pub fn find_document_by_hash(conn: &Connection, hash: &str) -> Result<Option<String>, DocumentError> {
+    let result = conn.query_row(
+        "SELECT id FROM documents WHERE file_hash = ?1 LIMIT 1",
+        params![hash],
+        |row| row.get(0),
+    );
+
+    match result {
+        Ok(id) => Ok(Some(id)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(DocumentError::from(e)),
+    }
+}
+
+/// Set how much a document's chunks are favored in search.
+///
+/// Search scores are weighted by the boost: 1.0 (the default) is neutral,
+/// above 1.0 ranks the document higher. The caller checks that it's
+/// positive. Returns false if there's no document with that ID.
+pub fn set_document_boost(conn: &Connection, id: &str, boost: f32) -> Result<bool, DocumentError> {
+    let rows = conn.execute(
+        "UPDATE documents SET boost = ?1 WHERE id = ?2",
+        params![boost as f64, id],
+    )?;
+    Ok(rows > 0)
+}
+
+/// Mute or unmute a document.
+///
+/// A muted document keeps its chunks and embeddings but is left out of
+/// every search until it's unmuted. Returns false if there's no document
+/// with that ID.
+pub fn set_document_muted(conn: &Connection, id: &str, muted: bool) -> Result<bool, DocumentError> {
+    let rows = conn.execute(
+        "UPDATE documents SET muted = ?1 WHERE id = ?2",
+        params![muted, id],
+    )?;
+    Ok(rows > 0)
+}
+
+/// Change a document's display name, e.g. to something friendlier than
+/// its filename.
+///
+/// Only `name` changes; the stored file and its `path` stay as they are.
+/// The name is trimmed and can't be blank. Returns false if there's no
+/// document with that ID.
+pub fn rename_document(conn: &Connection, id: &str, new_name: &str) -> Result<bool, DocumentError> {
+    let new_name = new_name.trim();
+    if new_name.is_empty() {
+        return Err(DocumentError::InvalidInput("Document name cannot be empty".to_string()));
+    }
+
+    let rows = conn.execute(
+        "UPDATE documents SET name = ?1 WHERE id = ?2",
+        params![new_name, id],
+    )?;
+    Ok(rows > 0)
+}
+
 /// Save document metadata to the database.
 pub fn save_document(conn: &Connection, doc: &Document) -> Result<(), DocumentError> {
     conn.execute(
-        "INSERT INTO documents (id, name, doc_type, size, uploaded_at, path)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        "INSERT INTO documents
+         (id, name, doc_type, size, uploaded_at, path, word_count, source_path, modified_at,
+          language)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
         params![
             doc.id,
             doc.name,
@@ -221,6 +958,10 @@ pub fn save_document(conn: &Connection, doc: &Document) -> Result<(), DocumentEr
             doc.size as i64,
             doc.uploaded_at.to_rfc3339(),
             doc.path,
+            doc.word_count as i64,
+            doc.source_path,
+            doc.modified_at.map(|t| t.to_rfc3339()),
+            doc.language,
         ],
     )?;
     Ok(())
@@ -239,48 +980,114 @@ pub fn save_document_content(
     Ok(())
 }
 
+/// Append text to a document's content, creating it if there's none yet.
+///
+/// For large or streamed sources, so the whole text never has to be held
+/// in memory at once. Fragments are joined as-is, with no separator. Chunk
+/// the document once the last fragment is in: chunk offsets are positions
+/// in the full assembled text, which is what `get_document_content` returns.
+pub fn append_document_content(
+    conn: &Connection,
+    document_id: &str,
+    text: &str,
+) -> Result<(), DocumentError> {
+    conn.execute(
+        "INSERT INTO document_content (document_id, content) VALUES (?1, ?2)
+         ON CONFLICT(document_id) DO UPDATE SET content = content || excluded.content",
+        params![document_id, text],
+    )?;
+    Ok(())
+}
+
 /// Get all documents from the database.
 pub fn get_all_documents(conn: &Connection) -> Result<Vec<Document>, DocumentError> {
-    let mut stmt = conn.prepare(
-        "SELECT id, name, doc_type, size, uploaded_at, path FROM documents ORDER BY uploaded_at DESC"
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM documents ORDER BY uploaded_at DESC",
+        DOCUMENT_COLUMNS
+    ))?;
 
-    let docs = stmt.query_map([], |row| {
-        let doc_type_str: String = row.get(2)?;
-        let doc_type = DocumentType::from_extension(&doc_type_str).unwrap_or(DocumentType::Txt);
-
-        Ok(Document {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            doc_type,
-            size: row.get::<_, i64>(3)? as u64,
-            uploaded_at: parse_datetime(&row.get::<_, String>(4)?),
-            path: row.get(5)?,
-        })
-    })?;
+    let docs = stmt.query_map([], row_to_document)?;
 
     docs.collect::<Result<Vec<_>, _>>().map_err(DocumentError::from)
 }
 
+/// Get one page of documents, newest first.
+///
+/// Skips the first `offset` documents and returns at most `limit`. Documents
+/// uploaded at the same instant are ordered by ID, so pages never overlap
+/// or skip a document.
+pub fn get_all_documents_paged(
+    conn: &Connection,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<Document>, DocumentError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM documents ORDER BY uploaded_at DESC, id LIMIT ?1 OFFSET ?2",
+        DOCUMENT_COLUMNS
+    ))?;
+
+    let docs = stmt.query_map(params![limit as i64, offset as i64], row_to_document)?;
+
+    docs.collect::<Result<Vec<_>, _>>().map_err(DocumentError::from)
+}
+
+/// Get all documents in a language, by ISO 639-3 code (e.g. `"eng"`).
+pub fn get_documents_by_language(conn: &Connection, lang: &str) -> Result<Vec<Document>, DocumentError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM documents WHERE language = ?1 ORDER BY uploaded_at DESC",
+        DOCUMENT_COLUMNS
+    ))?;
+
+    let docs = stmt.query_map(params![lang], row_to_document)?;
+
+    docs.collect::<Result<Vec<_>, _>>().map_err(DocumentError::from)
+}
+
+/// Count all documents.
+pub fn count_documents(conn: &Connection) -> Result<usize, DocumentError> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM documents", [], |row| row.get(0))?;
+    Ok(count as usize)
+}
+
+/// A document's metadata with how many chunks and embeddings it has.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentExport {
+    #[serde(flatten)]
+    pub document: Document,
+    pub chunk_count: usize,
+    pub embedding_count: usize,
+}
+
+/// Export every document's metadata (not its content) as a JSON array of
+/// `DocumentExport`, for backups and auditing.
+pub fn export_documents_json(conn: &Connection) -> Result<String, DocumentError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {},
+                (SELECT COUNT(*) FROM chunks WHERE chunks.document_id = documents.id),
+                (SELECT COUNT(*) FROM embeddings WHERE embeddings.document_id = documents.id)
+         FROM documents ORDER BY uploaded_at DESC, id",
+        DOCUMENT_COLUMNS
+    ))?;
+
+    let exports = stmt
+        .query_map([], |row| {
+            Ok(DocumentExport {
+                document: row_to_document(row)?,
+                chunk_count: row.get::<_, i64>(10)? as usize,
+                embedding_count: row.get::<_, i64>(11)? as usize,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    serde_json::to_string_pretty(&exports).map_err(|e| DocumentError::IoError(e.into()))
+}
+
 /// Get a single document by ID.
 pub fn get_document(conn: &Connection, id: &str) -> Result<Option<Document>, DocumentError> {
-    let mut stmt = conn.prepare(
-        "SELECT id, name, doc_type, size, uploaded_at, path FROM documents WHERE id = ?1"
-    )?;
-
-    let result = stmt.query_row(params![id], |row| {
-        let doc_type_str: String = row.get(2)?;
-        let doc_type = DocumentType::from_extension(&doc_type_str).unwrap_or(DocumentType::Txt);
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM documents WHERE id = ?1", DOCUMENT_COLUMNS))?;
 
-        Ok(Document {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            doc_type,
-            size: row.get::<_, i64>(3)? as u64,
-            uploaded_at: parse_datetime(&row.get::<_, String>(4)?),
-            path: row.get(5)?,
-        })
-    });
+    let result = stmt.query_row(params![id], row_to_document);
 
     match result {
         Ok(doc) => Ok(Some(doc)),
@@ -289,6 +1096,14 @@ pub fn get_document(conn: &Connection, id: &str) -> Result<Option<Document>, Doc
     }
 }
 
+/// Check whether a document exists without loading it.
+pub fn document_exists(conn: &Connection, id: &str) -> Result<bool, DocumentError> {
+    let exists = conn
+        .prepare("SELECT 1 FROM documents WHERE id = ?1 LIMIT 1")?
+        .exists(params![id])?;
+    Ok(exists)
+}
+
 /// Get the extracted content of a document.
 pub fn get_document_content(conn: &Connection, document_id: &str) -> Result<Option<String>, DocumentError> {
     let mut stmt = conn.prepare(
@@ -304,6 +1119,32 @@ pub fn get_document_content(conn: &Connection, document_id: &str) -> Result<Opti
     }
 }
 
+/// Get a slice of a document's extracted content without loading all of it.
+///
+/// `char_start` and `char_len` count characters, not bytes. SQLite's `substr`
+/// works in characters on TEXT values, so the slice always falls on character
+/// boundaries and is valid UTF-8. A range past the end yields a shorter (or
+/// empty) string. Returns `None` if the document has no stored content.
+pub fn get_document_content_range(
+    conn: &Connection,
+    document_id: &str,
+    char_start: usize,
+    char_len: usize,
+) -> Result<Option<String>, DocumentError> {
+    let result = conn.query_row(
+        // substr is 1-based
+        "SELECT substr(content, ?2, ?3) FROM document_content WHERE document_id = ?1",
+        params![document_id, char_start as i64 + 1, char_len as i64],
+        |row| row.get(0),
+    );
+
+    match result {
+        Ok(content) => Ok(Some(content)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(DocumentError::from(e)),
+    }
+}
+
 /// Delete a document and its content.
 pub fn delete_document(conn: &Connection, id: &str) -> Result<bool, DocumentError> {
     // Content is deleted automatically via CASCADE
@@ -311,6 +1152,124 @@ pub fn delete_document(conn: &Connection, id: &str) -> Result<bool, DocumentErro
     Ok(rows > 0)
 }
 
+/// Recomputes word counts for every document from its stored content.
+///
+/// Backfills documents ingested before word counts were tracked, when
+/// `init_documents_table` adds the column. Returns the number of
+/// documents updated.
+fn recompute_word_counts(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT document_id, content FROM document_content")?;
+    let counts = stmt
+        .query_map([], |row| {
+            let id: String = row.get(0)?;
+            let content: String = row.get(1)?;
+            Ok((id, crate::text::count_words(&content)))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let tx = conn.unchecked_transaction()?;
+    let mut updated = 0;
+    for (id, count) in counts {
+        updated += tx.execute(
+            "UPDATE documents SET word_count = ?1 WHERE id = ?2",
+            params![count as i64, id],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(updated)
+}
+
+/// Re-reads a document's file at `Document.path` and replaces its stored content.
+///
+/// For refreshing content after the extractor improves or the file is
+/// corrected. Word count and language are recomputed along with it, in one
+/// transaction. Nothing is touched unless extraction succeeds, and chunks
+/// and embeddings are kept: they reflect the old content until the document
+/// is reindexed. Returns the new content's length in characters, or
+/// `NotFound` if the document or its file is gone.
+pub fn reextract_document(conn: &Connection, id: &str) -> Result<usize, DocumentError> {
+    let doc = get_document(conn, id)?.ok_or_else(|| DocumentError::NotFound(id.to_string()))?;
+
+    let path = Path::new(&doc.path);
+    if !path.exists() {
+        return Err(DocumentError::NotFound(doc.path.clone()));
+    }
+    let loaded = load_document(path, id)?;
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT OR REPLACE INTO document_content (document_id, content) VALUES (?1, ?2)",
+        params![id, loaded.content],
+    )?;
+    tx.execute(
+        "UPDATE documents SET word_count = ?1, language = ?2 WHERE id = ?3",
+        params![loaded.metadata.word_count as i64, loaded.metadata.language, id],
+    )?;
+    tx.commit()?;
+
+    Ok(loaded.content.chars().count())
+}
+
+/// Checks whether a document's source file changed since it was ingested.
+///
+/// Compares the modification time recorded at ingest with the file's
+/// current one, so the UI can offer to re-ingest. Returns `NotFound` if the
+/// document or its file no longer exists. Documents ingested before mtimes
+/// were recorded are never reported stale.
+pub fn document_is_stale(conn: &Connection, id: &str) -> Result<bool, DocumentError> {
+    let doc = get_document(conn, id)?.ok_or_else(|| DocumentError::NotFound(id.to_string()))?;
+
+    // Older rows have no source path; fall back to the stored copy
+    let path = if doc.source_path.is_empty() { &doc.path } else { &doc.source_path };
+
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(DocumentError::NotFound(path.clone()));
+        }
+        Err(e) => return Err(DocumentError::from(e)),
+    };
+
+    let Some(recorded) = doc.modified_at else {
+        return Ok(false);
+    };
+    let current = metadata.modified().ok().map(DateTime::<Utc>::from);
+
+    Ok(current != Some(recorded))
+}
+
+/// Columns selected for a `Document`, in the order `row_to_document` reads them.
+const DOCUMENT_COLUMNS: &str =
+    "id, name, doc_type, size, uploaded_at, path, word_count, source_path, modified_at, language";
+
+/// Maps a row of `DOCUMENT_COLUMNS`.
+///
+/// An unknown `doc_type` fails with an error that converts to
+/// `DocumentError::CorruptRow`, rather than being guessed at.
+fn row_to_document(row: &rusqlite::Row) -> rusqlite::Result<Document> {
+    let id: String = row.get(0)?;
+    let doc_type_str: String = row.get(2)?;
+    let doc_type = DocumentType::from_extension(&doc_type_str).ok_or_else(|| {
+        let reason = format!("document {} has unknown doc_type '{}'", id, doc_type_str);
+        let source = Box::new(CorruptRow(reason));
+        rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, source)
+    })?;
+
+    Ok(Document {
+        id,
+        name: row.get(1)?,
+        doc_type,
+        size: row.get::<_, i64>(3)? as u64,
+        uploaded_at: parse_datetime(&row.get::<_, String>(4)?),
+        path: row.get(5)?,
+        word_count: row.get::<_, i64>(6)? as u64,
+        source_path: row.get(7)?,
+        modified_at: row.get::<_, Option<String>>(8)?.map(|s| parse_datetime(&s)),
+        language: row.get(9)?,
+    })
+}
+
 /// Helper to parse datetime strings.
 fn parse_datetime(s: &str) -> DateTime<Utc> {
     DateTime::parse_from_rfc3339(s)
@@ -322,6 +1281,23 @@ fn parse_datetime(s: &str) -> DateTime<Utc> {
 mod tests {
     use super::*;
 
+    /// An empty text document stored at `/tmp/<name>`.
+    fn test_document(id: &str, name: &str) -> Document {
+        let path = format!("/tmp/{}", name);
+        Document {
+            id: id.to_string(),
+            name: name.to_string(),
+            doc_type: DocumentType::Txt,
+            size: 0,
+            uploaded_at: Utc::now(),
+            path: path.clone(),
+            word_count: 0,
+            source_path: path,
+            modified_at: None,
+            language: None,
+        }
+    }
+
     #[test]
     fn test_document_type_from_extension() {
         assert_eq!(DocumentType::from_extension("pdf"), Some(DocumentType::Pdf));
@@ -329,6 +1305,15 @@ mod tests {
         assert_eq!(DocumentType::from_extension("txt"), Some(DocumentType::Txt));
         assert_eq!(DocumentType::from_extension("md"), Some(DocumentType::Md));
         assert_eq!(DocumentType::from_extension("markdown"), Some(DocumentType::Md));
+        assert_eq!(DocumentType::from_extension("rst"), Some(DocumentType::Rst));
+        assert_eq!(DocumentType::from_extension("RST"), Some(DocumentType::Rst));
+        assert_eq!(DocumentType::from_extension("org"), Some(DocumentType::Org));
+        assert_eq!(DocumentType::Rst.as_str(), "rst");
+        assert_eq!(DocumentType::Org.as_str(), "org");
+        assert_eq!(DocumentType::from_extension("EPUB"), Some(DocumentType::Epub));
+        assert_eq!(DocumentType::Epub.as_str(), "epub");
+        assert_eq!(DocumentType::from_extension("jsonl"), Some(DocumentType::Json));
+        assert_eq!(DocumentType::Json.as_str(), "json");
         assert_eq!(DocumentType::from_extension("doc"), None);
     }
 
@@ -337,14 +1322,7 @@ mod tests {
         let conn = Connection::open_in_memory().unwrap();
         init_documents_table(&conn).unwrap();
 
-        let doc = Document {
-            id: "test-1".to_string(),
-            name: "test.txt".to_string(),
-            doc_type: DocumentType::Txt,
-            size: 1234,
-            uploaded_at: Utc::now(),
-            path: "/tmp/test.txt".to_string(),
-        };
+        let doc = test_document("test-1", "test.txt");
 
         save_document(&conn, &doc).unwrap();
         save_document_content(&conn, "test-1", "Hello, world!").unwrap();
@@ -356,4 +1334,619 @@ mod tests {
         let content = get_document_content(&conn, "test-1").unwrap();
         assert_eq!(content, Some("Hello, world!".to_string()));
     }
+
+    #[test]
+    fn test_append_document_content() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_documents_table(&conn).unwrap();
+        let doc = test_document("doc-1", "big.txt");
+        save_document(&conn, &doc).unwrap();
+
+        let fragments = ["First part, ", "naïve middle 日本語, ", "and the end."];
+        for fragment in fragments {
+            append_document_content(&conn, "doc-1", fragment).unwrap();
+        }
+
+        let content = get_document_content(&conn, "doc-1").unwrap().unwrap();
+        assert_eq!(content, fragments.concat());
+
+        // Chunk offsets index into the assembled text
+        let config = crate::chunker::ChunkConfig::new(20, 0).unwrap();
+        let chunks = crate::chunker::chunk_text("doc-1", &content, &config).unwrap();
+        let chars: Vec<char> = content.chars().collect();
+        for chunk in &chunks {
+            let span: String = chars[chunk.start_offset..chunk.end_offset].iter().collect();
+            assert_eq!(span.trim(), chunk.content);
+        }
+    }
+
+    #[test]
+    fn test_delete_document_cascades_to_chunks_and_embeddings() {
+        // Database::new enables foreign keys and creates every table
+        let db = crate::db::Database::new(":memory:").unwrap();
+
+        let doc = test_document("doc-1", "test.txt");
+        save_document(&db.conn, &doc).unwrap();
+        save_document_content(&db.conn, "doc-1", "Test content").unwrap();
+
+        let chunk = crate::chunker::Chunk {
+            id: "doc-1-0".to_string(),
+            document_id: "doc-1".to_string(),
+            chunk_index: 0,
+            content: "Test content".to_string(),
+            start_offset: 0,
+            end_offset: 12,
+        };
+        crate::chunker::save_chunks(&db.conn, &[chunk]).unwrap();
+        crate::vector_store::save_embedding(&db.conn, "doc-1-0", "doc-1", &[1.0, 0.0], "test-model")
+            .unwrap();
+
+        assert!(delete_document(&db.conn, "doc-1").unwrap());
+
+        assert!(get_document(&db.conn, "doc-1").unwrap().is_none());
+        assert!(get_document_content(&db.conn, "doc-1").unwrap().is_none());
+        assert!(crate::chunker::get_document_chunks(&db.conn, "doc-1").unwrap().is_empty());
+        assert!(crate::vector_store::get_embedding(&db.conn, "doc-1-0").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_get_document_content_range_multibyte() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_documents_table(&conn).unwrap();
+
+        let doc = test_document("doc-1", "test.txt");
+        save_document(&conn, &doc).unwrap();
+        save_document_content(&conn, "doc-1", "héllo wörld 🎉 日本語のテキスト").unwrap();
+
+        let slice = get_document_content_range(&conn, "doc-1", 6, 8).unwrap().unwrap();
+        assert_eq!(slice, "wörld 🎉 ");
+        assert_eq!(slice.chars().count(), 8);
+
+        let tail = get_document_content_range(&conn, "doc-1", 14, 100).unwrap().unwrap();
+        assert_eq!(tail, "日本語のテキスト");
+
+        assert_eq!(get_document_content_range(&conn, "doc-1", 500, 10).unwrap().as_deref(), Some(""));
+        assert!(get_document_content_range(&conn, "missing", 0, 10).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_word_count_backfill() {
+        let conn = Connection::open_in_memory().unwrap();
+        // A documents table from before word counts existed
+        conn.execute(
+            "CREATE TABLE documents (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                doc_type TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                uploaded_at TEXT NOT NULL,
+                path TEXT NOT NULL
+            )",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "CREATE TABLE document_content (document_id TEXT PRIMARY KEY, content TEXT NOT NULL)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO documents VALUES ('doc-1', 'mixed.txt', 'txt', 10, ?1, '/tmp/mixed.txt')",
+            params![Utc::now().to_rfc3339()],
+        )
+        .unwrap();
+        save_document_content(&conn, "doc-1", "The café served crêpes. 日本語 text, naïve résumé!")
+            .unwrap();
+
+        // The migration counts existing documents' words
+        init_documents_table(&conn).unwrap();
+
+        // 7 space-separated words plus one per ideograph
+        let doc = get_document(&conn, "doc-1").unwrap().unwrap();
+        assert_eq!(doc.word_count, 10);
+        assert_eq!(get_all_documents(&conn).unwrap()[0].word_count, 10);
+
+        // Once the column exists, counts aren't recomputed on every start
+        conn.execute("UPDATE documents SET word_count = 3", []).unwrap();
+        init_documents_table(&conn).unwrap();
+        assert_eq!(get_document(&conn, "doc-1").unwrap().unwrap().word_count, 3);
+        assert_eq!(recompute_word_counts(&conn).unwrap(), 1);
+        assert_eq!(get_document(&conn, "doc-1").unwrap().unwrap().word_count, 10);
+    }
+
+    #[test]
+    fn test_document_is_stale_after_file_changes() {
+        let path = std::env::temp_dir().join(format!("stale-{}.txt", uuid::Uuid::new_v4()));
+        fs::write(&path, "Original content").unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        init_documents_table(&conn).unwrap();
+        let loaded = load_document(&path, "doc-1").unwrap();
+        save_document(&conn, &loaded.metadata).unwrap();
+        assert!(!document_is_stale(&conn, "doc-1").unwrap());
+
+        // Bump the mtime as if the file had been edited
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        assert!(document_is_stale(&conn, "doc-1").unwrap());
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(document_is_stale(&conn, "doc-1"), Err(DocumentError::NotFound(_))));
+        assert!(matches!(document_is_stale(&conn, "missing"), Err(DocumentError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_documents_paged() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_documents_table(&conn).unwrap();
+
+        let start = Utc::now();
+        for i in 0..5 {
+            let doc = Document {
+                uploaded_at: start + chrono::Duration::minutes(i),
+                ..test_document(&format!("doc-{}", i), &format!("doc-{}.txt", i))
+            };
+            save_document(&conn, &doc).unwrap();
+        }
+        assert_eq!(count_documents(&conn).unwrap(), 5);
+
+        let page = |limit, offset| -> Vec<String> {
+            get_all_documents_paged(&conn, limit, offset)
+                .unwrap()
+                .into_iter()
+                .map(|doc| doc.id)
+                .collect()
+        };
+        assert_eq!(page(2, 0), vec!["doc-4", "doc-3"]);
+        assert_eq!(page(2, 2), vec!["doc-2", "doc-1"]);
+        assert_eq!(page(2, 4), vec!["doc-0"]);
+        assert!(page(2, 6).is_empty());
+
+        // Pages put together match the unpaged list
+        let all: Vec<String> =
+            get_all_documents(&conn).unwrap().into_iter().map(|doc| doc.id).collect();
+        assert_eq!([page(2, 0), page(2, 2), page(2, 4)].concat(), all);
+    }
+
+    #[test]
+    fn test_reextract_document_updates_content() {
+        let path = std::env::temp_dir().join(format!("reextract-{}.txt", uuid::Uuid::new_v4()));
+        fs::write(&path, "Original content").unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        init_documents_table(&conn).unwrap();
+        let loaded = load_document(&path, "doc-1").unwrap();
+        save_document(&conn, &loaded.metadata).unwrap();
+        save_document_content(&conn, "doc-1", &loaded.content).unwrap();
+
+        fs::write(&path, "Corrected content, now with café").unwrap();
+        assert_eq!(reextract_document(&conn, "doc-1").unwrap(), 32);
+
+        let content = get_document_content(&conn, "doc-1").unwrap();
+        assert_eq!(content.as_deref(), Some("Corrected content, now with café"));
+        assert_eq!(get_document(&conn, "doc-1").unwrap().unwrap().word_count, 5);
+
+        // A missing file leaves the stored content alone
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(reextract_document(&conn, "doc-1"), Err(DocumentError::NotFound(_))));
+        assert!(matches!(reextract_document(&conn, "missing"), Err(DocumentError::NotFound(_))));
+        let content = get_document_content(&conn, "doc-1").unwrap();
+        assert_eq!(content.as_deref(), Some("Corrected content, now with café"));
+    }
+
+    #[test]
+    fn test_document_size_limit() {
+        // A size over the default, as metadata would report for a huge file
+        let huge = DEFAULT_MAX_DOCUMENT_BYTES + 1;
+        assert!(matches!(
+            check_document_size(huge, DEFAULT_MAX_DOCUMENT_BYTES),
+            Err(DocumentError::TooLarge { size, limit })
+                if size == huge && limit == DEFAULT_MAX_DOCUMENT_BYTES
+        ));
+        // Exactly at the limit is fine
+        let limit = DEFAULT_MAX_DOCUMENT_BYTES;
+        assert!(check_document_size(limit, limit).is_ok());
+
+        let path = std::env::temp_dir().join(format!("limit-{}.txt", uuid::Uuid::new_v4()));
+        fs::write(&path, "Twenty bytes of text").unwrap();
+        assert!(matches!(
+            load_document_with_limit(&path, "doc-1", 10),
+            Err(DocumentError::TooLarge { size: 20, limit: 10 })
+        ));
+        assert!(load_document_with_limit(&path, "doc-1", 20).is_ok());
+        assert!(load_document(&path, "doc-1").is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_document_from_bytes() {
+        let bytes = "# Notes\n\nHello from memory.".as_bytes();
+        let loaded = load_document_from_bytes(bytes, "notes.md", "doc-1").unwrap();
+
+        assert_eq!(loaded.content, "# Notes\n\nHello from memory.");
+        assert_eq!(loaded.metadata.id, "doc-1");
+        assert_eq!(loaded.metadata.name, "notes.md");
+        assert_eq!(loaded.metadata.doc_type, DocumentType::Md);
+        assert_eq!(loaded.metadata.size, bytes.len() as u64);
+        assert_eq!(loaded.metadata.path, "notes.md");
+        assert_eq!(loaded.metadata.word_count, 4);
+        assert!(loaded.metadata.modified_at.is_none());
+
+        assert!(matches!(
+            load_document_from_bytes(b"data", "notes.docx", "doc-2"),
+            Err(DocumentError::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            load_document_from_bytes(&[0xff, 0xfe], "bad.txt", "doc-3"),
+            Err(DocumentError::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn test_text_file_bom_and_line_endings_normalized() {
+        let raw = "\u{FEFF}# Title\r\n\r\nFirst paragraph.\r\nSame paragraph.\r\r\
+                   Old Mac paragraph.\r\n";
+        let expected = "# Title\n\nFirst paragraph.\nSame paragraph.\n\nOld Mac paragraph.\n";
+
+        let path = std::env::temp_dir().join(format!("crlf-{}.md", uuid::Uuid::new_v4()));
+        fs::write(&path, raw).unwrap();
+        let from_file = load_document(&path, "doc-1").unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(from_file.content, expected);
+
+        let from_mem = load_document_from_bytes(raw.as_bytes(), "notes.md", "doc-1").unwrap();
+        assert_eq!(from_mem.content, expected);
+        assert!(!from_mem.content.contains('\u{FEFF}'));
+        assert!(!from_mem.content.contains('\r'));
+
+        // A BOM anywhere but the start is content, not a marker
+        assert_eq!(normalize_text_file("a\u{FEFF}b"), "a\u{FEFF}b");
+    }
+
+    #[test]
+    fn test_epub_chapters_in_reading_order() {
+        // Stored zip whose manifest lists chapter 2 first; the spine says 1, 2
+        let bytes = include_bytes!("../tests/fixtures/minimal.epub");
+        let loaded = load_document_from_bytes(bytes, "book.epub", "doc-1").unwrap();
+
+        assert_eq!(loaded.metadata.doc_type, DocumentType::Epub);
+        assert_eq!(loaded.metadata.name, "The Little Test Book");
+        assert_eq!(
+            loaded.content,
+            "Chapter One\n\n\
+             It was a dark and stormy night.\n\n\
+             Tom & Jerry said \u{201C}hello\u{201D}.\n\n\
+             Chapter Two\n\n\
+             The end.\nReally."
+        );
+
+        assert!(matches!(
+            load_document_from_bytes(b"not a zip", "broken.epub", "doc-2"),
+            Err(DocumentError::EpubError(_))
+        ));
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<html><head><title>Skip me</title></head><body>\
+                    <h2>Heading</h2><p>One &lt;two&gt;&#160;three&#x21;</p>\
+                    <script>var x = 1;</script><ul><li>a</li><li>b &unknown; c</li></ul>\
+                    </body></html>";
+        assert_eq!(html_to_text(html), "Heading\n\nOne <two> three!\n\na\n\nb &unknown; c");
+        assert_eq!(html_to_text("no tags at all"), "no tags at all");
+    }
+
+    #[test]
+    fn test_detects_and_stores_language() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_documents_table(&conn).unwrap();
+
+        let english = "The quick brown fox jumps over the lazy dog. It is one of the most \
+                       famous sentences in the English language and is used to test fonts.";
+        let spanish = "El rápido zorro marrón salta sobre el perro perezoso. Es una de las \
+                       frases más conocidas de la lengua española y se usa para probar fuentes.";
+
+        let en = load_document_from_bytes(english.as_bytes(), "en.txt", "doc-en").unwrap();
+        let es = load_document_from_bytes(spanish.as_bytes(), "es.txt", "doc-es").unwrap();
+        let short = load_document_from_bytes(b"Hola", "short.txt", "doc-short").unwrap();
+        assert_eq!(en.metadata.language.as_deref(), Some("eng"));
+        assert_eq!(es.metadata.language.as_deref(), Some("spa"));
+        assert_eq!(short.metadata.language, None);
+
+        for loaded in [&en, &es, &short] {
+            save_document(&conn, &loaded.metadata).unwrap();
+        }
+
+        let english_docs = get_documents_by_language(&conn, "eng").unwrap();
+        assert_eq!(english_docs.len(), 1);
+        assert_eq!(english_docs[0].id, "doc-en");
+        assert_eq!(english_docs[0].language.as_deref(), Some("eng"));
+
+        let spanish_docs = get_documents_by_language(&conn, "spa").unwrap();
+        assert_eq!(spanish_docs.len(), 1);
+        assert_eq!(spanish_docs[0].id, "doc-es");
+
+        let stored = get_document(&conn, "doc-short").unwrap().unwrap();
+        assert_eq!(stored.language, None);
+    }
+
+    #[test]
+    fn test_unknown_doc_type_is_reported() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_documents_table(&conn).unwrap();
+
+        conn.execute(
+            "INSERT INTO documents (id, name, doc_type, size, uploaded_at, path)
+             VALUES ('bad-1', 'mystery.xyz', 'xyz', 0, '2024-01-01T00:00:00Z', '/tmp/mystery.xyz')",
+            [],
+        )
+        .unwrap();
+
+        match get_all_documents(&conn) {
+            Err(DocumentError::CorruptRow(msg)) => {
+                assert!(msg.contains("'xyz'"));
+                assert!(msg.contains("bad-1"));
+            }
+            other => panic!("expected CorruptRow, got {:?}", other),
+        }
+        assert!(matches!(get_document(&conn, "bad-1"), Err(DocumentError::CorruptRow(_))));
+    }
+
+    #[test]
+    fn test_document_exists() {
+        let db = crate::db::Database::in_memory().unwrap();
+        let doc = test_document("doc-1", "doc.txt");
+        save_document(&db.conn, &doc).unwrap();
+
+        assert!(document_exists(&db.conn, "doc-1").unwrap());
+        assert!(!document_exists(&db.conn, "missing").unwrap());
+    }
+
+    #[test]
+    fn test_rename_document_keeps_path() {
+        let db = crate::db::Database::in_memory().unwrap();
+        let loaded =
+            load_document_from_text("Quarterly numbers", "q3-final-v2.txt", "doc-1").unwrap();
+        save_document(&db.conn, &loaded.metadata).unwrap();
+
+        assert!(rename_document(&db.conn, "doc-1", "  Q3 report ").unwrap());
+        let doc = get_document(&db.conn, "doc-1").unwrap().unwrap();
+        assert_eq!(doc.name, "Q3 report");
+        assert_eq!(doc.path, loaded.metadata.path);
+
+        assert!(matches!(
+            rename_document(&db.conn, "doc-1", " "),
+            Err(DocumentError::InvalidInput(_))
+        ));
+        assert_eq!(get_document(&db.conn, "doc-1").unwrap().unwrap().name, "Q3 report");
+        assert!(!rename_document(&db.conn, "missing", "Anything").unwrap());
+    }
+
+    #[test]
+    fn test_export_documents_json() {
+        let db = crate::db::Database::in_memory().unwrap();
+
+        for (i, chunk_count) in [2usize, 0].into_iter().enumerate() {
+            let id = format!("doc-{}", i);
+            let doc = Document {
+                size: 10,
+                word_count: 3,
+                language: Some("eng".to_string()),
+                ..test_document(&id, &format!("doc-{}.txt", i))
+            };
+            save_document(&db.conn, &doc).unwrap();
+            save_document_content(&db.conn, &id, "Not exported").unwrap();
+
+            let chunks: Vec<crate::chunker::Chunk> = (0..chunk_count)
+                .map(|index| crate::chunker::Chunk {
+                    id: format!("{}-{}", id, index),
+                    document_id: id.clone(),
+                    chunk_index: index,
+                    content: "Chunk".to_string(),
+                    start_offset: 0,
+                    end_offset: 5,
+                })
+                .collect();
+            crate::chunker::save_chunks(&db.conn, &chunks).unwrap();
+        }
+        // Only the first chunk of doc-0 is embedded
+        crate::vector_store::save_embedding(&db.conn, "doc-0-0", "doc-0", &[1.0, 0.0], "test-model")
+            .unwrap();
+
+        let json = export_documents_json(&db.conn).unwrap();
+        assert!(!json.contains("Not exported"));
+
+        let exported: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(exported.len(), 2);
+        let by_id = |id: &str| exported.iter().find(|d| d["id"] == id).unwrap();
+        assert_eq!(by_id("doc-0")["chunkCount"], 2);
+        assert_eq!(by_id("doc-0")["embeddingCount"], 1);
+        assert_eq!(by_id("doc-1")["chunkCount"], 0);
+        assert_eq!(by_id("doc-1")["embeddingCount"], 0);
+        assert_eq!(by_id("doc-1")["name"], "doc-1.txt");
+        assert_eq!(by_id("doc-1")["type"], "txt");
+        assert_eq!(by_id("doc-1")["language"], "eng");
+
+        // No documents is an empty array
+        let empty = crate::db::Database::in_memory().unwrap();
+        assert_eq!(export_documents_json(&empty.conn).unwrap(), "[]");
+    }
+
+    /// Builds a PDF with one line of text in Courier on each page, and
+    /// `title` in its metadata if given.
+    fn text_pdf(pages: &[&str], title: Option<&str>) -> Vec<u8> {
+        use pdf_extract::content::{Content, Operation};
+        use pdf_extract::{dictionary, Document, Object, Stream};
+
+        let mut pdf = Document::with_version("1.5");
+        let pages_id = pdf.new_object_id();
+        let font_id = pdf.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let resources_id = pdf.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+        });
+
+        let kids: Vec<Object> = pages
+            .iter()
+            .map(|text| {
+                let content = Content {
+                    operations: vec![
+                        Operation::new("BT", vec![]),
+                        Operation::new("Tf", vec!["F1".into(), 24.into()]),
+                        Operation::new("Td", vec![100.into(), 600.into()]),
+                        Operation::new("Tj", vec![Object::string_literal(*text)]),
+                        Operation::new("ET", vec![]),
+                    ],
+                };
+                let content_id =
+                    pdf.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+                pdf.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "Contents" => content_id,
+                })
+                .into()
+            })
+            .collect();
+
+        pdf.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => pages.len() as i64,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+        let catalog_id = pdf.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        pdf.trailer.set("Root", catalog_id);
+        if let Some(title) = title {
+            let info_id = pdf.add_object(dictionary! {
+                "Title" => pdf_extract::text_string(title),
+                "Author" => Object::string_literal("Ferris"),
+            });
+            pdf.trailer.set("Info", info_id);
+        }
+
+        let mut bytes = Vec::new();
+        pdf.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_extract_pdf_page_range() {
+        let dir = std::env::temp_dir().join(format!("pdf-pages-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("book.pdf");
+        let pages = ["Alpha page", "Bravo page", "Charlie page", "Delta page"];
+        let bytes = text_pdf(&pages, None);
+        fs::write(&path, &bytes).unwrap();
+        let extract = |pages| extract_pdf_from_mem(&bytes, Some(pages)).map(|(text, _)| text);
+
+        let text = extract(2..4).unwrap();
+        assert!(text.contains("Bravo") && text.contains("Charlie"), "got: {:?}", text);
+        assert!(!text.contains("Alpha") && !text.contains("Delta"), "got: {:?}", text);
+
+        // The end is clamped; ranges outside the document are rejected
+        assert!(extract(4..100).unwrap().contains("Delta"));
+        for pages in [0..2, 3..3, 5..6] {
+            assert!(matches!(extract(pages), Err(DocumentError::PdfError(_))));
+        }
+
+        // Loading with a page range only extracts those pages
+        let options = LoadOptions {
+            pdf_pages: Some(2..3),
+            ..LoadOptions::default()
+        };
+        let loaded = load_document_with_options(&path, "doc-1", &options).unwrap();
+        assert!(loaded.content.contains("Bravo"));
+        assert!(!loaded.content.contains("Charlie"));
+        assert!(load_document(&path, "doc-1").unwrap().content.contains("Delta"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_pdf_title_names_document() {
+        let dir = std::env::temp_dir().join(format!("pdf-meta-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let titled = dir.join("scan_0042.pdf");
+        let pdf = text_pdf(&["Fronds", "Spores"], Some("A Field Guide to Ferns"));
+        fs::write(&titled, pdf).unwrap();
+        let untitled = dir.join("notes.pdf");
+        fs::write(&untitled, text_pdf(&["Fronds"], None)).unwrap();
+
+        let meta = extract_pdf_metadata(&titled).unwrap();
+        assert_eq!(
+            meta,
+            PdfMeta {
+                title: Some("A Field Guide to Ferns".to_string()),
+                author: Some("Ferris".to_string()),
+                page_count: 2,
+            }
+        );
+
+        // The title is preferred over the filename, which is the fallback
+        let loaded = load_document(&titled, "doc-1").unwrap();
+        assert_eq!(loaded.metadata.name, "A Field Guide to Ferns");
+        assert!(loaded.content.contains("Spores"));
+        assert_eq!(load_document(&untitled, "doc-2").unwrap().metadata.name, "notes.pdf");
+        let bytes = fs::read(&titled).unwrap();
+        let from_bytes = load_document_from_bytes(&bytes, "upload.pdf", "doc-3").unwrap();
+        assert_eq!(from_bytes.metadata.name, "A Field Guide to Ferns");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_extract_jsonl_text() {
+        let path = std::env::temp_dir().join(format!("records-{}.jsonl", uuid::Uuid::new_v4()));
+        fs::write(
+            &path,
+            concat!(
+                r#"{"title": "Rust", "author": {"name": "Ferris"}, "#,
+                r#""tags": ["crab"], "year": 2015}"#,
+                "\n\n",
+                r#"{"title": "Go", "author": {"name": "Gopher"}, "year": 2009}"#,
+                "\n",
+            ),
+        )
+        .unwrap();
+
+        let extract = |path: &Path, fields: Option<Vec<String>>| {
+            let bytes = fs::read(path).unwrap();
+            extract_json_text_from_mem(&bytes, is_json_lines(path), fields.as_deref())
+        };
+
+        // All string values, in key order, with records as paragraphs
+        let text = extract(&path, None).unwrap();
+        assert_eq!(text, "Ferris\ncrab\nRust\n\nGopher\nGo");
+
+        // Only the selected fields, nested ones by dot path
+        let fields = vec!["title".to_string(), "author.name".to_string(), "year".to_string()];
+        let text = extract(&path, Some(fields)).unwrap();
+        assert_eq!(text, "Rust\nFerris\n2015\n\nGo\nGopher\n2009");
+
+        // A bad line is reported by number
+        fs::write(&path, "{\"title\": \"ok\"}\nnot json\n").unwrap();
+        let err = extract(&path, None).unwrap_err();
+        assert!(matches!(&err, DocumentError::JsonError(msg) if msg.starts_with("line 2")));
+
+        // A .json array works the same way, fanning out over nested arrays
+        let json_path = path.with_extension("json");
+        fs::write(&json_path, r#"[{"authors": [{"name": "A"}, {"name": "B"}]}, {"authors": []}]"#)
+            .unwrap();
+        let text = extract(&json_path, Some(vec!["authors.name".to_string()])).unwrap();
+        assert_eq!(text, "A\nB");
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&json_path).unwrap();
+    }
 }
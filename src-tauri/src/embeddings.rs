@@ -8,7 +8,8 @@
 //!
 //! 1. Text is tokenized into word pieces (subwords)
 //! 2. Tokens are fed through a transformer encoder
-//! 3. The output is mean-pooled to get a single vector
+//! 3. The token outputs are pooled into a single vector (mean pooling by
+//!    default; see [`Pooling`])
 //! 4. The vector is normalized for cosine similarity
 //!
 //! ## Why This Model?
@@ -21,16 +22,29 @@
 use candle_core::{DType, Device, Tensor};
 use candle_nn::VarBuilder;
 use candle_transformers::models::bert::{BertModel, Config, DTYPE};
-use hf_hub::{api::sync::ApiBuilder, Repo, RepoType};
-use std::path::PathBuf;
+use hf_hub::api::Progress;
+use hf_hub::api::sync::{ApiError, ApiRepo};
+use hf_hub::{api::sync::ApiBuilder, Cache, CacheRepo, Repo, RepoType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use tokenizers::Tokenizer;
+use tracing::{debug, info, instrument, warn};
 
 /// The embedding dimension for all-MiniLM-L6-v2.
 /// This is fixed by the model architecture.
 pub const EMBEDDING_DIM: usize = 384;
 
+/// Files needed to load the model, in (config, tokenizer, weights) order.
+const MODEL_FILES: [&str; 3] = ["config.json", "tokenizer.json", "model.safetensors"];
+
 /// The model ID on Hugging Face Hub.
-const MODEL_ID: &str = "sentence-transformers/all-MiniLM-L6-v2";
+///
+/// Also recorded alongside each stored embedding, so vectors from
+/// different models are never compared.
+pub const MODEL_ID: &str = "sentence-transformers/all-MiniLM-L6-v2";
 
 /// Errors that can occur during embedding operations.
 #[derive(Debug)]
@@ -55,6 +69,191 @@ impl std::fmt::Display for EmbeddingError {
 
 impl std::error::Error for EmbeddingError {}
 
+/// Progress of a model file download, reported to the UI.
+///
+/// Byte counts are cumulative for the current file.
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    pub file: String,
+    pub downloaded: u64,
+    pub total: u64,
+}
+
+/// Adapts hf-hub's `Progress` trait to a `DownloadProgress` callback.
+///
+/// hf-hub reports byte deltas; this accumulates them so the callback
+/// always sees the running total.
+struct ProgressRelay<'a, F: Fn(DownloadProgress)> {
+    callback: &'a F,
+    file: String,
+    downloaded: u64,
+    total: u64,
+}
+
+impl<'a, F: Fn(DownloadProgress)> ProgressRelay<'a, F> {
+    fn new(callback: &'a F) -> Self {
+        ProgressRelay {
+            callback,
+            file: String::new(),
+            downloaded: 0,
+            total: 0,
+        }
+    }
+
+    fn report(&self) {
+        (self.callback)(DownloadProgress {
+            file: self.file.clone(),
+            downloaded: self.downloaded,
+            total: self.total,
+        });
+    }
+}
+
+impl<F: Fn(DownloadProgress)> Progress for ProgressRelay<'_, F> {
+    fn init(&mut self, size: usize, filename: &str) {
+        self.file = filename.to_string();
+        self.total = size as u64;
+        self.downloaded = 0;
+        self.report();
+    }
+
+    fn update(&mut self, size: usize) {
+        self.downloaded = (self.downloaded + size as u64).min(self.total);
+        self.report();
+    }
+
+    fn finish(&mut self) {
+        self.downloaded = self.total;
+        self.report();
+    }
+}
+
+/// How model file downloads are retried on transient network errors.
+///
+/// Each file is fetched up to `max_attempts` times. The delay before a
+/// retry starts at `base_delay` and doubles after every failed attempt.
+/// Permanent errors, like a 404 for a missing file, are never retried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryConfig {
+    /// Total attempts per file, including the first (at least 1)
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub base_delay: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// How per-token outputs are combined into one sentence vector.
+///
+/// The right choice depends on how the checkpoint was trained;
+/// all-MiniLM-L6-v2 uses mean pooling.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pooling {
+    /// Average of the token vectors, ignoring padding
+    #[default]
+    Mean,
+    /// The first ([CLS]) token's vector
+    Cls,
+    /// Element-wise maximum over the token vectors, ignoring padding
+    Max,
+    /// Average of the token vectors weighted by each token ID's IDF, so
+    /// common subwords count for less. Tokens missing from the map get
+    /// weight 1.0. See [`compute_token_idf`] to build the map.
+    #[serde(skip)]
+    IdfWeighted(Arc<HashMap<u32, f32>>),
+}
+
+impl Pooling {
+    /// Pools `embeddings` (batch, seq_len, hidden) into (batch, hidden).
+    ///
+    /// `input_ids` (batch, seq_len) is only needed for IDF weighting.
+    fn apply(
+        &self,
+        embeddings: &Tensor,
+        attention_mask: &Tensor,
+        input_ids: &Tensor,
+    ) -> Result<Tensor, EmbeddingError> {
+        match self {
+            Pooling::Mean => mean_pooling(embeddings, attention_mask),
+            Pooling::Cls => cls_pooling(embeddings),
+            Pooling::Max => max_pooling(embeddings, attention_mask),
+            Pooling::IdfWeighted(idf) => {
+                idf_weighted_pooling(embeddings, attention_mask, input_ids, idf)
+            }
+        }
+    }
+}
+
+/// Computes smoothed inverse document frequencies for token IDs.
+///
+/// Each item of `documents` is one tokenized document. A token's IDF is
+/// `ln((1 + N) / (1 + df)) + 1`, where `N` is the number of documents and
+/// `df` how many contain the token, so every weight is at least 1.0.
+pub fn compute_token_idf<'a>(documents: impl IntoIterator<Item = &'a [u32]>) -> HashMap<u32, f32> {
+    let mut document_frequency: HashMap<u32, usize> = HashMap::new();
+    let mut document_count = 0;
+
+    for ids in documents {
+        document_count += 1;
+        let mut unique = ids.to_vec();
+        unique.sort_unstable();
+        unique.dedup();
+        for id in unique {
+            *document_frequency.entry(id).or_insert(0) += 1;
+        }
+    }
+
+    document_frequency
+        .into_iter()
+        .map(|(id, df)| {
+            let idf = ((1 + document_count) as f32 / (1 + df) as f32).ln() + 1.0;
+            (id, idf)
+        })
+        .collect()
+}
+
+/// Something that turns text into embedding vectors.
+///
+/// Implemented by [`EmbeddingModel`] and [`RemoteEmbedder`]. Ingest and
+/// retrieval take `&dyn Embedder`, so either backend (or a stub in tests)
+/// can be the one loaded.
+pub trait Embedder: Send + Sync {
+    /// Identifies the vector space, stored alongside each embedding so
+    /// vectors from different models are never compared.
+    fn model_id(&self) -> &str;
+
+    /// Encodes a document passage.
+    fn encode(&self, text: &str) -> Result<Vec<f32>, EmbeddingError>;
+
+    /// Encodes document passages, one embedding per text.
+    fn encode_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbeddingError>;
+
+    /// Encodes a passage without L2-normalizing it, keeping the magnitude
+    /// information some re-rankers use. [`normalize_vec`] of the result is
+    /// what `encode` returns.
+    fn encode_raw(&self, text: &str) -> Result<Vec<f32>, EmbeddingError>;
+
+    /// Encodes a search query. Backends that treat queries and passages
+    /// alike can rely on the default, which calls `encode`.
+    fn encode_query(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        self.encode(text)
+    }
+
+    /// The tokenizer the model reads its input with, for counting tokens.
+    /// `None` if it isn't available locally, as for a remote endpoint.
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        None
+    }
+}
+
 /// Wrapper around the BERT model for generating embeddings.
 ///
 /// This struct owns both the model and tokenizer, providing a simple
@@ -63,6 +262,12 @@ pub struct EmbeddingModel {
     model: BertModel,
     tokenizer: Tokenizer,
     device: Device,
+    model_id: String,
+    pooling: Pooling,
+    /// Prepended to queries by `encode_query`, e.g. `"query: "` for e5 models
+    query_prefix: Option<String>,
+    /// Prepended to documents by `encode_passage` and `encode_batch`
+    passage_prefix: Option<String>,
 }
 
 impl EmbeddingModel {
@@ -75,66 +280,108 @@ impl EmbeddingModel {
     ///
     /// First load will download ~90MB of model files.
     pub fn new() -> Result<Self, EmbeddingError> {
-        println!("Loading embedding model: {}", MODEL_ID);
-
-        // Use CPU device (GPU support requires feature flags)
-        let device = Device::Cpu;
-
-        // Download model files from Hugging Face Hub
-        let (config_path, tokenizer_path, weights_path) = download_model_files()?;
-
-        // Load the tokenizer
-        let tokenizer = Tokenizer::from_file(&tokenizer_path)
-            .map_err(|e| EmbeddingError::Tokenization(e.to_string()))?;
+        EmbeddingModelBuilder::default().build()
+    }
 
-        // Load and parse the model config
-        let config_str = std::fs::read_to_string(&config_path)
-            .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to read config: {}", e)))?;
-        let config: Config = serde_json::from_str(&config_str)
-            .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to parse config: {}", e)))?;
+    /// Creates a new embedding model, reporting download progress to `callback`.
+    ///
+    /// Same as [`EmbeddingModel::new`], but instead of printing a progress
+    /// bar to stdout (invisible in the desktop app), each file's progress is
+    /// passed to `callback`. Files already in the cache are reported once as
+    /// complete.
+    pub fn new_with_progress(callback: impl Fn(DownloadProgress)) -> Result<Self, EmbeddingError> {
+        EmbeddingModelBuilder::default().build_with_progress(callback)
+    }
 
-        // Load model weights from safetensors file
-        let vb = unsafe {
-            VarBuilder::from_mmaped_safetensors(&[weights_path], DTYPE, &device)
-                .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to load weights: {}", e)))?
-        };
+    /// Creates an embedding model from a pre-populated cache, without
+    /// touching the network.
+    ///
+    /// `cache_dir` uses the Hugging Face hub layout (the directory that
+    /// contains `models--sentence-transformers--all-MiniLM-L6-v2`). Fails
+    /// fast with a `ModelLoad` error naming every missing file - useful on
+    /// air-gapped machines.
+    pub fn new_offline(cache_dir: PathBuf) -> Result<Self, EmbeddingError> {
+        EmbeddingModelBuilder::default()
+            .with_cache_dir(cache_dir)
+            .with_offline(true)
+            .build()
+    }
 
-        // Build the model
-        let model = BertModel::load(vb, &config)
-            .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to build model: {}", e)))?;
+    /// The Hugging Face model ID this model was loaded from.
+    pub fn model_id(&self) -> &str {
+        &self.model_id
+    }
 
-        println!("Embedding model loaded successfully");
+    /// Computes token IDFs over `texts` with this model's tokenizer, for
+    /// [`Pooling::IdfWeighted`]. Pass the indexed corpus, e.g. every chunk.
+    pub fn token_idf(&self, texts: &[&str]) -> Result<HashMap<u32, f32>, EmbeddingError> {
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts.to_vec(), true)
+            .map_err(|e| EmbeddingError::Tokenization(e.to_string()))?;
 
-        Ok(EmbeddingModel {
-            model,
-            tokenizer,
-            device,
-        })
+        Ok(compute_token_idf(encodings.iter().map(|e| e.get_ids())))
     }
 
     /// Encodes a single text string into a vector embedding.
     ///
+    /// Same as [`EmbeddingModel::encode_passage`]; use
+    /// [`EmbeddingModel::encode_query`] for search queries.
     /// Returns a Vec<f32> of length EMBEDDING_DIM (384).
     pub fn encode(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        self.encode_passage(text)
+    }
+
+    /// Encodes a search query, prepending the query prefix if set.
+    pub fn encode_query(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        let inputs = tokenizer_inputs(self.query_prefix.as_deref(), &[text]);
+        let embeddings = self.encode_inputs(inputs, true)?;
+        Ok(embeddings.into_iter().next().unwrap())
+    }
+
+    /// Encodes a document passage, prepending the passage prefix if set.
+    pub fn encode_passage(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
         let embeddings = self.encode_batch(&[text])?;
         Ok(embeddings.into_iter().next().unwrap())
     }
 
-    /// Encodes multiple texts into vector embeddings.
+    /// Encodes multiple document passages into vector embeddings.
     ///
     /// Batch encoding is more efficient than encoding one at a time
     /// because it allows better GPU/CPU utilization.
+    /// The passage prefix is prepended to each text if set.
     ///
     /// Returns a Vec of embeddings, one per input text.
     pub fn encode_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbeddingError> {
-        if texts.is_empty() {
+        self.encode_inputs(tokenizer_inputs(self.passage_prefix.as_deref(), texts), true)
+    }
+
+    /// Encodes a passage without L2-normalizing the pooled vector.
+    ///
+    /// Keeps the magnitude information some re-rankers use. Call
+    /// [`normalize_vec`] on the result to get what `encode` returns.
+    pub fn encode_raw(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        let inputs = tokenizer_inputs(self.passage_prefix.as_deref(), &[text]);
+        let embeddings = self.encode_inputs(inputs, false)?;
+        Ok(embeddings.into_iter().next().unwrap())
+    }
+
+    /// Runs already-prefixed texts through the tokenizer and model.
+    ///
+    /// With `normalize` false the pooled vectors are returned as-is.
+    fn encode_inputs(
+        &self,
+        inputs: Vec<String>,
+        normalize: bool,
+    ) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        if inputs.is_empty() {
             return Ok(vec![]);
         }
 
         // Tokenize all texts
         let encodings = self
             .tokenizer
-            .encode_batch(texts.to_vec(), true)
+            .encode_batch(inputs, true)
             .map_err(|e| EmbeddingError::Tokenization(e.to_string()))?;
 
         // Find the maximum sequence length for padding
@@ -164,7 +411,7 @@ impl EmbeddingModel {
             all_token_type_ids.extend(padded_type_ids);
         }
 
-        let batch_size = texts.len();
+        let batch_size = encodings.len();
 
         // Convert to tensors
         let input_ids = Tensor::from_vec(
@@ -194,14 +441,18 @@ impl EmbeddingModel {
             .forward(&input_ids, &token_type_ids, Some(&attention_mask))
             .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
 
-        // Mean pooling: average the token embeddings, considering attention mask
-        let embeddings = mean_pooling(&output, &attention_mask)?;
+        // Pool the token embeddings into one vector per text
+        let embeddings = self.pooling.apply(&output, &attention_mask, &input_ids)?;
 
-        // Normalize embeddings for cosine similarity
-        let normalized = normalize(&embeddings)?;
+        // Normalize embeddings for cosine similarity (unless raw vectors were asked for)
+        let embeddings = if normalize {
+            normalize_embeddings(&embeddings)?
+        } else {
+            embeddings
+        };
 
         // Convert to Vec<Vec<f32>>
-        let result = normalized
+        let result = embeddings
             .to_vec2::<f32>()
             .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
 
@@ -209,181 +460,1432 @@ impl EmbeddingModel {
     }
 }
 
-/// Downloads model files from Hugging Face Hub.
-///
-/// Returns paths to (config.json, tokenizer.json, model.safetensors).
-fn download_model_files() -> Result<(PathBuf, PathBuf, PathBuf), EmbeddingError> {
-    // Set the HuggingFace endpoint explicitly to avoid URL parsing issues
-    std::env::set_var("HF_ENDPOINT", "https://huggingface.co");
+impl Embedder for EmbeddingModel {
+    fn model_id(&self) -> &str {
+        EmbeddingModel::model_id(self)
+    }
 
-    let cache_dir = dirs::cache_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join("huggingface")
-        .join("hub");
+    fn encode(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        EmbeddingModel::encode(self, text)
+    }
 
-    let api = ApiBuilder::new()
-        .with_cache_dir(cache_dir)
-        .with_progress(true)
-        .build()
-        .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to create API: {}", e)))?;
+    fn encode_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        EmbeddingModel::encode_batch(self, texts)
+    }
 
-    let repo = api.repo(Repo::new(MODEL_ID.to_string(), RepoType::Model));
+    fn encode_raw(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        EmbeddingModel::encode_raw(self, text)
+    }
 
-    println!("Downloading model files (if not cached)...");
+    fn encode_query(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        EmbeddingModel::encode_query(self, text)
+    }
 
-    let config_path = repo
-        .get("config.json")
-        .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to get config.json: {}", e)))?;
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        Some(&self.tokenizer)
+    }
+}
 
-    let tokenizer_path = repo
-        .get("tokenizer.json")
-        .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to get tokenizer.json: {}", e)))?;
+/// How long a single request to a remote embeddings endpoint may take.
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Where [`RemoteEmbedder`] sends its requests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteEmbedderConfig {
+    /// The server root, e.g. `http://localhost:11434` for Ollama;
+    /// `/v1/embeddings` is appended
+    pub base_url: String,
+    /// The model name sent with each request, e.g. `nomic-embed-text`.
+    /// Also used as the model ID of the stored embeddings.
+    pub model: String,
+    /// Sent as a bearer token if set; local servers usually need none
+    pub api_key: Option<String>,
+}
 
-    let weights_path = repo
-        .get("model.safetensors")
-        .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to get model.safetensors: {}", e)))?;
+/// An [`Embedder`] that calls an OpenAI-compatible `/v1/embeddings`
+/// endpoint instead of running a model locally.
+///
+/// Requests block the calling thread until the server answers. Servers
+/// don't all normalize their vectors, so `encode` and `encode_batch` do,
+/// like the local model; `encode_raw` returns them as sent.
+pub struct RemoteEmbedder {
+    client: reqwest::blocking::Client,
+    url: String,
+    config: RemoteEmbedderConfig,
+}
 
-    Ok((config_path, tokenizer_path, weights_path))
+/// Body of a `/v1/embeddings` request.
+#[derive(Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [&'a str],
 }
 
-/// Mean pooling over token embeddings.
-///
-/// This averages all token embeddings, but weighted by the attention mask
-/// so padding tokens don't contribute to the final embedding.
-fn mean_pooling(embeddings: &Tensor, attention_mask: &Tensor) -> Result<Tensor, EmbeddingError> {
-    // embeddings shape: (batch_size, seq_len, hidden_dim)
-    // attention_mask shape: (batch_size, seq_len)
+/// The part of a `/v1/embeddings` response we use.
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
 
-    // Expand attention mask to match embedding dimensions
-    let mask = attention_mask
-        .unsqueeze(2)
-        .map_err(|e| EmbeddingError::Inference(e.to_string()))?
-        .to_dtype(DType::F32)
-        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+    /// Position of the input this embedding belongs to
+    #[serde(default)]
+    index: usize,
+}
 
-    // Broadcast mask to embedding size
-    let mask = mask
-        .broadcast_as(embeddings.shape())
-        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+impl RemoteEmbedder {
+    /// Creates a client for the endpoint in `config`. Nothing is sent
+    /// until the first encode.
+    pub fn new(config: RemoteEmbedderConfig) -> Result<Self, EmbeddingError> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(REMOTE_TIMEOUT)
+            .build()
+            .map_err(|e| EmbeddingError::ModelLoad(e.to_string()))?;
+        let url = format!("{}/v1/embeddings", config.base_url.trim_end_matches('/'));
+        Ok(Self { client, url, config })
+    }
 
-    // Multiply embeddings by mask and sum
-    let masked = embeddings
-        .mul(&mask)
-        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+    /// Sends `texts` with `request` on a thread of its own: reqwest's
+    /// blocking client panics if it's used on an async runtime's thread,
+    /// which is where async Tauri commands run.
+    fn request_off_thread(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        if texts.is_empty() {
+            return Ok(vec![]);
+        }
 
-    let summed = masked
-        .sum(1)
-        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+        std::thread::scope(|scope| scope.spawn(|| self.request(texts)).join())
+            .unwrap_or_else(|_| {
+                Err(EmbeddingError::Inference("Embeddings request thread panicked".to_string()))
+            })
+    }
 
-    // Sum mask for averaging
-    let mask_sum = mask
-        .sum(1)
-        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+    /// Gets the embeddings of `texts` from the endpoint, as sent.
+    fn request(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        let body = EmbeddingsRequest {
+            model: &self.config.model,
+            input: texts,
+        };
+        let mut request = self.client.post(&self.url).json(&body);
+        if let Some(api_key) = &self.config.api_key {
+            request = request.bearer_auth(api_key);
+        }
 
-    // Avoid division by zero
-    let mask_sum = mask_sum
-        .clamp(1e-9, f64::MAX)
-        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+        let response = request.send().map_err(|e| {
+            EmbeddingError::Inference(format!("Request to {} failed: {}", self.url, e))
+        })?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().unwrap_or_default();
+            return Err(EmbeddingError::Inference(format!(
+                "{} returned {}: {}",
+                self.url,
+                status,
+                body.trim()
+            )));
+        }
 
-    // Divide to get mean
-    summed
-        .div(&mask_sum)
-        .map_err(|e| EmbeddingError::Inference(e.to_string()))
-}
+        let mut response: EmbeddingsResponse = response
+            .json()
+            .map_err(|e| EmbeddingError::Inference(format!("Invalid embeddings response: {}", e)))?;
+        if response.data.len() != texts.len() {
+            return Err(EmbeddingError::Inference(format!(
+                "Expected {} embeddings, got {}",
+                texts.len(),
+                response.data.len()
+            )));
+        }
 
-/// L2 normalize embeddings.
-///
-/// Normalized embeddings allow using dot product as cosine similarity,
-/// which is faster than computing cosine similarity directly.
-fn normalize(embeddings: &Tensor) -> Result<Tensor, EmbeddingError> {
-    // Compute L2 norm along the last dimension
-    let squared = embeddings
-        .sqr()
-        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+        // Servers aren't required to return embeddings in input order
+        response.data.sort_by_key(|data| data.index);
+        Ok(response.data.into_iter().map(|data| data.embedding).collect())
+    }
+}
 
-    let sum_squared = squared
-        .sum_keepdim(1)
-        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+impl Embedder for RemoteEmbedder {
+    fn model_id(&self) -> &str {
+        &self.config.model
+    }
 
-    let norm = sum_squared
-        .sqrt()
-        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+    fn encode(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        let embeddings = self.encode_batch(&[text])?;
+        Ok(embeddings.into_iter().next().unwrap())
+    }
 
-    // Avoid division by zero
-    let norm = norm
-        .clamp(1e-12, f64::MAX)
-        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+    /// Sends all `texts` in one request.
+    fn encode_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        let embeddings = self.request_off_thread(texts)?;
+        Ok(embeddings.iter().map(|embedding| normalize_vec(embedding)).collect())
+    }
 
-    embeddings
-        .broadcast_div(&norm)
-        .map_err(|e| EmbeddingError::Inference(e.to_string()))
+    fn encode_raw(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        let embeddings = self.request_off_thread(&[text])?;
+        Ok(embeddings.into_iter().next().unwrap())
+    }
 }
 
-/// Compute cosine similarity between two embeddings.
+/// A deterministic [`Embedder`] for tests that don't need the real model.
 ///
-/// For normalized embeddings, this is just the dot product.
-pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-    assert_eq!(a.len(), b.len(), "Embedding dimensions must match");
-    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
-}
-
+/// Each word is hashed into one of `MOCK_EMBEDDING_DIM` buckets and the
+/// counts are normalized, so texts sharing words score as similar.
+/// `encode_raw` returns the counts.
 #[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    #[ignore] // Requires model download, run with: cargo test -- --ignored
-    fn test_embedding_model() {
-        let model = EmbeddingModel::new().expect("Failed to load model");
+pub struct MockEmbedder;
 
-        let text = "This is a test sentence.";
-        let embedding = model.encode(text).expect("Failed to encode");
+#[cfg(test)]
+pub const MOCK_EMBEDDING_DIM: usize = 64;
 
-        assert_eq!(embedding.len(), EMBEDDING_DIM);
+#[cfg(test)]
+impl Embedder for MockEmbedder {
+    fn model_id(&self) -> &str {
+        "mock-embedder"
+    }
 
-        // Check that embedding is normalized (L2 norm ~= 1)
-        let norm: f32 = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
-        assert!((norm - 1.0).abs() < 0.01, "Embedding should be normalized");
+    fn encode(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        Ok(normalize_vec(&self.encode_raw(text)?))
     }
 
-    #[test]
-    #[ignore] // Requires model download
-    fn test_batch_encoding() {
-        let model = EmbeddingModel::new().expect("Failed to load model");
+    fn encode_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        texts.iter().map(|text| self.encode(text)).collect()
+    }
 
-        let texts = vec!["First sentence.", "Second sentence.", "Third sentence."];
-        let embeddings = model.encode_batch(&texts).expect("Failed to encode batch");
+    fn encode_raw(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        use std::hash::{DefaultHasher, Hash, Hasher};
 
-        assert_eq!(embeddings.len(), 3);
-        for emb in &embeddings {
-            assert_eq!(emb.len(), EMBEDDING_DIM);
+        let mut counts = vec![0.0; MOCK_EMBEDDING_DIM];
+        for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            let mut hasher = DefaultHasher::new();
+            word.to_lowercase().hash(&mut hasher);
+            counts[hasher.finish() as usize % MOCK_EMBEDDING_DIM] += 1.0;
         }
+        Ok(counts)
     }
+}
 
-    #[test]
-    #[ignore] // Requires model download
-    fn test_semantic_similarity() {
-        let model = EmbeddingModel::new().expect("Failed to load model");
+/// Builds an [`EmbeddingModel`] with non-default settings.
+///
+/// Every setting has a default matching [`EmbeddingModel::new`], so only
+/// the ones that differ need to be set:
+///
+/// ```ignore
+/// let model = EmbeddingModelBuilder::new()
+///     .with_model_id("intfloat/e5-small-v2")
+///     .with_prefixes("query: ", "passage: ")
+///     .with_retry(RetryConfig { max_attempts: 5, base_delay: Duration::from_secs(2) })
+///     .build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct EmbeddingModelBuilder {
+    device: Device,
+    /// Hugging Face model ID; must be a BERT-style sentence transformer
+    model_id: String,
+    pooling: Pooling,
+    query_prefix: Option<String>,
+    passage_prefix: Option<String>,
+    /// Hugging Face cache directory (`None` uses the default one)
+    cache_dir: Option<PathBuf>,
+    /// Load only from the cache, never touching the network
+    offline: bool,
+    retry: RetryConfig,
+}
 
-        let similar1 = model.encode("The cat sat on the mat").unwrap();
-        let similar2 = model.encode("A cat is sitting on a mat").unwrap();
-        let different = model.encode("The stock market crashed today").unwrap();
+impl Default for EmbeddingModelBuilder {
+    fn default() -> Self {
+        EmbeddingModelBuilder {
+            // CPU by default (GPU support requires feature flags)
+            device: Device::Cpu,
+            model_id: MODEL_ID.to_string(),
+            pooling: Pooling::default(),
+            query_prefix: None,
+            passage_prefix: None,
+            cache_dir: None,
+            offline: false,
+            retry: RetryConfig::default(),
+        }
+    }
+}
 
-        let sim_similar = cosine_similarity(&similar1, &similar2);
-        let sim_different = cosine_similarity(&similar1, &different);
+impl EmbeddingModelBuilder {
+    /// Creates a builder with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        println!("Similarity (similar sentences): {}", sim_similar);
-        println!("Similarity (different sentences): {}", sim_different);
+    /// Sets the device the model runs on (CPU by default).
+    pub fn with_device(mut self, device: Device) -> Self {
+        self.device = device;
+        self
+    }
 
-        // Similar sentences should have higher similarity
-        assert!(
-            sim_similar > sim_different,
-            "Similar sentences should have higher cosine similarity"
-        );
+    /// Sets the Hugging Face model to load (all-MiniLM-L6-v2 by default).
+    pub fn with_model_id(mut self, model_id: &str) -> Self {
+        self.model_id = model_id.to_string();
+        self
     }
 
-    #[test]
-    fn test_cosine_similarity() {
+    /// Sets how token outputs are pooled (mean pooling by default).
+    pub fn with_pooling(mut self, pooling: Pooling) -> Self {
+        self.pooling = pooling;
+        self
+    }
+
+    /// Sets the prefixes instruction-tuned retrieval models expect,
+    /// e.g. `("query: ", "passage: ")` for e5 models.
+    ///
+    /// all-MiniLM-L6-v2 uses no prefixes, which is the default.
+    pub fn with_prefixes(mut self, query_prefix: &str, passage_prefix: &str) -> Self {
+        self.query_prefix = Some(query_prefix.to_string());
+        self.passage_prefix = Some(passage_prefix.to_string());
+        self
+    }
+
+    /// Sets the Hugging Face cache directory model files are stored in.
+    ///
+    /// It uses the Hugging Face hub layout (the directory that contains
+    /// `models--sentence-transformers--all-MiniLM-L6-v2`).
+    pub fn with_cache_dir(mut self, cache_dir: PathBuf) -> Self {
+        self.cache_dir = Some(cache_dir);
+        self
+    }
+
+    /// Loads only from the cache, like [`EmbeddingModel::new_offline`].
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Sets how model file downloads are retried.
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Downloads the model files if needed (unless offline) and loads the model.
+    #[instrument(
+        name = "load_model",
+        skip_all,
+        fields(model_id = %self.model_id, offline = self.offline)
+    )]
+    pub fn build(self) -> Result<EmbeddingModel, EmbeddingError> {
+        let cache_dir = self.cache_dir.clone().unwrap_or_else(default_cache_dir);
+
+        let paths = if self.offline {
+            info!("Loading embedding model from the cache");
+            find_cached_model_files(&self.model_id, cache_dir)?
+        } else {
+            info!("Loading embedding model");
+            download_model_files(&self.model_id, cache_dir, &self.retry)?
+        };
+
+        self.load(paths)
+    }
+
+    /// Loads just the model's tokenizer, e.g. to count tokens before (or
+    /// without) loading the model.
+    ///
+    /// Only `tokenizer.json`, well under a megabyte, is downloaded if it
+    /// isn't cached (and never when offline); the weights aren't touched.
+    pub fn load_tokenizer(&self) -> Result<Tokenizer, EmbeddingError> {
+        let cache_dir = self.cache_dir.clone().unwrap_or_else(default_cache_dir);
+        let model_repo = Repo::new(self.model_id.clone(), RepoType::Model);
+        let file = MODEL_FILES[1];
+
+        let path = match Cache::new(cache_dir.clone()).repo(model_repo.clone()).get(file) {
+            Some(path) => path,
+            None if self.offline => {
+                return Err(missing_from_cache(&self.model_id, &[file], &cache_dir));
+            }
+            None => {
+                // Set the HuggingFace endpoint explicitly to avoid URL parsing issues
+                std::env::set_var("HF_ENDPOINT", "https://huggingface.co");
+                let blobs_dir = cache_dir.join(model_repo.folder_name()).join("blobs");
+                let api = ApiBuilder::new()
+                    .with_cache_dir(cache_dir)
+                    .with_progress(false)
+                    .build()
+                    .map_err(|e| {
+                        EmbeddingError::ModelLoad(format!("Failed to create API: {}", e))
+                    })?;
+                let repo = api.repo(model_repo);
+                download_verified(&self.retry, file, None, &blobs_dir, || repo.download(file))?
+            }
+        };
+
+        Tokenizer::from_file(&path).map_err(|e| EmbeddingError::Tokenization(e.to_string()))
+    }
+
+    /// Same as [`EmbeddingModelBuilder::build`], but reports download
+    /// progress to `callback`; see [`EmbeddingModel::new_with_progress`].
+    #[instrument(name = "load_model", skip_all, fields(model_id = %self.model_id))]
+    pub fn build_with_progress(
+        self,
+        callback: impl Fn(DownloadProgress),
+    ) -> Result<EmbeddingModel, EmbeddingError> {
+        if self.offline {
+            // Nothing to download
+            return self.build();
+        }
+
+        info!("Loading embedding model");
+
+        let cache_dir = self.cache_dir.clone().unwrap_or_else(default_cache_dir);
+        let paths =
+            download_model_files_with_progress(&self.model_id, cache_dir, &callback, &self.retry)?;
+
+        self.load(paths)
+    }
+
+    /// Loads the tokenizer, config and weights from local files.
+    fn load(
+        self,
+        (config_path, tokenizer_path, weights_path): (PathBuf, PathBuf, PathBuf),
+    ) -> Result<EmbeddingModel, EmbeddingError> {
+        // Load the tokenizer
+        let tokenizer = Tokenizer::from_file(&tokenizer_path)
+            .map_err(|e| EmbeddingError::Tokenization(e.to_string()))?;
+
+        // Load and parse the model config
+        let config_str = std::fs::read_to_string(&config_path)
+            .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to read config: {}", e)))?;
+        let config: Config = serde_json::from_str(&config_str)
+            .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to parse config: {}", e)))?;
+
+        // Load model weights from safetensors file
+        let vb = unsafe {
+            VarBuilder::from_mmaped_safetensors(&[weights_path], DTYPE, &self.device)
+                .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to load weights: {}", e)))?
+        };
+
+        // Build the model
+        let model = BertModel::load(vb, &config)
+            .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to build model: {}", e)))?;
+
+        info!("Embedding model loaded");
+
+        Ok(EmbeddingModel {
+            model,
+            tokenizer,
+            device: self.device,
+            model_id: self.model_id,
+            pooling: self.pooling,
+            query_prefix: self.query_prefix,
+            passage_prefix: self.passage_prefix,
+        })
+    }
+}
+
+/// Returns true if a failed download might succeed when retried.
+///
+/// Connection failures, server errors (5xx), rate limiting (429) and I/O
+/// errors while streaming the file are transient. Anything else - a 404,
+/// a malformed response - will fail the same way again.
+fn is_retryable(error: &ApiError) -> bool {
+    match error {
+        ApiError::RequestError(e) => match e.as_ref() {
+            ureq::Error::Status(code, _) => *code == 429 || *code >= 500,
+            ureq::Error::Transport(_) => true,
+        },
+        ApiError::IoError(_) => true,
+        ApiError::TooManyRetries(inner) => is_retryable(inner),
+        _ => false,
+    }
+}
+
+/// Calls `fetch` until it succeeds, a permanent error occurs, or
+/// `retry.max_attempts` is reached, backing off exponentially in between.
+///
+/// Returns the last error if every attempt fails.
+fn fetch_with_retry<T>(
+    retry: &RetryConfig,
+    file: &str,
+    mut fetch: impl FnMut() -> Result<T, ApiError>,
+) -> Result<T, ApiError> {
+    let max_attempts = retry.max_attempts.max(1);
+    let mut delay = retry.base_delay;
+
+    for attempt in 1.. {
+        match fetch() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < max_attempts && is_retryable(&e) => {
+                warn!(file, attempt, max_attempts, error = %e, ?delay, "Download failed, retrying");
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    unreachable!("the retry loop only exits by returning")
+}
+
+/// Size and SHA-256 of a model file, as listed by the Hub.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ExpectedFile {
+    size: u64,
+    /// Only listed for LFS files, which include the weights
+    sha256: Option<String>,
+}
+
+/// The subset of the Hub's repo listing (`?blobs=true`) we read.
+#[derive(Deserialize)]
+struct RepoListing {
+    siblings: Vec<ListedFile>,
+}
+
+#[derive(Deserialize)]
+struct ListedFile {
+    rfilename: String,
+    size: Option<u64>,
+    lfs: Option<LfsInfo>,
+}
+
+#[derive(Deserialize)]
+struct LfsInfo {
+    sha256: String,
+}
+
+/// Parses the Hub's repo listing into expected files by name.
+///
+/// Files without a listed size can't be checked and are left out.
+fn parse_expected_files(json: &str) -> Result<HashMap<String, ExpectedFile>, serde_json::Error> {
+    let listing: RepoListing = serde_json::from_str(json)?;
+    Ok(listing
+        .siblings
+        .into_iter()
+        .filter_map(|file| {
+            let size = file.size?;
+            let sha256 = file.lfs.map(|lfs| lfs.sha256);
+            Some((file.rfilename, ExpectedFile { size, sha256 }))
+        })
+        .collect())
+}
+
+/// Fetches the expected size and hash of each file in the repo.
+///
+/// Integrity checks are best effort: if the listing can't be fetched the
+/// map is empty and files are used unchecked, as before.
+fn fetch_expected_files(repo: &ApiRepo) -> HashMap<String, ExpectedFile> {
+    let listing = repo
+        .info_request()
+        .query("blobs", "true")
+        .call()
+        .map_err(|e| e.to_string())
+        .and_then(|response| response.into_string().map_err(|e| e.to_string()))
+        .and_then(|body| parse_expected_files(&body).map_err(|e| e.to_string()));
+
+    listing.unwrap_or_else(|e| {
+        warn!(error = %e, "Couldn't list model files, skipping integrity checks");
+        HashMap::new()
+    })
+}
+
+/// Where a repo's listing is saved, next to its `blobs` and `snapshots`,
+/// so cached files can be checked without asking the Hub on every load.
+fn saved_listing_path(cache_dir: &Path, repo: &Repo) -> PathBuf {
+    cache_dir.join(repo.folder_name()).join("expected_files.json")
+}
+
+/// Reads the listing saved by `save_listing`. Empty if there's none yet,
+/// so cached files are used unchecked, as before.
+fn load_saved_listing(path: &Path) -> HashMap<String, ExpectedFile> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Saves a repo's listing for `load_saved_listing`.
+fn save_listing(path: &Path, expected: &HashMap<String, ExpectedFile>) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string(expected)?)
+}
+
+/// Fetches the repo's listing before a download and saves it for later
+/// loads. Falls back to `saved` if the Hub can't be reached.
+fn refresh_expected_files(
+    repo: &ApiRepo,
+    path: &Path,
+    saved: HashMap<String, ExpectedFile>,
+) -> HashMap<String, ExpectedFile> {
+    let fetched = fetch_expected_files(repo);
+    if fetched.is_empty() {
+        return saved;
+    }
+
+    // Best effort: without it the next load just trusts the cache
+    if let Err(e) = save_listing(path, &fetched) {
+        warn!(error = %e, "Couldn't save model file listing");
+    }
+    fetched
+}
+
+/// Checks a model file against its expected size and, if `check_hash` is
+/// set and the Hub lists one, its SHA-256. Returns why it doesn't match.
+fn verify_model_file(path: &Path, expected: &ExpectedFile, check_hash: bool) -> Result<(), String> {
+    let size = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+    if size != expected.size {
+        return Err(format!("expected {} bytes, found {}", expected.size, size));
+    }
+
+    if let (true, Some(sha256)) = (check_hash, &expected.sha256) {
+        let actual = crate::documents::hash_file(path).map_err(|e| e.to_string())?;
+        if actual != *sha256 {
+            return Err(format!("expected SHA-256 {}, found {}", sha256, actual));
+        }
+    }
+    Ok(())
+}
+
+/// Deletes a cached model file. hf-hub caches files as symlinks into a
+/// blob directory (copies on Windows), so the blob is removed as well.
+fn remove_model_file(path: &Path) {
+    if let Ok(blob) = std::fs::canonicalize(path) {
+        std::fs::remove_file(blob).ok();
+    }
+    std::fs::remove_file(path).ok();
+}
+
+/// Deletes partial downloads (`*.part`) in a repo's blob directory.
+///
+/// hf-hub resumes a download by appending to its partial file. One it
+/// can't resume from fails every attempt with `InvalidResume`.
+fn remove_partial_downloads(blobs_dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(blobs_dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.extension().is_some_and(|ext| ext == "part") {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
+
+/// Finds a usable cached copy of each of `MODEL_FILES` (`None` if it
+/// needs downloading), and the listing to verify downloads against.
+///
+/// The Hub is only asked for the listing if something needs downloading;
+/// otherwise the copy saved by the last download is enough.
+fn check_cached_files(
+    cache: &CacheRepo,
+    repo: &ApiRepo,
+    listing_path: &Path,
+) -> (Vec<Option<PathBuf>>, HashMap<String, ExpectedFile>) {
+    let saved = load_saved_listing(listing_path);
+    let cached: Vec<Option<PathBuf>> = MODEL_FILES
+        .iter()
+        .map(|file| usable_cached_file(cache.get(file), file, saved.get(*file)))
+        .collect();
+
+    let expected = if cached.iter().all(Option::is_some) {
+        saved
+    } else {
+        refresh_expected_files(repo, listing_path, saved)
+    };
+    (cached, expected)
+}
+
+/// Returns a cached model file if it has the expected size, as saved by
+/// the last download (see `load_saved_listing`).
+///
+/// A cached file of the wrong size is left from an interrupted or corrupt
+/// download; it's deleted so the caller downloads it again. Cached files
+/// aren't hashed, to keep startup fast.
+fn usable_cached_file(
+    cached: Option<PathBuf>,
+    file: &str,
+    expected: Option<&ExpectedFile>,
+) -> Option<PathBuf> {
+    let path = cached?;
+    match expected.map(|expected| verify_model_file(&path, expected, false)) {
+        Some(Err(reason)) => {
+            warn!(file, %reason, "Cached model file is corrupt, downloading it again");
+            remove_model_file(&path);
+            None
+        }
+        _ => Some(path),
+    }
+}
+
+/// Downloads a model file with `download` and verifies it, deleting and
+/// downloading it again if it's corrupt, up to `retry.max_attempts` times.
+///
+/// Transient network errors are retried inside each attempt by
+/// `fetch_with_retry`, where hf-hub resumes from the partial file.
+/// A partial file it can't resume from is deleted from `blobs_dir`.
+fn download_verified(
+    retry: &RetryConfig,
+    file: &str,
+    expected: Option<&ExpectedFile>,
+    blobs_dir: &Path,
+    mut download: impl FnMut() -> Result<PathBuf, ApiError>,
+) -> Result<PathBuf, EmbeddingError> {
+    let max_attempts = retry.max_attempts.max(1);
+    let failed = |e: &dyn std::fmt::Display| {
+        EmbeddingError::ModelLoad(format!("Failed to get {}: {}", file, e))
+    };
+
+    for attempt in 1.. {
+        let path = match fetch_with_retry(retry, file, &mut download) {
+            Ok(path) => path,
+            Err(ApiError::InvalidResume) if attempt < max_attempts => {
+                warn!(file, "Partial download is corrupt, starting over");
+                remove_partial_downloads(blobs_dir);
+                continue;
+            }
+            Err(e) => return Err(failed(&e)),
+        };
+
+        let Some(expected) = expected else {
+            return Ok(path);
+        };
+        match verify_model_file(&path, expected, true) {
+            Ok(()) => return Ok(path),
+            Err(reason) => {
+                remove_model_file(&path);
+                if attempt >= max_attempts {
+                    return Err(failed(&format!("corrupt after {} attempts, {}", attempt, reason)));
+                }
+                warn!(file, %reason, attempt, max_attempts, "Downloaded file is corrupt, retrying");
+            }
+        }
+    }
+
+    unreachable!("the retry loop only exits by returning")
+}
+
+/// Downloads model files from Hugging Face Hub, retrying transient failures.
+///
+/// Each downloaded file is checked against the size and hash the Hub lists
+/// for it; corrupt or truncated files are deleted and downloaded again.
+/// The Hub is only asked for that listing when something needs
+/// downloading; cached files are checked against the saved copy.
+///
+/// Returns paths to (config.json, tokenizer.json, model.safetensors).
+#[instrument(name = "download_model", skip(cache_dir, retry))]
+fn download_model_files(
+    model_id: &str,
+    cache_dir: PathBuf,
+    retry: &RetryConfig,
+) -> Result<(PathBuf, PathBuf, PathBuf), EmbeddingError> {
+    // Set the HuggingFace endpoint explicitly to avoid URL parsing issues
+    std::env::set_var("HF_ENDPOINT", "https://huggingface.co");
+
+    let model_repo = Repo::new(model_id.to_string(), RepoType::Model);
+    let cache = Cache::new(cache_dir.clone()).repo(model_repo.clone());
+    let blobs_dir = cache_dir.join(model_repo.folder_name()).join("blobs");
+    let listing_path = saved_listing_path(&cache_dir, &model_repo);
+
+    let api = ApiBuilder::new()
+        .with_cache_dir(cache_dir)
+        .with_progress(true)
+        .build()
+        .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to create API: {}", e)))?;
+
+    let repo = api.repo(model_repo);
+    let (cached, expected) = check_cached_files(&cache, &repo, &listing_path);
+
+    debug!("Downloading model files (if not cached)");
+
+    let mut paths = Vec::with_capacity(MODEL_FILES.len());
+    for (file, cached) in MODEL_FILES.into_iter().zip(cached) {
+        let path = match cached {
+            Some(path) => path,
+            None => download_verified(retry, file, expected.get(file), &blobs_dir, || {
+                repo.download(file)
+            })?,
+        };
+        paths.push(path);
+    }
+
+    let weights_path = paths.pop().unwrap_or_default();
+    let tokenizer_path = paths.pop().unwrap_or_default();
+    let config_path = paths.pop().unwrap_or_default();
+
+    Ok((config_path, tokenizer_path, weights_path))
+}
+
+/// Downloads model files, relaying per-file progress to `callback`.
+/// Files are verified as in `download_model_files`.
+///
+/// Returns paths to (config.json, tokenizer.json, model.safetensors).
+#[instrument(name = "download_model", skip(cache_dir, callback, retry))]
+fn download_model_files_with_progress<F: Fn(DownloadProgress)>(
+    model_id: &str,
+    cache_dir: PathBuf,
+    callback: &F,
+    retry: &RetryConfig,
+) -> Result<(PathBuf, PathBuf, PathBuf), EmbeddingError> {
+    // Set the HuggingFace endpoint explicitly to avoid URL parsing issues
+    std::env::set_var("HF_ENDPOINT", "https://huggingface.co");
+
+    let model_repo = Repo::new(model_id.to_string(), RepoType::Model);
+    let cache = Cache::new(cache_dir.clone()).repo(model_repo.clone());
+    let blobs_dir = cache_dir.join(model_repo.folder_name()).join("blobs");
+    let listing_path = saved_listing_path(&cache_dir, &model_repo);
+
+    let api = ApiBuilder::new()
+        .with_cache_dir(cache_dir)
+        .with_progress(false)
+        .build()
+        .map_err(|e| EmbeddingError::ModelLoad(format!("Failed to create API: {}", e)))?;
+
+    let repo = api.repo(model_repo);
+    let (cached, expected) = check_cached_files(&cache, &repo, &listing_path);
+
+    let mut paths = Vec::with_capacity(MODEL_FILES.len());
+    for (file, cached) in MODEL_FILES.into_iter().zip(cached) {
+        let path = match cached {
+            Some(path) => {
+                // Already downloaded - report it as complete
+                let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                callback(DownloadProgress {
+                    file: file.to_string(),
+                    downloaded: size,
+                    total: size,
+                });
+                path
+            }
+            None => download_verified(retry, file, expected.get(file), &blobs_dir, || {
+                repo.download_with_progress(file, ProgressRelay::new(callback))
+            })?,
+        };
+        paths.push(path);
+    }
+
+    let weights_path = paths.pop().unwrap_or_default();
+    let tokenizer_path = paths.pop().unwrap_or_default();
+    let config_path = paths.pop().unwrap_or_default();
+
+    Ok((config_path, tokenizer_path, weights_path))
+}
+
+/// Counts the tokens `text` takes up with `tokenizer`, without special
+/// tokens.
+///
+/// Text beyond the tokenizer's truncation length is counted too, so
+/// this works for long chat messages, not just model inputs.
+pub fn count_tokens(tokenizer: &Tokenizer, text: &str) -> Result<usize, EmbeddingError> {
+    let encoding = tokenizer
+        .encode(text, false)
+        .map_err(|e| EmbeddingError::Tokenization(e.to_string()))?;
+
+    // Truncated text ends up in the overflowing encodings
+    let overflow: usize = encoding.get_overflowing().iter().map(|e| e.get_ids().len()).sum();
+    Ok(encoding.get_ids().len() + overflow)
+}
+
+/// The default Hugging Face cache directory.
+fn default_cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("huggingface")
+        .join("hub")
+}
+
+/// Locates model files in an existing Hugging Face cache, without downloading.
+///
+/// Returns paths to (config.json, tokenizer.json, model.safetensors), or a
+/// `ModelLoad` error listing every file that's missing from the cache.
+fn find_cached_model_files(
+    model_id: &str,
+    cache_dir: PathBuf,
+) -> Result<(PathBuf, PathBuf, PathBuf), EmbeddingError> {
+    let cache = Cache::new(cache_dir.clone());
+    let repo = cache.repo(Repo::new(model_id.to_string(), RepoType::Model));
+
+    let config = repo.get(MODEL_FILES[0]);
+    let tokenizer = repo.get(MODEL_FILES[1]);
+    let weights = repo.get(MODEL_FILES[2]);
+
+    match (config, tokenizer, weights) {
+        (Some(config), Some(tokenizer), Some(weights)) => Ok((config, tokenizer, weights)),
+        (config, tokenizer, weights) => {
+            let missing: Vec<&str> = [config.is_none(), tokenizer.is_none(), weights.is_none()]
+                .iter()
+                .zip(MODEL_FILES)
+                .filter(|(is_missing, _)| **is_missing)
+                .map(|(_, file)| file)
+                .collect();
+
+            Err(missing_from_cache(model_id, &missing, &cache_dir))
+        }
+    }
+}
+
+/// The `ModelLoad` error for model files an offline load can't find.
+fn missing_from_cache(model_id: &str, missing: &[&str], cache_dir: &Path) -> EmbeddingError {
+    EmbeddingError::ModelLoad(format!(
+        "Offline mode: missing {} for {} in cache {}",
+        missing.join(", "),
+        model_id,
+        cache_dir.display()
+    ))
+}
+
+/// Builds the tokenizer input for each text, with `prefix` prepended.
+fn tokenizer_inputs(prefix: Option<&str>, texts: &[&str]) -> Vec<String> {
+    texts
+        .iter()
+        .map(|text| format!("{}{}", prefix.unwrap_or_default(), text))
+        .collect()
+}
+
+/// Mean pooling over token embeddings.
+///
+/// This averages all token embeddings, but weighted by the attention mask
+/// so padding tokens don't contribute to the final embedding.
+fn mean_pooling(embeddings: &Tensor, attention_mask: &Tensor) -> Result<Tensor, EmbeddingError> {
+    // embeddings shape: (batch_size, seq_len, hidden_dim)
+    // attention_mask shape: (batch_size, seq_len)
+
+    // Expand attention mask to match embedding dimensions
+    let mask = attention_mask
+        .unsqueeze(2)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?
+        .to_dtype(DType::F32)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    // Broadcast mask to embedding size
+    let mask = mask
+        .broadcast_as(embeddings.shape())
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    // Multiply embeddings by mask and sum
+    let masked = embeddings
+        .mul(&mask)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    let summed = masked
+        .sum(1)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    // Sum mask for averaging
+    let mask_sum = mask
+        .sum(1)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    // Avoid division by zero
+    let mask_sum = mask_sum
+        .clamp(1e-9, f64::MAX)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    // Divide to get mean
+    summed
+        .div(&mask_sum)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))
+}
+
+/// IDF-weighted mean pooling over token embeddings.
+///
+/// Like [`mean_pooling`], but each token's vector is weighted by the IDF of
+/// its ID (1.0 if it isn't in `idf`) before averaging. Padding has weight 0.
+fn idf_weighted_pooling(
+    embeddings: &Tensor,
+    attention_mask: &Tensor,
+    input_ids: &Tensor,
+    idf: &HashMap<u32, f32>,
+) -> Result<Tensor, EmbeddingError> {
+    let ids = input_ids
+        .to_vec2::<i64>()
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+    let mask = attention_mask
+        .to_vec2::<i64>()
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    // One weight per token: its IDF, or 0 for padding
+    let weights: Vec<f32> = ids
+        .iter()
+        .flatten()
+        .zip(mask.iter().flatten())
+        .map(|(&id, &m)| {
+            if m == 0 {
+                0.0
+            } else {
+                idf.get(&(id as u32)).copied().unwrap_or(1.0)
+            }
+        })
+        .collect();
+
+    let (batch_size, seq_len) = input_ids
+        .dims2()
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+    let weights = Tensor::from_vec(weights, (batch_size, seq_len), embeddings.device())
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?
+        .unsqueeze(2)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?
+        .broadcast_as(embeddings.shape())
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    let summed = embeddings
+        .mul(&weights)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?
+        .sum(1)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    // Avoid division by zero
+    let weight_sum = weights
+        .sum(1)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?
+        .clamp(1e-9, f64::MAX)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    summed
+        .div(&weight_sum)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))
+}
+
+/// CLS pooling: the hidden state of the first token.
+///
+/// Checkpoints trained with a classification head summarize the whole
+/// sentence in this token, so padding doesn't matter here.
+fn cls_pooling(embeddings: &Tensor) -> Result<Tensor, EmbeddingError> {
+    // The [CLS] token is always first: take position 0 of the sequence axis
+    embeddings
+        .narrow(1, 0, 1)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?
+        .squeeze(1)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))
+}
+
+/// Max pooling: the largest value of each dimension across non-padding tokens.
+fn max_pooling(embeddings: &Tensor, attention_mask: &Tensor) -> Result<Tensor, EmbeddingError> {
+    let mask = attention_mask
+        .unsqueeze(2)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?
+        .to_dtype(DType::F32)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?
+        .broadcast_as(embeddings.shape())
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    // Push padding positions far below any real value: mask 1 -> 0, mask 0 -> -1e9
+    let penalty = mask
+        .affine(1e9, -1e9)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    embeddings
+        .add(&penalty)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?
+        .max(1)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))
+}
+
+/// L2 normalize embeddings.
+///
+/// Normalized embeddings allow using dot product as cosine similarity,
+/// which is faster than computing cosine similarity directly.
+fn normalize_embeddings(embeddings: &Tensor) -> Result<Tensor, EmbeddingError> {
+    // Compute L2 norm along the last dimension
+    let squared = embeddings
+        .sqr()
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    let sum_squared = squared
+        .sum_keepdim(1)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    let norm = sum_squared
+        .sqrt()
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    // Avoid division by zero
+    let norm = norm
+        .clamp(1e-12, f64::MAX)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))?;
+
+    embeddings
+        .broadcast_div(&norm)
+        .map_err(|e| EmbeddingError::Inference(e.to_string()))
+}
+
+/// L2-normalizes a single embedding so its length is 1.
+///
+/// A zero vector is returned unchanged.
+pub fn normalize_vec(embedding: &[f32]) -> Vec<f32> {
+    let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return embedding.to_vec();
+    }
+    embedding.iter().map(|x| x / norm).collect()
+}
+
+/// Compute cosine similarity between two embeddings.
+///
+/// For normalized embeddings, this is just the dot product.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    assert_eq!(a.len(), b.len(), "Embedding dimensions must match");
+    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+}
+
+/// Pairwise cosine similarities between embeddings, e.g. a document's chunks.
+///
+/// `matrix[i][j]` is the cosine similarity of `embeddings[i]` and
+/// `embeddings[j]`. Each vector is normalized once, and since the matrix is
+/// symmetric only the upper triangle is computed and then mirrored. The
+/// diagonal is 1.0, except for zero vectors, which score 0.0 against
+/// everything.
+pub fn similarity_matrix(embeddings: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    let normalized: Vec<Vec<f32>> = embeddings.iter().map(|e| normalize_vec(e)).collect();
+    let n = normalized.len();
+    let mut matrix = vec![vec![0.0; n]; n];
+
+    for i in 0..n {
+        for j in i..n {
+            let score = cosine_similarity(&normalized[i], &normalized[j]);
+            matrix[i][j] = score;
+            matrix[j][i] = score;
+        }
+    }
+
+    matrix
+}
+
+/// How two embeddings are compared during search.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SimilarityMetric {
+    /// Dot product - equal to cosine similarity for normalized vectors,
+    /// which is what this module's model produces. Higher is better.
+    #[default]
+    Dot,
+    /// Cosine similarity computed explicitly, for un-normalized vectors.
+    /// Higher is better.
+    Cosine,
+    /// Euclidean (L2) distance. Lower is better.
+    Euclidean,
+}
+
+impl SimilarityMetric {
+    /// Whether a larger score means a closer match.
+    pub fn higher_is_better(&self) -> bool {
+        !matches!(self, SimilarityMetric::Euclidean)
+    }
+}
+
+/// Compares two embeddings with the given metric.
+///
+/// See [`SimilarityMetric::higher_is_better`] for how to order the scores.
+pub fn similarity(metric: SimilarityMetric, a: &[f32], b: &[f32]) -> f32 {
+    match metric {
+        SimilarityMetric::Dot => cosine_similarity(a, b),
+        SimilarityMetric::Cosine => {
+            let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+            let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if norm_a == 0.0 || norm_b == 0.0 {
+                // A zero vector has no direction to compare
+                0.0
+            } else {
+                cosine_similarity(a, b) / (norm_a * norm_b)
+            }
+        }
+        SimilarityMetric::Euclidean => {
+            assert_eq!(a.len(), b.len(), "Embedding dimensions must match");
+            a.iter().zip(b.iter()).map(|(x, y)| (x - y).powi(2)).sum::<f32>().sqrt()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore] // Requires model download, run with: cargo test -- --ignored
+    fn test_embedding_model() {
+        let model = EmbeddingModel::new().expect("Failed to load model");
+
+        let text = "This is a test sentence.";
+        let embedding = model.encode(text).expect("Failed to encode");
+
+        assert_eq!(embedding.len(), EMBEDDING_DIM);
+
+        // Check that embedding is normalized (L2 norm ~= 1)
+        let norm: f32 = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 0.01, "Embedding should be normalized");
+    }
+
+    #[test]
+    #[ignore] // Requires model download
+    fn test_batch_encoding() {
+        let model = EmbeddingModel::new().expect("Failed to load model");
+
+        let texts = vec!["First sentence.", "Second sentence.", "Third sentence."];
+        let embeddings = model.encode_batch(&texts).expect("Failed to encode batch");
+
+        assert_eq!(embeddings.len(), 3);
+        for emb in &embeddings {
+            assert_eq!(emb.len(), EMBEDDING_DIM);
+        }
+    }
+
+    #[test]
+    #[ignore] // Requires model download
+    fn test_semantic_similarity() {
+        let model = EmbeddingModel::new().expect("Failed to load model");
+
+        let similar1 = model.encode("The cat sat on the mat").unwrap();
+        let similar2 = model.encode("A cat is sitting on a mat").unwrap();
+        let different = model.encode("The stock market crashed today").unwrap();
+
+        let sim_similar = cosine_similarity(&similar1, &similar2);
+        let sim_different = cosine_similarity(&similar1, &different);
+
+        debug!(sim_similar, sim_different, "Similarities");
+
+        // Similar sentences should have higher similarity
+        assert!(
+            sim_similar > sim_different,
+            "Similar sentences should have higher cosine similarity"
+        );
+    }
+
+    #[test]
+    fn test_offline_missing_files_error() {
+        let empty_dir = std::env::temp_dir().join(format!("hf-offline-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&empty_dir).unwrap();
+
+        let err = match EmbeddingModel::new_offline(empty_dir.clone()) {
+            Err(e) => e,
+            Ok(_) => panic!("Loading from an empty cache should fail"),
+        };
+
+        let message = err.to_string();
+        assert!(matches!(err, EmbeddingError::ModelLoad(_)));
+        for file in MODEL_FILES {
+            assert!(message.contains(file), "{} not named in: {}", file, message);
+        }
+        assert!(message.contains(&empty_dir.display().to_string()));
+
+        std::fs::remove_dir_all(&empty_dir).ok();
+    }
+
+    #[test]
+    fn test_progress_relay_reports_increasing_bytes() {
+        use std::cell::RefCell;
+
+        let events = RefCell::new(Vec::new());
+        let callback = |p: DownloadProgress| events.borrow_mut().push(p);
+
+        // Stub downloader driving the relay the way hf-hub does:
+        // init with the file size, byte deltas, then finish
+        let mut relay = ProgressRelay::new(&callback);
+        relay.init(100, "model.safetensors");
+        for delta in [30, 30, 30, 10] {
+            relay.update(delta);
+        }
+        relay.finish();
+
+        let events = events.into_inner();
+        let downloaded: Vec<u64> = events.iter().map(|p| p.downloaded).collect();
+        assert_eq!(downloaded, vec![0, 30, 60, 90, 100, 100]);
+        assert!(downloaded.windows(2).all(|w| w[0] <= w[1]));
+        assert!(events.iter().all(|p| p.file == "model.safetensors" && p.total == 100));
+    }
+
+    #[test]
+    fn test_corrupt_download_is_deleted_and_retried() {
+        let dir = std::env::temp_dir().join(format!("hf-verify-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let weights = vec![7u8; 1000];
+        let reference = dir.join("reference");
+        std::fs::write(&reference, &weights).unwrap();
+        let listing = format!(
+            r#"{{"siblings": [
+                {{"rfilename": "config.json"}},
+                {{"rfilename": "model.safetensors", "size": 1000, "lfs": {{"sha256": "{}"}}}}
+            ]}}"#,
+            crate::documents::hash_file(&reference).unwrap()
+        );
+        let listed = parse_expected_files(&listing).unwrap();
+        assert!(!listed.contains_key("config.json"));
+
+        // Later loads read the listing from disk rather than the Hub
+        let listing_path = dir.join("models--test").join("expected_files.json");
+        assert!(load_saved_listing(&listing_path).is_empty());
+        save_listing(&listing_path, &listed).unwrap();
+        let saved = load_saved_listing(&listing_path);
+        assert_eq!(saved, listed);
+        let expected = saved.get("model.safetensors");
+
+        // Stub downloader: the first attempt is cut off partway through
+        let path = dir.join("model.safetensors");
+        let mut attempts = 0;
+        let download = || {
+            attempts += 1;
+            let len = if attempts == 1 { 400 } else { weights.len() };
+            std::fs::write(&path, &weights[..len])?;
+            Ok(path.clone())
+        };
+        let retry = RetryConfig {
+            max_attempts: 3,
+            base_delay: Duration::ZERO,
+        };
+
+        let result = download_verified(&retry, "model.safetensors", expected, &dir, download);
+        assert_eq!(result.unwrap(), path);
+        assert_eq!(attempts, 2);
+        assert_eq!(std::fs::read(&path).unwrap(), weights);
+
+        // A truncated cached copy is discarded; a complete one is used as-is
+        std::fs::write(&path, &weights[..10]).unwrap();
+        assert_eq!(usable_cached_file(Some(path.clone()), "model.safetensors", expected), None);
+        assert!(!path.exists());
+        std::fs::write(&path, &weights).unwrap();
+        assert_eq!(
+            usable_cached_file(Some(path.clone()), "model.safetensors", expected),
+            Some(path.clone())
+        );
+
+        // Content that never verifies fails once attempts run out
+        let result = download_verified(&retry, "model.safetensors", expected, &dir, || {
+            std::fs::write(&path, vec![0u8; 1000])?;
+            Ok(path.clone())
+        });
+        assert!(matches!(result, Err(EmbeddingError::ModelLoad(msg)) if msg.contains("SHA-256")));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Two sequences of three tokens (hidden size 2); the mask marks
+    /// the last token of the first and the last two of the second as padding.
+    /// Also returns token IDs: 10, 20 and padding, then 30 and padding.
+    fn pooling_inputs() -> (Tensor, Tensor, Tensor) {
+        let embeddings = Tensor::from_vec(
+            vec![
+                1.0f32, 2.0, 3.0, -4.0, 100.0, 100.0, // sequence 1
+                -1.0, 5.0, 100.0, 100.0, 100.0, 100.0, // sequence 2
+            ],
+            (2, 3, 2),
+            &Device::Cpu,
+        )
+        .unwrap();
+        let mask = Tensor::from_vec(vec![1i64, 1, 0, 1, 0, 0], (2, 3), &Device::Cpu).unwrap();
+        let ids = Tensor::from_vec(vec![10i64, 20, 0, 30, 0, 0], (2, 3), &Device::Cpu).unwrap();
+        (embeddings, mask, ids)
+    }
+
+    #[test]
+    fn test_pooling_strategies() {
+        let (embeddings, mask, ids) = pooling_inputs();
+        let pool = |pooling: Pooling| -> Vec<Vec<f32>> {
+            pooling.apply(&embeddings, &mask, &ids).unwrap().to_vec2().unwrap()
+        };
+
+        // Padding (the 100s) never leaks into mean or max
+        assert_eq!(pool(Pooling::Mean), vec![vec![2.0, -1.0], vec![-1.0, 5.0]]);
+        assert_eq!(pool(Pooling::Max), vec![vec![3.0, 2.0], vec![-1.0, 5.0]]);
+        assert_eq!(pool(Pooling::Cls), vec![vec![1.0, 2.0], vec![-1.0, 5.0]]);
+        assert_eq!(Pooling::default(), Pooling::Mean);
+    }
+
+    #[test]
+    fn test_idf_weighted_pooling() {
+        let (embeddings, mask, ids) = pooling_inputs();
+
+        // Token 10 weighs 3, token 20 weighs 1; token 30 isn't in the map (1.0)
+        // and the padding ID 0 is ignored even though it has a weight
+        let idf: HashMap<u32, f32> = [(10, 3.0), (20, 1.0), (0, 50.0)].into_iter().collect();
+        let pooled: Vec<Vec<f32>> = Pooling::IdfWeighted(Arc::new(idf))
+            .apply(&embeddings, &mask, &ids)
+            .unwrap()
+            .to_vec2()
+            .unwrap();
+
+        // (3 * [1, 2] + 1 * [3, -4]) / 4 = [1.5, 0.5]
+        assert_eq!(pooled, vec![vec![1.5, 0.5], vec![-1.0, 5.0]]);
+
+        // Uniform weights reduce to plain mean pooling
+        let uniform: HashMap<u32, f32> = [(10, 2.0), (20, 2.0)].into_iter().collect();
+        let pooled: Vec<Vec<f32>> = Pooling::IdfWeighted(Arc::new(uniform))
+            .apply(&embeddings, &mask, &ids)
+            .unwrap()
+            .to_vec2()
+            .unwrap();
+        assert_eq!(pooled, vec![vec![2.0, -1.0], vec![-1.0, 5.0]]);
+    }
+
+    #[test]
+    fn test_compute_token_idf() {
+        let docs: Vec<Vec<u32>> = vec![vec![1, 2, 2], vec![1, 3], vec![1]];
+        let idf = compute_token_idf(docs.iter().map(Vec::as_slice));
+
+        // In every document: the minimum weight
+        assert!((idf[&1] - 1.0).abs() < 1e-6);
+        // Rarer tokens weigh more; repeats within a document count once
+        assert!((idf[&2] - (2.0f32.ln() + 1.0)).abs() < 1e-6);
+        assert_eq!(idf[&2], idf[&3]);
+        assert!(!idf.contains_key(&4));
+    }
+
+    #[test]
+    fn test_prefixes_prepended_to_tokenizer_input() {
+        assert_eq!(
+            tokenizer_inputs(Some("query: "), &["what is rust"]),
+            vec!["query: what is rust"]
+        );
+        assert_eq!(
+            tokenizer_inputs(Some("passage: "), &["Rust is a language.", "It is fast."]),
+            vec!["passage: Rust is a language.", "passage: It is fast."]
+        );
+        // No prefix leaves the text untouched
+        assert_eq!(tokenizer_inputs(None, &["plain"]), vec!["plain"]);
+    }
+
+    #[test]
+    #[ignore] // Requires model download
+    fn test_encode_raw_normalizes_to_encode() {
+        let model = EmbeddingModel::new().expect("Failed to load model");
+        let text = "Raw vectors keep their magnitude.";
+
+        let raw = model.encode_raw(text).unwrap();
+        let normalized = model.encode(text).unwrap();
+
+        for (a, b) in normalize_vec(&raw).iter().zip(normalized.iter()) {
+            assert!((a - b).abs() < 1e-5, "Mismatch: {} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_normalize_vec() {
+        let unit = normalize_vec(&[3.0, 4.0]);
+        assert!((unit[0] - 0.6).abs() < 1e-6);
+        assert!((unit[1] - 0.8).abs() < 1e-6);
+        assert_eq!(normalize_vec(&[0.0, 0.0]), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_similarity_matrix() {
+        let embeddings = vec![vec![1.0, 0.0], vec![3.0, 4.0], vec![0.0, -2.0]];
+        let matrix = similarity_matrix(&embeddings);
+
+        assert_eq!(matrix.len(), 3);
+        for (i, row) in matrix.iter().enumerate() {
+            assert!((row[i] - 1.0).abs() < 1e-6);
+            for (j, &score) in row.iter().enumerate() {
+                assert_eq!(score, matrix[j][i]);
+            }
+        }
+        assert!((matrix[0][1] - 0.6).abs() < 1e-6);
+        assert!((matrix[0][2] - 0.0).abs() < 1e-6);
+        assert!((matrix[1][2] + 0.8).abs() < 1e-6);
+
+        assert!(similarity_matrix(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_similarity_metrics() {
+        let a = [3.0, 4.0];
+        assert!((similarity(SimilarityMetric::Dot, &a, &[1.0, 0.0]) - 3.0).abs() < 1e-6);
+        assert!((similarity(SimilarityMetric::Cosine, &a, &[1.0, 0.0]) - 0.6).abs() < 1e-6);
+        assert!((similarity(SimilarityMetric::Euclidean, &a, &[0.0, 0.0]) - 5.0).abs() < 1e-6);
+        assert_eq!(similarity(SimilarityMetric::Cosine, &a, &[0.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
         // Test with known vectors
         let a = vec![1.0, 0.0, 0.0];
         let b = vec![1.0, 0.0, 0.0];
@@ -392,4 +1894,250 @@ mod tests {
         let c = vec![0.0, 1.0, 0.0];
         assert!(cosine_similarity(&a, &c).abs() < 0.001); // Orthogonal = 0
     }
+
+    fn no_delay(max_attempts: u32) -> RetryConfig {
+        RetryConfig {
+            max_attempts,
+            base_delay: Duration::ZERO,
+        }
+    }
+
+    fn transient_error() -> ApiError {
+        ApiError::IoError(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset"))
+    }
+
+    fn not_found_error() -> ApiError {
+        let response = ureq::Response::new(404, "Not Found", "").unwrap();
+        ApiError::RequestError(Box::new(ureq::Error::Status(404, response)))
+    }
+
+    #[test]
+    fn test_retry_succeeds_on_third_attempt() {
+        let mut attempts = 0;
+        let result = fetch_with_retry(&no_delay(3), "config.json", || {
+            attempts += 1;
+            if attempts < 3 {
+                Err(transient_error())
+            } else {
+                Ok(attempts)
+            }
+        });
+
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn test_retry_gives_up_after_max_attempts() {
+        let mut attempts = 0;
+        let result: Result<(), _> = fetch_with_retry(&no_delay(2), "config.json", || {
+            attempts += 1;
+            Err(transient_error())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 2);
+    }
+
+    #[test]
+    fn test_retry_skips_permanent_errors() {
+        assert!(!is_retryable(&not_found_error()));
+        let response = ureq::Response::new(503, "Service Unavailable", "").unwrap();
+        assert!(is_retryable(&ApiError::RequestError(Box::new(ureq::Error::Status(503, response)))));
+
+        let mut attempts = 0;
+        let result: Result<(), _> = fetch_with_retry(&no_delay(5), "missing.json", || {
+            attempts += 1;
+            Err(not_found_error())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_builder_sets_fields() {
+        let builder = EmbeddingModelBuilder::new();
+        assert_eq!(builder.model_id, MODEL_ID);
+        assert!(!builder.offline);
+        assert_eq!(builder.retry, RetryConfig::default());
+
+        let retry = no_delay(5);
+        let builder = EmbeddingModelBuilder::new()
+            .with_device(Device::Cpu)
+            .with_model_id("intfloat/e5-small-v2")
+            .with_pooling(Pooling::Cls)
+            .with_prefixes("query: ", "passage: ")
+            .with_cache_dir(PathBuf::from("/tmp/models"))
+            .with_offline(true)
+            .with_retry(retry);
+
+        assert!(builder.device.same_device(&Device::Cpu));
+        assert_eq!(builder.model_id, "intfloat/e5-small-v2");
+        assert_eq!(builder.pooling, Pooling::Cls);
+        assert_eq!(builder.query_prefix.as_deref(), Some("query: "));
+        assert_eq!(builder.passage_prefix.as_deref(), Some("passage: "));
+        assert_eq!(builder.cache_dir, Some(PathBuf::from("/tmp/models")));
+        assert!(builder.offline);
+        assert_eq!(builder.retry, retry);
+    }
+
+    #[test]
+    fn test_builder_offline_uses_model_id() {
+        let empty_dir = std::env::temp_dir().join(format!("hf-builder-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&empty_dir).unwrap();
+
+        let result = EmbeddingModelBuilder::new()
+            .with_model_id("intfloat/e5-small-v2")
+            .with_cache_dir(empty_dir.clone())
+            .with_offline(true)
+            .build();
+
+        let err = match result {
+            Err(err) => err.to_string(),
+            Ok(_) => panic!("expected missing files error"),
+        };
+        assert!(err.contains("intfloat/e5-small-v2"));
+        assert!(err.contains("config.json"));
+
+        std::fs::remove_dir_all(&empty_dir).ok();
+    }
+
+    #[test]
+    fn test_mock_embedder_is_deterministic() {
+        let embedder: &dyn Embedder = &MockEmbedder;
+        let a = embedder.encode("Bike chain maintenance").unwrap();
+        assert_eq!(a.len(), MOCK_EMBEDDING_DIM);
+        assert_eq!(a, embedder.encode_query("bike chain MAINTENANCE").unwrap());
+
+        let batch = embedder.encode_batch(&["Bike chain maintenance", "tomato seedlings"]).unwrap();
+        assert_eq!(batch[0], a);
+        assert!(cosine_similarity(&a, &batch[1]) < 0.99);
+    }
+
+    /// Answers one HTTP request on a local port with `status` and `body`.
+    ///
+    /// Returns the server's base URL and a handle that yields the raw
+    /// request it received.
+    fn serve_once(
+        status: &'static str,
+        body: &'static str,
+    ) -> (String, std::thread::JoinHandle<String>) {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(length) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut request_body = vec![0; content_length];
+            reader.read_exact(&mut request_body).unwrap();
+            request.push_str(&String::from_utf8(request_body).unwrap());
+
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+            request
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_remote_embedder_parses_batch() {
+        // Out of order, as the spec allows
+        let (url, server) = serve_once(
+            "200 OK",
+            r#"{"object": "list", "model": "nomic-embed-text", "data": [
+                {"object": "embedding", "index": 1, "embedding": [0.0, 1.0]},
+                {"object": "embedding", "index": 0, "embedding": [1.0, 0.0]}
+            ]}"#,
+        );
+        let embedder = RemoteEmbedder::new(RemoteEmbedderConfig {
+            base_url: format!("{}/", url),
+            model: "nomic-embed-text".to_string(),
+            api_key: Some("secret".to_string()),
+        })
+        .unwrap();
+
+        let embeddings = embedder.encode_batch(&["first", "second"]).unwrap();
+        assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert_eq!(Embedder::model_id(&embedder), "nomic-embed-text");
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /v1/embeddings "), "{}", request);
+        assert!(request.to_ascii_lowercase().contains("authorization: bearer secret"));
+        assert!(request.ends_with(r#"{"model":"nomic-embed-text","input":["first","second"]}"#));
+    }
+
+    #[test]
+    fn test_remote_embedder_http_error() {
+        let (url, server) = serve_once("401 Unauthorized", r#"{"error": "invalid api key"}"#);
+        let embedder = RemoteEmbedder::new(RemoteEmbedderConfig {
+            base_url: url,
+            model: "text-embedding-3-small".to_string(),
+            api_key: None,
+        })
+        .unwrap();
+
+        let err = embedder.encode("hello").err().unwrap();
+        assert!(matches!(&err, EmbeddingError::Inference(msg) if msg.contains("401")), "{}", err);
+        assert!(!server.join().unwrap().to_ascii_lowercase().contains("authorization"));
+    }
+
+    #[test]
+    fn test_remote_embedder_normalizes_unless_raw() {
+        let config = |url| RemoteEmbedderConfig {
+            base_url: url,
+            model: "nomic-embed-text".to_string(),
+            api_key: None,
+        };
+        let response = r#"{"data": [{"embedding": [3.0, 4.0]}]}"#;
+
+        let (url, server) = serve_once("200 OK", response);
+        let embedder = RemoteEmbedder::new(config(url)).unwrap();
+        assert_eq!(embedder.encode("hello").unwrap(), vec![0.6, 0.8]);
+        server.join().unwrap();
+
+        let (url, server) = serve_once("200 OK", response);
+        let embedder = RemoteEmbedder::new(config(url)).unwrap();
+        assert_eq!(embedder.encode_raw("hello").unwrap(), vec![3.0, 4.0]);
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_remote_embedder_on_async_runtime() {
+        // Async Tauri commands encode on the runtime's threads
+        let (url, server) = serve_once("200 OK", r#"{"data": [{"embedding": [0.6, 0.8]}]}"#);
+        let embedder = tokio::task::spawn_blocking(move || {
+            RemoteEmbedder::new(RemoteEmbedderConfig {
+                base_url: url,
+                model: "nomic-embed-text".to_string(),
+                api_key: None,
+            })
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(embedder.encode("hello").unwrap(), vec![0.6, 0.8]);
+        server.join().unwrap();
+    }
 }
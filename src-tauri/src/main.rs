@@ -6,23 +6,131 @@ mod commands;
 mod db;
 mod documents;
 mod embeddings;
+mod text;
 mod vector_store;
 
 use commands::{
-    add_message, chat, create_chat, delete_chat, get_all_chats, get_chat, update_chat_title,
+    add_message, add_messages, chat, chat_exists, create_chat, delete_chat, delete_message,
+    find_chats_by_title, find_similar_chats, get_all_chats, get_chat, get_chat_cited_documents,
+    get_chat_summary, get_chat_token_total, get_last_chat, get_recent_messages, import_chat,
+    messages_per_day, set_chat_pinned, update_chat_title,
+    // Tag commands
+    add_chat_tag, get_chat_tags, get_chats_by_tag, remove_chat_tag,
     // Document commands
-    delete_document_cmd, get_all_documents, get_document_content, upload_document,
+    clear_document_embeddings, count_documents, delete_document_cmd, document_exists,
+    document_is_stale, export_documents, get_all_documents, get_document, get_document_content,
+    get_document_content_range, get_documents_by_language, get_documents_paged, get_pdf_metadata,
+    ingest_bytes, ingest_directory, ingest_text, reextract_document, rename_document,
+    set_document_boost, set_document_muted, upload_document,
     // Chunk commands
-    get_chunk_stats, get_document_chunks,
+    chunk_similarities, chunk_stats, get_chunk, get_chunk_source_excerpt, get_chunk_stats,
+    get_document_chunks, preview_chunking, reindex_document,
     // Embedding commands
-    get_embedding_stats, index_all_documents, index_document, init_embedding_model,
-    is_model_loaded, search_documents,
-    AppPaths, DbState, EmbeddingState,
+    count_tokens, embed_text, get_embedding_stats, index_all_documents, index_document,
+    init_embedding_model, init_remote_embedder, is_model_loaded, nearest_documents, rag_status,
+    rank_embeddings, repair_integrity, search_documents, test_embedding_model,
+    // Settings
+    get_settings, update_settings,
+    // Operation control
+    cancel_operation, end_incognito_session, optimize_database, reset_app_data,
+    start_incognito_session,
+    AppPaths, CancellationState, DbState, EmbeddingState, IncognitoState, IndexState,
+    TokenizerState,
 };
 use db::Database;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 // Manager trait provides `path()` and `manage()` methods on App
 use tauri::Manager;
+use tracing::info;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+/// Environment variable that overrides the app data directory,
+/// e.g. for portable installs or testing against a scratch directory.
+const DATA_DIR_ENV: &str = "LOCALCHATBOT_DATA_DIR";
+
+/// Get the directory where user data is stored, creating it if needed.
+///
+/// Uses `LOCALCHATBOT_DATA_DIR` when set, otherwise the platform default:
+/// On Linux: ~/.local/share/<app-identifier>/
+/// On macOS: ~/Library/Application Support/<app-identifier>/
+/// On Windows: C:\Users\<User>\AppData\Roaming\<app-identifier>\
+fn resolve_data_dir(app: &tauri::App) -> PathBuf {
+    resolve_data_dir_or(|| {
+        app.path()
+            .app_data_dir()
+            .expect("Failed to get app data directory")
+    })
+}
+
+/// Resolves the data directory from `LOCALCHATBOT_DATA_DIR`, calling `default`
+/// only when it's unset or empty. Split out of `resolve_data_dir` so it can be
+/// tested without a running app.
+fn resolve_data_dir_or(default: impl FnOnce() -> PathBuf) -> PathBuf {
+    let dir = match std::env::var_os(DATA_DIR_ENV) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => default(),
+    };
+
+    // Create the directory if it doesn't exist
+    std::fs::create_dir_all(&dir).expect("Failed to create app data directory");
+    dir
+}
+
+/// Daily log files kept in the `logs` directory before the oldest is deleted.
+const MAX_LOG_FILES: usize = 7;
+
+/// Installs the global tracing subscriber, logging to stdout and to a daily
+/// rotating file in `log_dir`.
+///
+/// The level defaults to `info` and can be changed with `RUST_LOG`, e.g.
+/// `RUST_LOG=local_chatbot=debug`. Returns the file writer's guard, which
+/// must be kept for as long as the app runs.
+fn init_logging(log_dir: &Path) -> Result<WorkerGuard, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(log_dir)?;
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("localchatbot")
+        .filename_suffix("log")
+        .max_log_files(MAX_LOG_FILES)
+        .build(log_dir)?;
+    let (file_writer, guard) = tracing_appender::non_blocking(appender);
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(fmt::layer())
+        .with(fmt::layer().with_ansi(false).with_writer(file_writer))
+        .try_init()?;
+
+    Ok(guard)
+}
+
+/// Keyring service and account under which the database key is stored.
+#[cfg(feature = "sqlcipher")]
+const KEYRING_SERVICE: &str = "LocalChatbot";
+#[cfg(feature = "sqlcipher")]
+const KEYRING_ACCOUNT: &str = "database-key";
+
+/// Gets the database encryption key from the OS keyring.
+///
+/// On first launch there's no key yet, so a random one is generated and
+/// stored. Losing the keyring entry means losing the database.
+#[cfg(feature = "sqlcipher")]
+fn database_key() -> Result<String, keyring::Error> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_ACCOUNT)?;
+    match entry.get_password() {
+        Ok(key) => Ok(key),
+        Err(keyring::Error::NoEntry) => {
+            // Two v4 UUIDs give 244 random bits
+            let key = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+            entry.set_password(&key)?;
+            Ok(key)
+        }
+        Err(e) => Err(e),
+    }
+}
 
 fn main() {
     tauri::Builder::default()
@@ -33,46 +141,59 @@ fn main() {
         // This is where we initialize resources like the database
         .setup(|app| {
             // Get the app's data directory - this is where user data should be stored
-            // On Linux: ~/.local/share/<app-identifier>/
-            // On macOS: ~/Library/Application Support/<app-identifier>/
-            // On Windows: C:\Users\<User>\AppData\Roaming\<app-identifier>\
-            let app_data_dir = app
-                .path()
-                .app_data_dir()
-                .expect("Failed to get app data directory");
-
-            // Create the directory if it doesn't exist
-            std::fs::create_dir_all(&app_data_dir)
-                .expect("Failed to create app data directory");
+            let app_data_dir = resolve_data_dir(app);
+
+            // Start logging first so everything below is captured. The guard
+            // is managed so the log file writer lives as long as the app
+            let log_guard = init_logging(&app_data_dir.join("logs"))
+                .expect("Failed to initialize logging");
+            app.manage(log_guard);
 
             // Create documents subdirectory for storing uploaded files
             let documents_dir = app_data_dir.join("documents");
             std::fs::create_dir_all(&documents_dir)
                 .expect("Failed to create documents directory");
 
-            println!("App data directory: {:?}", app_data_dir);
-            println!("Documents directory: {:?}", documents_dir);
+            info!(?app_data_dir, ?documents_dir, "Resolved data directories");
 
             // Database file path
             let db_path = app_data_dir.join("chat_history.db");
-            println!("Database location: {:?}", db_path);
+            info!(?db_path, "Opening database");
 
-            // Initialize the database
+            // Initialize the database, encrypted if built with `sqlcipher`
             // The `expect` will panic with our message if database creation fails
             // In production, you might want more graceful error handling
-            let database = Database::new(&db_path)
-                .expect("Failed to initialize database");
+            #[cfg(feature = "sqlcipher")]
+            let database = {
+                let key = database_key().expect("Failed to read database key from the OS keyring");
+                Database::new_encrypted(&db_path, &key)
+            };
+            #[cfg(not(feature = "sqlcipher"))]
+            let database = Database::new(&db_path);
+            let database = database.expect("Failed to initialize database");
 
             // Register the database as managed state
             // Tauri will make this available to any command that requests State<DbState>
             app.manage(DbState(Mutex::new(database)));
 
+            // Holds the on-disk database aside during an incognito session
+            app.manage(IncognitoState::default());
+
             // Register app paths
             app.manage(AppPaths { documents_dir });
 
             // Register embedding model state (initially empty, loaded on demand)
             app.manage(EmbeddingState(Mutex::new(None)));
 
+            // In-memory search index, saved here so a restart needn't rebuild it
+            app.manage(IndexState::with_snapshot(app_data_dir.join("vector_index.bin")));
+
+            // Tokenizer for counting tokens before the model is loaded
+            app.manage(TokenizerState(Mutex::new(None)));
+
+            // Register cancellation flags for long-running operations
+            app.manage(CancellationState::default());
+
             Ok(())
         })
         // Register all commands that the frontend can invoke
@@ -82,25 +203,105 @@ fn main() {
             create_chat,
             get_all_chats,
             get_chat,
+            chat_exists,
+            get_last_chat,
+            get_chat_summary,
+            get_chat_cited_documents,
+            get_chat_token_total,
+            messages_per_day,
+            find_chats_by_title,
+            find_similar_chats,
+            get_recent_messages,
             delete_chat,
+            import_chat,
+            delete_message,
             add_message,
+            add_messages,
             update_chat_title,
+            set_chat_pinned,
+            // Tag commands
+            add_chat_tag,
+            remove_chat_tag,
+            get_chat_tags,
+            get_chats_by_tag,
             // Document commands
             get_all_documents,
+            get_documents_paged,
+            count_documents,
+            export_documents,
+            get_documents_by_language,
+            get_document,
+            document_exists,
             upload_document,
+            get_pdf_metadata,
+            ingest_directory,
+            ingest_text,
+            ingest_bytes,
             delete_document_cmd,
+            clear_document_embeddings,
             get_document_content,
+            get_document_content_range,
+            document_is_stale,
+            reextract_document,
+            rename_document,
+            set_document_boost,
+            set_document_muted,
             // Chunk commands
             get_document_chunks,
+            get_chunk,
+            get_chunk_source_excerpt,
             get_chunk_stats,
+            chunk_stats,
+            preview_chunking,
+            reindex_document,
             // Embedding commands
             init_embedding_model,
+            init_remote_embedder,
             is_model_loaded,
+            test_embedding_model,
+            count_tokens,
+            embed_text,
             index_document,
             index_all_documents,
             search_documents,
+            nearest_documents,
+            rank_embeddings,
+            chunk_similarities,
             get_embedding_stats,
+            rag_status,
+            repair_integrity,
+            // Settings
+            get_settings,
+            update_settings,
+            // Operation control
+            cancel_operation,
+            reset_app_data,
+            start_incognito_session,
+            end_incognito_session,
+            optimize_database,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_dir_env_override() {
+        let dir = std::env::temp_dir().join(format!("localchatbot-{}", uuid::Uuid::new_v4()));
+        std::env::set_var(DATA_DIR_ENV, &dir);
+
+        let resolved = resolve_data_dir_or(|| panic!("default shouldn't be used when the env var is set"));
+        std::env::remove_var(DATA_DIR_ENV);
+
+        assert_eq!(resolved, dir);
+        assert!(dir.is_dir());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Unset falls back to the default
+        let fallback = std::env::temp_dir();
+        assert_eq!(resolve_data_dir_or(|| fallback.clone()), fallback);
+    }
+}
@@ -0,0 +1,320 @@
+//! Text utilities shared by the RAG pipeline.
+//!
+//! This covers sentence splitting, which the chunker uses to pick natural
+//! break points, word counting and language detection for document
+//! metadata, query preprocessing for keyword search, and cleaning up chunk
+//! text before it's embedded.
+//!
+//! ## Sentence Boundaries
+//!
+//! A naive splitter treats every `.` followed by whitespace as the end of a
+//! sentence, which breaks "Dr. Smith" or "e.g. this" in two. Here a period
+//! only ends a sentence if the word it's attached to isn't a known
+//! abbreviation. Decimal numbers like "3.14" are never split because the
+//! period isn't followed by whitespace.
+
+use std::collections::HashSet;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Common abbreviations that are followed by a period mid-sentence.
+///
+/// Compared case-insensitively against the word before the period,
+/// without the period itself.
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "mt", "vs", "e.g", "i.e", "cf", "fig",
+    "vol", "approx", "dept", "inc", "ltd", "co", "jan", "feb", "mar", "apr", "jun", "jul",
+    "aug", "sep", "sept", "oct", "nov", "dec",
+];
+
+/// Common English words that carry little meaning for keyword matching.
+///
+/// Used as the default stopword list in [`PreprocessOptions`].
+pub const ENGLISH_STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from", "has", "have", "he",
+    "her", "his", "how", "i", "if", "in", "into", "is", "it", "its", "me", "my", "no", "not",
+    "of", "on", "or", "our", "she", "so", "such", "than", "that", "the", "their", "them", "then",
+    "there", "these", "they", "this", "to", "was", "we", "were", "what", "when", "where",
+    "which", "who", "why", "will", "with", "you", "your",
+];
+
+/// Returns true if `c` can end a sentence.
+pub fn is_sentence_terminator(c: char) -> bool {
+    matches!(c, '.' | '!' | '?')
+}
+
+/// Returns true if `word` is a known abbreviation (case-insensitive).
+///
+/// `word` is the text before the period, e.g. `"Dr"` or `"e.g"`.
+/// Leading brackets and quotes are ignored.
+pub fn is_abbreviation(word: &str) -> bool {
+    let word = word
+        .trim_start_matches(['(', '[', '"', '\'', '\u{201C}', '\u{2018}'])
+        .to_lowercase();
+    ABBREVIATIONS.contains(&word.as_str())
+}
+
+/// Decides whether `terminator` ends a sentence, given the word before it.
+///
+/// `word` is the run of non-whitespace characters right before the
+/// terminator. Only periods can be abbreviations; `!` and `?` always end
+/// a sentence.
+pub fn ends_sentence(word: &str, terminator: char) -> bool {
+    terminator != '.' || !is_abbreviation(word)
+}
+
+/// Splits text into sentences.
+///
+/// A sentence ends at `.`, `!` or `?` followed by whitespace (or the end of
+/// the text), unless the period belongs to an abbreviation. Returned slices
+/// borrow from `text` and are trimmed; empty sentences are skipped.
+pub fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0; // Byte offset where the current sentence starts
+    let mut word_start = 0; // Byte offset where the current word starts
+
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c.is_whitespace() {
+            word_start = i + c.len_utf8();
+            continue;
+        }
+
+        if !is_sentence_terminator(c) {
+            continue;
+        }
+
+        let at_boundary = chars.peek().is_none_or(|&(_, next)| next.is_whitespace());
+        if at_boundary && ends_sentence(&text[word_start..i], c) {
+            let end = i + c.len_utf8();
+            let sentence = text[start..end].trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence);
+            }
+            start = end;
+        }
+    }
+
+    let rest = text[start..].trim();
+    if !rest.is_empty() {
+        sentences.push(rest);
+    }
+
+    sentences
+}
+
+/// Counts the words in `text` using Unicode word boundaries (UAX #29).
+///
+/// Unlike splitting on whitespace, punctuation isn't counted as a word and
+/// scripts written without spaces still count: each Chinese or Japanese
+/// ideograph counts as one word.
+pub fn count_words(text: &str) -> usize {
+    text.unicode_words().count()
+}
+
+/// Texts shorter than this (in characters) are too ambiguous to detect a language from.
+const MIN_LANGUAGE_CHARS: usize = 20;
+
+/// How much of a text (in characters) is used for language detection.
+/// The opening of a document is enough, and large PDFs stay fast.
+const LANGUAGE_SAMPLE_CHARS: usize = 10_000;
+
+/// Detects the language of `text`, returning its ISO 639-3 code (e.g. `"eng"`).
+///
+/// Returns `None` for short text, or when `whatlang` isn't confident
+/// enough to call it reliable.
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let text = text.trim();
+    if text.chars().count() < MIN_LANGUAGE_CHARS {
+        return None;
+    }
+
+    let sample = match text.char_indices().nth(LANGUAGE_SAMPLE_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    };
+
+    let info = whatlang::detect(sample)?;
+    info.is_reliable().then(|| info.lang().code())
+}
+
+/// Options for [`preprocess_query`].
+///
+/// The default lowercases, strips punctuation and removes
+/// [`ENGLISH_STOPWORDS`].
+#[derive(Debug, Clone)]
+pub struct PreprocessOptions {
+    /// Lowercase every token
+    pub lowercase: bool,
+    /// Split on Unicode word boundaries, dropping punctuation.
+    /// When false, tokens are split on whitespace only.
+    pub strip_punctuation: bool,
+    /// Tokens to drop, compared case-insensitively. Entries must be lowercase.
+    pub stopwords: HashSet<String>,
+}
+
+impl Default for PreprocessOptions {
+    fn default() -> Self {
+        Self {
+            lowercase: true,
+            strip_punctuation: true,
+            stopwords: ENGLISH_STOPWORDS.iter().map(|w| w.to_string()).collect(),
+        }
+    }
+}
+
+/// Normalizes a search query into tokens for keyword (BM25) matching.
+///
+/// With the default options, `"The Quick Brown Fox!"` becomes
+/// `["quick", "brown", "fox"]`.
+pub fn preprocess_query(text: &str, opts: &PreprocessOptions) -> Vec<String> {
+    let tokens: Vec<&str> = if opts.strip_punctuation {
+        text.unicode_words().collect()
+    } else {
+        text.split_whitespace().collect()
+    };
+
+    tokens
+        .into_iter()
+        .filter(|token| !opts.stopwords.contains(&token.to_lowercase()))
+        .map(|token| if opts.lowercase { token.to_lowercase() } else { token.to_string() })
+        .collect()
+}
+
+/// Cleans up extracted text for embedding.
+///
+/// Words hyphenated across a line break (`"embed-\nding"`) are joined back
+/// together, every run of whitespace becomes a single space, and the ends
+/// are trimmed. A hyphen only counts as a line-break hyphen when a letter
+/// comes before it and a lowercase letter starts the next line, so list
+/// dashes survive; a genuinely hyphenated word split at the hyphen
+/// (`"well-\nknown"`) loses its hyphen.
+pub fn normalize_chunk_text(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut normalized = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '-' && i > 0 && chars[i - 1].is_alphabetic() {
+            if let Some(next) = hyphen_break_end(&chars, i + 1) {
+                i = next;
+                continue;
+            }
+        }
+
+        if c.is_whitespace() {
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            if !normalized.is_empty() && i < chars.len() {
+                normalized.push(' ');
+            }
+            continue;
+        }
+
+        normalized.push(c);
+        i += 1;
+    }
+    normalized
+}
+
+/// If a line-break hyphen ends just before `start` (whitespace containing
+/// a newline, then a lowercase letter), returns the index of that letter.
+fn hyphen_break_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut i = start;
+    let mut newline = false;
+    while i < chars.len() && chars[i].is_whitespace() {
+        newline |= chars[i] == '\n';
+        i += 1;
+    }
+    (newline && chars.get(i).is_some_and(|c| c.is_lowercase())).then_some(i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abbreviation_does_not_split() {
+        assert_eq!(split_sentences("Dr. Smith went home."), vec!["Dr. Smith went home."]);
+        assert_eq!(
+            split_sentences("Use a tool, e.g. this one. Then stop."),
+            vec!["Use a tool, e.g. this one.", "Then stop."]
+        );
+    }
+
+    #[test]
+    fn test_decimal_does_not_split() {
+        assert_eq!(split_sentences("3.14 is pi. Yes."), vec!["3.14 is pi.", "Yes."]);
+    }
+
+    #[test]
+    fn test_other_terminators_and_remainder() {
+        assert_eq!(
+            split_sentences("Really?! Yes! No way. trailing text"),
+            vec!["Really?!", "Yes!", "No way.", "trailing text"]
+        );
+        assert!(split_sentences("   ").is_empty());
+    }
+
+    #[test]
+    fn test_multibyte_text() {
+        assert_eq!(
+            split_sentences("Café crème. Über alles!"),
+            vec!["Café crème.", "Über alles!"]
+        );
+    }
+
+    #[test]
+    fn test_count_words_mixed_languages() {
+        assert_eq!(count_words("Hello, world! It's 3.14 - done."), 5);
+        assert_eq!(count_words("Ünïcödé wörds and café"), 4);
+        // Ideographs count individually, English words as usual
+        assert_eq!(count_words("RAG 检索增强 works"), 6);
+        assert_eq!(count_words("  ... !!! "), 0);
+    }
+
+    #[test]
+    fn test_preprocess_query_defaults() {
+        let opts = PreprocessOptions::default();
+        assert_eq!(preprocess_query("The Quick Brown Fox!", &opts), vec!["quick", "brown", "fox"]);
+        assert!(preprocess_query("  ... !!! ", &opts).is_empty());
+    }
+
+    #[test]
+    fn test_preprocess_query_options() {
+        let keep_case = PreprocessOptions {
+            lowercase: false,
+            ..Default::default()
+        };
+        assert_eq!(preprocess_query("The Quick Brown Fox!", &keep_case), vec!["Quick", "Brown", "Fox"]);
+
+        let keep_punctuation = PreprocessOptions {
+            strip_punctuation: false,
+            ..Default::default()
+        };
+        assert_eq!(preprocess_query("The Quick Brown Fox!", &keep_punctuation), vec!["quick", "brown", "fox!"]);
+
+        let custom_stopwords = PreprocessOptions {
+            stopwords: ["fox".to_string()].into_iter().collect(),
+            ..Default::default()
+        };
+        assert_eq!(preprocess_query("The Quick Brown Fox!", &custom_stopwords), vec!["the", "quick", "brown"]);
+    }
+
+    #[test]
+    fn test_normalize_joins_hyphenated_line_breaks() {
+        assert_eq!(normalize_chunk_text("Each embed-\nding is stored."), "Each embedding is stored.");
+        assert_eq!(normalize_chunk_text("vec-  \r\n   tors"), "vectors");
+        // Not a line-break hyphen: no newline, a capital next, or no word before
+        assert_eq!(normalize_chunk_text("state-of-the-art"), "state-of-the-art");
+        assert_eq!(normalize_chunk_text("Rust-\nBased"), "Rust- Based");
+        assert_eq!(normalize_chunk_text("Items:\n-\nfirst"), "Items: - first");
+    }
+
+    #[test]
+    fn test_normalize_collapses_whitespace() {
+        assert_eq!(normalize_chunk_text("  too   many\tspaces \n\n here  "), "too many spaces here");
+        assert_eq!(normalize_chunk_text("Café  crème"), "Café crème");
+        assert_eq!(normalize_chunk_text(" \n\t "), "");
+    }
+}
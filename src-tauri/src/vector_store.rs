@@ -6,19 +6,37 @@
 //!
 //! ## Architecture
 //!
-//! - Embeddings are stored in SQLite as BLOBs (binary data)
+//! - Embeddings are stored in SQLite as BLOBs (binary data), either as raw
+//!   f32s or int8-quantized (see [`StorageFormat`])
 //! - On search, embeddings are loaded into memory for fast comparison
-//! - Cosine similarity is used for ranking results
+//! - Results are ranked by a `SimilarityMetric` (dot product by default),
+//!   weighted by each document's boost
 //!
 //! ## Why Simple Brute-Force?
 //!
 //! For collections under ~10,000 chunks, linear search is fast enough
 //! (milliseconds) and has zero complexity. More sophisticated indexes
 //! (HNSW, IVF) add complexity and are only needed at larger scale.
+//!
+//! ## Snapshots
+//!
+//! A [`VectorIndex`] keeps one model's embeddings in memory so searches
+//! skip the database. Building it means reading every embedding, so it can
+//! be saved to a snapshot file and reloaded on the next launch. Triggers
+//! bump a generation counter on every change to the embeddings table; a
+//! snapshot taken at a different generation is stale and gets rebuilt.
 
-use crate::embeddings::{cosine_similarity, EMBEDDING_DIM};
+use crate::chunker::Chunk;
+use crate::embeddings::{similarity, SimilarityMetric, MODEL_ID};
+use crate::text::{preprocess_query, PreprocessOptions};
+use rayon::prelude::*;
 use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Take, Write};
+use std::path::Path;
+use tracing::{instrument, warn};
 
 /// A search result with similarity score.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,64 +47,260 @@ pub struct SearchResult {
     pub document_id: String,
     /// The actual text content
     pub content: String,
-    /// Cosine similarity score (0.0 to 1.0, higher = more similar)
+    /// Similarity score: cosine similarity (-1.0 to 1.0, higher = more similar,
+    /// or 0.0 to 1.0 after `normalize_score`) for the dot and cosine metrics,
+    /// L2 distance (lower = more similar) for Euclidean. Boosts can push a
+    /// similarity past 1.0
+    pub score: f32,
+    /// Why the chunk matched, when requested (see `explain_results`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<SearchExplanation>,
+}
+
+/// Why a chunk matched a query, for showing alongside a result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchExplanation {
+    /// Query keywords that also appear in the chunk, in query order.
+    /// Stopwords and case are ignored, so this is often empty for a
+    /// purely semantic match.
+    pub matched_terms: Vec<String>,
+    /// The result's score, in the same units as `SearchResult::score`
     pub score: f32,
 }
 
+/// A whole document ranked by its chunks' scores.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentSearchResult {
+    /// The document ID
+    pub document_id: String,
+    /// The chunk scores combined by a `DocumentAggregation`, in the
+    /// metric's units (lower is better for Euclidean)
+    pub score: f32,
+    /// The document's best-scoring chunk
+    pub best_chunk_id: String,
+    /// That chunk's text
+    pub snippet: String,
+}
+
+/// How chunk scores are combined into one score per document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DocumentAggregation {
+    /// The best chunk's score. Favors a document with one very relevant passage.
+    #[default]
+    Max,
+    /// The mean of the best `n` chunk scores, or of all of them if the
+    /// document has fewer. Favors documents that are relevant throughout.
+    MeanOfTop(usize),
+}
+
+/// How an embedding's BLOB is encoded, recorded per row in the `format` column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageFormat {
+    /// Little-endian f32s, 4 bytes per component (lossless)
+    #[default]
+    F32,
+    /// Scalar-quantized: a little-endian f32 scale, then one i8 per
+    /// component, about a quarter of the size. Each component comes back
+    /// within half a step (`max |x| / 254`) of the original.
+    Q8,
+}
+
+impl StorageFormat {
+    /// The name stored in the `format` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageFormat::F32 => "f32",
+            StorageFormat::Q8 => "q8",
+        }
+    }
+
+    /// Parses a `format` column value.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "f32" => Some(StorageFormat::F32),
+            "q8" => Some(StorageFormat::Q8),
+            _ => None,
+        }
+    }
+
+    fn encode(&self, embedding: &[f32]) -> Vec<u8> {
+        match self {
+            StorageFormat::F32 => embedding_to_bytes(embedding),
+            StorageFormat::Q8 => embedding_to_bytes_q8(embedding),
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Vec<f32> {
+        match self {
+            StorageFormat::F32 => bytes_to_embedding(bytes),
+            StorageFormat::Q8 => bytes_to_embedding_q8(bytes),
+        }
+    }
+}
+
 /// Initialize the embeddings table in SQLite.
 ///
-/// Stores chunk embeddings as binary BLOBs for efficient storage.
+/// Stores chunk embeddings as binary BLOBs for efficient storage,
+/// along with the model that produced them, their dimension and
+/// their `StorageFormat`.
 pub fn init_embeddings_table(conn: &Connection) -> Result<(), rusqlite::Error> {
     conn.execute(
         "CREATE TABLE IF NOT EXISTS embeddings (
             chunk_id TEXT PRIMARY KEY,
             document_id TEXT NOT NULL,
             embedding BLOB NOT NULL,
+            model_id TEXT NOT NULL DEFAULT '',
+            dim INTEGER NOT NULL DEFAULT 0,
+            format TEXT NOT NULL DEFAULT 'f32',
             FOREIGN KEY (chunk_id) REFERENCES chunks(id) ON DELETE CASCADE,
             FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE CASCADE
         )",
         [],
     )?;
 
+    // Migration: older databases lack model_id/dim. Every embedding stored
+    // before they existed came from the default model, and the dimension
+    // can be recovered from the BLOB size (4 bytes per f32).
+    crate::db::add_column_if_missing(conn, "embeddings", "model_id", "TEXT NOT NULL DEFAULT ''")?;
+    crate::db::add_column_if_missing(conn, "embeddings", "dim", "INTEGER NOT NULL DEFAULT 0")?;
+    conn.execute(
+        "UPDATE embeddings SET model_id = ?1 WHERE model_id = ''",
+        params![MODEL_ID],
+    )?;
+    conn.execute(
+        "UPDATE embeddings SET dim = LENGTH(embedding) / 4 WHERE dim = 0",
+        [],
+    )?;
+
+    // Migration: quantized storage was added later; every older row is f32
+    crate::db::add_column_if_missing(conn, "embeddings", "format", "TEXT NOT NULL DEFAULT 'f32'")?;
+
     // Index for fast lookup by document
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_embeddings_document_id ON embeddings(document_id)",
         [],
     )?;
 
+    // Generation counter for `VectorIndex` snapshots: a single row, bumped by
+    // triggers on every change, including rows removed by CASCADE. It isn't
+    // part of `Database::reset`, so a snapshot from before a reset never
+    // looks fresh afterwards.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS embeddings_generation (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            generation INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO embeddings_generation (id, generation) VALUES (1, 0)",
+        [],
+    )?;
+    for event in ["INSERT", "UPDATE", "DELETE"] {
+        conn.execute(
+            &format!(
+                "CREATE TRIGGER IF NOT EXISTS embeddings_generation_{} AFTER {} ON embeddings
+                 BEGIN
+                     UPDATE embeddings_generation SET generation = generation + 1 WHERE id = 1;
+                 END",
+                event.to_lowercase(),
+                event
+            ),
+            [],
+        )?;
+    }
+    // Boosts and mutes are part of every ranking, so changing one makes
+    // snapshots stale too. The documents table is created first except in
+    // standalone tests.
+    let has_documents: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'documents')",
+        [],
+        |row| row.get(0),
+    )?;
+    if has_documents {
+        conn.execute(
+            "CREATE TRIGGER IF NOT EXISTS embeddings_generation_boost
+             AFTER UPDATE OF boost ON documents
+             BEGIN
+                 UPDATE embeddings_generation SET generation = generation + 1 WHERE id = 1;
+             END",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TRIGGER IF NOT EXISTS embeddings_generation_muted
+             AFTER UPDATE OF muted ON documents
+             BEGIN
+                 UPDATE embeddings_generation SET generation = generation + 1 WHERE id = 1;
+             END",
+            [],
+        )?;
+    }
+
     Ok(())
 }
 
+/// The embeddings generation counter, which changes whenever any embedding does.
+pub fn embeddings_generation(conn: &Connection) -> Result<i64, rusqlite::Error> {
+    conn.query_row(
+        "SELECT generation FROM embeddings_generation WHERE id = 1",
+        [],
+        |row| row.get(0),
+    )
+}
+
 /// Save an embedding for a chunk.
 ///
 /// The embedding is stored as a BLOB (binary large object).
 /// SQLite handles the binary data efficiently.
+/// `model_id` identifies the model that produced the vector.
 pub fn save_embedding(
     conn: &Connection,
     chunk_id: &str,
     document_id: &str,
     embedding: &[f32],
+    model_id: &str,
+) -> Result<(), rusqlite::Error> {
+    save_embedding_with_format(conn, chunk_id, document_id, embedding, model_id, StorageFormat::F32)
+}
+
+/// Save an embedding for a chunk, encoded as `format`.
+///
+/// Readers decode each row by its own format, so formats can be mixed.
+pub fn save_embedding_with_format(
+    conn: &Connection,
+    chunk_id: &str,
+    document_id: &str,
+    embedding: &[f32],
+    model_id: &str,
+    format: StorageFormat,
 ) -> Result<(), rusqlite::Error> {
-    // Convert f32 slice to bytes
-    let bytes = embedding_to_bytes(embedding);
+    let bytes = format.encode(embedding);
 
     conn.execute(
-        "INSERT OR REPLACE INTO embeddings (chunk_id, document_id, embedding)
-         VALUES (?1, ?2, ?3)",
-        params![chunk_id, document_id, bytes],
+        "INSERT OR REPLACE INTO embeddings (chunk_id, document_id, embedding, model_id, dim, format)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            chunk_id,
+            document_id,
+            bytes,
+            model_id,
+            embedding.len() as i64,
+            format.as_str()
+        ],
     )?;
 
     Ok(())
 }
 
 /// Get the embedding for a specific chunk.
+///
+/// Quantized embeddings are returned dequantized.
 pub fn get_embedding(conn: &Connection, chunk_id: &str) -> Result<Option<Vec<f32>>, rusqlite::Error> {
-    let mut stmt = conn.prepare("SELECT embedding FROM embeddings WHERE chunk_id = ?1")?;
+    let mut stmt = conn.prepare("SELECT embedding, format FROM embeddings WHERE chunk_id = ?1")?;
 
-    let result = stmt.query_row(params![chunk_id], |row| {
-        let bytes: Vec<u8> = row.get(0)?;
-        Ok(bytes_to_embedding(&bytes))
-    });
+    let result = stmt.query_row(params![chunk_id], |row| row_embedding(row, 0, 1));
 
     match result {
         Ok(embedding) => Ok(Some(embedding)),
@@ -95,66 +309,659 @@ pub fn get_embedding(conn: &Connection, chunk_id: &str) -> Result<Option<Vec<f32
     }
 }
 
-/// Search for similar chunks using cosine similarity.
+/// A stored embedding loaded into memory, ready to be scored.
+struct Candidate {
+    chunk_id: String,
+    document_id: String,
+    content: String,
+    embedding: Vec<f32>,
+    /// The document's boost (see `boosted_score`)
+    boost: f32,
+}
+
+/// Search for similar chunks.
 ///
-/// Returns the top `k` most similar chunks to the query embedding.
+/// Returns the top `k` most similar chunks to the query embedding under
+/// `metric` - highest score first, or lowest distance first for Euclidean.
+/// Scores are weighted by each document's boost before ranking.
+/// Only embeddings produced by `model_id` with the query's dimension are
+/// scored; vectors from other models live in a different space. Embeddings
+/// from `model_id` with another dimension (e.g. stored before a model
+/// change) are skipped with a warning rather than compared.
 ///
 /// ## Algorithm
 ///
-/// 1. Load all embeddings from the database
-/// 2. Compute cosine similarity with the query
-/// 3. Sort by similarity (descending)
+/// 1. Load matching embeddings from the database (single-threaded - SQLite reads are sequential)
+/// 2. Score each one against the query in parallel across cores
+/// 3. Sort by score, best first
 /// 4. Return top k results
 pub fn search_similar(
     conn: &Connection,
     query_embedding: &[f32],
     k: usize,
+    model_id: &str,
+    metric: SimilarityMetric,
 ) -> Result<Vec<SearchResult>, rusqlite::Error> {
-    // Load all embeddings with their chunk info
+    let (results, skipped) =
+        search_similar_counting_skipped(conn, query_embedding, k, model_id, metric)?;
+    if skipped > 0 {
+        warn!(
+            skipped,
+            model_id,
+            dim = query_embedding.len(),
+            "Skipped embeddings whose dimension doesn't match the query"
+        );
+    }
+    Ok(results)
+}
+
+/// Like `search_similar`, also returning how many of `model_id`'s
+/// embeddings were skipped for having a different dimension than the query.
+#[instrument(name = "search", skip(conn, query_embedding))]
+pub fn search_similar_counting_skipped(
+    conn: &Connection,
+    query_embedding: &[f32],
+    k: usize,
+    model_id: &str,
+    metric: SimilarityMetric,
+) -> Result<(Vec<SearchResult>, usize), rusqlite::Error> {
+    let dim = query_embedding.len() as i64;
+    let mismatched: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM embeddings WHERE model_id = ?1 AND dim != ?2",
+        params![model_id, dim],
+        |row| row.get(0),
+    )?;
+
+    // Load compatible embeddings with their chunk info; quantized ones are
+    // dequantized and scored like any other
     let mut stmt = conn.prepare(
-        "SELECT e.chunk_id, e.document_id, e.embedding, c.content
+        "SELECT e.chunk_id, e.document_id, e.embedding, c.content, e.format,
+                COALESCE(d.boost, 1.0)
          FROM embeddings e
-         JOIN chunks c ON e.chunk_id = c.id"
+         JOIN chunks c ON e.chunk_id = c.id
+         LEFT JOIN documents d ON e.document_id = d.id
+         WHERE e.model_id = ?1 AND e.dim = ?2 AND COALESCE(d.muted, 0) = 0"
     )?;
 
-    let mut results: Vec<SearchResult> = stmt
-        .query_map([], |row| {
-            let chunk_id: String = row.get(0)?;
-            let document_id: String = row.get(1)?;
-            let bytes: Vec<u8> = row.get(2)?;
-            let content: String = row.get(3)?;
-
-            let embedding = bytes_to_embedding(&bytes);
-            let score = cosine_similarity(query_embedding, &embedding);
-
-            Ok(SearchResult {
-                chunk_id,
-                document_id,
-                content,
-                score,
+    let candidates: Vec<Candidate> = stmt
+        .query_map(params![model_id, dim], |row| {
+            Ok(Candidate {
+                chunk_id: row.get(0)?,
+                document_id: row.get(1)?,
+                content: row.get(3)?,
+                embedding: row_embedding(row, 2, 4)?,
+                boost: row.get::<_, f64>(5)? as f32,
             })
         })?
         .filter_map(|r| r.ok())
         .collect();
 
-    // Sort by score descending
-    results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    // A `dim` that disagrees with the stored bytes would otherwise panic
+    // when scored
+    let (candidates, corrupt): (Vec<&Candidate>, Vec<&Candidate>) = candidates
+        .iter()
+        .partition(|c| c.embedding.len() == query_embedding.len());
+
+    let results = rank_candidates(&candidates, query_embedding, k, metric);
+    Ok((results, mismatched as usize + corrupt.len()))
+}
+
+/// Ranks whole documents by how well their chunks match the query.
+///
+/// Every chunk is scored as in `search_similar` (including document boosts),
+/// then each document's chunk scores are combined by `aggregation`.
+/// Returns the top `k` documents, best first.
+pub fn search_documents_aggregated(
+    conn: &Connection,
+    query_embedding: &[f32],
+    k: usize,
+    model_id: &str,
+    metric: SimilarityMetric,
+    aggregation: DocumentAggregation,
+) -> Result<Vec<DocumentSearchResult>, rusqlite::Error> {
+    // All chunks, best first, so each document's best chunks come first
+    let chunks = search_similar(conn, query_embedding, usize::MAX, model_id, metric)?;
+    let top_n = match aggregation {
+        DocumentAggregation::Max => 1,
+        DocumentAggregation::MeanOfTop(n) => n.max(1),
+    };
+
+    // (result, sum of the top scores, how many were summed), in order of
+    // each document's best chunk
+    let mut documents: Vec<(DocumentSearchResult, f32, usize)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for chunk in chunks {
+        match positions.get(&chunk.document_id) {
+            Some(&i) => {
+                let (_, sum, count) = &mut documents[i];
+                if *count < top_n {
+                    *sum += chunk.score;
+                    *count += 1;
+                }
+            }
+            None => {
+                positions.insert(chunk.document_id.clone(), documents.len());
+                let score = chunk.score;
+                let result = DocumentSearchResult {
+                    document_id: chunk.document_id,
+                    score,
+                    best_chunk_id: chunk.chunk_id,
+                    snippet: chunk.content,
+                };
+                documents.push((result, score, 1));
+            }
+        }
+    }
+
+    // Averaging can reorder documents, so rank them again (stably, so
+    // ties keep the order of their best chunks)
+    let scores: Vec<f32> = documents
+        .iter()
+        .map(|(_, sum, count)| sum / *count as f32)
+        .collect();
+    Ok(top_k(&scores, k, metric)
+        .into_iter()
+        .map(|(i, score)| DocumentSearchResult {
+            score,
+            ..documents[i].0.clone()
+        })
+        .collect())
+}
+
+/// Score candidates against the query in parallel, weight each score by
+/// its document's boost and keep the top `k`.
+fn rank_candidates(
+    candidates: &[&Candidate],
+    query_embedding: &[f32],
+    k: usize,
+    metric: SimilarityMetric,
+) -> Vec<SearchResult> {
+    let embeddings: Vec<&[f32]> = candidates.iter().map(|c| c.embedding.as_slice()).collect();
+    let scores: Vec<f32> = score_embeddings(&embeddings, query_embedding, metric)
+        .into_iter()
+        .zip(candidates)
+        .map(|(score, c)| boosted_score(score, c.boost, metric))
+        .collect();
+
+    top_k(&scores, k, metric)
+        .into_iter()
+        .map(|(i, score)| SearchResult {
+            chunk_id: candidates[i].chunk_id.clone(),
+            document_id: candidates[i].document_id.clone(),
+            content: candidates[i].content.clone(),
+            score,
+            explanation: None,
+        })
+        .collect()
+}
+
+/// Fills in each result's `explanation` with the keywords it shares with
+/// `query`.
+///
+/// Opt-in because it tokenizes every result's content, which ranking
+/// alone doesn't need.
+pub fn explain_results(results: &mut [SearchResult], query: &str) {
+    let opts = PreprocessOptions::default();
+    let query_terms = preprocess_query(query, &opts);
+    for result in results {
+        let chunk_terms: HashSet<String> =
+            preprocess_query(&result.content, &opts).into_iter().collect();
+        let mut seen = HashSet::new();
+        let matched_terms = query_terms
+            .iter()
+            .filter(|term| chunk_terms.contains(*term) && seen.insert(*term))
+            .cloned()
+            .collect();
+        result.explanation = Some(SearchExplanation {
+            matched_terms,
+            score: result.score,
+        });
+    }
+}
+
+/// Ranks embeddings the caller already holds, without touching the database.
+///
+/// Scores each `(id, embedding)` candidate by cosine similarity to `query`
+/// and returns the best `k` as `(id, score)` pairs, best first. Candidates
+/// whose dimension differs from the query's are skipped.
+pub fn rank_embeddings(
+    query: &[f32],
+    candidates: &[(String, Vec<f32>)],
+    k: usize,
+) -> Vec<(String, f32)> {
+    let candidates: Vec<&(String, Vec<f32>)> =
+        candidates.iter().filter(|(_, e)| e.len() == query.len()).collect();
+    let embeddings: Vec<&[f32]> = candidates.iter().map(|(_, e)| e.as_slice()).collect();
+
+    let scores = score_embeddings(&embeddings, query, SimilarityMetric::Cosine);
+    top_k(&scores, k, SimilarityMetric::Cosine)
+        .into_iter()
+        .map(|(i, score)| (candidates[i].0.clone(), score))
+        .collect()
+}
+
+/// Scores `embeddings` against the query in parallel, in input order.
+fn score_embeddings(
+    embeddings: &[&[f32]],
+    query_embedding: &[f32],
+    metric: SimilarityMetric,
+) -> Vec<f32> {
+    embeddings
+        .par_iter()
+        .map(|embedding| similarity(metric, query_embedding, embedding))
+        .collect()
+}
+
+/// Applies a document's boost to a score.
+///
+/// Similarities are multiplied by the boost. Euclidean scores are
+/// distances, where lower is better, so they're divided by it instead: a
+/// boost above 1.0 always moves a result up.
+fn boosted_score(score: f32, boost: f32, metric: SimilarityMetric) -> f32 {
+    if metric.higher_is_better() {
+        score * boost
+    } else {
+        score / boost
+    }
+}
+
+/// Maps a similarity score from [-1, 1] to [0, 1] with `(score + 1) / 2`,
+/// so opposite vectors score 0.0, unrelated ones 0.5 and identical ones 1.0.
+///
+/// Boosted scores outside [-1, 1] are clamped. Euclidean distances aren't
+/// bounded, so they're returned unchanged. Only for display: ranking and
+/// `min_score` use the raw score.
+pub fn normalize_score(score: f32, metric: SimilarityMetric) -> f32 {
+    if metric.higher_is_better() {
+        ((score + 1.0) / 2.0).clamp(0.0, 1.0)
+    } else {
+        score
+    }
+}
+
+/// Returns the `(index, score)` of the best `k` scores, best first.
+///
+/// `par_iter` in `score_embeddings` preserves input order and the sort is
+/// stable, so ties are broken exactly as a serial pass would break them.
+fn top_k(scores: &[f32], k: usize, metric: SimilarityMetric) -> Vec<(usize, f32)> {
+    // Sort best first: descending scores, or ascending distances
+    let mut order: Vec<usize> = (0..scores.len()).collect();
+    order.sort_by(|&a, &b| {
+        let order = if metric.higher_is_better() {
+            scores[b].partial_cmp(&scores[a])
+        } else {
+            scores[a].partial_cmp(&scores[b])
+        };
+        order.unwrap_or(std::cmp::Ordering::Equal)
+    });
 
     // Return top k
-    results.truncate(k);
+    order.into_iter().take(k).map(|i| (i, scores[i])).collect()
+}
 
-    Ok(results)
+/// Stitches results that are adjacent chunks of the same document into one
+/// result, so text the chunks overlap on isn't sent to the LLM twice.
+///
+/// Runs of consecutive chunks (by `chunk_index`) become a single result that
+/// keeps the chunk ID, score and position of its best-ranked member. Results
+/// whose chunk isn't in `chunks_by_id` are passed through unchanged.
+pub fn merge_overlapping_results(
+    results: &[SearchResult],
+    chunks_by_id: &HashMap<String, Chunk>,
+) -> Vec<SearchResult> {
+    // (rank, result) for everything returned, ordered by rank at the end
+    let mut merged: Vec<(usize, SearchResult)> = Vec::new();
+    let mut known: Vec<(usize, &Chunk)> = Vec::new();
+    for (rank, result) in results.iter().enumerate() {
+        match chunks_by_id.get(&result.chunk_id) {
+            Some(chunk) => known.push((rank, chunk)),
+            None => merged.push((rank, result.clone())),
+        }
+    }
+
+    // Put each document's chunks in order; a repeated chunk keeps its best rank
+    known.sort_by(|(rank_a, a), (rank_b, b)| {
+        (&a.document_id, a.chunk_index, rank_a).cmp(&(&b.document_id, b.chunk_index, rank_b))
+    });
+    known.dedup_by(|(_, a), (_, b)| a.id == b.id);
+
+    let mut run_start = 0;
+    for i in 1..=known.len() {
+        let continues_run = i < known.len()
+            && known[i].1.document_id == known[i - 1].1.document_id
+            && known[i].1.chunk_index == known[i - 1].1.chunk_index + 1;
+        if !continues_run {
+            merged.push(stitch_run(&known[run_start..i], results));
+            run_start = i;
+        }
+    }
+
+    merged.sort_by_key(|(rank, _)| *rank);
+    merged.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Joins a non-empty run of consecutive chunks into one result, based on the
+/// run's best-ranked member.
+fn stitch_run(run: &[(usize, &Chunk)], results: &[SearchResult]) -> (usize, SearchResult) {
+    let rank = run.iter().map(|(rank, _)| *rank).min().expect("run is never empty");
+    let mut content = run[0].1.content.clone();
+    for pair in run.windows(2) {
+        let overlap = pair[0].1.end_offset.saturating_sub(pair[1].1.start_offset);
+        append_without_overlap(&mut content, &pair[1].1.content, overlap);
+    }
+
+    let result = SearchResult {
+        content,
+        ..results[rank].clone()
+    };
+    (rank, result)
+}
+
+/// Appends `next` to `content`, dropping the start of `next` that `content`
+/// already ends with.
+///
+/// `max_overlap` is the overlap in characters according to the offsets.
+/// Chunk content is trimmed, so the text actually shared can be a little
+/// shorter; the longest shared prefix up to that length is dropped.
+fn append_without_overlap(content: &mut String, next: &str, max_overlap: usize) {
+    // Byte offset in `next` after each of its first `max_overlap` characters
+    let prefix_ends: Vec<usize> = next
+        .char_indices()
+        .map(|(i, _)| i)
+        .skip(1)
+        .chain(std::iter::once(next.len()))
+        .take(max_overlap)
+        .collect();
+
+    match prefix_ends.into_iter().rev().find(|&end| content.ends_with(&next[..end])) {
+        Some(end) => content.push_str(&next[end..]),
+        None => {
+            // The whitespace between the chunks was trimmed away
+            content.push(' ');
+            content.push_str(next);
+        }
+    }
+}
+
+/// Errors from saving or loading a `VectorIndex` snapshot.
+#[derive(Debug)]
+pub enum VectorIndexError {
+    Io(std::io::Error),
+    Database(rusqlite::Error),
+    /// The file isn't a snapshot this version can read
+    InvalidSnapshot(String),
+}
+
+impl std::fmt::Display for VectorIndexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VectorIndexError::Io(e) => write!(f, "IO error: {}", e),
+            VectorIndexError::Database(e) => write!(f, "Database error: {}", e),
+            VectorIndexError::InvalidSnapshot(msg) => write!(f, "Invalid index snapshot: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for VectorIndexError {}
+
+impl From<std::io::Error> for VectorIndexError {
+    fn from(e: std::io::Error) -> Self {
+        VectorIndexError::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for VectorIndexError {
+    fn from(e: rusqlite::Error) -> Self {
+        VectorIndexError::Database(e)
+    }
+}
+
+/// Identifies a snapshot file, followed by the format version.
+const SNAPSHOT_MAGIC: &[u8; 4] = b"LCVI";
+const SNAPSHOT_VERSION: u32 = 2;
+
+/// Largest embedding dimension a snapshot may record; real models stay
+/// well below it, so anything larger means the file is corrupt.
+const MAX_SNAPSHOT_DIM: usize = 65_536;
+
+/// One model's embeddings held in memory for searching without the database.
+///
+/// Searches rank exactly like [`search_similar`]. The index doesn't follow
+/// later changes to the database; rebuild it (or reload a snapshot, which
+/// checks for staleness) after embeddings are added or removed.
+pub struct VectorIndex {
+    model_id: String,
+    /// `embeddings_generation` when the index was built
+    generation: i64,
+    /// Rows in the embeddings table (all models) when the index was built
+    row_count: usize,
+    candidates: Vec<Candidate>,
+}
+
+impl VectorIndex {
+    /// Loads every embedding produced by `model_id` from the database.
+    pub fn build(conn: &Connection, model_id: &str) -> Result<Self, rusqlite::Error> {
+        let generation = embeddings_generation(conn)?;
+        let (row_count, _) = get_embedding_stats(conn)?;
+
+        let mut stmt = conn.prepare(
+            "SELECT e.chunk_id, e.document_id, e.embedding, c.content, e.format,
+                    COALESCE(d.boost, 1.0)
+             FROM embeddings e
+             JOIN chunks c ON e.chunk_id = c.id
+             LEFT JOIN documents d ON e.document_id = d.id
+             WHERE e.model_id = ?1 AND COALESCE(d.muted, 0) = 0
+             ORDER BY e.rowid",
+        )?;
+
+        let candidates = stmt
+            .query_map(params![model_id], |row| {
+                Ok(Candidate {
+                    chunk_id: row.get(0)?,
+                    document_id: row.get(1)?,
+                    content: row.get(3)?,
+                    embedding: row_embedding(row, 2, 4)?,
+                    boost: row.get::<_, f64>(5)? as f32,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(VectorIndex {
+            model_id: model_id.to_string(),
+            generation,
+            row_count,
+            candidates,
+        })
+    }
+
+    /// Number of embeddings in the index.
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    /// Returns true if the index holds no embeddings.
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// The model whose embeddings the index holds.
+    pub fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    /// Returns the top `k` chunks for the query, like [`search_similar`].
+    ///
+    /// Only embeddings with the query's dimension are scored.
+    pub fn search(
+        &self,
+        query_embedding: &[f32],
+        k: usize,
+        metric: SimilarityMetric,
+    ) -> Vec<SearchResult> {
+        let candidates: Vec<&Candidate> = self
+            .candidates
+            .iter()
+            .filter(|c| c.embedding.len() == query_embedding.len())
+            .collect();
+        rank_candidates(&candidates, query_embedding, k, metric)
+    }
+
+    /// Returns true if the database changed since the index was built.
+    pub fn is_stale(&self, conn: &Connection) -> Result<bool, rusqlite::Error> {
+        let (row_count, _) = get_embedding_stats(conn)?;
+        Ok(embeddings_generation(conn)? != self.generation || row_count != self.row_count)
+    }
+
+    /// Writes a snapshot of the index to `path`.
+    ///
+    /// The snapshot is written to a temporary file first and renamed into
+    /// place, so a crash never leaves a half-written snapshot behind.
+    pub fn save(&self, path: &Path) -> Result<(), VectorIndexError> {
+        let tmp_path = path.with_extension("tmp");
+        let mut out = BufWriter::new(fs::File::create(&tmp_path)?);
+
+        out.write_all(SNAPSHOT_MAGIC)?;
+        out.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        out.write_all(&self.generation.to_le_bytes())?;
+        out.write_all(&(self.row_count as u64).to_le_bytes())?;
+        write_str(&mut out, &self.model_id)?;
+        out.write_all(&(self.candidates.len() as u64).to_le_bytes())?;
+
+        for c in &self.candidates {
+            write_str(&mut out, &c.chunk_id)?;
+            write_str(&mut out, &c.document_id)?;
+            write_str(&mut out, &c.content)?;
+            out.write_all(&c.boost.to_le_bytes())?;
+            out.write_all(&(c.embedding.len() as u64).to_le_bytes())?;
+            out.write_all(&embedding_to_bytes(&c.embedding))?;
+        }
+
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Reads a snapshot written by [`VectorIndex::save`].
+    ///
+    /// Returns `None` if there's no snapshot at `path`, or if it's stale:
+    /// embeddings were added, removed or changed since it was taken. The
+    /// caller should then [`build`](VectorIndex::build) a fresh index.
+    /// A corrupt snapshot is an `InvalidSnapshot` error, which also calls
+    /// for a rebuild; lengths it records are checked against the file size
+    /// before anything is allocated for them.
+    pub fn load(path: &Path, conn: &Connection) -> Result<Option<Self>, VectorIndexError> {
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        // The limit counts down to what's left of the file as it's read
+        let file_len = file.metadata()?.len();
+        let mut input = BufReader::new(file).take(file_len);
+
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(VectorIndexError::InvalidSnapshot("not an index snapshot".to_string()));
+        }
+        let version = read_u64_le::<4>(&mut input)? as u32;
+        if version < SNAPSHOT_VERSION {
+            // Older snapshots lack document boosts; rebuild them
+            return Ok(None);
+        }
+        if version != SNAPSHOT_VERSION {
+            return Err(VectorIndexError::InvalidSnapshot(format!(
+                "unsupported version {}",
+                version
+            )));
+        }
+
+        let generation = read_u64_le::<8>(&mut input)? as i64;
+        let row_count = read_u64_le::<8>(&mut input)? as usize;
+        let model_id = read_str(&mut input)?;
+
+        let index = VectorIndex {
+            model_id,
+            generation,
+            row_count,
+            candidates: Vec::new(),
+        };
+        // Check before reading the (possibly large) body
+        if index.is_stale(conn)? {
+            return Ok(None);
+        }
+
+        let count = read_u64_le::<8>(&mut input)? as usize;
+        let mut candidates = Vec::with_capacity(count.min(row_count));
+        for _ in 0..count {
+            let chunk_id = read_str(&mut input)?;
+            let document_id = read_str(&mut input)?;
+            let content = read_str(&mut input)?;
+            let boost = f32::from_bits(read_u64_le::<4>(&mut input)? as u32);
+            let dim = read_u64_le::<8>(&mut input)?;
+            if dim > MAX_SNAPSHOT_DIM as u64 {
+                return Err(VectorIndexError::InvalidSnapshot(format!(
+                    "embedding dimension {} is too large",
+                    dim
+                )));
+            }
+            let bytes = read_bytes(&mut input, dim * 4)?;
+
+            candidates.push(Candidate {
+                chunk_id,
+                document_id,
+                content,
+                embedding: bytes_to_embedding(&bytes),
+                boost,
+            });
+        }
+
+        Ok(Some(VectorIndex { candidates, ..index }))
+    }
+}
+
+/// Writes a length-prefixed UTF-8 string.
+fn write_str(out: &mut impl Write, s: &str) -> std::io::Result<()> {
+    out.write_all(&(s.len() as u64).to_le_bytes())?;
+    out.write_all(s.as_bytes())
+}
+
+/// Reads a string written by `write_str`.
+fn read_str(input: &mut Take<impl Read>) -> Result<String, VectorIndexError> {
+    let len = read_u64_le::<8>(input)?;
+    let bytes = read_bytes(input, len)?;
+    String::from_utf8(bytes).map_err(|e| VectorIndexError::InvalidSnapshot(e.to_string()))
+}
+
+/// Reads `len` bytes, failing without allocating if fewer than that are
+/// left in the file (the limit of `input`).
+fn read_bytes(input: &mut Take<impl Read>, len: u64) -> Result<Vec<u8>, VectorIndexError> {
+    if len > input.limit() {
+        return Err(VectorIndexError::InvalidSnapshot(format!(
+            "length {} runs past the end of the file",
+            len
+        )));
+    }
+    let mut bytes = vec![0u8; len as usize];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Reads an `N`-byte little-endian unsigned integer.
+fn read_u64_le<const N: usize>(input: &mut impl Read) -> std::io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes[..N])?;
+    Ok(u64::from_le_bytes(bytes))
 }
 
 /// Delete embeddings for a document.
 ///
 /// Called when a document is deleted to clean up its embeddings.
-pub fn delete_document_embeddings(conn: &Connection, document_id: &str) -> Result<(), rusqlite::Error> {
+pub fn delete_document_embeddings(conn: &Connection, document_id: &str) -> Result<usize, rusqlite::Error> {
     conn.execute(
         "DELETE FROM embeddings WHERE document_id = ?1",
         params![document_id],
-    )?;
-    Ok(())
+    )
 }
 
 /// Get statistics about stored embeddings.
@@ -174,6 +981,21 @@ pub fn get_embedding_stats(conn: &Connection) -> Result<(usize, usize), rusqlite
     Ok((total_embeddings as usize, total_docs as usize))
 }
 
+/// Count chunks that have no embedding yet.
+///
+/// A non-zero count means the index is incomplete, e.g. documents uploaded
+/// before the model was loaded or an ingest that failed part way.
+pub fn count_unembedded_chunks(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM chunks c
+         LEFT JOIN embeddings e ON e.chunk_id = c.id
+         WHERE e.chunk_id IS NULL",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(count as usize)
+}
+
 /// Check if embeddings exist for a chunk.
 pub fn has_embedding(conn: &Connection, chunk_id: &str) -> Result<bool, rusqlite::Error> {
     let count: i64 = conn.query_row(
@@ -187,7 +1009,7 @@ pub fn has_embedding(conn: &Connection, chunk_id: &str) -> Result<bool, rusqlite
 /// Convert a f32 embedding to bytes for SQLite storage.
 ///
 /// Uses little-endian byte order for consistency.
-fn embedding_to_bytes(embedding: &[f32]) -> Vec<u8> {
+pub(crate) fn embedding_to_bytes(embedding: &[f32]) -> Vec<u8> {
     embedding
         .iter()
         .flat_map(|f| f.to_le_bytes())
@@ -195,16 +1017,81 @@ fn embedding_to_bytes(embedding: &[f32]) -> Vec<u8> {
 }
 
 /// Convert bytes from SQLite back to f32 embedding.
-fn bytes_to_embedding(bytes: &[u8]) -> Vec<f32> {
+pub(crate) fn bytes_to_embedding(bytes: &[u8]) -> Vec<f32> {
     bytes
         .chunks_exact(4)
         .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
         .collect()
 }
 
+/// Quantize an embedding to `StorageFormat::Q8` bytes.
+///
+/// The scale maps the largest absolute component to 127, so the full i8
+/// range is used whatever the vector's magnitude. A zero vector gets a
+/// scale of 0.
+pub fn embedding_to_bytes_q8(embedding: &[f32]) -> Vec<u8> {
+    let max_abs = embedding.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+    let scale = max_abs / 127.0;
+
+    let mut bytes = Vec::with_capacity(4 + embedding.len());
+    bytes.extend_from_slice(&scale.to_le_bytes());
+    bytes.extend(embedding.iter().map(|x| {
+        let q = if scale > 0.0 { (x / scale).round() } else { 0.0 };
+        q.clamp(-127.0, 127.0) as i8 as u8
+    }));
+    bytes
+}
+
+/// Dequantize `StorageFormat::Q8` bytes back to f32s.
+///
+/// Bytes too short to hold the scale decode to an empty vector.
+pub fn bytes_to_embedding_q8(bytes: &[u8]) -> Vec<f32> {
+    let Some((scale, components)) = bytes.split_first_chunk::<4>() else {
+        return Vec::new();
+    };
+    let scale = f32::from_le_bytes(*scale);
+    components.iter().map(|&b| b as i8 as f32 * scale).collect()
+}
+
+/// Reads an embedding from a row, decoding the BLOB in column `bytes_col`
+/// by the `StorageFormat` named in column `format_col`.
+fn row_embedding(
+    row: &rusqlite::Row,
+    bytes_col: usize,
+    format_col: usize,
+) -> rusqlite::Result<Vec<f32>> {
+    let bytes: Vec<u8> = row.get(bytes_col)?;
+    let name: String = row.get(format_col)?;
+    let format = StorageFormat::from_name(&name).ok_or_else(|| {
+        let reason = format!("unknown embedding format '{}'", name);
+        let source = reason.into();
+        rusqlite::Error::FromSqlConversionFailure(format_col, rusqlite::types::Type::Text, source)
+    })?;
+    Ok(format.decode(&bytes))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::embeddings::{cosine_similarity, EMBEDDING_DIM};
+
+    /// Stores an empty text document, `<id>.txt`, for chunks to belong to.
+    fn insert_test_document(conn: &Connection, id: &str) {
+        let path = format!("/tmp/{}.txt", id);
+        let doc = crate::documents::Document {
+            id: id.to_string(),
+            name: format!("{}.txt", id),
+            doc_type: crate::documents::DocumentType::Txt,
+            size: 0,
+            uploaded_at: chrono::Utc::now(),
+            path: path.clone(),
+            word_count: 0,
+            source_path: path,
+            modified_at: None,
+            language: None,
+        };
+        crate::documents::save_document(conn, &doc).unwrap();
+    }
 
     #[test]
     fn test_embedding_bytes_roundtrip() {
@@ -219,9 +1106,71 @@ mod tests {
     }
 
     #[test]
-    fn test_database_operations() {
-        use chrono::Utc;
+    fn test_q8_quantization_error_and_ranking() {
+        // Deterministic pseudo-random unit vectors, like the model's output
+        let mut state = 0x2545_f491u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32 - 0.5
+        };
+        let vectors: Vec<Vec<f32>> = (0..20)
+            .map(|_| {
+                let raw: Vec<f32> = (0..EMBEDDING_DIM).map(|_| next()).collect();
+                crate::embeddings::normalize_vec(&raw)
+            })
+            .collect();
+
+        // Every component is within half a quantization step
+        for vector in &vectors {
+            let bytes = embedding_to_bytes_q8(vector);
+            assert_eq!(bytes.len(), 4 + EMBEDDING_DIM);
+            let recovered = bytes_to_embedding_q8(&bytes);
+            let max_abs = vector.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+            let tolerance = max_abs / 254.0 + 1e-6;
+            for (a, b) in vector.iter().zip(&recovered) {
+                assert!((a - b).abs() <= tolerance, "{} != {}", a, b);
+            }
+        }
+        assert_eq!(bytes_to_embedding_q8(&embedding_to_bytes_q8(&[0.0; 4])), vec![0.0; 4]);
+        assert!(bytes_to_embedding_q8(&[1, 2]).is_empty());
+
+        // Store the same vectors in both formats and search: rankings match
+        let db = crate::db::Database::in_memory().unwrap();
+        let conn = &db.conn;
+        insert_test_document(conn, "doc-1");
+        for (i, vector) in vectors.iter().enumerate() {
+            for (model, format) in [("full", StorageFormat::F32), ("quantized", StorageFormat::Q8)] {
+                let chunk_id = format!("{}-{}", model, i);
+                conn.execute(
+                    "INSERT INTO chunks
+                     (id, document_id, chunk_index, content, start_offset, end_offset)
+                     VALUES (?1, 'doc-1', ?2, ?3, 0, 1)",
+                    params![chunk_id, i as i64, i.to_string()],
+                )
+                .unwrap();
+                save_embedding_with_format(conn, &chunk_id, "doc-1", vector, model, format).unwrap();
+            }
+        }
+
+        let query = &vectors[0];
+        let ranked = |model| -> Vec<String> {
+            search_similar(conn, query, 5, model, SimilarityMetric::Cosine)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.content)
+                .collect()
+        };
+        assert_eq!(ranked("quantized"), ranked("full"));
+        assert_eq!(ranked("quantized")[0], "0");
+
+        let stored = get_embedding(conn, "quantized-3").unwrap().unwrap();
+        assert!(cosine_similarity(&stored, &vectors[3]) > 0.999);
+    }
 
+    #[test]
+    fn test_database_operations() {
         let conn = Connection::open_in_memory().unwrap();
 
         // Set up all required tables
@@ -230,15 +1179,7 @@ mod tests {
         init_embeddings_table(&conn).unwrap();
 
         // Create a document
-        let doc = crate::documents::Document {
-            id: "doc-1".to_string(),
-            name: "test.txt".to_string(),
-            doc_type: crate::documents::DocumentType::Txt,
-            size: 100,
-            uploaded_at: Utc::now(),
-            path: "/tmp/test.txt".to_string(),
-        };
-        crate::documents::save_document(&conn, &doc).unwrap();
+        insert_test_document(&conn, "doc-1");
 
         // Create a chunk
         let chunk = crate::chunker::Chunk {
@@ -253,7 +1194,7 @@ mod tests {
 
         // Save an embedding
         let embedding: Vec<f32> = (0..EMBEDDING_DIM).map(|i| i as f32 / EMBEDDING_DIM as f32).collect();
-        save_embedding(&conn, "doc-1-0", "doc-1", &embedding).unwrap();
+        save_embedding(&conn, "doc-1-0", "doc-1", &embedding, MODEL_ID).unwrap();
 
         // Retrieve the embedding
         let retrieved = get_embedding(&conn, "doc-1-0").unwrap().unwrap();
@@ -265,8 +1206,572 @@ mod tests {
         assert_eq!(docs, 1);
 
         // Search (should find the chunk)
-        let results = search_similar(&conn, &embedding, 10).unwrap();
+        let results = search_similar(&conn, &embedding, 10, MODEL_ID, SimilarityMetric::Dot).unwrap();
         assert_eq!(results.len(), 1);
         assert!(results[0].score > 0.99); // Should be very similar to itself
     }
+
+    #[test]
+    fn test_parallel_ranking_matches_serial() {
+        // Deterministic pseudo-random vectors (simple LCG, no extra deps)
+        let mut seed: u32 = 42;
+        let mut next = move || {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (seed >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+        };
+
+        let dim = 64;
+        let query: Vec<f32> = (0..dim).map(|_| next()).collect();
+        let candidates: Vec<Candidate> = (0..10_000)
+            .map(|i| Candidate {
+                chunk_id: format!("chunk-{}", i),
+                document_id: format!("doc-{}", i % 50),
+                content: String::new(),
+                embedding: (0..dim).map(|_| next()).collect(),
+                boost: 1.0,
+            })
+            .collect();
+
+        // Serial reference path
+        let mut serial: Vec<(String, f32)> = candidates
+            .iter()
+            .map(|c| (c.chunk_id.clone(), cosine_similarity(&query, &c.embedding)))
+            .collect();
+        serial.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        serial.truncate(10);
+
+        let candidates: Vec<&Candidate> = candidates.iter().collect();
+        let parallel = rank_candidates(&candidates, &query, 10, SimilarityMetric::Dot);
+
+        assert_eq!(parallel.len(), 10);
+        for (p, s) in parallel.iter().zip(serial.iter()) {
+            assert_eq!(p.chunk_id, s.0);
+            assert_eq!(p.score, s.1);
+        }
+    }
+
+    #[test]
+    fn test_search_only_scores_matching_model() {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::documents::init_documents_table(&conn).unwrap();
+        crate::chunker::init_chunks_table(&conn).unwrap();
+        init_embeddings_table(&conn).unwrap();
+
+        insert_test_document(&conn, "doc-1");
+
+        let chunks: Vec<crate::chunker::Chunk> = ["a", "b"]
+            .iter()
+            .enumerate()
+            .map(|(i, content)| crate::chunker::Chunk {
+                id: format!("doc-1-{}", i),
+                document_id: "doc-1".to_string(),
+                chunk_index: i,
+                content: content.to_string(),
+                start_offset: 0,
+                end_offset: 1,
+            })
+            .collect();
+        crate::chunker::save_chunks(&conn, &chunks).unwrap();
+
+        let embedding = vec![1.0, 0.0, 0.0];
+        save_embedding(&conn, "doc-1-0", "doc-1", &embedding, "model-a").unwrap();
+        save_embedding(&conn, "doc-1-1", "doc-1", &embedding, "model-b").unwrap();
+
+        let results = search_similar(&conn, &embedding, 10, "model-a", SimilarityMetric::Dot).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].chunk_id, "doc-1-0");
+
+        // Same model but a different dimension is skipped too
+        let results = search_similar(&conn, &[1.0, 0.0], 10, "model-a", SimilarityMetric::Dot).unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_search_skips_and_counts_mismatched_dimensions() {
+        let db = crate::db::Database::in_memory().unwrap();
+        let conn = &db.conn;
+        insert_test_document(conn, "doc-1");
+        conn.execute_batch(
+            "INSERT INTO chunks (id, document_id, chunk_index, content, start_offset, end_offset)
+             VALUES ('new', 'doc-1', 0, 'new', 0, 3), ('old', 'doc-1', 1, 'old', 3, 6);",
+        )
+        .unwrap();
+
+        let mut query = vec![0.0; 384];
+        query[0] = 1.0;
+        let mut legacy = vec![0.0; 128];
+        legacy[0] = 1.0;
+        save_embedding(conn, "new", "doc-1", &query, MODEL_ID).unwrap();
+        save_embedding(conn, "old", "doc-1", &legacy, MODEL_ID).unwrap();
+
+        let search = |metric| search_similar_counting_skipped(conn, &query, 10, MODEL_ID, metric);
+        let metrics = [SimilarityMetric::Dot, SimilarityMetric::Cosine, SimilarityMetric::Euclidean];
+        for metric in metrics {
+            let (results, skipped) = search(metric).unwrap();
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].chunk_id, "new");
+            assert_eq!(skipped, 1);
+        }
+        let results = search_similar(conn, &query, 10, MODEL_ID, SimilarityMetric::Dot).unwrap();
+        assert_eq!(results.len(), 1);
+
+        // A `dim` column that disagrees with the stored bytes is skipped too
+        conn.execute("UPDATE embeddings SET dim = 384 WHERE chunk_id = 'old'", []).unwrap();
+        let (results, skipped) = search(SimilarityMetric::Dot).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(skipped, 1);
+    }
+
+    #[test]
+    fn test_model_id_migration_backfills_legacy_rows() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE embeddings (
+                chunk_id TEXT PRIMARY KEY,
+                document_id TEXT NOT NULL,
+                embedding BLOB NOT NULL
+            )",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO embeddings (chunk_id, document_id, embedding) VALUES ('c', 'd', ?1)",
+            params![embedding_to_bytes(&[0.5; 8])],
+        )
+        .unwrap();
+
+        init_embeddings_table(&conn).unwrap();
+
+        let (model_id, dim): (String, i64) = conn
+            .query_row("SELECT model_id, dim FROM embeddings WHERE chunk_id = 'c'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(model_id, MODEL_ID);
+        assert_eq!(dim, 8);
+    }
+
+    #[test]
+    fn test_count_unembedded_chunks() {
+        let db = crate::db::Database::new(":memory:").unwrap();
+        insert_test_document(&db.conn, "doc-1");
+
+        let chunks: Vec<crate::chunker::Chunk> = (0..2)
+            .map(|i| crate::chunker::Chunk {
+                id: format!("doc-1-{}", i),
+                document_id: "doc-1".to_string(),
+                chunk_index: i,
+                content: format!("Chunk {}", i),
+                start_offset: 0,
+                end_offset: 7,
+            })
+            .collect();
+        crate::chunker::save_chunks(&db.conn, &chunks).unwrap();
+        assert_eq!(count_unembedded_chunks(&db.conn).unwrap(), 2);
+
+        // Embed only the first chunk; the second is left orphaned
+        save_embedding(&db.conn, "doc-1-0", "doc-1", &[1.0, 0.0], "test-model").unwrap();
+        assert_eq!(count_unembedded_chunks(&db.conn).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_ranking_cosine_vs_euclidean() {
+        let candidate = |id: &str, embedding: Vec<f32>| Candidate {
+            chunk_id: id.to_string(),
+            document_id: "doc-1".to_string(),
+            content: String::new(),
+            embedding,
+            boost: 1.0,
+        };
+        // "far" points the same way as the query but is much longer;
+        // "near" is close in space but off at an angle
+        let candidates = || {
+            vec![
+                candidate("far", vec![10.0, 0.0]),
+                candidate("near", vec![1.0, 0.5]),
+            ]
+        };
+        let query = [1.0, 0.0];
+        let ranked = |metric| -> Vec<String> {
+            let candidates = candidates();
+            rank_candidates(&candidates.iter().collect::<Vec<_>>(), &query, 2, metric)
+                .into_iter()
+                .map(|r| r.chunk_id)
+                .collect()
+        };
+
+        assert_eq!(ranked(SimilarityMetric::Cosine), vec!["far", "near"]);
+        // Smallest distance ranks first
+        assert_eq!(ranked(SimilarityMetric::Euclidean), vec!["near", "far"]);
+    }
+
+    #[test]
+    fn test_normalized_scores() {
+        let candidate = |id: &str, embedding: Vec<f32>| Candidate {
+            chunk_id: id.to_string(),
+            document_id: "doc-1".to_string(),
+            content: String::new(),
+            embedding,
+            boost: 1.0,
+        };
+        let candidates = [
+            candidate("same", vec![0.6, 0.8]),
+            candidate("orthogonal", vec![-0.8, 0.6]),
+            candidate("opposite", vec![-0.6, -0.8]),
+        ];
+        let query = [0.6, 0.8];
+
+        for metric in [SimilarityMetric::Dot, SimilarityMetric::Cosine] {
+            let results =
+                rank_candidates(&candidates.iter().collect::<Vec<_>>(), &query, 3, metric);
+            let normalized: Vec<f32> =
+                results.iter().map(|r| normalize_score(r.score, metric)).collect();
+            assert!((results[2].score + 1.0).abs() < 1e-5);
+            assert!((normalized[0] - 1.0).abs() < 1e-5);
+            assert!((normalized[1] - 0.5).abs() < 1e-5);
+            assert!(normalized[2].abs() < 1e-5);
+        }
+
+        // Boosted scores stay in range, and distances are left alone
+        assert_eq!(normalize_score(1.35, SimilarityMetric::Dot), 1.0);
+        assert_eq!(normalize_score(1.35, SimilarityMetric::Euclidean), 1.35);
+    }
+
+    #[test]
+    fn test_rank_embeddings_without_database() {
+        let candidates = vec![
+            ("far".to_string(), vec![0.0, 1.0]),
+            ("exact".to_string(), vec![2.0, 0.0]),
+            ("opposite".to_string(), vec![-1.0, 0.0]),
+            ("close".to_string(), vec![0.8, 0.6]),
+            ("wrong-dim".to_string(), vec![1.0, 0.0, 0.0]),
+        ];
+
+        let ranked = rank_embeddings(&[1.0, 0.0], &candidates, 3);
+        let ids: Vec<&str> = ranked.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["exact", "close", "far"]);
+
+        // Cosine, so the un-normalized exact match still scores 1.0
+        assert!((ranked[0].1 - 1.0).abs() < 1e-6);
+        assert!((ranked[1].1 - 0.8).abs() < 1e-6);
+        assert!(ranked[2].1.abs() < 1e-6);
+
+        assert_eq!(rank_embeddings(&[1.0, 0.0], &candidates, 10).len(), 4);
+        assert!(rank_embeddings(&[1.0, 0.0], &candidates, 0).is_empty());
+    }
+
+    #[test]
+    fn test_index_snapshot_roundtrip_and_staleness() {
+        let db = crate::db::Database::in_memory().unwrap();
+        let conn = &db.conn;
+        insert_test_document(conn, "doc-1");
+
+        let vectors = [[1.0, 0.0, 0.0], [0.6, 0.8, 0.0], [0.0, 0.6, 0.8], [0.0, 0.0, 1.0]];
+        let chunks: Vec<crate::chunker::Chunk> = (0..vectors.len())
+            .map(|i| crate::chunker::Chunk {
+                id: format!("doc-1-{}", i),
+                document_id: "doc-1".to_string(),
+                chunk_index: i,
+                content: format!("Chunk number {} ✓", i),
+                start_offset: 0,
+                end_offset: 0,
+            })
+            .collect();
+        crate::chunker::save_chunks(conn, &chunks).unwrap();
+        for (chunk, vector) in chunks.iter().zip(vectors.iter()) {
+            save_embedding(conn, &chunk.id, "doc-1", vector, "test-model").unwrap();
+        }
+
+        let path = std::env::temp_dir().join(format!("index-{}.bin", uuid::Uuid::new_v4()));
+        assert!(VectorIndex::load(&path, conn).unwrap().is_none());
+
+        let index = VectorIndex::build(conn, "test-model").unwrap();
+        assert_eq!(index.len(), 4);
+        index.save(&path).unwrap();
+
+        let loaded = VectorIndex::load(&path, conn).unwrap().expect("snapshot should be fresh");
+        assert_eq!(loaded.model_id(), "test-model");
+        assert_eq!(loaded.len(), 4);
+
+        let query = [0.8, 0.6, 0.0];
+        for metric in [SimilarityMetric::Dot, SimilarityMetric::Euclidean] {
+            let fresh = VectorIndex::build(conn, "test-model").unwrap().search(&query, 3, metric);
+            let restored = loaded.search(&query, 3, metric);
+            let from_db = search_similar(conn, &query, 3, "test-model", metric).unwrap();
+
+            assert_eq!(restored.len(), 3);
+            for ((a, b), c) in restored.iter().zip(fresh.iter()).zip(from_db.iter()) {
+                assert_eq!(a.chunk_id, b.chunk_id);
+                assert_eq!(a.chunk_id, c.chunk_id);
+                assert_eq!(a.content, b.content);
+                assert_eq!(a.score, b.score);
+            }
+        }
+
+        // Any change to the embeddings makes the snapshot stale, even if the
+        // row count ends up the same
+        save_embedding(conn, "doc-1-0", "doc-1", &[0.0, 1.0, 0.0], "test-model").unwrap();
+        assert!(loaded.is_stale(conn).unwrap());
+        assert!(VectorIndex::load(&path, conn).unwrap().is_none());
+
+        std::fs::write(&path, b"garbage").unwrap();
+        assert!(matches!(
+            VectorIndex::load(&path, conn),
+            Err(VectorIndexError::InvalidSnapshot(_))
+        ));
+
+        // Corrupt lengths are rejected before anything is allocated for them
+        VectorIndex::build(conn, "test-model").unwrap().save(&path).unwrap();
+        let snapshot = std::fs::read(&path).unwrap();
+        let load_with = |offset: usize, len: u64| {
+            let mut bytes = snapshot.clone();
+            bytes[offset..offset + 8].copy_from_slice(&len.to_le_bytes());
+            std::fs::write(&path, bytes).unwrap();
+            VectorIndex::load(&path, conn)
+        };
+        // The model ID's length follows the 24-byte header; the last
+        // embedding's dimension precedes its three floats
+        let last_dim = snapshot.len() - 3 * 4 - 8;
+        for (offset, len) in [(24, u64::MAX), (last_dim, 1 << 40), (last_dim, 4)] {
+            assert!(matches!(load_with(offset, len), Err(VectorIndexError::InvalidSnapshot(_))));
+        }
+        assert!(load_with(last_dim, 3).unwrap().is_some());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_explanation_lists_shared_keywords() {
+        let mut results = vec![SearchResult {
+            chunk_id: "c".to_string(),
+            document_id: "d".to_string(),
+            content: "Rust's borrow checker rejects dangling references.".to_string(),
+            score: 0.8,
+            explanation: None,
+        }];
+
+        explain_results(&mut results, "How does the Borrow Checker treat references? borrow");
+        let explanation = results[0].explanation.as_ref().unwrap();
+        assert_eq!(explanation.matched_terms, vec!["borrow", "checker", "references"]);
+        assert_eq!(explanation.score, 0.8);
+
+        // Left out of the JSON unless requested
+        results[0].explanation = None;
+        let json = serde_json::to_value(&results[0]).unwrap();
+        assert!(json.get("explanation").is_none());
+    }
+
+    #[test]
+    fn test_muted_document_is_excluded() {
+        let db = crate::db::Database::in_memory().unwrap();
+        let conn = &db.conn;
+        insert_test_document(conn, "best");
+        insert_test_document(conn, "other");
+        conn.execute_batch(
+            "INSERT INTO chunks (id, document_id, chunk_index, content, start_offset, end_offset)
+             VALUES ('best-0', 'best', 0, 'a', 0, 1), ('other-0', 'other', 0, 'b', 0, 1);",
+        )
+        .unwrap();
+        let query = [1.0, 0.0];
+        save_embedding(conn, "best-0", "best", &[1.0, 0.0], "test-model").unwrap();
+        save_embedding(conn, "other-0", "other", &[0.6, 0.8], "test-model").unwrap();
+
+        let top_hit = || {
+            search_similar(conn, &query, 1, "test-model", SimilarityMetric::Dot).unwrap()[0]
+                .chunk_id
+                .clone()
+        };
+        assert_eq!(top_hit(), "best-0");
+
+        let index = VectorIndex::build(conn, "test-model").unwrap();
+        assert!(crate::documents::set_document_muted(conn, "best", true).unwrap());
+        assert!(index.is_stale(conn).unwrap());
+
+        // Still indexed, but never returned
+        assert!(has_embedding(conn, "best-0").unwrap());
+        assert_eq!(top_hit(), "other-0");
+        let results =
+            search_similar(conn, &query, 10, "test-model", SimilarityMetric::Dot).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(VectorIndex::build(conn, "test-model").unwrap().len(), 1);
+
+        assert!(crate::documents::set_document_muted(conn, "best", false).unwrap());
+        assert_eq!(top_hit(), "best-0");
+        assert!(!crate::documents::set_document_muted(conn, "missing", true).unwrap());
+    }
+
+    #[test]
+    fn test_document_boost_outranks_closer_match() {
+        let db = crate::db::Database::in_memory().unwrap();
+        let conn = &db.conn;
+        insert_test_document(conn, "plain");
+        insert_test_document(conn, "trusted");
+        conn.execute_batch(
+            "INSERT INTO chunks (id, document_id, chunk_index, content, start_offset, end_offset)
+             VALUES ('plain-0', 'plain', 0, 'a', 0, 1), ('trusted-0', 'trusted', 0, 'b', 0, 1);",
+        )
+        .unwrap();
+        let query = [1.0, 0.0];
+        save_embedding(conn, "plain-0", "plain", &[0.95, 0.312], "test-model").unwrap();
+        save_embedding(conn, "trusted-0", "trusted", &[0.9, 0.436], "test-model").unwrap();
+
+        let ranked = |metric| -> Vec<String> {
+            search_similar(conn, &query, 2, "test-model", metric)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.chunk_id)
+                .collect()
+        };
+        assert_eq!(ranked(SimilarityMetric::Dot), vec!["plain-0", "trusted-0"]);
+
+        let index = VectorIndex::build(conn, "test-model").unwrap();
+        assert!(crate::documents::set_document_boost(conn, "trusted", 1.5).unwrap());
+        assert!(index.is_stale(conn).unwrap());
+
+        // The boost lifts the slightly less similar chunk above the other,
+        // for distances as well as similarities
+        for metric in [SimilarityMetric::Dot, SimilarityMetric::Euclidean] {
+            assert_eq!(ranked(metric), vec!["trusted-0", "plain-0"]);
+            let indexed: Vec<String> = VectorIndex::build(conn, "test-model")
+                .unwrap()
+                .search(&query, 2, metric)
+                .into_iter()
+                .map(|r| r.chunk_id)
+                .collect();
+            assert_eq!(indexed, ranked(metric));
+        }
+        let results = search_similar(conn, &query, 2, "test-model", SimilarityMetric::Dot).unwrap();
+        assert!((results[0].score - 0.9 * 1.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_document_aggregation_max_vs_mean() {
+        let db = crate::db::Database::in_memory().unwrap();
+        let conn = &db.conn;
+        insert_test_document(conn, "broad");
+        insert_test_document(conn, "spike");
+        // Unit vectors scoring `similarity` against the query [1, 0]
+        let unit = |similarity: f32| [similarity, (1.0 - similarity * similarity).sqrt()];
+        let chunks = [
+            ("broad-0", "broad", 0.8),
+            ("broad-1", "broad", 0.78),
+            ("broad-2", "broad", 0.75),
+            ("spike-0", "spike", 0.95),
+            ("spike-1", "spike", 0.1),
+            ("spike-2", "spike", 0.05),
+        ];
+        for (i, (chunk_id, doc, similarity)) in chunks.into_iter().enumerate() {
+            conn.execute(
+                "INSERT INTO chunks
+                     (id, document_id, chunk_index, content, start_offset, end_offset)
+                 VALUES (?1, ?2, ?3, ?1, 0, 1)",
+                params![chunk_id, doc, i as i64],
+            )
+            .unwrap();
+            save_embedding(conn, chunk_id, doc, &unit(similarity), "test-model").unwrap();
+        }
+
+        let rank = |aggregation| {
+            search_documents_aggregated(
+                conn,
+                &[1.0, 0.0],
+                10,
+                "test-model",
+                SimilarityMetric::Dot,
+                aggregation,
+            )
+            .unwrap()
+        };
+
+        // One very similar chunk wins on its best score...
+        let max = rank(DocumentAggregation::Max);
+        let ids: Vec<&str> = max.iter().map(|r| r.document_id.as_str()).collect();
+        assert_eq!(ids, vec!["spike", "broad"]);
+        assert!((max[0].score - 0.95).abs() < 1e-5);
+        assert_eq!(max[0].best_chunk_id, "spike-0");
+        assert_eq!(max[0].snippet, "spike-0");
+
+        // ...but several moderately similar chunks win on average
+        let mean = rank(DocumentAggregation::MeanOfTop(3));
+        let ids: Vec<&str> = mean.iter().map(|r| r.document_id.as_str()).collect();
+        assert_eq!(ids, vec!["broad", "spike"]);
+        assert!((mean[0].score - (0.8 + 0.78 + 0.75) / 3.0).abs() < 1e-5);
+        // The best chunk is still reported, whatever the aggregation
+        assert_eq!(mean[1].best_chunk_id, "spike-0");
+
+        assert_eq!(
+            search_documents_aggregated(
+                conn,
+                &[1.0, 0.0],
+                1,
+                "test-model",
+                SimilarityMetric::Dot,
+                DocumentAggregation::Max
+            )
+            .unwrap()
+            .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_merge_overlapping_results() {
+        let text = "The quick brown fox jumps over the lazy dog while the cat watches \
+                    from a sunny windowsill and the bird sings in the old oak tree.";
+        let config = crate::chunker::ChunkConfig::new(40, 12).unwrap();
+        let chunks = crate::chunker::chunk_text("doc", text, &config).unwrap();
+        assert!(chunks.len() >= 3);
+        assert!(chunks[0].end_offset > chunks[1].start_offset);
+
+        let result = |chunk_id: &str, document_id: &str, content: &str, score| SearchResult {
+            chunk_id: chunk_id.to_string(),
+            document_id: document_id.to_string(),
+            content: content.to_string(),
+            score,
+            explanation: None,
+        };
+        let chunks_by_id: HashMap<String, Chunk> =
+            chunks.iter().map(|c| (c.id.clone(), c.clone())).collect();
+
+        // Two adjacent chunks become the exact span they cover, with no
+        // duplicated overlap
+        let results = vec![
+            result(&chunks[1].id, "doc", &chunks[1].content, 0.9),
+            result(&chunks[0].id, "doc", &chunks[0].content, 0.8),
+        ];
+        let merged = merge_overlapping_results(&results, &chunks_by_id);
+        let expected: String = text
+            .chars()
+            .skip(chunks[0].start_offset)
+            .take(chunks[1].end_offset - chunks[0].start_offset)
+            .collect();
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].content, expected.trim());
+        assert_eq!(merged[0].chunk_id, chunks[1].id);
+        assert_eq!(merged[0].score, 0.9);
+
+        // A chunk found later bridges two runs; unknown and non-adjacent
+        // results keep their places
+        let last = chunks.len() - 1;
+        let results = vec![
+            result(&chunks[0].id, "doc", &chunks[0].content, 0.9),
+            result("elsewhere", "other", "unrelated", 0.8),
+            result(&chunks[2].id, "doc", &chunks[2].content, 0.7),
+            result(&chunks[1].id, "doc", &chunks[1].content, 0.6),
+        ];
+        let merged = merge_overlapping_results(&results, &chunks_by_id);
+        let ids: Vec<&str> = merged.iter().map(|r| r.chunk_id.as_str()).collect();
+        assert_eq!(ids, vec![chunks[0].id.as_str(), "elsewhere"]);
+        assert!(merged[0].content.starts_with(&chunks[0].content));
+        assert!(merged[0].content.ends_with(&chunks[2].content));
+        if last > 2 {
+            let results = vec![
+                result(&chunks[0].id, "doc", &chunks[0].content, 0.9),
+                result(&chunks[last].id, "doc", &chunks[last].content, 0.8),
+            ];
+            let merged = merge_overlapping_results(&results, &chunks_by_id);
+            let contents: Vec<&str> = merged.iter().map(|r| r.content.as_str()).collect();
+            assert_eq!(contents, vec![chunks[0].content.as_str(), chunks[last].content.as_str()]);
+        }
+    }
 }
@@ -12,8 +12,12 @@ interface DocumentListProps {
 function getFileIcon(type: Document['type']) {
   switch (type) {
     case 'pdf':
+    case 'epub':
       return FileText;
     case 'md':
+    case 'rst':
+    case 'org':
+    case 'json':
       return FileCode;
     default:
       return File;
@@ -1,5 +1,6 @@
 import { useState, useCallback, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { errorMessage } from '@/lib/utils';
 import {
   Chat,
   Message,
@@ -55,7 +56,7 @@ export function useChat() {
         }
       } catch (err) {
         console.error('Failed to load chats:', err);
-        setError(err instanceof Error ? err.message : String(err));
+        setError(errorMessage(err));
       } finally {
         setIsInitializing(false);
       }
@@ -109,7 +110,7 @@ export function useChat() {
       return newChat;
     } catch (err) {
       console.error('Failed to create chat:', err);
-      setError(err instanceof Error ? err.message : String(err));
+      setError(errorMessage(err));
       return null;
     }
   }, []);
@@ -132,7 +133,7 @@ export function useChat() {
         await invoke('delete_chat', { chatId });
       } catch (err) {
         console.error('Failed to delete chat:', err);
-        setError(err instanceof Error ? err.message : String(err));
+        setError(errorMessage(err));
 
         // Reload chats to restore state on error
         const backendChats = await invoke<BackendChat[]>('get_all_chats');
@@ -259,7 +260,7 @@ export function useChat() {
         );
       } catch (err) {
         console.error('Failed to send message:', err);
-        setError(err instanceof Error ? err.message : String(err));
+        setError(errorMessage(err));
       } finally {
         setIsLoading(false);
       }
@@ -1,5 +1,6 @@
 import { useState, useCallback, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { errorMessage } from '@/lib/utils';
 import { open } from '@tauri-apps/plugin-dialog';
 import { Document, IndexStatus } from '@/types';
 
@@ -22,7 +23,7 @@ function convertBackendDocument(doc: BackendDocument): Document {
   return {
     id: doc.id,
     name: doc.name,
-    type: doc.type as 'pdf' | 'txt' | 'md',
+    type: doc.type as Document['type'],
     size: doc.size,
     uploadedAt: new Date(doc.uploadedAt),
   };
@@ -61,7 +62,7 @@ export function useDocuments() {
         setDocuments(frontendDocs);
       } catch (err) {
         console.error('Failed to load documents:', err);
-        setError(err instanceof Error ? err.message : String(err));
+        setError(errorMessage(err));
       } finally {
         setIsInitializing(false);
       }
@@ -87,7 +88,7 @@ export function useDocuments() {
         filters: [
           {
             name: 'Documents',
-            extensions: ['pdf', 'txt', 'md', 'markdown'],
+            extensions: ['pdf', 'txt', 'md', 'markdown', 'rst', 'org', 'epub', 'json', 'jsonl', 'ndjson'],
           },
         ],
       });
@@ -125,7 +126,7 @@ export function useDocuments() {
       return newDoc;
     } catch (err) {
       console.error('Failed to upload document:', err);
-      setError(err instanceof Error ? err.message : String(err));
+      setError(errorMessage(err));
       return null;
     } finally {
       setIsUploading(false);
@@ -151,7 +152,7 @@ export function useDocuments() {
       await invoke('delete_document_cmd', { documentId });
     } catch (err) {
       console.error('Failed to delete document:', err);
-      setError(err instanceof Error ? err.message : String(err));
+      setError(errorMessage(err));
 
       // Reload documents on error to restore state
       const backendDocs = await invoke<BackendDocument[]>('get_all_documents');
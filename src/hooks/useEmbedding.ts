@@ -1,11 +1,18 @@
 import { useState, useEffect, useCallback } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { errorMessage } from '@/lib/utils';
 
 export interface SearchResult {
   chunk_id: string;
   document_id: string;
   content: string;
   score: number;
+  explanation?: SearchExplanation;
+}
+
+export interface SearchExplanation {
+  matched_terms: string[];
+  score: number;
 }
 
 export interface EmbeddingStats {
@@ -42,7 +49,7 @@ export function useEmbedding() {
         console.log('Embedding model ready');
       } catch (err) {
         console.error('Failed to load embedding model:', err);
-        setError(err instanceof Error ? err.message : String(err));
+        setError(errorMessage(err));
         setModelStatus('error');
       }
     };
@@ -108,7 +115,7 @@ export function useEmbedding() {
       await invoke<string>('init_embedding_model');
       setModelStatus('ready');
     } catch (err) {
-      setError(err instanceof Error ? err.message : String(err));
+      setError(errorMessage(err));
       setModelStatus('error');
     }
   }, []);
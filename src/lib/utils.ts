@@ -4,3 +4,35 @@ import { twMerge } from "tailwind-merge";
 export function cn(...inputs: ClassValue[]) {
   return twMerge(clsx(inputs));
 }
+
+/**
+ * Error returned by a failed Tauri command (see `CommandError` in Rust).
+ */
+export interface CommandError {
+  kind:
+    | 'NotFound'
+    | 'Database'
+    | 'Embedding'
+    | 'InvalidInput'
+    | 'Io'
+    | 'Cancelled'
+    | 'Internal';
+  message: string;
+}
+
+export function isCommandError(err: unknown): err is CommandError {
+  return (
+    typeof err === 'object' &&
+    err !== null &&
+    'kind' in err &&
+    'message' in err
+  );
+}
+
+/**
+ * Extracts a human-readable message from anything thrown by `invoke`.
+ */
+export function errorMessage(err: unknown): string {
+  if (isCommandError(err)) return err.message;
+  return err instanceof Error ? err.message : String(err);
+}
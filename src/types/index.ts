@@ -24,7 +24,7 @@ export interface Chat {
 export interface Document {
   id: string;
   name: string;
-  type: 'pdf' | 'txt' | 'md';
+  type: 'pdf' | 'txt' | 'md' | 'rst' | 'org' | 'epub' | 'json';
   size: number;
   uploadedAt: Date;
 }